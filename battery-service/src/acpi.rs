@@ -10,7 +10,7 @@ use embedded_services::{
     },
     ec_type::protocols::mctp,
     error, info,
-    power::policy::PowerCapability,
+    power::policy::{PowerCapability, charge_limit},
     trace,
 };
 
@@ -53,6 +53,7 @@ pub(crate) enum AcpiCmd {
     SetBms = 13,
     SetBma = 14,
     GetSta = 15,
+    SetChargeLimit = 16,
 }
 
 impl TryFrom<u8> for AcpiCmd {
@@ -74,6 +75,7 @@ impl TryFrom<u8> for AcpiCmd {
             13 => Ok(AcpiCmd::SetBms),
             14 => Ok(AcpiCmd::SetBma),
             15 => Ok(AcpiCmd::GetSta),
+            16 => Ok(AcpiCmd::SetChargeLimit),
             _ => Err(PayloadError::MalformedPayload),
         }
     }
@@ -85,22 +87,60 @@ impl From<AcpiCmd> for u8 {
     }
 }
 
-pub(crate) fn compute_bst(cache: &DynamicBatteryMsgs) -> embedded_batteries_async::acpi::BstReturn {
-    let charging = if cache.battery_status & (1 << 6) == 0 {
+/// _BST battery state bit set while charging is held by the charge limit
+const BST_CHARGE_LIMITING: u32 = 1 << 3;
+
+pub(crate) fn compute_bst(
+    cache: &DynamicBatteryMsgs,
+    charge_limiting: bool,
+) -> embedded_batteries_async::acpi::BstReturn {
+    let mut battery_state = if cache.battery_status & (1 << 6) == 0 {
         embedded_batteries_async::acpi::BatteryState::CHARGING
     } else {
         embedded_batteries_async::acpi::BatteryState::DISCHARGING
     };
 
-    // TODO: add critical energy state and charge limiting state
+    if charge_limiting {
+        battery_state |= embedded_batteries_async::acpi::BatteryState::from_bits_retain(BST_CHARGE_LIMITING);
+    }
+
+    // TODO: add critical energy state
     embedded_batteries_async::acpi::BstReturn {
-        battery_state: charging,
+        battery_state,
         battery_remaining_capacity: cache.remaining_capacity_mwh,
         battery_present_rate: cache.current_ma.unsigned_abs().into(),
         battery_present_voltage: cache.voltage_mv.into(),
     }
 }
 
+/// Decode a host charge limit request
+///
+/// Modes are 0: disabled, 1: band, 2: scheduled full charge after `full_charge_delay_min`, 3: storage.
+pub(crate) fn decode_charge_limit(
+    mode: u32,
+    upper_pct: u32,
+    lower_pct: u32,
+    full_charge_delay_min: u32,
+) -> Option<charge_limit::Mode> {
+    let upper_pct = u8::try_from(upper_pct).ok();
+    let lower_pct = u8::try_from(lower_pct).ok();
+    match mode {
+        0 => Some(charge_limit::Mode::Disabled),
+        1 => Some(charge_limit::Mode::Band {
+            upper_pct: upper_pct?,
+            lower_pct: lower_pct?,
+        }),
+        2 => Some(charge_limit::Mode::Scheduled {
+            upper_pct: upper_pct?,
+            lower_pct: lower_pct?,
+            full_charge_at: embassy_time::Instant::now()
+                + embassy_time::Duration::from_secs(u64::from(full_charge_delay_min) * 60),
+        }),
+        3 => Some(charge_limit::Mode::Storage),
+        _ => None,
+    }
+}
+
 pub(crate) fn compute_bix<'a>(
     static_cache: &'a StaticBatteryMsgs,
     dynamic_cache: &'a DynamicBatteryMsgs,
@@ -313,7 +353,7 @@ impl crate::context::Context {
             mctp::Odp::BatteryGetBstRequest { battery_id } => {
                 if let Some(fg) = self.get_fuel_gauge(DeviceId(battery_id)) {
                     request.payload = mctp::Odp::BatteryGetBstResponse {
-                        bst: compute_bst(&fg.get_dynamic_battery_cache().await),
+                        bst: compute_bst(&fg.get_dynamic_battery_cache().await, fg.is_charge_limiting().await),
                     };
                     request.status = 0;
                 } else {
@@ -688,4 +728,103 @@ impl crate::context::Context {
         .await
         .unwrap();
     }
+
+    pub(super) async fn charge_limit_handler(&self, request: &mut StdHostRequest) {
+        trace!("Battery service: got charge limit command!");
+
+        match request.payload {
+            mctp::Odp::BatterySetChargeLimitRequest {
+                battery_id,
+                mode,
+                upper_pct,
+                lower_pct,
+                full_charge_delay_min,
+            } => {
+                let result = if let Some(fg) = self.get_fuel_gauge(DeviceId(battery_id)) {
+                    if let Some(mode) = decode_charge_limit(mode, upper_pct, lower_pct, full_charge_delay_min) {
+                        info!(
+                            "Battery service: New charge limit mode {:?} for ID {}",
+                            mode, battery_id
+                        );
+                        fg.set_charge_limit_mode(mode).await.map_err(|_| ())
+                    } else {
+                        Err(())
+                    }
+                } else {
+                    error!("Battery service: FG not found when trying to process ACPI cmd!");
+                    Err(())
+                };
+
+                // Apply the new mode right away instead of waiting for the next poll
+                if result.is_ok() {
+                    self.update_charge_limit(DeviceId(battery_id)).await;
+                }
+
+                if result.is_ok() {
+                    request.payload = mctp::Odp::BatterySetChargeLimitResponse { status: 0 };
+                    request.status = 0;
+                } else {
+                    error!("Battery service: invalid charge limit request!");
+                    request.payload = mctp::Odp::BatterySetChargeLimitResponse { status: 1 };
+                    request.status = 1;
+                }
+            }
+            _ => error!("Battery service: command and body mismatch!"),
+        }
+
+        super::comms_send(
+            crate::EndpointID::External(embedded_services::comms::External::Host),
+            &StdHostMsg::Response(*request),
+        )
+        .await
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_time::{Duration, Instant};
+
+    /// Test decoding of each charge limit mode
+    #[test]
+    fn test_decode_charge_limit() {
+        assert_eq!(decode_charge_limit(0, 0, 0, 0), Some(charge_limit::Mode::Disabled));
+        assert_eq!(
+            decode_charge_limit(1, 80, 75, 0),
+            Some(charge_limit::Mode::Band {
+                upper_pct: 80,
+                lower_pct: 75,
+            })
+        );
+        assert_eq!(decode_charge_limit(3, 0, 0, 0), Some(charge_limit::Mode::Storage));
+        assert_eq!(decode_charge_limit(4, 80, 75, 0), None);
+    }
+
+    /// Test that the scheduled full charge starts after the requested delay
+    #[test]
+    fn test_decode_charge_limit_scheduled() {
+        let delay = Duration::from_secs(90 * 60);
+        let before = Instant::now();
+        let mode = decode_charge_limit(2, 60, 55, 90);
+        let after = Instant::now();
+
+        assert!(matches!(
+            mode,
+            Some(charge_limit::Mode::Scheduled {
+                upper_pct: 60,
+                lower_pct: 55,
+                full_charge_at,
+            }) if full_charge_at >= before + delay && full_charge_at <= after + delay
+        ));
+    }
+
+    /// Test that out of range percentages are rejected instead of truncated
+    #[test]
+    fn test_decode_charge_limit_out_of_range() {
+        assert_eq!(decode_charge_limit(1, 256, 75, 0), None);
+        assert_eq!(decode_charge_limit(2, 80, 0x1_0000, 0), None);
+        // Modes without a band ignore the percentages
+        assert_eq!(decode_charge_limit(0, 256, 256, 0), Some(charge_limit::Mode::Disabled));
+    }
 }
@@ -1,15 +1,17 @@
 use crate::device::{self, DeviceId};
 use crate::device::{Device, FuelGaugeError};
+use crate::regulation;
 use embassy_sync::channel::Channel;
 use embassy_sync::channel::TrySendError;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
-use embassy_time::{Duration, with_timeout};
+use embassy_time::{Duration, Instant, Timer, with_timeout};
 use embedded_services::GlobalRawMutex;
 use embedded_services::comms::MailboxDelegateError;
 use embedded_services::ec_type::message::StdHostRequest;
 use embedded_services::ec_type::protocols::acpi::BatteryCmd;
-use embedded_services::power::policy::PowerCapability;
+use embedded_services::power::policy::battery::BatteryContainer;
+use embedded_services::power::policy::{self, PowerCapability};
 use embedded_services::{IntrusiveList, debug, error, info, intrusive_list, trace, warn};
 
 use core::ops::DerefMut;
@@ -310,6 +312,7 @@ impl Context {
                     .await
                 {
                     error!("Error pinging fuel gauge with ID {:?}, {:?}", event.device_id, e);
+                    self.update_power_policy(event.device_id, false).await;
                     return Err(StateMachineError::DeviceError);
                 }
                 if let Err(e) = self
@@ -347,6 +350,7 @@ impl Context {
                             // transition to the NotPresent state.
                             if self.get_state_machine_retry_count() > self.get_state_machine_max_retries() {
                                 *state = State::NotPresent;
+                                self.update_power_policy(event.device_id, false).await;
                                 return Err(StateMachineError::NoOpRecoveryFailed);
                             }
                             Err(StateMachineError::DeviceTimeout)
@@ -361,6 +365,7 @@ impl Context {
                             // transition to the NotPresent state.
                             if self.get_state_machine_retry_count() > self.get_state_machine_max_retries() {
                                 *state = State::NotPresent;
+                                self.update_power_policy(event.device_id, false).await;
                                 return Err(StateMachineError::NoOpRecoveryFailed);
                             }
                             Err(StateMachineError::DeviceTimeout)
@@ -397,6 +402,7 @@ impl Context {
                             );
                             return Err(StateMachineError::DeviceError);
                         }
+                        self.update_power_policy(event.device_id, true).await;
                         Ok(InnerStateMachineResponse::Complete)
                     }
                 },
@@ -422,11 +428,74 @@ impl Context {
                 BatteryCmd::SetBms => self.bms_handler(acpi_msg).await,
                 BatteryCmd::SetBma => self.bma_handler(acpi_msg).await,
                 BatteryCmd::GetSta => self.sta_handler(acpi_msg).await,
+                BatteryCmd::SetChargeLimit => self.charge_limit_handler(acpi_msg).await,
             },
             _ => error!("Battery service: host command not found!"),
         }
     }
 
+    /// Report the battery as a power source to the power policy and its charging request to the chargers charging it
+    ///
+    /// A battery that isn't present or stopped responding is reported as not present and isn't charged.
+    async fn update_power_policy(&self, id: DeviceId, responding: bool) {
+        let Some(fg) = self.get_fuel_gauge(id) else {
+            return;
+        };
+
+        let (status, inputs) = if responding {
+            let cache = fg.get_dynamic_battery_cache().await;
+            let status = policy::battery::Status {
+                present: true,
+                state_of_charge_pct: cache.relative_soc_pct.min(100) as u8,
+                sus_power_mw: cache.sus_power_mw,
+                temperature_dk: cache.battery_temp_dk,
+            };
+            let charge_limited = !fg.update_charge_limit(Instant::now()).await;
+            (status, regulation::battery_inputs(&cache, true, charge_limited))
+        } else {
+            let status = policy::battery::Status {
+                present: false,
+                ..Default::default()
+            };
+            (status, Default::default())
+        };
+        let battery = fg.get_battery();
+        battery.update(Some(status)).await;
+        policy::policy::update_charger_battery(battery.id(), inputs).await;
+    }
+
+    /// Re-evaluate the charge limit of a battery and report it to the chargers
+    pub(crate) async fn update_charge_limit(&self, id: DeviceId) {
+        let Some(fg) = self.get_fuel_gauge(id) else {
+            return;
+        };
+
+        // The state machine is shared by all fuel gauges, go by the status last reported for this battery instead
+        let responding = fg.get_battery().status().await.is_some_and(|status| status.present);
+        self.update_power_policy(id, responding).await;
+    }
+
+    /// Wait until the charge limit of a battery changes on its own, e.g. a scheduled full charge starts
+    pub(crate) async fn wait_charge_limit_deadline(&self) -> DeviceId {
+        let now = Instant::now();
+        let mut next: Option<(Instant, DeviceId)> = None;
+        for fg in self.fuel_gauges.iter_only::<Device>() {
+            if let Some(deadline) = fg.charge_limit_deadline(now).await
+                && next.is_none_or(|(next_deadline, _)| deadline < next_deadline)
+            {
+                next = Some((deadline, fg.id()));
+            }
+        }
+
+        match next {
+            Some((deadline, id)) => {
+                Timer::at(deadline).await;
+                id
+            }
+            None => core::future::pending().await,
+        }
+    }
+
     pub(crate) fn get_fuel_gauge(&self, id: DeviceId) -> Option<&'static Device> {
         for device in &self.fuel_gauges {
             if let Some(data) = device.data::<Device>() {
@@ -446,6 +515,7 @@ impl Context {
             return Err(embedded_services::Error::NodeAlreadyInList);
         }
 
+        policy::policy::register_battery(device)?;
         self.fuel_gauges.push(device)
     }
 
@@ -552,3 +622,78 @@ impl Default for Context {
         Self::new()
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use embassy_futures::{block_on, join::join};
+    use embassy_sync::once_lock::OnceLock;
+
+    const NOT_PRESENT: policy::battery::Status = policy::battery::Status {
+        present: false,
+        state_of_charge_pct: 0,
+        sus_power_mw: 0,
+        temperature_dk: 0,
+    };
+
+    /// Answer the next `count` commands sent to the fuel gauge
+    async fn respond(device: &Device, count: usize, response: device::Response) {
+        for _ in 0..count {
+            device.receive_command().await;
+            device.send_response(response).await;
+        }
+    }
+
+    /// Test booting without a fuel gauge and removing the battery at runtime
+    #[test]
+    fn test_battery_not_present() {
+        static DEVICE: OnceLock<Device> = OnceLock::new();
+        let device = DEVICE.get_or_init(|| Device::new(DeviceId(0)));
+        let context = Context::new();
+        context.register_fuel_gauge(device).unwrap();
+        let event = |event| BatteryEvent {
+            event,
+            device_id: DeviceId(0),
+        };
+
+        // Unknown until the fuel gauge has been probed
+        assert_eq!(block_on(device.get_battery().status()), None);
+
+        // No fuel gauge at boot
+        let (res, _) = block_on(join(
+            context.do_state_machine(event(BatteryEventInner::DoInit)),
+            respond(device, 1, Err(FuelGaugeError::BusError)),
+        ));
+        assert_eq!(res, Err(StateMachineError::DeviceError));
+        assert_eq!(block_on(device.get_battery().status()), Some(NOT_PRESENT));
+
+        // Battery inserted
+        let ok = Ok(device::InternalResponse::Complete);
+        let (res, _) = block_on(join(
+            context.do_state_machine(event(BatteryEventInner::DoInit)),
+            respond(device, 2, ok),
+        ));
+        assert!(res.is_ok());
+        let (res, _) = block_on(join(
+            context.do_state_machine(event(BatteryEventInner::PollStaticData)),
+            respond(device, 1, ok),
+        ));
+        assert!(res.is_ok());
+        let (res, _) = block_on(join(
+            context.do_state_machine(event(BatteryEventInner::PollDynamicData)),
+            respond(device, 1, ok),
+        ));
+        assert!(res.is_ok());
+        assert!(block_on(device.get_battery().status()).unwrap().present);
+
+        // Battery removed, the fuel gauge stops responding
+        let (res, _) = block_on(join(
+            context.do_state_machine(event(BatteryEventInner::Timeout)),
+            respond(device, 1, Err(FuelGaugeError::BusError)),
+        ));
+        assert_eq!(res, Err(StateMachineError::NoOpRecoveryFailed));
+        assert_eq!(block_on(context.get_state()), State::NotPresent);
+        assert_eq!(block_on(device.get_battery().status()), Some(NOT_PRESENT));
+    }
+}
@@ -2,11 +2,12 @@ use embassy_sync::{
     channel::Channel,
     mutex::{Mutex, MutexGuard},
 };
-use embassy_time::Duration;
+use embassy_time::{Duration, Instant};
 use embedded_batteries_async::{
     acpi::{BmcControlFlags, BmdCapabilityFlags, BmdStatusFlags, PowerThresholdSupport},
     smart_battery::BatteryModeFields,
 };
+use embedded_services::power::policy::{battery, charge_limit};
 use embedded_services::{GlobalRawMutex, Node, NodeContainer, SyncCell};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -169,6 +170,8 @@ pub struct Device {
     dynamic_battery_cache: Mutex<GlobalRawMutex, DynamicBatteryMsgs>,
     static_battery_cache: Mutex<GlobalRawMutex, StaticBatteryMsgs>,
     timeout: SyncCell<Duration>,
+    charge_limit: Mutex<GlobalRawMutex, charge_limit::Limiter>,
+    power_source: battery::Device,
 }
 
 impl Device {
@@ -181,6 +184,8 @@ impl Device {
             dynamic_battery_cache: Mutex::default(),
             static_battery_cache: Mutex::default(),
             timeout: SyncCell::new(Duration::from_secs(60)),
+            charge_limit: Mutex::new(charge_limit::Limiter::new()),
+            power_source: battery::Device::new(battery::BatteryId(id.0)),
         }
     }
 
@@ -215,6 +220,27 @@ impl Device {
         self.response.send(response).await
     }
 
+    /// Set the charge limit mode of this battery, the hold state is re-evaluated on the next update.
+    pub async fn set_charge_limit_mode(&self, mode: charge_limit::Mode) -> Result<(), charge_limit::InvalidMode> {
+        self.charge_limit.lock().await.set_mode(mode)
+    }
+
+    /// Update the charge limit with the cached state of charge, returns true if the battery may be charged.
+    pub async fn update_charge_limit(&self, now: Instant) -> bool {
+        let soc_pct = self.dynamic_battery_cache.lock().await.relative_soc_pct.min(100) as u8;
+        self.charge_limit.lock().await.update(soc_pct, now)
+    }
+
+    /// Returns true if charging is currently held by the charge limit.
+    pub async fn is_charge_limiting(&self) -> bool {
+        self.charge_limit.lock().await.is_limiting()
+    }
+
+    /// Returns the time at which the charge limit must be updated, see [`charge_limit::Limiter::deadline`].
+    pub async fn charge_limit_deadline(&self, now: Instant) -> Option<Instant> {
+        self.charge_limit.lock().await.deadline(now)
+    }
+
     /// Set dynamic battery cache with updated values.
     pub async fn set_dynamic_battery_cache(&self, new_values: DynamicBatteryMsgs) {
         *self.dynamic_battery_cache.lock().await = new_values;
@@ -267,3 +293,9 @@ impl NodeContainer for Device {
         &self.node
     }
 }
+
+impl battery::BatteryContainer for Device {
+    fn get_battery(&self) -> &battery::Device {
+        &self.power_source
+    }
+}
@@ -3,7 +3,7 @@
 use core::{any::Any, convert::Infallible};
 
 use context::BatteryEvent;
-use embassy_futures::select::select;
+use embassy_futures::select::select3;
 use embedded_services::{
     comms::{self, EndpointID},
     ec_type::message::StdHostRequest,
@@ -14,6 +14,8 @@ mod acpi;
 pub mod context;
 pub mod controller;
 pub mod device;
+pub mod pd;
+pub mod regulation;
 pub mod task;
 pub mod wrapper;
 
@@ -49,9 +51,16 @@ impl Service {
 
     /// Wait for next event.
     pub async fn wait_next(&self) -> Event {
-        match select(self.context.wait_event(), self.context.wait_acpi_cmd()).await {
-            embassy_futures::select::Either::First(event) => Event::StateMachine(event),
-            embassy_futures::select::Either::Second(acpi_msg) => Event::AcpiRequest(acpi_msg),
+        match select3(
+            self.context.wait_event(),
+            self.context.wait_acpi_cmd(),
+            self.context.wait_charge_limit_deadline(),
+        )
+        .await
+        {
+            embassy_futures::select::Either3::First(event) => Event::StateMachine(event),
+            embassy_futures::select::Either3::Second(acpi_msg) => Event::AcpiRequest(acpi_msg),
+            embassy_futures::select::Either3::Third(id) => Event::ChargeLimitDeadline(id),
         }
     }
 
@@ -66,6 +75,10 @@ impl Service {
                 trace!("Battery service: ACPI cmd recvd");
                 self.context.process_acpi_cmd(&mut acpi_msg).await
             }
+            Event::ChargeLimitDeadline(id) => {
+                trace!("Battery service: charge limit deadline for ID {:?}", id);
+                self.context.update_charge_limit(id).await
+            }
         }
     }
 }
@@ -75,6 +88,7 @@ impl Service {
 pub enum Event {
     StateMachine(BatteryEvent),
     AcpiRequest(StdHostRequest),
+    ChargeLimitDeadline(device::DeviceId),
 }
 
 impl Default for Service {
@@ -0,0 +1,279 @@
+//! Bridge between fuel gauge caches and USB PD extended battery messages.
+//!
+//! A dual-role system must answer Get_Battery_Cap and Get_Battery_Status from a port partner. The PD controller answers
+//! these messages from the data set through the type-C service, this module keeps that data in sync with the cached
+//! fuel gauge values.
+use embassy_time::{Duration, Timer};
+use embedded_services::power::system as system_power;
+use embedded_services::type_c::controller::{BatteryCapabilities, BatteryChargingStatus, BatteryRef, BatteryStatus};
+use embedded_services::type_c::external;
+use embedded_services::{error, info, trace};
+use embedded_usb_pd::{GlobalPortId, PdError};
+
+use crate::context::State;
+use crate::device::{Device, DynamicBatteryMsgs, StaticBatteryMsgs};
+
+/// SBS BatteryStatus DISCHARGING flag
+const SBS_STATUS_DISCHARGING: u16 = 1 << 6;
+
+/// Bridge configuration
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Battery reference reported to port partners
+    pub battery: BatteryRef,
+    /// Battery vendor ID
+    pub vid: u16,
+    /// Battery product ID
+    pub pid: u16,
+    /// Interval between updates
+    pub update_interval: Duration,
+    /// Interval between updates while the host isn't running
+    pub sleep_update_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            battery: BatteryRef(0),
+            vid: 0,
+            pid: 0,
+            update_interval: Duration::from_secs(10),
+            sleep_update_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Convert a capacity in mWh to units of 0.1 Wh
+///
+/// 0xFFFF is reserved by the PD spec to indicate an unknown capacity, so the result saturates below it.
+fn mwh_to_dwh(capacity_mwh: u32) -> u16 {
+    u16::try_from(capacity_mwh / 100).unwrap_or(u16::MAX).min(u16::MAX - 1)
+}
+
+/// Compute the Battery_Capabilities response from the fuel gauge caches
+pub fn battery_capabilities(
+    config: &Config,
+    static_cache: &StaticBatteryMsgs,
+    dynamic_cache: &DynamicBatteryMsgs,
+    present: bool,
+) -> BatteryCapabilities {
+    BatteryCapabilities {
+        vid: config.vid,
+        pid: config.pid,
+        design_capacity_dwh: present.then(|| mwh_to_dwh(static_cache.design_capacity_mwh)),
+        last_full_charge_capacity_dwh: present.then(|| mwh_to_dwh(dynamic_cache.full_charge_capacity_mwh)),
+        invalid_battery_ref: false,
+    }
+}
+
+/// Compute the Battery_Status response from the dynamic fuel gauge cache
+pub fn battery_status(dynamic_cache: &DynamicBatteryMsgs, present: bool) -> BatteryStatus {
+    if !present {
+        return BatteryStatus {
+            present: false,
+            ..Default::default()
+        };
+    }
+
+    let charging_status = if dynamic_cache.battery_status & SBS_STATUS_DISCHARGING != 0 {
+        BatteryChargingStatus::Discharging
+    } else if dynamic_cache.current_ma > 0 {
+        BatteryChargingStatus::Charging
+    } else {
+        BatteryChargingStatus::Idle
+    };
+
+    BatteryStatus {
+        present_capacity_dwh: Some(mwh_to_dwh(dynamic_cache.remaining_capacity_mwh)),
+        charging_status,
+        present: true,
+        invalid_battery_ref: false,
+    }
+}
+
+/// Ports whose controller doesn't support extended battery messages, only the first 32 ports are tracked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UnsupportedPorts(u32);
+
+impl UnsupportedPorts {
+    /// Returns true if the port doesn't support extended battery messages
+    pub fn contains(&self, port: GlobalPortId) -> bool {
+        1u32.checked_shl(port.0.into()).is_some_and(|bit| self.0 & bit != 0)
+    }
+
+    /// Mark a port as not supporting extended battery messages
+    pub fn insert(&mut self, port: GlobalPortId) {
+        if let Some(bit) = 1u32.checked_shl(port.0.into()) {
+            self.0 |= bit;
+        }
+    }
+}
+
+/// Push the current battery capabilities and status to all type-C ports
+///
+/// Ports in `unsupported` are skipped, ports found not to support extended battery messages are added to it.
+pub async fn update(config: &Config, device: &Device, unsupported: &mut UnsupportedPorts) -> Result<(), PdError> {
+    let present = matches!(crate::get_state().await, State::Present(_));
+    let static_cache = device.get_static_battery_cache().await;
+    let dynamic_cache = device.get_dynamic_battery_cache().await;
+    let capabilities = battery_capabilities(config, &static_cache, &dynamic_cache, present);
+    let status = battery_status(&dynamic_cache, present);
+    trace!("PD battery capabilities: {:?}, status: {:?}", capabilities, status);
+
+    let mut result = Ok(());
+    for port in 0..external::get_num_ports() {
+        let port = GlobalPortId(port as u8);
+        if unsupported.contains(port) {
+            continue;
+        }
+
+        // Keep going so one failing port doesn't prevent the others from being updated
+        match external::set_battery_capabilities(port, config.battery, capabilities).await {
+            Ok(()) => {}
+            Err(PdError::UnrecognizedCommand) => {
+                info!(
+                    "Port{}: Extended battery messages not supported, no longer updating",
+                    port.0
+                );
+                unsupported.insert(port);
+                continue;
+            }
+            Err(e) => {
+                error!("Port{}: Failed to set battery capabilities: {:?}", port.0, e);
+                result = Err(e);
+            }
+        }
+
+        if let Err(e) = external::set_battery_status(port, config.battery, status).await {
+            error!("Port{}: Failed to set battery status: {:?}", port.0, e);
+            result = Err(e);
+        }
+    }
+
+    result
+}
+
+/// Periodically push battery data from the given fuel gauge to all type-C ports.
+pub async fn task(config: Config, device: &'static Device) {
+    let mut unsupported = UnsupportedPorts::default();
+    loop {
+        // Errors are logged in update, retry on the next interval
+        let _ = update(&config, device, &mut unsupported).await;
+        // The battery changes slowly while the host sleeps, no need to keep the bus busy
+        let interval = if system_power::state().await.is_running() {
+            config.update_interval
+        } else {
+            config.sleep_update_interval
+        };
+        Timer::after(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: Config = Config {
+        battery: BatteryRef(0),
+        vid: 0x1234,
+        pid: 0x5678,
+        update_interval: Duration::from_secs(10),
+        sleep_update_interval: Duration::from_secs(60),
+    };
+
+    fn dynamic_cache(remaining_capacity_mwh: u32, battery_status: u16, current_ma: i16) -> DynamicBatteryMsgs {
+        DynamicBatteryMsgs {
+            full_charge_capacity_mwh: 48000,
+            remaining_capacity_mwh,
+            battery_status,
+            current_ma,
+            ..Default::default()
+        }
+    }
+
+    /// Test capacity conversion and saturation below the reserved unknown value
+    #[test]
+    fn test_battery_capabilities() {
+        let static_cache = StaticBatteryMsgs {
+            design_capacity_mwh: 50050,
+            ..Default::default()
+        };
+        let capabilities = battery_capabilities(&CONFIG, &static_cache, &dynamic_cache(0, 0, 0), true);
+        assert_eq!(
+            capabilities,
+            BatteryCapabilities {
+                vid: 0x1234,
+                pid: 0x5678,
+                design_capacity_dwh: Some(500),
+                last_full_charge_capacity_dwh: Some(480),
+                invalid_battery_ref: false,
+            }
+        );
+
+        let static_cache = StaticBatteryMsgs {
+            design_capacity_mwh: u32::MAX,
+            ..Default::default()
+        };
+        let capabilities = battery_capabilities(&CONFIG, &static_cache, &dynamic_cache(0, 0, 0), true);
+        assert_eq!(capabilities.design_capacity_dwh, Some(0xFFFE));
+    }
+
+    /// Test that capacities aren't reported without a battery
+    #[test]
+    fn test_battery_capabilities_not_present() {
+        let capabilities = battery_capabilities(&CONFIG, &Default::default(), &dynamic_cache(0, 0, 0), false);
+        assert_eq!(capabilities.design_capacity_dwh, None);
+        assert_eq!(capabilities.last_full_charge_capacity_dwh, None);
+        assert_eq!((capabilities.vid, capabilities.pid), (0x1234, 0x5678));
+    }
+
+    /// Test charging status derived from the SBS status and current
+    #[test]
+    fn test_battery_status() {
+        let status = battery_status(&dynamic_cache(24000, SBS_STATUS_DISCHARGING, -1000), true);
+        assert_eq!(
+            status,
+            BatteryStatus {
+                present_capacity_dwh: Some(240),
+                charging_status: BatteryChargingStatus::Discharging,
+                present: true,
+                invalid_battery_ref: false,
+            }
+        );
+
+        let status = battery_status(&dynamic_cache(24000, 0, 1000), true);
+        assert_eq!(status.charging_status, BatteryChargingStatus::Charging);
+
+        let status = battery_status(&dynamic_cache(24000, 0, 0), true);
+        assert_eq!(status.charging_status, BatteryChargingStatus::Idle);
+    }
+
+    /// Test the status reported without a battery
+    #[test]
+    fn test_battery_status_not_present() {
+        let status = battery_status(&dynamic_cache(24000, SBS_STATUS_DISCHARGING, -1000), false);
+        assert_eq!(
+            status,
+            BatteryStatus {
+                present: false,
+                ..Default::default()
+            }
+        );
+    }
+
+    /// Test tracking ports without extended battery message support
+    #[test]
+    fn test_unsupported_ports() {
+        let mut unsupported = UnsupportedPorts::default();
+        assert!(!unsupported.contains(GlobalPortId(1)));
+
+        unsupported.insert(GlobalPortId(1));
+        assert!(unsupported.contains(GlobalPortId(1)));
+        assert!(!unsupported.contains(GlobalPortId(0)));
+
+        // Ports past the tracked range are always updated
+        unsupported.insert(GlobalPortId(40));
+        assert!(!unsupported.contains(GlobalPortId(40)));
+    }
+}
@@ -0,0 +1,85 @@
+//! Bridge between fuel gauge caches and charge regulation.
+//!
+//! The chargers regulate their voltage and current from the fuel gauge charging request and pack measurements, this
+//! module converts the cached fuel gauge values and the battery's charge limit into the inputs the power policy
+//! forwards to the chargers.
+use embedded_services::power::policy::charger::BatteryInputs;
+
+use crate::device::DynamicBatteryMsgs;
+
+/// SBS BatteryStatus FULLY_CHARGED flag
+const SBS_STATUS_FULLY_CHARGED: u16 = 1 << 5;
+
+/// Compute the charge regulation inputs from the dynamic fuel gauge cache and the battery's charge limit
+pub fn battery_inputs(dynamic_cache: &DynamicBatteryMsgs, present: bool, charge_limited: bool) -> BatteryInputs {
+    if !present {
+        return BatteryInputs::default();
+    }
+
+    BatteryInputs {
+        present: true,
+        requested_voltage_mv: dynamic_cache.charging_voltage_mv,
+        requested_current_ma: dynamic_cache.charging_current_ma,
+        voltage_mv: dynamic_cache.voltage_mv,
+        current_ma: dynamic_cache.current_ma,
+        fully_charged: dynamic_cache.battery_status & SBS_STATUS_FULLY_CHARGED != 0,
+        state_of_charge_pct: dynamic_cache.relative_soc_pct.min(100) as u8,
+        charge_limited,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dynamic_cache(battery_status: u16, relative_soc_pct: u16) -> DynamicBatteryMsgs {
+        DynamicBatteryMsgs {
+            relative_soc_pct,
+            voltage_mv: 8100,
+            battery_status,
+            charging_voltage_mv: 8400,
+            charging_current_ma: 3000,
+            current_ma: 2500,
+            ..Default::default()
+        }
+    }
+
+    /// Test conversion of the fuel gauge charging request and measurements
+    #[test]
+    fn test_battery_inputs() {
+        assert_eq!(
+            battery_inputs(&dynamic_cache(0, 80), true, false),
+            BatteryInputs {
+                present: true,
+                requested_voltage_mv: 8400,
+                requested_current_ma: 3000,
+                voltage_mv: 8100,
+                current_ma: 2500,
+                fully_charged: false,
+                state_of_charge_pct: 80,
+                charge_limited: false,
+            }
+        );
+
+        let inputs = battery_inputs(&dynamic_cache(SBS_STATUS_FULLY_CHARGED, 100), true, false);
+        assert!(inputs.fully_charged);
+        assert_eq!(inputs.state_of_charge_pct, 100);
+
+        assert!(battery_inputs(&dynamic_cache(0, 80), true, true).charge_limited);
+
+        // Out of range state of charge is clamped
+        assert_eq!(
+            battery_inputs(&dynamic_cache(0, 300), true, false).state_of_charge_pct,
+            100
+        );
+    }
+
+    /// Test that nothing is requested without a battery
+    #[test]
+    fn test_battery_inputs_not_present() {
+        assert_eq!(
+            battery_inputs(&dynamic_cache(SBS_STATUS_FULLY_CHARGED, 80), false, true),
+            BatteryInputs::default()
+        );
+    }
+}
@@ -1,59 +1,246 @@
+//! Host side of CFU, in some cases this will originate from a OS driver for CFU
+//!
+//! Images are read from a storage source. Each image is a 16-byte offer followed by a payload made of records, each
+//! record being a little-endian 32-bit firmware address, a one byte length and that many bytes of content.
 use core::future::Future;
 
-use embedded_cfu_protocol::components::CfuComponentTraits;
+use embassy_time::{Duration, Timer};
 use embedded_cfu_protocol::host::{CfuHostStates, CfuUpdater};
 use embedded_cfu_protocol::protocol_definitions::*;
-use embedded_cfu_protocol::{
-    CfuImage,
-    writer::{CfuWriterAsync, CfuWriterError, CfuWriterNop},
-};
+use embedded_cfu_protocol::writer::{CfuWriterAsync, CfuWriterError};
+use embedded_services::cfu::component::{InternalResponseData, RequestData};
+use embedded_services::cfu::route_request;
+use embedded_services::{error, info, trace};
 use heapless::Vec;
 
 use crate::CfuError;
 
+/// Size of an offer in storage
+pub const OFFER_LEN: usize = 16;
+/// Size of the address and length preceding each payload record
+pub const RECORD_HEADER_LEN: usize = 5;
+
+/// Location of an image in storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImageLocation {
+    /// Offset of the offer
+    pub offer: usize,
+    /// Offset of the payload
+    pub payload: usize,
+    /// Length of the payload in bytes
+    pub payload_len: usize,
+}
+
+/// Outcome of offering an image to its component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OfferOutcome {
+    /// Offer was accepted and all content written
+    Updated,
+    /// Component skipped the offer, its firmware is already up to date
+    Skipped,
+    /// Component rejected the offer
+    Rejected(OfferRejectReason),
+    /// Component rejected the content
+    ContentRejected(CfuUpdateContentResponseStatus),
+    /// Update could not be completed
+    Failed(CfuError),
+}
+
+/// Outcome of every image offered during an update
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    /// Component and outcome of each image, in offer order
+    pub results: Vec<(ComponentId, OfferOutcome), MAX_CMPT_COUNT>,
+}
+
+impl UpdateSummary {
+    fn count(&self, f: impl Fn(&OfferOutcome) -> bool) -> usize {
+        self.results.iter().filter(|(_, outcome)| f(outcome)).count()
+    }
+
+    /// Number of components updated
+    pub fn updated(&self) -> usize {
+        self.count(|outcome| *outcome == OfferOutcome::Updated)
+    }
+
+    /// Number of offers skipped
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| *outcome == OfferOutcome::Skipped)
+    }
+
+    /// Number of offers rejected
+    pub fn rejected(&self) -> usize {
+        self.count(|outcome| matches!(outcome, OfferOutcome::Rejected(_)))
+    }
+
+    /// Number of updates that failed after being accepted or couldn't be offered
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, OfferOutcome::ContentRejected(_) | OfferOutcome::Failed(_)))
+    }
+
+    /// Returns true if no update failed, skipped and rejected offers are expected when a component is up to date
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+/// Host configuration
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// Number of times a request is retried while the component is busy
+    pub busy_retries: u8,
+    /// Delay between retries
+    pub busy_delay: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            busy_retries: 3,
+            busy_delay: Duration::from_millis(100),
+        }
+    }
+}
+
 /// All host side Cfu traits, in some cases this will originate from a OS driver for CFU
 pub trait CfuHost<W>: CfuHostStates<W> {
-    /// Get all images
-    fn get_cfu_images<I: CfuImage>(&self) -> impl Future<Output = Result<Vec<I, MAX_CMPT_COUNT>, CfuError>>;
+    /// Read the offers of all images from storage
+    fn get_cfu_images(
+        &self,
+        writer: &mut W,
+    ) -> impl Future<Output = Result<Vec<FwUpdateOffer, MAX_CMPT_COUNT>, CfuError>>;
     /// Gets the firmware version of all components
     fn get_all_fw_versions(
-        self,
-        writer: &mut W,
+        &self,
         primary_cmpt: ComponentId,
     ) -> impl Future<Output = Result<GetFwVersionResponse, CfuError>>;
-    /// Goes through the offer list and returns a slice of offer responses
-    fn process_cfu_offers<'a>(
-        offer_commands: &'a [FwUpdateOffer],
+    /// Offers every image to its component and updates the components that accept
+    fn process_cfu_offers(&self, writer: &mut W) -> impl Future<Output = Result<UpdateSummary, CfuError>>;
+    /// Stream the content of an accepted image to its component, returns the last content response
+    fn update_cfu_content(
+        &self,
         writer: &mut W,
-    ) -> impl Future<Output = Result<&'a [FwUpdateOfferResponse], CfuError>>;
-    /// For a specific component, update its content
-    fn update_cfu_content(writer: &mut W) -> impl Future<Output = Result<FwUpdateContentResponse, CfuError>>;
-    /// For a specific image that was updated, validate its content
-    fn is_cfu_image_valid<I: CfuImage>(image: I) -> impl Future<Output = Result<bool, CfuError>>;
+        image: &ImageLocation,
+        component_id: ComponentId,
+    ) -> impl Future<Output = Result<FwUpdateContentResponse, CfuError>>;
 }
 
-pub struct CfuHostInstance<I: CfuImage, C: CfuComponentTraits> {
+/// Host updating components from images in storage
+pub struct CfuHostInstance {
     pub updater: CfuUpdater,
-    pub images: heapless::Vec<I, MAX_CMPT_COUNT>,
-    pub writer: CfuWriterNop,
-    pub primary_cmpt: C,
+    pub images: Vec<ImageLocation, MAX_CMPT_COUNT>,
+    pub primary_cmpt: ComponentId,
     pub host_token: HostToken,
+    pub config: Config,
 }
 
-impl<I: CfuImage, C: CfuComponentTraits> CfuHostInstance<I, C> {
-    #[allow(unused)]
-    fn new(primary_cmpt: C) -> Self {
+impl CfuHostInstance {
+    /// Create a new host updating the given images
+    pub fn new(primary_cmpt: ComponentId, images: Vec<ImageLocation, MAX_CMPT_COUNT>, config: Config) -> Self {
         Self {
             updater: CfuUpdater {},
-            images: Vec::new(),
-            writer: CfuWriterNop,
+            images,
             primary_cmpt,
             host_token: HostToken::Driver,
+            config,
+        }
+    }
+
+    /// Read from storage
+    async fn read(writer: &mut impl CfuWriterAsync, offset: usize, buf: &mut [u8]) -> Result<(), CfuError> {
+        writer
+            .cfu_read(Some(offset), buf)
+            .await
+            .map_err(|e| CfuError::ProtocolError(CfuProtocolError::WriterError(e)))
+    }
+
+    /// Read and parse an offer from storage
+    async fn read_offer(&self, writer: &mut impl CfuWriterAsync, offset: usize) -> Result<FwUpdateOffer, CfuError> {
+        let mut offer = [0u8; OFFER_LEN];
+        Self::read(writer, offset, &mut offer).await?;
+
+        // Segment number and flags in the first two bytes and the token in the fourth aren't used, the offer is sent
+        // with our own token
+        let [_, _, component_id, _, v0, v1, v2, v3, s0, s1, s2, s3, m0, m1, m2, m3] = offer;
+        Ok(FwUpdateOffer::new(
+            self.host_token,
+            component_id,
+            FwVersion::new(u32::from_le_bytes([v0, v1, v2, v3])),
+            u32::from_le_bytes([s0, s1, s2, s3]),
+            u32::from_le_bytes([m0, m1, m2, m3]),
+        ))
+    }
+
+    /// Route a request to a component, retrying while it reports being busy
+    async fn request(&self, component_id: ComponentId, request: RequestData) -> Result<InternalResponseData, CfuError> {
+        for _ in 0..=self.config.busy_retries {
+            match route_request(component_id, request).await? {
+                InternalResponseData::ComponentBusy => {}
+                InternalResponseData::OfferResponse(response) if response.status == OfferStatus::Busy => {}
+                response => return Ok(response),
+            }
+
+            trace!("Component {} busy, retrying", component_id);
+            Timer::after(self.config.busy_delay).await;
+        }
+
+        Err(CfuError::ComponentBusy)
+    }
+
+    /// Offer a single image and update the component if it accepts
+    async fn process_image(
+        &self,
+        writer: &mut impl CfuWriterAsync,
+        image: &ImageLocation,
+        offer: FwUpdateOffer,
+    ) -> Result<OfferOutcome, CfuError> {
+        let component_id = offer.component_info.component_id;
+        let response = match self.request(component_id, RequestData::GiveOffer(offer)).await? {
+            InternalResponseData::OfferResponse(response) => response,
+            response => {
+                error!("Invalid response to offer {:?} from comp {}", response, component_id);
+                return Err(CfuError::ProtocolError(CfuProtocolError::BadResponse));
+            }
+        };
+
+        match response.status {
+            OfferStatus::Accept => {}
+            // The component already runs this firmware or a newer one
+            _ if response.reject_reason == OfferRejectReason::OldFw => return Ok(OfferOutcome::Skipped),
+            _ => return Ok(OfferOutcome::Rejected(response.reject_reason)),
+        }
+
+        match self.update_cfu_content(writer, image, component_id).await {
+            Ok(response) if response.status == CfuUpdateContentResponseStatus::Success => {
+                match self.request(component_id, RequestData::FinalizeUpdate).await? {
+                    InternalResponseData::ComponentPrepared => Ok(OfferOutcome::Updated),
+                    // The component refused to switch to the new image, e.g. because it failed verification
+                    InternalResponseData::ContentResponse(response)
+                        if response.status != CfuUpdateContentResponseStatus::Success =>
+                    {
+                        Ok(OfferOutcome::ContentRejected(response.status))
+                    }
+                    response => {
+                        error!("Invalid response to finalize {:?} from comp {}", response, component_id);
+                        Err(CfuError::ProtocolError(CfuProtocolError::BadResponse))
+                    }
+                }
+            }
+            Ok(response) => {
+                self.request(component_id, RequestData::AbortUpdate).await?;
+                Ok(OfferOutcome::ContentRejected(response.status))
+            }
+            Err(e) => {
+                self.request(component_id, RequestData::AbortUpdate).await?;
+                Err(e)
+            }
         }
     }
 }
 
-impl<I: CfuImage, C: CfuComponentTraits, W: CfuWriterAsync> CfuHostStates<W> for CfuHostInstance<I, C> {
+impl<W: CfuWriterAsync> CfuHostStates<W> for CfuHostInstance {
     async fn start_transaction(self, _writer: &mut W) -> Result<FwUpdateOfferResponse, CfuProtocolError> {
         let _mock_cmd = FwUpdateOfferInformation::new(OfferInformationComponentInfo::new(
             HostToken::Driver,
@@ -123,65 +310,308 @@ impl<I: CfuImage, C: CfuComponentTraits, W: CfuWriterAsync> CfuHostStates<W> for
     }
 }
 
-impl<I: CfuImage, C: CfuComponentTraits, W: CfuWriterAsync> CfuHost<W> for CfuHostInstance<I, C> {
-    async fn get_cfu_images<T: CfuImage>(&self) -> Result<Vec<T, MAX_CMPT_COUNT>, CfuError> {
-        Err(CfuError::BadImage)
+impl<W: CfuWriterAsync> CfuHost<W> for CfuHostInstance {
+    async fn get_cfu_images(&self, writer: &mut W) -> Result<Vec<FwUpdateOffer, MAX_CMPT_COUNT>, CfuError> {
+        let mut offers = Vec::new();
+        for image in &self.images {
+            if image.payload_len == 0 {
+                error!("Image at {} has no content", image.offer);
+                return Err(CfuError::BadImage);
+            }
+
+            let offer = self.read_offer(writer, image.offer).await?;
+            // Images are limited to MAX_CMPT_COUNT so this can't fail
+            let _ = offers.push(offer);
+        }
+        Ok(offers)
     }
 
-    async fn get_all_fw_versions(
-        self,
-        _writer: &mut W,
-        primary_cmpt: ComponentId,
-    ) -> Result<GetFwVersionResponse, CfuError> {
-        let mut component_count: u8 = 0;
-        self.primary_cmpt.get_subcomponents().iter().for_each(|x| {
-            if x.is_some() {
-                component_count += 1
+    async fn get_all_fw_versions(&self, primary_cmpt: ComponentId) -> Result<GetFwVersionResponse, CfuError> {
+        match self.request(primary_cmpt, RequestData::FwVersionRequest).await? {
+            InternalResponseData::FwVersionResponse(response) => Ok(response),
+            response => {
+                error!(
+                    "Invalid response to get fw version {:?} from comp {}",
+                    response, primary_cmpt
+                );
+                Err(CfuError::ProtocolError(CfuProtocolError::BadResponse))
             }
-        });
-        let result = self.primary_cmpt.get_fw_version().await;
-        if result.is_ok() {
-            // convert bytes back to a GetFwVersionResponse
-            let primary_component_version_info = FwVerComponentInfo::new(
-                FwVersion {
-                    major: 0,
-                    minor: 1,
-                    variant: 0,
-                },
-                primary_cmpt,
-            );
+        }
+    }
 
-            let mut component_info = [FwVerComponentInfo::default(); MAX_CMPT_COUNT]; // Create an array with 7 default elements
-            component_info[0] = primary_component_version_info; // Set the first element to component_info
+    async fn process_cfu_offers(&self, writer: &mut W) -> Result<UpdateSummary, CfuError> {
+        let offers = self.get_cfu_images(writer).await?;
 
-            let resp = GetFwVersionResponse {
-                header: GetFwVersionResponseHeader::new(
-                    1, // Component count
-                    GetFwVerRespHeaderByte3::NoSpecialFlags,
-                ),
-                component_info,
+        let mut summary = UpdateSummary::default();
+        for (image, offer) in self.images.iter().zip(offers) {
+            let component_id = offer.component_info.component_id;
+            let outcome = match self.process_image(writer, image, offer).await {
+                Ok(outcome) => outcome,
+                Err(e) => OfferOutcome::Failed(e),
             };
-            Ok(resp)
-        } else {
-            Err(CfuError::ProtocolError(CfuProtocolError::BadResponse))
+            info!("Offer for comp {}: {:?}", component_id, outcome);
+            // Images are limited to MAX_CMPT_COUNT so this can't fail
+            let _ = summary.results.push((component_id, outcome));
         }
+        Ok(summary)
     }
 
-    async fn process_cfu_offers<'a>(
-        _offer_commands: &'a [FwUpdateOffer],
-        _writer: &mut W,
-    ) -> Result<&'a [FwUpdateOfferResponse], CfuError> {
-        // TODO
-        Err(CfuError::BadImage)
+    async fn update_cfu_content(
+        &self,
+        writer: &mut W,
+        image: &ImageLocation,
+        component_id: ComponentId,
+    ) -> Result<FwUpdateContentResponse, CfuError> {
+        let end = image.payload + image.payload_len;
+        let mut offset = image.payload;
+        let mut sequence_num: u16 = 0;
+        let mut response = FwUpdateContentResponse::default();
+
+        while offset < end {
+            let mut record = [0u8; RECORD_HEADER_LEN];
+            Self::read(writer, offset, &mut record).await?;
+            let [a0, a1, a2, a3, len] = record;
+            let mut firmware_address = u32::from_le_bytes([a0, a1, a2, a3]);
+            let mut remaining = usize::from(len);
+            offset += RECORD_HEADER_LEN;
+
+            if remaining == 0 || offset + remaining > end {
+                error!("Invalid payload record at {}", offset - RECORD_HEADER_LEN);
+                return Err(CfuError::BadImage);
+            }
+
+            // Records can be longer than a content command, split them
+            while remaining > 0 {
+                let data_length = remaining.min(DEFAULT_DATA_LENGTH);
+                let mut data = [0u8; DEFAULT_DATA_LENGTH];
+                Self::read(writer, offset, data.get_mut(..data_length).ok_or(CfuError::BadImage)?).await?;
+                offset += data_length;
+                remaining -= data_length;
+
+                let mut flags = 0;
+                if sequence_num == 0 {
+                    flags |= FW_UPDATE_FLAG_FIRST_BLOCK;
+                }
+                if offset == end {
+                    flags |= FW_UPDATE_FLAG_LAST_BLOCK;
+                }
+
+                let command = FwUpdateContentCommand {
+                    header: FwUpdateContentHeader {
+                        data_length: data_length as u8,
+                        sequence_num,
+                        firmware_address,
+                        flags,
+                    },
+                    data,
+                };
+                response = match self.request(component_id, RequestData::GiveContent(command)).await? {
+                    InternalResponseData::ContentResponse(response) if response.sequence == sequence_num => response,
+                    response => {
+                        error!("Invalid response to content {:?} from comp {}", response, component_id);
+                        return Err(CfuError::ProtocolError(CfuProtocolError::BadResponse));
+                    }
+                };
+
+                if response.status != CfuUpdateContentResponseStatus::Success {
+                    error!("Content {} rejected by comp {}", sequence_num, component_id);
+                    return Ok(response);
+                }
+
+                firmware_address += data_length as u32;
+                sequence_num = sequence_num.wrapping_add(1);
+            }
+        }
+
+        Ok(response)
     }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::indexing_slicing)]
+mod tests {
+    use core::cell::RefCell;
 
-    async fn update_cfu_content(_writer: &mut W) -> Result<FwUpdateContentResponse, CfuError> {
-        Err(CfuError::ProtocolError(CfuProtocolError::WriterError(
-            CfuWriterError::Other,
-        )))
+    use embassy_futures::select::select;
+    use embassy_sync::blocking_mutex::Mutex;
+    use embedded_services::GlobalRawMutex;
+    use embedded_services::cfu::component::CfuComponentDefault;
+    use static_cell::StaticCell;
+
+    use super::*;
+
+    const COMPONENT_ID: ComponentId = 1;
+    /// Component that isn't registered
+    const MISSING_COMPONENT_ID: ComponentId = 2;
+    const FLASH_LEN: usize = 512;
+    const STORAGE_LEN: usize = 512;
+
+    /// Content written to the component
+    static FLASH: Mutex<GlobalRawMutex, RefCell<[u8; FLASH_LEN]>> = Mutex::new(RefCell::new([0; FLASH_LEN]));
+
+    #[derive(Default)]
+    struct Flash;
+
+    impl CfuWriterAsync for Flash {
+        async fn cfu_write(&mut self, mem_offset: Option<usize>, data: &[u8]) -> Result<(), CfuWriterError> {
+            let offset = mem_offset.unwrap_or(0);
+            FLASH.lock(|flash| {
+                flash
+                    .borrow_mut()
+                    .get_mut(offset..offset + data.len())
+                    .ok_or(CfuWriterError::StorageError)?
+                    .copy_from_slice(data);
+                Ok(())
+            })
+        }
+
+        async fn cfu_write_read(
+            &mut self,
+            _mem_offset: Option<usize>,
+            _data: &[u8],
+            _read: &mut [u8],
+        ) -> Result<(), CfuWriterError> {
+            Err(CfuWriterError::Other)
+        }
+
+        async fn cfu_read(&mut self, _mem_offset: Option<usize>, _read: &mut [u8]) -> Result<(), CfuWriterError> {
+            Err(CfuWriterError::Other)
+        }
+
+        async fn cfu_storage(&mut self, _mem_offset: usize, _read: &[u8]) -> Result<(), CfuWriterError> {
+            Err(CfuWriterError::Other)
+        }
     }
 
-    async fn is_cfu_image_valid<T: CfuImage>(_image: T) -> Result<bool, CfuError> {
-        Ok(true)
+    /// Storage holding the images
+    #[derive(Default)]
+    struct Storage(Vec<u8, STORAGE_LEN>);
+
+    impl Storage {
+        fn push_offer(&mut self, component_id: ComponentId, version: u32) -> usize {
+            let offset = self.0.len();
+            let mut offer = [0u8; OFFER_LEN];
+            offer[2] = component_id;
+            offer[4..8].copy_from_slice(&version.to_le_bytes());
+            self.0.extend_from_slice(&offer).unwrap();
+            offset
+        }
+
+        fn push_record(&mut self, address: u32, data: &[u8]) {
+            self.0.extend_from_slice(&address.to_le_bytes()).unwrap();
+            self.0.push(data.len() as u8).unwrap();
+            self.0.extend_from_slice(data).unwrap();
+        }
+
+        fn push_image(&mut self, component_id: ComponentId, records: &[(u32, &[u8])]) -> ImageLocation {
+            let offer = self.push_offer(component_id, 0x100);
+            let payload = self.0.len();
+            for (address, data) in records {
+                self.push_record(*address, data);
+            }
+            ImageLocation {
+                offer,
+                payload,
+                payload_len: self.0.len() - payload,
+            }
+        }
+    }
+
+    impl CfuWriterAsync for Storage {
+        async fn cfu_write(&mut self, _mem_offset: Option<usize>, _data: &[u8]) -> Result<(), CfuWriterError> {
+            Err(CfuWriterError::Other)
+        }
+
+        async fn cfu_write_read(
+            &mut self,
+            _mem_offset: Option<usize>,
+            _data: &[u8],
+            _read: &mut [u8],
+        ) -> Result<(), CfuWriterError> {
+            Err(CfuWriterError::Other)
+        }
+
+        async fn cfu_read(&mut self, mem_offset: Option<usize>, read: &mut [u8]) -> Result<(), CfuWriterError> {
+            let offset = mem_offset.unwrap_or(0);
+            read.copy_from_slice(
+                self.0
+                    .get(offset..offset + read.len())
+                    .ok_or(CfuWriterError::StorageError)?,
+            );
+            Ok(())
+        }
+
+        async fn cfu_storage(&mut self, _mem_offset: usize, _read: &[u8]) -> Result<(), CfuWriterError> {
+            Err(CfuWriterError::Other)
+        }
+    }
+
+    /// Test updating a component end-to-end
+    #[tokio::test]
+    async fn test_update() {
+        static COMPONENT: StaticCell<CfuComponentDefault<Flash>> = StaticCell::new();
+        embedded_services::cfu::init();
+        let component = COMPONENT.init(CfuComponentDefault::new(
+            COMPONENT_ID,
+            true,
+            [None; MAX_SUBCMPT_COUNT],
+            Flash,
+        ));
+        embedded_services::cfu::register_device(component).await.unwrap();
+
+        // Longer than a content command so it gets split
+        let content: [u8; 2 * DEFAULT_DATA_LENGTH + 1] = core::array::from_fn(|i| i as u8);
+        let (first, second) = content.split_at(DEFAULT_DATA_LENGTH + 1);
+        let mut storage = Storage::default();
+        let mut images = Vec::new();
+        images
+            .push(storage.push_image(COMPONENT_ID, &[(0, first), (first.len() as u32, second)]))
+            .unwrap();
+        images
+            .push(storage.push_image(MISSING_COMPONENT_ID, &[(0, &[0xff])]))
+            .unwrap();
+        let host = CfuHostInstance::new(COMPONENT_ID, images, Config::default());
+
+        let component_task = async {
+            loop {
+                component.process_request().await.unwrap();
+            }
+        };
+        let host_task = async {
+            let versions = CfuHost::<Storage>::get_all_fw_versions(&host, COMPONENT_ID)
+                .await
+                .unwrap();
+            assert_eq!(versions.component_info[0].fw_version, FwVersion::default());
+
+            let summary = host.process_cfu_offers(&mut storage).await.unwrap();
+            assert_eq!(
+                summary.results.as_slice(),
+                &[
+                    (COMPONENT_ID, OfferOutcome::Updated),
+                    (MISSING_COMPONENT_ID, OfferOutcome::Failed(CfuError::InvalidComponent)),
+                ]
+            );
+            assert_eq!(summary.updated(), 1);
+            assert_eq!(summary.failed(), 1);
+            assert!(!summary.is_success());
+        };
+
+        // The component never stops processing requests
+        select(component_task, host_task).await;
+        FLASH.lock(|flash| assert_eq!(flash.borrow()[..content.len()], content));
+    }
+
+    /// Test that a record running past the end of the payload is rejected
+    #[tokio::test]
+    async fn test_bad_payload() {
+        let mut storage = Storage::default();
+        let mut image = storage.push_image(COMPONENT_ID, &[(0, &[0; 8])]);
+        image.payload_len -= 1;
+        let host = CfuHostInstance::new(COMPONENT_ID, Vec::new(), Config::default());
+
+        assert_eq!(
+            host.update_cfu_content(&mut storage, &image, COMPONENT_ID).await,
+            Err(CfuError::BadImage)
+        );
     }
 }
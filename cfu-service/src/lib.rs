@@ -1,23 +1,49 @@
 #![no_std]
 
+use embassy_sync::mutex::Mutex;
 use embedded_cfu_protocol::client::CfuReceiveContent;
 use embedded_cfu_protocol::components::CfuComponentTraits;
 use embedded_cfu_protocol::protocol_definitions::*;
 use embedded_services::cfu::component::*;
 use embedded_services::cfu::{CfuError, ContextToken};
-use embedded_services::{comms, error, info, trace};
+use embedded_services::{GlobalRawMutex, comms, error, info, trace};
+
+use crate::host::OFFER_LEN;
 
 pub mod buffer;
 pub mod host;
 mod responses;
 pub mod splitter;
 pub mod task;
+pub mod verify;
+
+/// Extended offer command asking to be notified once components are ready for offers
+const OFFER_NOTIFY_ON_READY: u8 = 0x01;
+
+/// Update in progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Update {
+    /// Component accepted an offer and is receiving content
+    Receiving(ComponentId),
+    /// Component received all of its content and is waiting to be finalized
+    Delivered(ComponentId),
+}
+
+impl Update {
+    fn component(self) -> ComponentId {
+        match self {
+            Self::Receiving(comp) | Self::Delivered(comp) => comp,
+        }
+    }
+}
 
 pub struct CfuClient {
     /// Cfu Client context
     context: ContextToken,
     /// Comms endpoint
     tp: comms::Endpoint,
+    /// Component that accepted the current offer
+    updating: Mutex<GlobalRawMutex, Option<Update>>,
 }
 
 impl<T, C> CfuReceiveContent<T, C, ()> for CfuClient {
@@ -42,6 +68,7 @@ impl CfuClient {
         Some(Self {
             context: ContextToken::create()?,
             tp: comms::Endpoint::uninit(comms::EndpointID::Internal(comms::Internal::Nonvol)),
+            updating: Mutex::new(None),
         })
     }
     pub async fn process_request(&self) -> Result<(), CfuError> {
@@ -75,39 +102,373 @@ impl CfuClient {
                 }
                 Err(CfuError::InvalidComponent)
             }
-            RequestData::GiveContent(_content_cmd) => Ok(()),
-            RequestData::GiveOffer(_offer_cmd) => Ok(()),
-            RequestData::PrepareComponentForUpdate => Ok(()),
-            RequestData::AbortUpdate => Ok(()),
-            RequestData::FinalizeUpdate => Ok(()),
-            RequestData::GiveOfferExtended(_) => {
-                // Don't currently support extended offers
-                self.context
-                    .send_response(InternalResponseData::OfferResponse(
-                        FwUpdateOfferResponse::new_with_failure(
-                            HostToken::Driver,
-                            OfferRejectReason::InvalidComponent,
-                            OfferStatus::Reject,
-                        ),
-                    ))
-                    .await;
+            RequestData::GiveOffer(offer) => {
+                let response = self.process_give_offer(offer).await;
+                self.context.send_response(response).await;
+                Ok(())
+            }
+            RequestData::GiveContent(content) => {
+                let response = self.process_give_content(content).await;
+                self.context.send_response(response).await;
+                Ok(())
+            }
+            RequestData::GiveOfferExtended(offer) => {
+                let response = self.process_give_offer_extended(offer).await;
+                self.context.send_response(response).await;
+                Ok(())
+            }
+            RequestData::GiveOfferInformation(offer) => {
+                let response = self.process_give_offer_information(offer).await;
+                self.context.send_response(response).await;
                 Ok(())
             }
-            RequestData::GiveOfferInformation(_) => {
-                // Don't currently support information offers
-                self.context
-                    .send_response(InternalResponseData::OfferResponse(
-                        FwUpdateOfferResponse::new_with_failure(
-                            HostToken::Driver,
-                            OfferRejectReason::InvalidComponent,
-                            OfferStatus::Reject,
-                        ),
-                    ))
-                    .await;
+            RequestData::PrepareComponentForUpdate | RequestData::AbortUpdate | RequestData::FinalizeUpdate => {
+                let device = self.context.get_device(comp).await?;
+                if matches!(request.data, RequestData::AbortUpdate | RequestData::FinalizeUpdate) {
+                    // The update is over whether the component succeeds or not
+                    let mut updating = self.updating.lock().await;
+                    if updating.is_some_and(|update| update.component() == comp) {
+                        *updating = None;
+                    }
+                }
+
+                let resp = device
+                    .execute_device_request(request.data)
+                    .await
+                    .map_err(CfuError::ProtocolError)?;
+                self.context.send_response(resp).await;
                 Ok(())
             }
         }
     }
+
+    /// Route an offer to the component it targets
+    async fn process_give_offer(&self, offer: FwUpdateOffer) -> InternalResponseData {
+        let token = offer.component_info.token;
+        let comp = offer.component_info.component_id;
+        let Ok(device) = self.context.get_device(comp).await else {
+            error!("Offer for unknown comp {}", comp);
+            return responses::create_offer_rejection(token, OfferRejectReason::InvalidComponent);
+        };
+
+        let mut updating = self.updating.lock().await;
+        if updating.is_some_and(|update| update.component() != comp) {
+            info!("Update in progress, comp {} busy", comp);
+            return InternalResponseData::ComponentBusy;
+        }
+
+        let resp = match device.execute_device_request(RequestData::GiveOffer(offer)).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                error!("Failed to give offer to comp {}: {:?}", comp, e);
+                return responses::create_offer_rejection(token, OfferRejectReason::InvalidComponent);
+            }
+        };
+
+        if let InternalResponseData::OfferResponse(r) = resp
+            && r.status == OfferStatus::Accept
+        {
+            *updating = Some(Update::Receiving(comp));
+        }
+        resp
+    }
+
+    /// Route content to the component that accepted the current offer
+    async fn process_give_content(&self, content: FwUpdateContentCommand) -> InternalResponseData {
+        let sequence = content.header.sequence_num;
+        let mut updating = self.updating.lock().await;
+        let Some(Update::Receiving(comp)) = *updating else {
+            error!("Content {} without an accepted offer", sequence);
+            return responses::create_content_rejection(sequence);
+        };
+
+        let resp = match self.context.get_device(comp).await {
+            Ok(device) => device
+                .execute_device_request(RequestData::GiveContent(content))
+                .await
+                .unwrap_or_else(|_| responses::create_content_rejection(sequence)),
+            Err(_) => responses::create_content_rejection(sequence),
+        };
+
+        // The component is done receiving content after the last block or a failure
+        let last_block = content.header.flags & FW_UPDATE_FLAG_LAST_BLOCK != 0;
+        match resp {
+            InternalResponseData::ContentResponse(r) if r.status == CfuUpdateContentResponseStatus::Success => {
+                if last_block {
+                    *updating = Some(Update::Delivered(comp));
+                }
+            }
+            _ => *updating = None,
+        }
+        resp
+    }
+
+    /// Process an extended offer, components are ready for offers unless one is being updated
+    async fn process_give_offer_extended(&self, offer: FwUpdateOfferExtended) -> InternalResponseData {
+        let token = offer.component_info.token;
+        let bytes: [u8; OFFER_LEN] = (&offer).into();
+        if !is_notify_on_ready(bytes) {
+            info!("Unknown extended offer command {:?}, rejected", bytes);
+            return responses::create_offer_rejection(token, OfferRejectReason::InvalidComponent);
+        }
+
+        if let Some(update) = *self.updating.lock().await {
+            info!("Update in progress on comp {}, not ready", update.component());
+            return InternalResponseData::ComponentBusy;
+        }
+
+        InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_accept(token))
+    }
+
+    /// Process an information offer marking the start of a transaction or the start and end of the offer list
+    async fn process_give_offer_information(&self, offer: FwUpdateOfferInformation) -> InternalResponseData {
+        let token = offer.component_info.token;
+        let bytes: [u8; OFFER_LEN] = (&offer).into();
+        match information_code(bytes) {
+            // Host is starting over, anything in progress won't be completed
+            Some(OfferInformationCodeValues::StartEntireTransaction) => self.abort_update(true).await,
+            Some(OfferInformationCodeValues::StartOfferList) => {}
+            // Content for every accepted offer has been sent by now, complete content is still waiting to be finalized
+            Some(OfferInformationCodeValues::EndOfferList) => self.abort_update(false).await,
+            None => info!("Unknown information offer {:?}, ignored", bytes),
+        }
+
+        InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_accept(token))
+    }
+
+    /// Abort the update of the component that accepted the current offer, if any
+    ///
+    /// A component that received all of its content is only aborted if `delivered` is set.
+    async fn abort_update(&self, delivered: bool) {
+        let comp = {
+            let mut updating = self.updating.lock().await;
+            let comp = match *updating {
+                Some(Update::Receiving(comp)) => comp,
+                Some(Update::Delivered(comp)) if delivered => comp,
+                _ => return,
+            };
+            *updating = None;
+            comp
+        };
+
+        info!("Aborting update of comp {}", comp);
+        if let Ok(device) = self.context.get_device(comp).await
+            && let Err(e) = device.execute_device_request(RequestData::AbortUpdate).await
+        {
+            error!("Failed to abort update of comp {}: {:?}", comp, e);
+        }
+    }
 }
 
 impl comms::MailboxDelegate for CfuClient {}
+
+/// Returns true if a serialized extended offer carries the notify on ready command, in its first byte
+fn is_notify_on_ready(offer: [u8; OFFER_LEN]) -> bool {
+    let [command_code, ..] = offer;
+    command_code == OFFER_NOTIFY_ON_READY
+}
+
+/// Encoding of an information code, taken from a serialized reference offer
+fn information_code_byte(code: OfferInformationCodeValues) -> u8 {
+    let offer = FwUpdateOfferInformation::new(OfferInformationComponentInfo::new(
+        HostToken::Driver,
+        SpecialComponentIds::Info,
+        code,
+    ));
+    let [code, ..]: [u8; OFFER_LEN] = (&offer).into();
+    code
+}
+
+/// Information code of a serialized information offer, in its first byte whatever the host token
+fn information_code(offer: [u8; OFFER_LEN]) -> Option<OfferInformationCodeValues> {
+    let [code, ..] = offer;
+    if code == information_code_byte(OfferInformationCodeValues::StartEntireTransaction) {
+        Some(OfferInformationCodeValues::StartEntireTransaction)
+    } else if code == information_code_byte(OfferInformationCodeValues::StartOfferList) {
+        Some(OfferInformationCodeValues::StartOfferList)
+    } else if code == information_code_byte(OfferInformationCodeValues::EndOfferList) {
+        Some(OfferInformationCodeValues::EndOfferList)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::indexing_slicing)]
+mod tests {
+    use embassy_futures::select::select3;
+    use embedded_cfu_protocol::writer::CfuWriterNop;
+    use embedded_services::cfu;
+    use static_cell::StaticCell;
+
+    use super::*;
+
+    const COMPONENT_ID: ComponentId = 3;
+    /// Component that isn't registered
+    const MISSING_COMPONENT_ID: ComponentId = 4;
+
+    fn information_offer(token: HostToken, code: OfferInformationCodeValues) -> FwUpdateOfferInformation {
+        FwUpdateOfferInformation::new(OfferInformationComponentInfo::new(
+            token,
+            SpecialComponentIds::Info,
+            code,
+        ))
+    }
+
+    fn information(code: OfferInformationCodeValues) -> RequestData {
+        RequestData::GiveOfferInformation(information_offer(HostToken::Driver, code))
+    }
+
+    fn offer(component_id: ComponentId) -> RequestData {
+        RequestData::GiveOffer(FwUpdateOffer::new(
+            HostToken::Driver,
+            component_id,
+            FwVersion::new(0x211),
+            0,
+            0,
+        ))
+    }
+
+    fn content(flags: u8) -> RequestData {
+        RequestData::GiveContent(FwUpdateContentCommand {
+            header: FwUpdateContentHeader {
+                data_length: DEFAULT_DATA_LENGTH as u8,
+                sequence_num: 0,
+                firmware_address: 0,
+                flags,
+            },
+            data: [0; DEFAULT_DATA_LENGTH],
+        })
+    }
+
+    fn is_accepted(response: InternalResponseData) -> bool {
+        matches!(response, InternalResponseData::OfferResponse(r) if r.status == OfferStatus::Accept)
+    }
+
+    fn content_status(response: InternalResponseData) -> Option<CfuUpdateContentResponseStatus> {
+        match response {
+            InternalResponseData::ContentResponse(r) => Some(r.status),
+            _ => None,
+        }
+    }
+
+    /// Test routing a full transaction through the client
+    #[tokio::test]
+    async fn test_transaction() {
+        static COMPONENT: StaticCell<CfuComponentDefault<CfuWriterNop>> = StaticCell::new();
+        cfu::init();
+        let component = COMPONENT.init(CfuComponentDefault::new(
+            COMPONENT_ID,
+            true,
+            [None; MAX_SUBCMPT_COUNT],
+            CfuWriterNop,
+        ));
+        cfu::register_device(component).await.unwrap();
+        let client = CfuClient::create().unwrap();
+
+        let client_task = async {
+            loop {
+                client.process_request().await.unwrap();
+            }
+        };
+        let component_task = async {
+            loop {
+                component.process_request().await.unwrap();
+            }
+        };
+        let host_task = async {
+            let response = cfu::send_request(
+                COMPONENT_ID,
+                information(OfferInformationCodeValues::StartEntireTransaction),
+            )
+            .await
+            .unwrap();
+            assert!(is_accepted(response));
+            // Offers from the tool are answered with its token
+            let response = cfu::send_request(
+                COMPONENT_ID,
+                RequestData::GiveOfferInformation(information_offer(
+                    HostToken::Tool,
+                    OfferInformationCodeValues::StartOfferList,
+                )),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                response,
+                InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_accept(HostToken::Tool))
+            );
+
+            // Offers for unknown components are rejected and content needs an accepted offer
+            let response = cfu::send_request(MISSING_COMPONENT_ID, offer(MISSING_COMPONENT_ID))
+                .await
+                .unwrap();
+            assert!(matches!(
+                response,
+                InternalResponseData::OfferResponse(r)
+                    if r.status == OfferStatus::Reject && r.reject_reason == OfferRejectReason::InvalidComponent
+            ));
+            let response = cfu::send_request(COMPONENT_ID, content(FW_UPDATE_FLAG_FIRST_BLOCK))
+                .await
+                .unwrap();
+            assert_eq!(
+                content_status(response),
+                Some(CfuUpdateContentResponseStatus::ErrorInvalid)
+            );
+
+            let response = cfu::send_request(COMPONENT_ID, offer(COMPONENT_ID)).await.unwrap();
+            assert!(is_accepted(response));
+            let response = cfu::send_request(
+                COMPONENT_ID,
+                content(FW_UPDATE_FLAG_FIRST_BLOCK | FW_UPDATE_FLAG_LAST_BLOCK),
+            )
+            .await
+            .unwrap();
+            assert_eq!(content_status(response), Some(CfuUpdateContentResponseStatus::Success));
+
+            // The component is no longer receiving content after the last block
+            let response = cfu::send_request(COMPONENT_ID, content(FW_UPDATE_FLAG_FIRST_BLOCK))
+                .await
+                .unwrap();
+            assert_eq!(
+                content_status(response),
+                Some(CfuUpdateContentResponseStatus::ErrorInvalid)
+            );
+
+            // The end of the offer list doesn't abort an update waiting to be finalized
+            let response = cfu::send_request(COMPONENT_ID, information(OfferInformationCodeValues::EndOfferList))
+                .await
+                .unwrap();
+            assert!(is_accepted(response));
+            assert_eq!(*client.updating.lock().await, Some(Update::Delivered(COMPONENT_ID)));
+
+            let response = cfu::send_request(COMPONENT_ID, RequestData::FinalizeUpdate)
+                .await
+                .unwrap();
+            assert_eq!(response, InternalResponseData::ComponentPrepared);
+            assert_eq!(*client.updating.lock().await, None);
+        };
+
+        // The client and component never stop processing requests
+        select3(client_task, component_task, host_task).await;
+    }
+
+    /// Test decoding offers from their serialized form whatever the host token
+    #[test]
+    fn test_decode_offers() {
+        for token in [HostToken::Driver, HostToken::Tool] {
+            let offer: [u8; OFFER_LEN] = (&information_offer(token, OfferInformationCodeValues::EndOfferList)).into();
+            assert!(matches!(
+                information_code(offer),
+                Some(OfferInformationCodeValues::EndOfferList)
+            ));
+        }
+
+        let mut offer = [0u8; OFFER_LEN];
+        offer[0] = 0xFF;
+        assert!(information_code(offer).is_none());
+        assert!(!is_notify_on_ready(offer));
+        offer[0] = OFFER_NOTIFY_ON_READY;
+        assert!(is_notify_on_ready(offer));
+    }
+}
@@ -1,6 +1,7 @@
 use embedded_cfu_protocol::protocol_definitions::{
-    CfuUpdateContentResponseStatus, ComponentId, FwUpdateContentResponse, FwVerComponentInfo, FwVersion,
-    GetFwVerRespHeaderByte3, GetFwVersionResponse, GetFwVersionResponseHeader, MAX_CMPT_COUNT,
+    CfuUpdateContentResponseStatus, ComponentId, FwUpdateContentResponse, FwUpdateOfferResponse, FwVerComponentInfo,
+    FwVersion, GetFwVerRespHeaderByte3, GetFwVersionResponse, GetFwVersionResponseHeader, HostToken, MAX_CMPT_COUNT,
+    OfferRejectReason, OfferStatus,
 };
 
 use embedded_services::cfu::component::InternalResponseData;
@@ -27,3 +28,13 @@ pub(crate) fn create_content_rejection(sequence: u16) -> InternalResponseData {
         CfuUpdateContentResponseStatus::ErrorInvalid,
     ))
 }
+
+// Returns an offer rejection response for the given host token and reason.
+// This is used when an offer cannot be routed to the component it targets.
+pub(crate) fn create_offer_rejection(token: HostToken, reason: OfferRejectReason) -> InternalResponseData {
+    InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_with_failure(
+        token,
+        reason,
+        OfferStatus::Reject,
+    ))
+}
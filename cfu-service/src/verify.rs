@@ -0,0 +1,414 @@
+//! Image verification stage components opt into before finalizing an update
+//!
+//! Images carry a signature over the digest of their content in their last bytes. Content is hashed as it's streamed,
+//! through a platform CRC or SHA provider, and the signature checked against a trusted public key once the last block
+//! is received. Offers older than the anti-rollback version persisted in storage are rejected, the version is raised
+//! when a verified image is finalized.
+use core::fmt::Debug;
+use core::future::Future;
+
+use embedded_cfu_protocol::protocol_definitions::*;
+use embedded_services::cfu::verify::{ImageVerifier, StartError};
+use embedded_services::{error, info};
+use embedded_storage_async::nor_flash::NorFlash;
+use heapless::Vec;
+use platform_service::embedded_crc::{EmbeddedCrc, EmbeddedCrcError};
+
+/// Longest signature supported
+pub const MAX_SIGNATURE_LEN: usize = 128;
+
+/// Size of the anti-rollback version in storage
+const ROLLBACK_VERSION_LEN: usize = 4;
+
+/// Digest over streamed content
+pub trait ContentDigest {
+    /// Digest value
+    type Output: AsRef<[u8]>;
+    /// Error type
+    type Error: Debug;
+
+    /// Start a new digest
+    fn reset(&mut self);
+    /// Add content to the digest
+    fn update(&mut self, data: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Digest of all content added since the last reset
+    fn finalize(&mut self) -> Self::Output;
+}
+
+impl ContentDigest for EmbeddedCrc<u32> {
+    type Output = [u8; 4];
+    type Error = EmbeddedCrcError;
+
+    fn reset(&mut self) {
+        EmbeddedCrc::<u32>::reset(self);
+    }
+
+    async fn update(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.calculate(data).await.map(|_| ())
+    }
+
+    fn finalize(&mut self) -> Self::Output {
+        self.read_crc().to_le_bytes()
+    }
+}
+
+/// Signature check against a trusted public key
+pub trait SignatureVerifier {
+    /// Length of the signature at the end of images, at most [`MAX_SIGNATURE_LEN`]
+    fn signature_len(&self) -> usize;
+    /// Returns true if the signature over the digest was made by the trusted key
+    fn verify(&self, digest: &[u8], signature: &[u8]) -> impl Future<Output = bool>;
+}
+
+/// Anti-rollback version persisted in a storage partition
+///
+/// The storage must accept 4-byte reads and writes at its start.
+pub struct RollbackCounter<F> {
+    storage: F,
+}
+
+impl<F: NorFlash> RollbackCounter<F> {
+    /// Create a new counter stored at the start of `storage`
+    pub fn new(storage: F) -> Self {
+        Self { storage }
+    }
+
+    /// Oldest firmware version that can be installed, erased storage allows any version
+    pub async fn read(&mut self) -> Result<u32, F::Error> {
+        let mut version = [0u8; ROLLBACK_VERSION_LEN];
+        self.storage.read(0, &mut version).await?;
+        Ok(match u32::from_le_bytes(version) {
+            u32::MAX => 0,
+            version => version,
+        })
+    }
+
+    /// Prevent versions older than `version` from being installed
+    pub async fn write(&mut self, version: u32) -> Result<(), F::Error> {
+        self.storage.erase(0, F::ERASE_SIZE as u32).await?;
+        self.storage.write(0, &version.to_le_bytes()).await
+    }
+}
+
+/// State of the image being verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// No offer accepted
+    Idle,
+    /// Receiving the content of an image with the given version
+    Receiving(u32),
+    /// Image with the given version passed verification
+    Verified(u32),
+}
+
+/// Verification stage
+pub struct Verifier<D, S, F> {
+    digest: D,
+    signature: S,
+    rollback: RollbackCounter<F>,
+    /// Content not yet hashed because it could be part of the signature
+    trailer: Vec<u8, { MAX_SIGNATURE_LEN + DEFAULT_DATA_LENGTH }>,
+    state: State,
+}
+
+impl<D: ContentDigest, S: SignatureVerifier, F: NorFlash> Verifier<D, S, F> {
+    /// Create a new verification stage
+    pub fn new(digest: D, signature: S, rollback: RollbackCounter<F>) -> Self {
+        Self {
+            digest,
+            signature,
+            rollback,
+            trailer: Vec::new(),
+            state: State::Idle,
+        }
+    }
+
+    /// Hash everything but the bytes that could still be the signature
+    async fn hash_trailer(&mut self, keep: usize) -> Result<(), CfuUpdateContentResponseStatus> {
+        let Some(len) = self.trailer.len().checked_sub(keep) else {
+            return Ok(());
+        };
+
+        let data = self
+            .trailer
+            .get(..len)
+            .ok_or(CfuUpdateContentResponseStatus::ErrorInvalid)?;
+        if self.digest.update(data).await.is_err() {
+            error!("Failed to hash content");
+            return Err(CfuUpdateContentResponseStatus::ErrorInvalid);
+        }
+
+        self.trailer.copy_within(len.., 0);
+        self.trailer.truncate(keep);
+        Ok(())
+    }
+}
+
+/// Firmware version of an offer as a single word, major version in the most significant byte
+fn offer_version(offer: &FwUpdateOffer) -> u32 {
+    let version = offer.firmware_version;
+    (u32::from(version.major) << 24) | (u32::from(version.minor) << 8) | u32::from(version.variant)
+}
+
+impl<D: ContentDigest, S: SignatureVerifier, F: NorFlash> ImageVerifier for Verifier<D, S, F> {
+    async fn start(&mut self, offer: &FwUpdateOffer) -> Result<(), StartError> {
+        self.state = State::Idle;
+        let version = offer_version(offer);
+        let minimum = self.rollback.read().await.map_err(|_| {
+            error!("Failed to read anti-rollback version");
+            StartError::Storage
+        })?;
+        if version < minimum {
+            info!("Rejecting version {:#x}, older than {:#x}", version, minimum);
+            return Err(StartError::Rejected(OfferRejectReason::OldFw));
+        }
+
+        self.digest.reset();
+        self.trailer.clear();
+        self.state = State::Receiving(version);
+        Ok(())
+    }
+
+    async fn update(&mut self, content: &FwUpdateContentCommand) -> Result<(), CfuUpdateContentResponseStatus> {
+        if !matches!(self.state, State::Receiving(_)) {
+            return Err(CfuUpdateContentResponseStatus::ErrorInvalid);
+        }
+
+        let data = content
+            .data
+            .get(..usize::from(content.header.data_length))
+            .ok_or(CfuUpdateContentResponseStatus::ErrorInvalid)?;
+        // Making room for a full block beforehand means this can't fail
+        self.hash_trailer(MAX_SIGNATURE_LEN).await?;
+        self.trailer
+            .extend_from_slice(data)
+            .map_err(|_| CfuUpdateContentResponseStatus::ErrorInvalid)
+    }
+
+    async fn verify(&mut self) -> Result<(), CfuUpdateContentResponseStatus> {
+        let State::Receiving(version) = self.state else {
+            return Err(CfuUpdateContentResponseStatus::ErrorInvalid);
+        };
+        self.state = State::Idle;
+
+        let signature_len = self.signature.signature_len();
+        self.hash_trailer(signature_len).await?;
+        if self.trailer.len() != signature_len {
+            error!("Image too short to be signed");
+            return Err(CfuUpdateContentResponseStatus::ErrorInvalid);
+        }
+
+        let digest = self.digest.finalize();
+        if !self.signature.verify(digest.as_ref(), &self.trailer).await {
+            error!("Invalid image signature");
+            return Err(CfuUpdateContentResponseStatus::ErrorSignature);
+        }
+
+        self.state = State::Verified(version);
+        Ok(())
+    }
+
+    async fn finalize(&mut self) -> Result<(), CfuUpdateContentResponseStatus> {
+        let State::Verified(version) = self.state else {
+            error!("Finalizing an image that wasn't verified");
+            return Err(CfuUpdateContentResponseStatus::ErrorInvalid);
+        };
+        self.state = State::Idle;
+
+        let minimum = self
+            .rollback
+            .read()
+            .await
+            .map_err(|_| CfuUpdateContentResponseStatus::ErrorWrite)?;
+        if version > minimum {
+            self.rollback.write(version).await.map_err(|_| {
+                error!("Failed to write anti-rollback version");
+                CfuUpdateContentResponseStatus::ErrorWrite
+            })?;
+        }
+        Ok(())
+    }
+
+    fn abort(&mut self) {
+        self.state = State::Idle;
+        self.trailer.clear();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::indexing_slicing)]
+mod tests {
+    use embedded_storage_async::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
+
+    use super::*;
+
+    const FLASH_LEN: usize = 16;
+    const KEY: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+    const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+    struct Flash([u8; FLASH_LEN]);
+
+    impl ErrorType for Flash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for Flash {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(
+                self.0
+                    .get(offset..offset + bytes.len())
+                    .ok_or(NorFlashErrorKind::OutOfBounds)?,
+            );
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            FLASH_LEN
+        }
+    }
+
+    impl NorFlash for Flash {
+        const WRITE_SIZE: usize = 1;
+        const ERASE_SIZE: usize = FLASH_LEN;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.0
+                .get_mut(from as usize..to as usize)
+                .ok_or(NorFlashErrorKind::OutOfBounds)?
+                .fill(0xff);
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            self.0
+                .get_mut(offset..offset + bytes.len())
+                .ok_or(NorFlashErrorKind::OutOfBounds)?
+                .copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    /// Signs by xoring the digest with the key
+    struct XorKey;
+
+    impl SignatureVerifier for XorKey {
+        fn signature_len(&self) -> usize {
+            KEY.len()
+        }
+
+        async fn verify(&self, digest: &[u8], signature: &[u8]) -> bool {
+            digest.iter().zip(KEY).map(|(d, k)| d ^ k).eq(signature.iter().copied())
+        }
+    }
+
+    fn verifier() -> Verifier<EmbeddedCrc<u32>, XorKey, Flash> {
+        Verifier::new(
+            EmbeddedCrc::<u32>::new(&crc::CRC_32_ISO_HDLC),
+            XorKey,
+            RollbackCounter::new(Flash([0xff; FLASH_LEN])),
+        )
+    }
+
+    fn offer(version: u32) -> FwUpdateOffer {
+        FwUpdateOffer::new(HostToken::Driver, 1, FwVersion::new(version), 0, 0)
+    }
+
+    /// Content followed by its signature
+    fn signed_image(content: &[u8], key: [u8; 4]) -> heapless::Vec<u8, 512> {
+        let mut image = heapless::Vec::new();
+        image.extend_from_slice(content).unwrap();
+        for (d, k) in CRC.checksum(content).to_le_bytes().iter().zip(key) {
+            image.push(d ^ k).unwrap();
+        }
+        image
+    }
+
+    async fn stream(verifier: &mut Verifier<EmbeddedCrc<u32>, XorKey, Flash>, image: &[u8]) {
+        for (i, chunk) in image.chunks(DEFAULT_DATA_LENGTH).enumerate() {
+            let mut data = [0u8; DEFAULT_DATA_LENGTH];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let content = FwUpdateContentCommand {
+                header: FwUpdateContentHeader {
+                    data_length: chunk.len() as u8,
+                    sequence_num: i as u16,
+                    firmware_address: (i * DEFAULT_DATA_LENGTH) as u32,
+                    flags: 0,
+                },
+                data,
+            };
+            verifier.update(&content).await.unwrap();
+        }
+    }
+
+    /// Test that signed images are accepted and raise the anti-rollback version
+    #[tokio::test]
+    async fn test_signed() {
+        let mut verifier = verifier();
+        let content: [u8; 2 * DEFAULT_DATA_LENGTH + 3] = core::array::from_fn(|i| i as u8);
+        let image = signed_image(&content, KEY);
+
+        verifier.start(&offer(0x200)).await.unwrap();
+        stream(&mut verifier, &image).await;
+        verifier.verify().await.unwrap();
+        verifier.finalize().await.unwrap();
+        assert_eq!(verifier.rollback.read().await.unwrap(), 0x200);
+
+        // Downgrades are rejected, the same version can be installed again
+        assert_eq!(
+            verifier.start(&offer(0x100)).await,
+            Err(StartError::Rejected(OfferRejectReason::OldFw))
+        );
+        verifier.start(&offer(0x200)).await.unwrap();
+    }
+
+    /// Test that images that aren't signed by the trusted key can't be finalized
+    #[tokio::test]
+    async fn test_unsigned() {
+        let mut verifier = verifier();
+        let content: [u8; DEFAULT_DATA_LENGTH] = core::array::from_fn(|i| i as u8);
+
+        verifier.start(&offer(0x200)).await.unwrap();
+        stream(&mut verifier, &signed_image(&content, [0; 4])).await;
+        assert_eq!(
+            verifier.verify().await,
+            Err(CfuUpdateContentResponseStatus::ErrorSignature)
+        );
+        assert_eq!(
+            verifier.finalize().await,
+            Err(CfuUpdateContentResponseStatus::ErrorInvalid)
+        );
+
+        // Too short to even hold a signature
+        verifier.start(&offer(0x200)).await.unwrap();
+        stream(&mut verifier, &content[..2]).await;
+        assert_eq!(
+            verifier.verify().await,
+            Err(CfuUpdateContentResponseStatus::ErrorInvalid)
+        );
+        assert_eq!(verifier.rollback.read().await.unwrap(), 0);
+    }
+
+    /// Test that an aborted image can't be verified or finalized
+    #[tokio::test]
+    async fn test_abort() {
+        let mut verifier = verifier();
+        let content: [u8; DEFAULT_DATA_LENGTH] = core::array::from_fn(|i| i as u8);
+
+        verifier.start(&offer(0x200)).await.unwrap();
+        stream(&mut verifier, &signed_image(&content, KEY)).await;
+        verifier.abort();
+        assert_eq!(
+            verifier.verify().await,
+            Err(CfuUpdateContentResponseStatus::ErrorInvalid)
+        );
+        assert_eq!(
+            verifier.finalize().await,
+            Err(CfuUpdateContentResponseStatus::ErrorInvalid)
+        );
+        assert_eq!(verifier.rollback.read().await.unwrap(), 0);
+    }
+}
@@ -2,7 +2,7 @@ use embassy_sync::{once_lock::OnceLock, signal::Signal};
 use embedded_services::GlobalRawMutex;
 use embedded_services::buffer::{OwnedRef, SharedRef};
 use embedded_services::comms::{self, EndpointID, Internal};
-use embedded_services::ec_type::message::StdHostRequest;
+use embedded_services::ec_type::message::{StdHostPayload, StdHostRequest};
 use embedded_services::{debug, error};
 
 // Maximum number of bytes to request per defmt frame write grant.
@@ -69,15 +69,17 @@ impl Service {
 
 impl comms::MailboxDelegate for Service {
     fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
-        if let Some(_request) = message.data.get::<StdHostRequest>() {
+        if let Some(request) = message.data.get::<StdHostRequest>() {
             // Host sent an ACPI/MCTP request (e.g. GetDebugBuffer). Treat this as the
             // trigger to send the staged debug buffer back to the host.
             embedded_services::trace!("Received host ACPI request for debug buffer from {:?}", message.from);
-            // We only use the signal as a wakeup; the defmt task ignores any payload here.
-            if self.frame_available.load(core::sync::atomic::Ordering::SeqCst) {
-                response_notify_signal().signal(());
+            // The signal carries the request tag so the response can be matched to the request.
+            if matches!(request.payload, StdHostPayload::DebugDumpPdJournalRequest) {
+                pd_journal_notify_signal().signal(request.tag);
+            } else if self.frame_available.load(core::sync::atomic::Ordering::SeqCst) {
+                response_notify_signal().signal(request.tag);
             } else {
-                no_avail_notify_signal().signal(());
+                no_avail_notify_signal().signal(request.tag);
             }
         } else {
             error!("Received unknown message from host");
@@ -90,11 +92,14 @@ impl comms::MailboxDelegate for Service {
 static DEBUG_SERVICE: OnceLock<Service> = OnceLock::new();
 
 // Global signal used to notify tasks waiting on a Host response path (e.g., ACPI response).
-// We only need a wake-up, so the payload is unit `()` to avoid lifetime coupling.
-static RESP_NOTIFY: OnceLock<Signal<GlobalRawMutex, ()>> = OnceLock::new();
+// The payload is the MCTP tag of the request, which the response has to carry.
+static RESP_NOTIFY: OnceLock<Signal<GlobalRawMutex, u8>> = OnceLock::new();
 
 // For no frame avail task
-static NO_AVAIL_NOTIFY: OnceLock<Signal<GlobalRawMutex, ()>> = OnceLock::new();
+static NO_AVAIL_NOTIFY: OnceLock<Signal<GlobalRawMutex, u8>> = OnceLock::new();
+
+// For the PD journal task
+static PD_JOURNAL_NOTIFY: OnceLock<Signal<GlobalRawMutex, u8>> = OnceLock::new();
 
 pub(crate) fn owned_buffer() -> OwnedRef<'static, u8> {
     defmt_acpi_buf::get_mut().expect("defmt staging buffer already initialized elsewhere")
@@ -109,14 +114,18 @@ pub(crate) fn frame_available(avail: bool) {
     s.frame_available.store(avail, core::sync::atomic::Ordering::SeqCst);
 }
 
-pub(crate) fn response_notify_signal() -> &'static Signal<GlobalRawMutex, ()> {
+pub(crate) fn response_notify_signal() -> &'static Signal<GlobalRawMutex, u8> {
     RESP_NOTIFY.get_or_init(Signal::new)
 }
 
-pub(crate) fn no_avail_notify_signal() -> &'static Signal<GlobalRawMutex, ()> {
+pub(crate) fn no_avail_notify_signal() -> &'static Signal<GlobalRawMutex, u8> {
     NO_AVAIL_NOTIFY.get_or_init(Signal::new)
 }
 
+pub(crate) fn pd_journal_notify_signal() -> &'static Signal<GlobalRawMutex, u8> {
+    PD_JOURNAL_NOTIFY.get_or_init(Signal::new)
+}
+
 /// Returns the endpoint ID of the transport used by the debug service.
 pub async fn host_endpoint_id() -> EndpointID {
     let svc = DEBUG_SERVICE.get().await;
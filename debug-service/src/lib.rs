@@ -5,6 +5,7 @@
 
 mod debug_service;
 mod defmt_ring_logger;
+pub mod pd_journal;
 pub mod task;
 
 pub use debug_service::*;
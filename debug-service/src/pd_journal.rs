@@ -0,0 +1,35 @@
+//! Dump the type-C PD event journals to the debug log
+use embedded_services::type_c::external;
+use embedded_services::{error, info};
+use embedded_usb_pd::GlobalPortId;
+
+/// Dump the PD event journal of every type-C port to the debug log.
+///
+/// Entries are emitted through the embedded-services logging macros and reach the host through the same path as every
+/// other debug message. The host triggers a dump with the ODP `DebugCmd::DumpPdJournal` command, see
+/// [`crate::task::pd_journal_to_host_task`].
+pub async fn dump_pd_journal() {
+    for port in 0..external::get_num_ports() {
+        let port = GlobalPortId(port as u8);
+        let mut sequence = 0;
+
+        loop {
+            match external::get_journal_entry(port, sequence).await {
+                Ok(Some(entry)) => {
+                    info!(
+                        "Port{}: #{} @{}ms: {:?}",
+                        port.0, entry.sequence, entry.timestamp_ms, entry.event
+                    );
+
+                    // Sequence numbers wrap, the journal compares them by wrapping difference
+                    sequence = entry.sequence.wrapping_add(1);
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Port{}: Failed to read PD journal: {:?}", port.0, e);
+                    break;
+                }
+            }
+        }
+    }
+}
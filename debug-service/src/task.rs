@@ -60,7 +60,7 @@ pub async fn defmt_to_host_task() -> Result<embedded_services::Never, Error> {
 
         // Wait for host notification/ack via the debug service
         frame_available(true);
-        let _n = response_notify_signal().wait().await;
+        let tag = response_notify_signal().wait().await;
         frame_available(false);
         embedded_services::trace!("host ack received, sending defmt response");
 
@@ -72,6 +72,7 @@ pub async fn defmt_to_host_task() -> Result<embedded_services::Never, Error> {
                     embedded_services::ec_type::protocols::debug::DebugCmd::GetMsgs,
                 ),
                 status: 0,
+                tag,
                 payload: StdHostPayload::DebugGetMsgsResponse {
                     debug_buf: {
                         let access = shared_buffer().borrow().map_err(Error::Buffer)?;
@@ -93,6 +94,32 @@ pub async fn defmt_to_host_task() -> Result<embedded_services::Never, Error> {
     }
 }
 
+/// Dump the PD event journals to the debug messages when the host asks for them
+pub async fn pd_journal_to_host_task() -> embedded_services::Never {
+    embedded_services::info!("PD journal to host task start");
+    use crate::debug_service::{host_endpoint_id, pd_journal_notify_signal};
+    use embedded_services::comms::{self, EndpointID, Internal};
+    use embedded_services::ec_type::message::HostMsg;
+
+    let host_ep = host_endpoint_id().await;
+
+    loop {
+        let tag = pd_journal_notify_signal().wait().await;
+        crate::pd_journal::dump_pd_journal().await;
+
+        // The entries are read back through the debug messages
+        let msg = HostMsg::Response(StdHostRequest {
+            command: embedded_services::ec_type::message::OdpCommand::Debug(
+                embedded_services::ec_type::protocols::debug::DebugCmd::DumpPdJournal,
+            ),
+            status: 0,
+            tag,
+            payload: StdHostPayload::DebugDumpPdJournalResponse,
+        });
+        let _ = comms::send(EndpointID::Internal(Internal::Debug), host_ep, &msg).await;
+    }
+}
+
 pub async fn no_avail_to_host_task() -> Result<embedded_services::Never, Error> {
     embedded_services::define_static_buffer!(no_avail_acpi_buf, u8, [0u8; 12]);
 
@@ -111,17 +138,17 @@ pub async fn no_avail_to_host_task() -> Result<embedded_services::Never, Error>
         buf[4..12].copy_from_slice(&0xDEADBEEFu64.to_be_bytes());
     }
 
-    let msg = HostMsg::Response(StdHostRequest {
-        command: embedded_services::ec_type::message::OdpCommand::Debug(
-            embedded_services::ec_type::protocols::debug::DebugCmd::GetMsgs,
-        ),
-        status: 1,
-        payload: StdHostPayload::ErrorResponse {},
-    });
-
     // Send DEADBEEF if host requests frame but non available
     loop {
-        no_avail_notify_signal().wait().await;
+        let tag = no_avail_notify_signal().wait().await;
+        let msg = HostMsg::Response(StdHostRequest {
+            command: embedded_services::ec_type::message::OdpCommand::Debug(
+                embedded_services::ec_type::protocols::debug::DebugCmd::GetMsgs,
+            ),
+            status: 1,
+            tag,
+            payload: StdHostPayload::ErrorResponse {},
+        });
         let _ = comms::send(EndpointID::Internal(Internal::Debug), host_ep, &msg).await;
     }
 }
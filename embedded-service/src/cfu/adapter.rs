@@ -0,0 +1,437 @@
+//! Bridge between the CFU component model and [`crate::fw_update::FwUpdate`] implementors
+//!
+//! The adapter holds no reference to the device, callers own both the device and the [`FwUpdateState`] and pass them
+//! in with each request. This lets components process CFU requests from their existing event loop while holding
+//! whatever locks the device requires.
+use embedded_cfu_protocol::protocol_definitions::*;
+
+use crate::cfu::component::{InternalResponseData, RequestData};
+use crate::fw_update::{self, Error as _, FwUpdate};
+use crate::{debug, error, trace};
+
+/// Base interval for checking for FW update timeouts and recovery attempts
+pub const DEFAULT_FW_UPDATE_TICK_INTERVAL_MS: u64 = 5000;
+/// Default number of ticks before we consider a firmware update to have timed out
+/// 300 seconds at 5 seconds per tick
+pub const DEFAULT_FW_UPDATE_TIMEOUT_TICKS: u8 = 60;
+
+/// Trait for validating firmware versions before applying an update
+// TODO: remove this once we have a better framework for OEM customization
+// See https://github.com/OpenDevicePartnership/embedded-services/issues/326
+pub trait FwOfferValidator {
+    /// Determine if we are accepting the firmware update offer, returns a CFU offer response
+    fn validate(&self, current: FwVersion, offer: &FwUpdateOffer) -> FwUpdateOfferResponse;
+}
+
+/// Current state of the firmware update process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FwUpdateState {
+    /// None in progress
+    #[default]
+    Idle,
+    /// Firmware update in progress
+    /// Contains number of ticks that have passed since the update started
+    InProgress(u8),
+    /// Firmware update has failed and the device is in an unknown state
+    Recovery,
+}
+
+impl FwUpdateState {
+    /// Check if the firmware update is in progress
+    pub fn in_progress(&self) -> bool {
+        matches!(self, FwUpdateState::InProgress(_) | FwUpdateState::Recovery)
+    }
+}
+
+/// CFU adapter for a single component
+pub struct Adapter<V: FwOfferValidator> {
+    /// CFU component ID
+    component_id: ComponentId,
+    /// OEM offer validation
+    validator: V,
+    /// Number of ticks before an update is considered to have timed out
+    timeout_ticks: u8,
+}
+
+impl<V: FwOfferValidator> Adapter<V> {
+    /// Create a new adapter with the default timeout
+    pub fn new(component_id: ComponentId, validator: V) -> Self {
+        Self::with_timeout_ticks(component_id, validator, DEFAULT_FW_UPDATE_TIMEOUT_TICKS)
+    }
+
+    /// Create a new adapter with the given timeout
+    pub fn with_timeout_ticks(component_id: ComponentId, validator: V, timeout_ticks: u8) -> Self {
+        Self {
+            component_id,
+            validator,
+            timeout_ticks,
+        }
+    }
+
+    /// Get the CFU component ID
+    pub fn component_id(&self) -> ComponentId {
+        self.component_id
+    }
+
+    /// Create a FW version response
+    fn create_fw_version_response(&self, version: u32) -> InternalResponseData {
+        let dev_inf = FwVerComponentInfo::new(FwVersion::new(version), self.component_id);
+        let comp_info: [FwVerComponentInfo; MAX_CMPT_COUNT] = [dev_inf; MAX_CMPT_COUNT];
+        InternalResponseData::FwVersionResponse(GetFwVersionResponse {
+            header: GetFwVersionResponseHeader::new(1, GetFwVerRespHeaderByte3::NoSpecialFlags),
+            component_info: comp_info,
+        })
+    }
+
+    /// Create an offer rejection response
+    fn create_offer_rejection(reason: OfferRejectReason) -> InternalResponseData {
+        InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_with_failure(
+            HostToken::Driver,
+            reason,
+            OfferStatus::Reject,
+        ))
+    }
+
+    /// Create a content response
+    fn create_content_response(
+        content: &FwUpdateContentCommand,
+        status: CfuUpdateContentResponseStatus,
+    ) -> InternalResponseData {
+        InternalResponseData::ContentResponse(FwUpdateContentResponse::new(content.header.sequence_num, status))
+    }
+
+    /// Process a GetFwVersion command
+    async fn process_get_fw_version<D: FwUpdate>(&self, device: &mut D) -> InternalResponseData {
+        match device.get_active_fw_version().await {
+            Ok(version) => self.create_fw_version_response(version),
+            Err(e) => {
+                error!("Failed to get active firmware version: {:?}", e.kind());
+                self.create_fw_version_response(0xffffffff)
+            }
+        }
+    }
+
+    /// Process a GiveOffer command
+    async fn process_give_offer<D: FwUpdate>(&self, device: &mut D, offer: &FwUpdateOffer) -> InternalResponseData {
+        if offer.component_info.component_id != self.component_id {
+            return Self::create_offer_rejection(OfferRejectReason::InvalidComponent);
+        }
+
+        match device.validate_offer().await {
+            Ok(fw_update::OfferStatus::Accept) => {}
+            Ok(fw_update::OfferStatus::Busy) => {
+                debug!("Device busy, rejecting offer");
+                return InternalResponseData::ComponentBusy;
+            }
+            Ok(fw_update::OfferStatus::Reject(reason)) => {
+                debug!("Device rejected offer: {:?}", reason);
+                return Self::create_offer_rejection(match reason {
+                    fw_update::RejectReason::OldFirmware => OfferRejectReason::OldFw,
+                    _ => OfferRejectReason::InvalidComponent,
+                });
+            }
+            Err(e) => {
+                error!("Failed to validate offer: {:?}", e.kind());
+                return Self::create_offer_rejection(OfferRejectReason::InvalidComponent);
+            }
+        }
+
+        match device.get_active_fw_version().await {
+            Ok(version) => InternalResponseData::OfferResponse(self.validator.validate(FwVersion::new(version), offer)),
+            Err(e) => {
+                error!("Failed to get active firmware version: {:?}", e.kind());
+                Self::create_offer_rejection(OfferRejectReason::InvalidComponent)
+            }
+        }
+    }
+
+    /// Process an AbortUpdate command
+    async fn process_abort_update<D: FwUpdate>(
+        &self,
+        device: &mut D,
+        state: &mut FwUpdateState,
+    ) -> InternalResponseData {
+        match device.abort_fw_update().await {
+            Ok(_) => {
+                debug!("FW update aborted successfully");
+                *state = FwUpdateState::Idle;
+            }
+            Err(e) => {
+                error!("Failed to abort FW update: {:?}", e.kind());
+                *state = FwUpdateState::Recovery;
+            }
+        }
+
+        InternalResponseData::ComponentPrepared
+    }
+
+    /// Verify and activate the new image once the last block has been written
+    async fn complete_update<D: FwUpdate>(
+        &self,
+        device: &mut D,
+        state: &mut FwUpdateState,
+    ) -> Result<(), CfuUpdateContentResponseStatus> {
+        if let Err(e) = device.verify_fw_update().await {
+            error!("Failed to verify FW update: {:?}", e.kind());
+            // Bad image, the device is still running the old image so we can just exit update mode
+            *state = match device.abort_fw_update().await {
+                Ok(_) => FwUpdateState::Idle,
+                Err(_) => FwUpdateState::Recovery,
+            };
+            return Err(CfuUpdateContentResponseStatus::ErrorInvalid);
+        }
+
+        match device.activate_fw_update().await {
+            Ok(_) => {
+                debug!("FW update activated successfully");
+                *state = FwUpdateState::Idle;
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to activate FW update: {:?}", e.kind());
+                *state = FwUpdateState::Recovery;
+                Err(CfuUpdateContentResponseStatus::ErrorInvalid)
+            }
+        }
+    }
+
+    /// Process a GiveContent command
+    async fn process_give_content<D: FwUpdate>(
+        &self,
+        device: &mut D,
+        state: &mut FwUpdateState,
+        content: &FwUpdateContentCommand,
+    ) -> InternalResponseData {
+        let Some(data) = content.data.get(0..content.header.data_length as usize) else {
+            return Self::create_content_response(content, CfuUpdateContentResponseStatus::ErrorPrepare);
+        };
+
+        if content.header.flags & FW_UPDATE_FLAG_FIRST_BLOCK != 0 {
+            debug!("Got first block");
+            match device.start_fw_update().await {
+                Ok(_) => {
+                    debug!("FW update started successfully");
+                    *state = FwUpdateState::InProgress(0);
+                }
+                Err(e) => {
+                    error!("Failed to start FW update: {:?}", e.kind());
+                    *state = FwUpdateState::Recovery;
+                    return Self::create_content_response(content, CfuUpdateContentResponseStatus::ErrorPrepare);
+                }
+            }
+        }
+
+        if let Err(e) = device
+            .write_fw_contents(content.header.firmware_address as usize, data)
+            .await
+        {
+            error!("Failed to write block: {:?}", e.kind());
+            return Self::create_content_response(content, CfuUpdateContentResponseStatus::ErrorWrite);
+        }
+        trace!("Block written, progress: {:?}", device.progress());
+
+        if content.header.flags & FW_UPDATE_FLAG_LAST_BLOCK != 0 {
+            if let Err(status) = self.complete_update(device, state).await {
+                return Self::create_content_response(content, status);
+            }
+        }
+
+        Self::create_content_response(content, CfuUpdateContentResponseStatus::Success)
+    }
+
+    /// Process a CFU tick, aborts the update if it has timed out and retries recovery
+    pub async fn process_tick<D: FwUpdate>(&self, device: &mut D, state: &mut FwUpdateState) {
+        match *state {
+            FwUpdateState::Idle => {
+                // No FW update in progress, nothing to do
+                return;
+            }
+            FwUpdateState::InProgress(ticks) => {
+                if ticks.saturating_add(1) < self.timeout_ticks {
+                    trace!("CFU tick: {}", ticks);
+                    *state = FwUpdateState::InProgress(ticks + 1);
+                    return;
+                } else {
+                    error!("FW update timed out after {} ticks", ticks);
+                }
+            }
+            FwUpdateState::Recovery => {
+                // Continue recovery process
+            }
+        };
+
+        // Update timed out, attempt to exit the FW update
+        *state = FwUpdateState::Recovery;
+        match device.abort_fw_update().await {
+            Ok(_) => {
+                debug!("FW update aborted successfully");
+                *state = FwUpdateState::Idle;
+            }
+            Err(e) => error!("Failed to abort FW update: {:?}", e.kind()),
+        }
+    }
+
+    /// Process a CFU request
+    pub async fn process_request<D: FwUpdate>(
+        &self,
+        device: &mut D,
+        state: &mut FwUpdateState,
+        request: &RequestData,
+    ) -> InternalResponseData {
+        if *state == FwUpdateState::Recovery {
+            debug!("FW update in recovery state, rejecting command");
+            return InternalResponseData::ComponentBusy;
+        }
+
+        match request {
+            RequestData::FwVersionRequest => {
+                debug!("Got FwVersionRequest");
+                self.process_get_fw_version(device).await
+            }
+            RequestData::GiveOffer(offer) => {
+                debug!("Got GiveOffer");
+                self.process_give_offer(device, offer).await
+            }
+            RequestData::GiveContent(content) => {
+                debug!("Got GiveContent");
+                self.process_give_content(device, state, content).await
+            }
+            RequestData::AbortUpdate => {
+                debug!("Got AbortUpdate");
+                self.process_abort_update(device, state).await
+            }
+            RequestData::FinalizeUpdate => {
+                debug!("Got FinalizeUpdate");
+                InternalResponseData::ComponentPrepared
+            }
+            RequestData::PrepareComponentForUpdate => {
+                debug!("Got PrepareComponentForUpdate");
+                InternalResponseData::ComponentPrepared
+            }
+            RequestData::GiveOfferExtended(_) => {
+                debug!("Got GiveExtendedOffer, rejecting");
+                Self::create_offer_rejection(OfferRejectReason::InvalidComponent)
+            }
+            RequestData::GiveOfferInformation(_) => {
+                debug!("Got GiveOfferInformation, rejecting");
+                Self::create_offer_rejection(OfferRejectReason::InvalidComponent)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::fw_update::{ErrorKind, ErrorType, Progress, State};
+
+    struct AcceptAll;
+
+    impl FwOfferValidator for AcceptAll {
+        fn validate(&self, _current: FwVersion, _offer: &FwUpdateOffer) -> FwUpdateOfferResponse {
+            FwUpdateOfferResponse::new_accept(HostToken::Driver)
+        }
+    }
+
+    #[derive(Default)]
+    struct Device {
+        progress: Progress,
+        abort_calls: usize,
+        fail_abort: bool,
+    }
+
+    impl ErrorType for Device {
+        type Error = ErrorKind;
+    }
+
+    impl FwUpdate for Device {
+        async fn get_active_fw_version(&mut self) -> Result<u32, Self::Error> {
+            Ok(0x1234)
+        }
+
+        async fn start_fw_update(&mut self) -> Result<(), Self::Error> {
+            self.progress = Progress {
+                state: State::Writing,
+                bytes_written: 0,
+            };
+            Ok(())
+        }
+
+        async fn write_fw_contents(&mut self, _offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+            self.progress.bytes_written += data.len();
+            Ok(())
+        }
+
+        async fn activate_fw_update(&mut self) -> Result<(), Self::Error> {
+            self.progress = Progress::default();
+            Ok(())
+        }
+
+        async fn abort_fw_update(&mut self) -> Result<(), Self::Error> {
+            self.abort_calls += 1;
+            if self.fail_abort {
+                Err(ErrorKind::Bus)
+            } else {
+                self.progress = Progress::default();
+                Ok(())
+            }
+        }
+
+        async fn rollback_fw_update(&mut self) -> Result<(), Self::Error> {
+            Err(ErrorKind::Unsupported)
+        }
+
+        fn progress(&self) -> Progress {
+            self.progress
+        }
+    }
+
+    /// Test that an update is aborted once it times out
+    #[tokio::test]
+    async fn test_timeout() {
+        let adapter = Adapter::with_timeout_ticks(1, AcceptAll, 2);
+        let mut device = Device::default();
+        let mut state = FwUpdateState::InProgress(0);
+
+        adapter.process_tick(&mut device, &mut state).await;
+        assert_eq!(state, FwUpdateState::InProgress(1));
+        assert_eq!(device.abort_calls, 0);
+
+        adapter.process_tick(&mut device, &mut state).await;
+        assert_eq!(state, FwUpdateState::Idle);
+        assert_eq!(device.abort_calls, 1);
+    }
+
+    /// Test that requests are rejected until recovery succeeds
+    #[tokio::test]
+    async fn test_recovery() {
+        let adapter = Adapter::new(1, AcceptAll);
+        let mut device = Device {
+            fail_abort: true,
+            ..Default::default()
+        };
+        let mut state = FwUpdateState::InProgress(0);
+
+        let response = adapter
+            .process_request(&mut device, &mut state, &RequestData::AbortUpdate)
+            .await;
+        assert_eq!(response, InternalResponseData::ComponentPrepared);
+        assert_eq!(state, FwUpdateState::Recovery);
+
+        let response = adapter
+            .process_request(&mut device, &mut state, &RequestData::FwVersionRequest)
+            .await;
+        assert_eq!(response, InternalResponseData::ComponentBusy);
+
+        device.fail_abort = false;
+        adapter.process_tick(&mut device, &mut state).await;
+        assert_eq!(state, FwUpdateState::Idle);
+
+        let response = adapter
+            .process_request(&mut device, &mut state, &RequestData::FwVersionRequest)
+            .await;
+        assert_eq!(response, adapter.create_fw_version_response(0x1234));
+    }
+}
@@ -9,6 +9,7 @@ use embedded_cfu_protocol::writer::{CfuWriterAsync, CfuWriterError};
 use heapless::Vec;
 
 use super::CfuError;
+use super::verify::{ImageVerifier, NoVerification, StartError};
 use crate::GlobalRawMutex;
 use crate::cfu::route_request;
 use crate::intrusive_list;
@@ -183,13 +184,17 @@ impl CfuDevice {
 }
 
 /// Example for CFU Component
-pub struct CfuComponentDefault<W> {
+///
+/// Content is written as it's received, before the image is verified. Components using a verifier must write to a
+/// staging partition that only replaces the running image once the update is finalized.
+pub struct CfuComponentDefault<W, V = NoVerification> {
     device: CfuDevice,
     is_dual_bank: bool,
     is_primary: bool,
     storage_offset: usize,
     subcomponents: [Option<ComponentId>; MAX_SUBCMPT_COUNT],
     writer: Mutex<GlobalRawMutex, W>,
+    verifier: Mutex<GlobalRawMutex, V>,
 }
 
 impl<W: CfuWriterAsync + Default> Default for CfuComponentDefault<W> {
@@ -198,7 +203,7 @@ impl<W: CfuWriterAsync + Default> Default for CfuComponentDefault<W> {
     }
 }
 
-impl<W: CfuWriterAsync> CfuDeviceContainer for CfuComponentDefault<W> {
+impl<W: CfuWriterAsync, V: ImageVerifier> CfuDeviceContainer for CfuComponentDefault<W, V> {
     fn get_cfu_component_device(&self) -> &CfuDevice {
         &self.device
     }
@@ -211,6 +216,19 @@ impl<W: CfuWriterAsync> CfuComponentDefault<W> {
         is_primary: bool,
         subcomponents: [Option<ComponentId>; MAX_SUBCMPT_COUNT],
         writer: W,
+    ) -> Self {
+        Self::with_verifier(id, is_primary, subcomponents, writer, NoVerification)
+    }
+}
+
+impl<W: CfuWriterAsync, V: ImageVerifier> CfuComponentDefault<W, V> {
+    /// Constructor for a component verifying images before finalizing their update
+    pub fn with_verifier(
+        id: ComponentId,
+        is_primary: bool,
+        subcomponents: [Option<ComponentId>; MAX_SUBCMPT_COUNT],
+        writer: W,
+        verifier: V,
     ) -> Self {
         Self {
             device: CfuDevice::new(id),
@@ -219,6 +237,7 @@ impl<W: CfuWriterAsync> CfuComponentDefault<W> {
             storage_offset: 0,
             subcomponents,
             writer: Mutex::new(writer),
+            verifier: Mutex::new(verifier),
         }
     }
     /// wait for a request and process it
@@ -280,27 +299,54 @@ impl<W: CfuWriterAsync> CfuComponentDefault<W> {
                 self.storage_prepare()
                     .await
                     .map_err(|_| CfuError::ProtocolError(CfuProtocolError::BadResponse))?;
+                self.device.send_response(InternalResponseData::ComponentPrepared).await;
             }
             RequestData::GiveOffer(buf) => {
-                // accept any and all offers regardless of what version it is
-                if buf.component_info.component_id == self.get_component_id() {
-                    let resp = FwUpdateOfferResponse::new_accept(HostToken::Driver);
-                    self.device
-                        .send_response(InternalResponseData::OfferResponse(resp))
-                        .await;
-                }
+                // accept any and all offers for this component the verifier doesn't reject
+                let resp = if buf.component_info.component_id != self.get_component_id() {
+                    InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_with_failure(
+                        HostToken::Driver,
+                        OfferRejectReason::InvalidComponent,
+                        OfferStatus::Reject,
+                    ))
+                } else {
+                    match self.verifier.lock().await.start(&buf).await {
+                        Ok(()) => {
+                            InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_accept(HostToken::Driver))
+                        }
+                        Err(StartError::Rejected(reason)) => InternalResponseData::OfferResponse(
+                            FwUpdateOfferResponse::new_with_failure(HostToken::Driver, reason, OfferStatus::Reject),
+                        ),
+                        // The host tries again later
+                        Err(StartError::Storage) => InternalResponseData::ComponentBusy,
+                    }
+                };
+                self.device.send_response(resp).await;
             }
             RequestData::GiveContent(buf) => {
-                let offset = buf.header.firmware_address as usize;
-                self.writer
-                    .lock()
-                    .await
-                    .cfu_write(Some(offset), &buf.data)
-                    .await
-                    .map_err(|e| CfuError::ProtocolError(CfuProtocolError::WriterError(e)))?;
+                let status = match self.write_content(&buf).await {
+                    Ok(()) => CfuUpdateContentResponseStatus::Success,
+                    Err(status) => status,
+                };
+                self.device
+                    .send_response(InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
+                        buf.header.sequence_num,
+                        status,
+                    )))
+                    .await;
+            }
+            RequestData::AbortUpdate => {
+                self.verifier.lock().await.abort();
+                self.device.send_response(InternalResponseData::ComponentPrepared).await;
+            }
+            RequestData::FinalizeUpdate => {
+                // Don't switch to the staged image if it wasn't verified
+                let response = match self.verifier.lock().await.finalize().await {
+                    Ok(()) => InternalResponseData::ComponentPrepared,
+                    Err(status) => InternalResponseData::ContentResponse(FwUpdateContentResponse::new(0, status)),
+                };
+                self.device.send_response(response).await;
             }
-            RequestData::AbortUpdate => {}
-            RequestData::FinalizeUpdate => {}
             RequestData::GiveOfferExtended(_) => {
                 // Reject any extended offers
                 self.device
@@ -328,9 +374,31 @@ impl<W: CfuWriterAsync> CfuComponentDefault<W> {
         }
         Ok(())
     }
+
+    /// Write a block of content and verify the image once the last block is received
+    async fn write_content(&self, content: &FwUpdateContentCommand) -> Result<(), CfuUpdateContentResponseStatus> {
+        let offset = content.header.firmware_address as usize;
+        let data = content
+            .data
+            .get(..usize::from(content.header.data_length))
+            .ok_or(CfuUpdateContentResponseStatus::ErrorInvalid)?;
+        self.writer
+            .lock()
+            .await
+            .cfu_write(Some(offset), data)
+            .await
+            .map_err(|_| CfuUpdateContentResponseStatus::ErrorWrite)?;
+
+        let mut verifier = self.verifier.lock().await;
+        verifier.update(content).await?;
+        if content.header.flags & FW_UPDATE_FLAG_LAST_BLOCK != 0 {
+            verifier.verify().await?;
+        }
+        Ok(())
+    }
 }
 
-impl<W: CfuWriterAsync> CfuComponentInfo for CfuComponentDefault<W> {
+impl<W: CfuWriterAsync, V: ImageVerifier> CfuComponentInfo for CfuComponentDefault<W, V> {
     fn get_component_id(&self) -> ComponentId {
         self.device.component_id()
     }
@@ -351,7 +419,7 @@ impl<W: CfuWriterAsync> CfuComponentInfo for CfuComponentDefault<W> {
     }
 }
 
-impl<W: CfuWriterAsync> CfuWriterAsync for CfuComponentDefault<W> {
+impl<W: CfuWriterAsync, V: ImageVerifier> CfuWriterAsync for CfuComponentDefault<W, V> {
     async fn cfu_write(&mut self, mem_offset: Option<usize>, data: &[u8]) -> Result<(), CfuWriterError> {
         self.writer.lock().await.cfu_write(mem_offset, data).await
     }
@@ -373,7 +441,7 @@ impl<W: CfuWriterAsync> CfuWriterAsync for CfuComponentDefault<W> {
     }
 }
 
-impl<W: CfuWriterAsync> CfuComponentStorage for CfuComponentDefault<W> {
+impl<W: CfuWriterAsync, V: ImageVerifier> CfuComponentStorage for CfuComponentDefault<W, V> {
     fn get_storage_offset(&self) -> usize {
         self.storage_offset
     }
@@ -398,7 +466,7 @@ async fn default_get_fw_version() -> Result<FwVersion, CfuProtocolError> {
     Ok(FwVersion::default())
 }
 
-impl<W: CfuWriterAsync + Default> CfuComponentTraits for CfuComponentDefault<W> {}
+impl<W: CfuWriterAsync + Default, V: ImageVerifier> CfuComponentTraits for CfuComponentDefault<W, V> {}
 
 /// Example Wrapper for CFU Component
 /// Takes type which implements `CFUComponentTraits` and `CfuDeviceContainer`
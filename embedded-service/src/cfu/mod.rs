@@ -1,6 +1,8 @@
 //! Cfu Service related data structures and messages
 //pub mod action;
+pub mod adapter;
 pub mod component;
+pub mod verify;
 
 use core::sync::atomic::{AtomicBool, Ordering};
 
@@ -0,0 +1,54 @@
+//! Verification of images before a component finalizes their update
+use core::future::Future;
+
+use embedded_cfu_protocol::protocol_definitions::*;
+
+/// Error starting the verification of an offered image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartError {
+    /// The offer is rejected for the given reason
+    Rejected(OfferRejectReason),
+    /// Verification state couldn't be read from storage, the offer can be retried later
+    Storage,
+}
+
+/// Verification a component opts into before finalizing an update
+pub trait ImageVerifier {
+    /// Check an offer before it's accepted and start verifying the image it describes
+    fn start(&mut self, offer: &FwUpdateOffer) -> impl Future<Output = Result<(), StartError>>;
+    /// Add a block of content to the image being verified
+    fn update(
+        &mut self,
+        content: &FwUpdateContentCommand,
+    ) -> impl Future<Output = Result<(), CfuUpdateContentResponseStatus>>;
+    /// Verify the complete image, called once the last block has been received
+    fn verify(&mut self) -> impl Future<Output = Result<(), CfuUpdateContentResponseStatus>>;
+    /// Called when the update is finalized, fails if the image wasn't verified
+    fn finalize(&mut self) -> impl Future<Output = Result<(), CfuUpdateContentResponseStatus>>;
+    /// Called when the update is aborted, drops the image being verified
+    fn abort(&mut self);
+}
+
+/// Accepts every image
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoVerification;
+
+impl ImageVerifier for NoVerification {
+    async fn start(&mut self, _offer: &FwUpdateOffer) -> Result<(), StartError> {
+        Ok(())
+    }
+
+    async fn update(&mut self, _content: &FwUpdateContentCommand) -> Result<(), CfuUpdateContentResponseStatus> {
+        Ok(())
+    }
+
+    async fn verify(&mut self) -> Result<(), CfuUpdateContentResponseStatus> {
+        Ok(())
+    }
+
+    async fn finalize(&mut self) -> Result<(), CfuUpdateContentResponseStatus> {
+        Ok(())
+    }
+
+    fn abort(&mut self) {}
+}
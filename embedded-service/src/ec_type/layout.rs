@@ -0,0 +1,462 @@
+//! Declarative description of the memory map shared with the host
+//!
+//! Sections are declared once with `memory_section!` and `memory_map!`, the section table, read-only enforcement
+//! and the conversions between fields and messages are derived from the declaration. Platforms can append their own
+//! sections after the standard ones with [`OemSection`].
+use super::Error;
+use super::message::OemMessage;
+use super::structure::{ECMemory, SectionId, Version};
+use crate::comms::EndpointID;
+
+/// Host access to a section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Access {
+    /// Only written by the EC
+    ReadOnly,
+    /// Also written by the host, writes are forwarded to the owner of the section
+    ReadWrite,
+}
+
+/// Description of a section of the memory map
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Section {
+    /// Section identifier
+    pub id: SectionId,
+    /// Offset from the start of the memory map
+    pub offset: usize,
+    /// Size in bytes
+    pub size: usize,
+    /// Host access
+    pub access: Access,
+    /// Service notified of host writes
+    pub owner: Option<EndpointID>,
+    /// Minor version of the memory map that introduced the section
+    pub since_minor: u8,
+}
+
+impl Section {
+    /// Returns true if `offset` is in the section
+    pub const fn contains(&self, offset: usize) -> bool {
+        offset >= self.offset && offset < self.offset + self.size
+    }
+
+    /// Returns true if the section is part of `version` of the memory map
+    pub const fn available_in(&self, version: Version) -> bool {
+        self.since_minor <= version.minor
+    }
+}
+
+/// Conversions between the fields of a section and its message type
+pub trait SectionFields {
+    /// Message type, with a variant per field
+    type Message;
+
+    /// Message for the field at `offset` in the section and the size of the field
+    fn field_message(&self, offset: usize) -> Option<(Self::Message, usize)>;
+
+    /// Update the field a message refers to
+    fn update(&mut self, msg: &Self::Message);
+}
+
+/// Convert the field of a section at `offset` in the memory map to a message
+///
+/// `base` is the offset of the section. Advances `offset` and `length` past the field.
+pub fn field_to_message<S: SectionFields>(
+    section: &S,
+    base: usize,
+    offset: &mut usize,
+    length: &mut usize,
+) -> Result<S::Message, Error> {
+    let local_offset = offset.checked_sub(base).ok_or(Error::InvalidLocation)?;
+    let (msg, size) = section.field_message(local_offset).ok_or(Error::InvalidLocation)?;
+    *offset += size;
+    *length = length.saturating_sub(size);
+    Ok(msg)
+}
+
+/// OEM-defined section, placed after the standard sections
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OemSection {
+    /// Platform-defined section number
+    pub id: u8,
+    /// Offset from the end of the standard sections
+    pub offset: usize,
+    /// Size in bytes
+    pub size: usize,
+    /// Host access
+    pub access: Access,
+    /// Service notified of host writes
+    pub owner: EndpointID,
+}
+
+impl OemSection {
+    /// Description of the section in the memory map
+    pub const fn section(&self) -> Section {
+        Section {
+            id: SectionId::Oem(self.id),
+            offset: size_of::<ECMemory>() + self.offset,
+            size: self.size,
+            access: self.access,
+            owner: Some(self.owner),
+            since_minor: 0,
+        }
+    }
+}
+
+/// Check that OEM sections fit in the `len` bytes after the standard sections and don't overlap
+pub fn validate_oem_sections(sections: &[OemSection], len: usize) -> Result<(), Error> {
+    for (i, section) in sections.iter().enumerate() {
+        if section.size == 0 || section.offset + section.size > len {
+            return Err(Error::InvalidLocation);
+        }
+
+        let overlaps = sections.iter().skip(i + 1).any(|other| {
+            other.id == section.id
+                || (other.offset < section.offset + section.size && section.offset < other.offset + other.size)
+        });
+        if overlaps {
+            return Err(Error::InvalidLocation);
+        }
+    }
+
+    Ok(())
+}
+
+/// Section containing `offset`, standard sections first then OEM sections
+pub fn find_section(oem_sections: &[OemSection], offset: usize) -> Option<Section> {
+    ECMemory::section_at(offset).copied().or_else(|| {
+        oem_sections
+            .iter()
+            .map(OemSection::section)
+            .find(|section| section.contains(offset))
+    })
+}
+
+/// Copy the data of an OEM message into the OEM part of the memory map
+///
+/// `oem_memory` starts at the end of the standard sections.
+pub fn update_oem_section(oem_sections: &[OemSection], oem_memory: &mut [u8], msg: &OemMessage) -> Result<(), Error> {
+    let section = oem_sections
+        .iter()
+        .find(|section| section.id == msg.section)
+        .ok_or(Error::InvalidLocation)?;
+    if msg.offset + msg.data.len() > section.size {
+        return Err(Error::InvalidLocation);
+    }
+
+    let start = section.offset + msg.offset;
+    oem_memory
+        .get_mut(start..start + msg.data.len())
+        .ok_or(Error::InvalidLocation)?
+        .copy_from_slice(&msg.data);
+    Ok(())
+}
+
+/// Declare a section of the memory map and derive the conversions between its fields and its message type
+///
+/// Each field maps to a variant of the message type carrying the field's value.
+macro_rules! memory_section {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident => $msg:ident {
+            $($field:ident: $ty:ty => $variant:ident,)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[repr(C, packed)]
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl $crate::ec_type::layout::SectionFields for $name {
+            type Message = $crate::ec_type::message::$msg;
+
+            fn field_message(&self, offset: usize) -> Option<(Self::Message, usize)> {
+                $(
+                    if offset == core::mem::offset_of!($name, $field) {
+                        return Some(($crate::ec_type::message::$msg::$variant(self.$field), size_of::<$ty>()));
+                    }
+                )*
+                None
+            }
+
+            fn update(&mut self, msg: &Self::Message) {
+                match *msg {
+                    $($crate::ec_type::message::$msg::$variant(value) => self.$field = value,)*
+                }
+            }
+        }
+    };
+}
+pub(crate) use memory_section;
+
+/// Declare the memory map and derive its section table
+///
+/// Each field is a section with its [`SectionId`] variant, the message type of its fields if it has one, its
+/// [`Access`], the internal endpoint that owns it if the host can write it and the minor version that introduced it.
+/// Sections must be packed so their fields can be borrowed in place.
+macro_rules! memory_map {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $field:ident: $ty:ident => $id:ident $(($msg:ident))?, $access:ident $(, owner $owner:ident)?,
+                since $since:literal;
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        #[repr(C, packed)]
+        #[derive(Clone, Copy, Debug, Default)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        /// Section of the memory map
+        #[allow(missing_docs)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        pub enum SectionId {
+            $($id,)*
+            /// OEM-defined section
+            Oem(u8),
+        }
+
+        /// Message for a field of a standard section
+        #[allow(missing_docs)]
+        #[derive(Clone, Copy, Debug)]
+        pub enum SectionMessage {
+            $($($id($crate::ec_type::message::$msg),)?)*
+        }
+
+        impl SectionMessage {
+            /// Section message carried by comms data
+            pub fn from_data(data: &$crate::comms::Data) -> Option<Self> {
+                $($(
+                    if let Some(msg) = data.get::<$crate::ec_type::message::$msg>() {
+                        return Some(Self::$id(*msg));
+                    }
+                )?)*
+                None
+            }
+
+            /// Send the message to `to`
+            pub async fn send(
+                &self,
+                from: $crate::comms::EndpointID,
+                to: $crate::comms::EndpointID,
+            ) -> Result<(), core::convert::Infallible> {
+                match self {
+                    $($(Self::$id(msg) => {
+                        let msg: &$crate::ec_type::message::$msg = msg;
+                        $crate::comms::send(from, to, msg).await
+                    })?)*
+                }
+            }
+        }
+
+        impl $name {
+            /// Standard sections, in memory order
+            pub const SECTIONS: &'static [$crate::ec_type::layout::Section] = &[$(
+                $crate::ec_type::layout::Section {
+                    id: SectionId::$id,
+                    offset: core::mem::offset_of!($name, $field),
+                    size: size_of::<$ty>(),
+                    access: $crate::ec_type::layout::Access::$access,
+                    owner: $crate::ec_type::layout::memory_map!(@owner $($owner)?),
+                    since_minor: $since,
+                },
+            )*];
+
+            /// Standard section containing `offset`
+            pub fn section_at(offset: usize) -> Option<&'static $crate::ec_type::layout::Section> {
+                Self::SECTIONS.iter().find(|section| section.contains(offset))
+            }
+
+            /// Message for the field of section `id` at `offset` in the memory map
+            ///
+            /// Advances `offset` and `length` past the field.
+            pub fn field_message(
+                &self,
+                id: SectionId,
+                offset: &mut usize,
+                length: &mut usize,
+            ) -> Result<SectionMessage, $crate::ec_type::Error> {
+                match id {
+                    $($(SectionId::$id => {
+                        let msg: $crate::ec_type::message::$msg = $crate::ec_type::layout::field_to_message(
+                            &self.$field,
+                            core::mem::offset_of!($name, $field),
+                            offset,
+                            length,
+                        )?;
+                        Ok(SectionMessage::$id(msg))
+                    })?)*
+                    _ => Err($crate::ec_type::Error::InvalidLocation),
+                }
+            }
+
+            /// Update the field a message refers to
+            pub fn update(&mut self, msg: &SectionMessage) {
+                use $crate::ec_type::layout::SectionFields;
+
+                match msg {
+                    $($(SectionMessage::$id(msg) => {
+                        let msg: &$crate::ec_type::message::$msg = msg;
+                        self.$field.update(msg);
+                    })?)*
+                }
+            }
+        }
+    };
+    (@owner $owner:ident) => {
+        Some($crate::comms::EndpointID::Internal($crate::comms::Internal::$owner))
+    };
+    (@owner) => {
+        None
+    };
+}
+pub(crate) use memory_map;
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use core::mem::offset_of;
+
+    use super::*;
+    use crate::comms::Internal;
+    use crate::ec_type::message::ThermalMessage;
+    use crate::ec_type::structure::{EC_MEMMAP_VERSION, SectionMessage, Thermal};
+
+    const OEM_SECTIONS: [OemSection; 2] = [
+        OemSection {
+            id: 0,
+            offset: 0,
+            size: 8,
+            access: Access::ReadOnly,
+            owner: EndpointID::Internal(Internal::Oem(0)),
+        },
+        OemSection {
+            id: 1,
+            offset: 8,
+            size: 4,
+            access: Access::ReadWrite,
+            owner: EndpointID::Internal(Internal::Oem(1)),
+        },
+    ];
+
+    #[test]
+    fn test_sections() {
+        let mut end = 0;
+        for section in ECMemory::SECTIONS {
+            assert_eq!(section.offset, end);
+            end += section.size;
+        }
+        assert_eq!(end, size_of::<ECMemory>());
+
+        let section = ECMemory::section_at(offset_of!(ECMemory, therm) + 4).unwrap();
+        assert_eq!(section.id, SectionId::Thermal);
+        assert_eq!(section.access, Access::ReadWrite);
+        assert_eq!(section.owner, Some(EndpointID::Internal(Internal::Thermal)));
+        assert_eq!(
+            ECMemory::section_at(offset_of!(ECMemory, caps)).unwrap().access,
+            Access::ReadOnly
+        );
+        assert_eq!(ECMemory::section_at(size_of::<ECMemory>()), None);
+    }
+
+    #[test]
+    fn test_field_message() {
+        let mut memory_map = ECMemory::default();
+        memory_map.update(&SectionMessage::Thermal(ThermalMessage::CoolMode(3)));
+        assert_eq!({ memory_map.therm.cool_mode }, 3);
+
+        let mut offset = offset_of!(ECMemory, therm) + offset_of!(Thermal, cool_mode);
+        let mut length = 8;
+        let msg = memory_map
+            .field_message(SectionId::Thermal, &mut offset, &mut length)
+            .unwrap();
+        assert!(matches!(msg, SectionMessage::Thermal(ThermalMessage::CoolMode(3))));
+        assert_eq!(offset, offset_of!(ECMemory, therm) + offset_of!(Thermal, dba_limit));
+        assert_eq!(length, 4);
+
+        // Sections without messages and offsets in the middle of a field
+        let mut offset = offset_of!(ECMemory, notif);
+        assert_eq!(
+            memory_map
+                .field_message(SectionId::Notifications, &mut offset, &mut length)
+                .err(),
+            Some(Error::InvalidLocation)
+        );
+        let mut offset = offset_of!(ECMemory, therm) + 1;
+        assert_eq!(
+            memory_map
+                .field_message(SectionId::Thermal, &mut offset, &mut length)
+                .err(),
+            Some(Error::InvalidLocation)
+        );
+    }
+
+    #[test]
+    fn test_oem_sections() {
+        assert_eq!(validate_oem_sections(&OEM_SECTIONS, 12), Ok(()));
+        assert_eq!(validate_oem_sections(&OEM_SECTIONS, 11), Err(Error::InvalidLocation));
+
+        let mut overlapping = OEM_SECTIONS;
+        overlapping[1].offset = 4;
+        assert_eq!(validate_oem_sections(&overlapping, 12), Err(Error::InvalidLocation));
+
+        let section = find_section(&OEM_SECTIONS, size_of::<ECMemory>() + 9).unwrap();
+        assert_eq!(section.id, SectionId::Oem(1));
+        assert_eq!(section.offset, size_of::<ECMemory>() + 8);
+        assert_eq!(find_section(&OEM_SECTIONS, size_of::<ECMemory>() + 12), None);
+        assert_eq!(find_section(&OEM_SECTIONS, 0).unwrap().id, SectionId::Version);
+    }
+
+    #[test]
+    fn test_update_oem_section() {
+        let mut oem_memory = [0u8; 12];
+        let msg = OemMessage {
+            section: 1,
+            offset: 2,
+            data: heapless::Vec::from_slice(&[1, 2]).unwrap(),
+        };
+        update_oem_section(&OEM_SECTIONS, &mut oem_memory, &msg).unwrap();
+        assert_eq!(oem_memory, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2]);
+
+        // Data past the end of the section
+        let msg = OemMessage { offset: 3, ..msg };
+        assert_eq!(
+            update_oem_section(&OEM_SECTIONS, &mut oem_memory, &msg),
+            Err(Error::InvalidLocation)
+        );
+    }
+
+    #[test]
+    fn test_version() {
+        let older = Version {
+            minor: 0,
+            ..EC_MEMMAP_VERSION
+        };
+        let newer = Version {
+            minor: EC_MEMMAP_VERSION.minor + 1,
+            ..EC_MEMMAP_VERSION
+        };
+        let other_major = Version {
+            major: EC_MEMMAP_VERSION.major + 1,
+            ..EC_MEMMAP_VERSION
+        };
+        assert!(EC_MEMMAP_VERSION.supports(EC_MEMMAP_VERSION));
+        assert!(EC_MEMMAP_VERSION.supports(older));
+        assert!(!EC_MEMMAP_VERSION.supports(newer));
+        assert!(!EC_MEMMAP_VERSION.supports(other_major));
+
+        let section = ECMemory::section_at(offset_of!(ECMemory, batt)).unwrap();
+        assert!(section.available_in(EC_MEMMAP_VERSION));
+        assert!(!section.available_in(older));
+    }
+}
@@ -1,6 +1,6 @@
 //! EC Internal Messages
 
-use crate::ec_type::protocols::{acpi, debug, mctp::OdpCommandCode, mptf};
+use crate::ec_type::protocols::{acpi, debug, mctp::OdpCommandCode, mptf, power};
 
 #[allow(missing_docs)]
 #[derive(Clone, Copy, Debug)]
@@ -14,6 +14,7 @@ pub enum CapabilitiesMessage {
     TempMask(u16),
     KeyMask(u16),
     DebugMask(u16),
+    Res0(u16),
 }
 
 #[allow(missing_docs)]
@@ -68,6 +69,22 @@ pub enum BatteryMessage {
     SampleTime(u32),
 }
 
+/// Maximum number of bytes carried by an [`OemMessage`]
+pub const OEM_MESSAGE_MAX_LEN: usize = 32;
+
+/// Contents of an OEM-defined section of the memory map
+///
+/// Sent to the owner of the section when the host writes it, and to the eSPI service to update it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OemMessage {
+    /// Platform-defined section number
+    pub section: u8,
+    /// Offset of the data in the section
+    pub offset: usize,
+    /// Section contents
+    pub data: heapless::Vec<u8, OEM_MESSAGE_MAX_LEN>,
+}
+
 /// ACPI Message, compatible with comms system
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -76,16 +93,31 @@ pub struct HostRequest<Command: Copy, Payload: Copy> {
     pub command: Command,
     /// Status code
     pub status: u8,
+    /// MCTP message tag of the request, kept in the response so that the transport can match it to its request
+    pub tag: u8,
     /// Data payload
     pub payload: Payload,
 }
 
+/// Priority of a notification to the host
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NotificationPriority {
+    /// Informational, can be delayed and coalesced with duplicates
+    #[default]
+    Info,
+    /// Critical (e.g. thermal or battery critical), sent ahead of informational notifications
+    Critical,
+}
+
 /// Notification type to be sent to Host
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct NotificationMsg {
     /// Interrupt offset
     pub offset: u8,
+    /// Priority
+    pub priority: NotificationPriority,
 }
 
 #[allow(missing_docs)]
@@ -130,6 +162,8 @@ pub enum OdpCommand {
     Thermal(mptf::ThermalCmd),
     /// Debug commands
     Debug(debug::DebugCmd),
+    /// Power policy commands
+    Power(power::PowerCmd),
 }
 
 /// Standard Battery Service Model Number String Size
@@ -214,6 +248,9 @@ impl From<OdpCommandCode> for OdpCommand {
             OdpCommandCode::BatteryGetStaRequest | OdpCommandCode::BatteryGetStaResponse => {
                 OdpCommand::Battery(acpi::BatteryCmd::GetSta)
             }
+            OdpCommandCode::BatterySetChargeLimitRequest | OdpCommandCode::BatterySetChargeLimitResponse => {
+                OdpCommand::Battery(acpi::BatteryCmd::SetChargeLimit)
+            }
             OdpCommandCode::ThermalGetTmpRequest | OdpCommandCode::ThermalGetTmpResponse => {
                 OdpCommand::Thermal(mptf::ThermalCmd::GetTmp)
             }
@@ -235,6 +272,15 @@ impl From<OdpCommandCode> for OdpCommand {
             OdpCommandCode::DebugGetMsgsRequest | OdpCommandCode::DebugGetMsgsResponse => {
                 OdpCommand::Debug(debug::DebugCmd::GetMsgs)
             }
+            OdpCommandCode::DebugDumpPdJournalRequest | OdpCommandCode::DebugDumpPdJournalResponse => {
+                OdpCommand::Debug(debug::DebugCmd::DumpPdJournal)
+            }
+            OdpCommandCode::PowerGetPolicyRequest | OdpCommandCode::PowerGetPolicyResponse => {
+                OdpCommand::Power(power::PowerCmd::GetPolicy)
+            }
+            OdpCommandCode::PowerSetSystemPowerStateRequest | OdpCommandCode::PowerSetSystemPowerStateResponse => {
+                OdpCommand::Power(power::PowerCmd::SetSystemPowerState)
+            }
         }
     }
 }
@@ -258,6 +304,7 @@ impl From<OdpCommand> for OdpCommandCode {
             OdpCommand::Battery(acpi::BatteryCmd::SetBms) => OdpCommandCode::BatterySetBmsRequest,
             OdpCommand::Battery(acpi::BatteryCmd::SetBma) => OdpCommandCode::BatterySetBmaRequest,
             OdpCommand::Battery(acpi::BatteryCmd::GetSta) => OdpCommandCode::BatteryGetStaRequest,
+            OdpCommand::Battery(acpi::BatteryCmd::SetChargeLimit) => OdpCommandCode::BatterySetChargeLimitRequest,
             OdpCommand::Thermal(mptf::ThermalCmd::GetTmp) => OdpCommandCode::ThermalGetTmpRequest,
             OdpCommand::Thermal(mptf::ThermalCmd::SetThrs) => OdpCommandCode::ThermalSetThrsRequest,
             OdpCommand::Thermal(mptf::ThermalCmd::GetThrs) => OdpCommandCode::ThermalGetThrsRequest,
@@ -265,6 +312,9 @@ impl From<OdpCommand> for OdpCommandCode {
             OdpCommand::Thermal(mptf::ThermalCmd::GetVar) => OdpCommandCode::ThermalGetVarRequest,
             OdpCommand::Thermal(mptf::ThermalCmd::SetVar) => OdpCommandCode::ThermalSetVarRequest,
             OdpCommand::Debug(debug::DebugCmd::GetMsgs) => OdpCommandCode::DebugGetMsgsRequest,
+            OdpCommand::Debug(debug::DebugCmd::DumpPdJournal) => OdpCommandCode::DebugDumpPdJournalRequest,
+            OdpCommand::Power(power::PowerCmd::GetPolicy) => OdpCommandCode::PowerGetPolicyRequest,
+            OdpCommand::Power(power::PowerCmd::SetSystemPowerState) => OdpCommandCode::PowerSetSystemPowerStateRequest,
         }
     }
 }
@@ -32,4 +32,6 @@ pub enum BatteryCmd {
     SetBma = 14,
     /// Device Status
     GetSta = 15,
+    /// Charge limit mode, not an ACPI method
+    SetChargeLimit = 16,
 }
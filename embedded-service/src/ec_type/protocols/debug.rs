@@ -5,4 +5,7 @@ pub enum DebugCmd {
     /// Get buffer of debug messages, if available.
     /// Can be used to poll debug messages.
     GetMsgs = 1,
+    /// Dump the PD event journal of every type-C port to the debug messages.
+    /// The entries are read back with [`DebugCmd::GetMsgs`].
+    DumpPdJournal = 2,
 }
@@ -177,6 +177,47 @@ pub fn build_mctp_header(
     Ok((ret, data_len_padded + 8))
 }
 
+/// Compute the SMBus packet error code (CRC-8, polynomial x^8 + x^2 + x + 1) of a packet.
+pub fn smbus_pec(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Offset of the MCTP header flags in an MCTP over SMBus packet, starting with the destination address
+pub const SMBUS_MCTP_FLAGS_OFFSET: usize = 7;
+/// Packet sequence number in the MCTP header flags
+pub const MCTP_SEQ_MASK: u8 = 0b0011_0000;
+const MCTP_SEQ_SHIFT: u8 = 4;
+/// Tag owner bit in the MCTP header flags
+pub const MCTP_TAG_OWNER: u8 = 0b0000_1000;
+/// Message tag in the MCTP header flags
+pub const MCTP_TAG_MASK: u8 = 0b0000_0111;
+
+/// Message tag of an MCTP over SMBus packet
+pub fn smbus_message_tag(packet: &[u8]) -> Option<u8> {
+    packet.get(SMBUS_MCTP_FLAGS_OFFSET).map(|flags| flags & MCTP_TAG_MASK)
+}
+
+/// Set the sequence number and tag owner bit of a serialized MCTP over SMBus packet and recompute its PEC
+///
+/// The packet must start with the destination address and end with the PEC.
+pub fn finalize_smbus_packet(packet: &mut [u8], sequence: u8, tag_owner: bool) -> Option<()> {
+    let (pec, data) = packet.split_last_mut()?;
+    let flags = data.get_mut(SMBUS_MCTP_FLAGS_OFFSET)?;
+    *flags = (*flags & !(MCTP_SEQ_MASK | MCTP_TAG_OWNER)) | ((sequence << MCTP_SEQ_SHIFT) & MCTP_SEQ_MASK);
+    if tag_owner {
+        *flags |= MCTP_TAG_OWNER;
+    }
+    *pec = smbus_pec(data);
+    Some(())
+}
+
 // 5 bits total
 #[derive(num_enum::IntoPrimitive, num_enum::TryFromPrimitive, Debug, PartialEq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -185,6 +226,7 @@ pub enum OdpService {
     Battery = 0x01,
     Thermal = 0x02,
     Debug = 0x03,
+    Power = 0x04,
 }
 
 // 10 bits total
@@ -236,7 +278,17 @@ pub enum OdpCommandCode {
     ThermalGetVarResponse = 0x34,
     ThermalSetVarResponse = 0x35,
     DebugGetMsgsRequest = 0x40,
+    DebugDumpPdJournalRequest = 0x41,
     DebugGetMsgsResponse = 0x50,
+    DebugDumpPdJournalResponse = 0x51,
+    // Battery commands beyond ACPI, the battery block above is full
+    BatterySetChargeLimitRequest = 0x60,
+    BatterySetChargeLimitResponse = 0x70,
+    // Power policy commands
+    PowerGetPolicyRequest = 0x61,
+    PowerSetSystemPowerStateRequest = 0x62,
+    PowerGetPolicyResponse = 0x71,
+    PowerSetSystemPowerStateResponse = 0x72,
 }
 
 // 3 byte header
@@ -399,6 +451,146 @@ impl<const MODEL_SIZE: usize, const SERIAL_SIZE: usize, const OEM_SIZE: usize>
     }
 }
 
+/// Maximum number of providers in a power policy response
+pub const POWER_POLICY_MAX_PROVIDERS: usize = 4;
+/// Maximum number of chargers in a power policy response
+pub const POWER_POLICY_MAX_CHARGERS: usize = 4;
+
+/// Provider entry of a power policy response
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerPolicyProvider {
+    /// Power policy device ID
+    pub device_id: u8,
+    /// Contract voltage in mV
+    pub voltage_mv: u16,
+    /// Contract current in mA
+    pub current_ma: u16,
+}
+
+/// Charger entry of a power policy response
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerPolicyCharger {
+    /// Charger ID
+    pub charger_id: u8,
+    /// Charger state
+    pub state: u8,
+    /// Voltage of the consumer the charger is attached to in mV, zero if none
+    pub voltage_mv: u16,
+    /// Current of the consumer the charger is attached to in mA, zero if none
+    pub current_ma: u16,
+}
+
+/// Power policy state reported to the host
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerPolicyState {
+    /// Consumer device ID, all ones if there is no consumer
+    pub consumer_id: Dword,
+    /// Consumer contract voltage in mV
+    pub consumer_voltage_mv: Dword,
+    /// Consumer contract current in mA
+    pub consumer_current_ma: Dword,
+    /// Input power budgeted for providers
+    pub providers_mw: Dword,
+    /// Input power budgeted for the chargers
+    pub charger_mw: Dword,
+    /// Power policy flags
+    pub flags: Dword,
+    /// Boot power sequencing state
+    pub boot_state: Dword,
+    /// Number of valid entries in `providers`
+    pub provider_count: u8,
+    /// Connected providers
+    pub providers: [PowerPolicyProvider; POWER_POLICY_MAX_PROVIDERS],
+    /// Number of valid entries in `chargers`
+    pub charger_count: u8,
+    /// Registered chargers
+    pub chargers: [PowerPolicyCharger; POWER_POLICY_MAX_CHARGERS],
+}
+
+impl PowerPolicyState {
+    const PROVIDER_SIZE: usize = 5;
+    const CHARGER_SIZE: usize = 6;
+    const PROVIDERS_START_IDX: usize = 29;
+    const CHARGERS_START_IDX: usize = Self::PROVIDERS_START_IDX + POWER_POLICY_MAX_PROVIDERS * Self::PROVIDER_SIZE + 1;
+    /// Size of the serialized state
+    pub const SIZE: usize = Self::CHARGERS_START_IDX + POWER_POLICY_MAX_CHARGERS * Self::CHARGER_SIZE;
+
+    pub fn to_bytes(self, dst_slice: &mut [u8]) -> Result<usize, OdpSerializeErr> {
+        if dst_slice.len() < Self::SIZE {
+            return Err(OdpSerializeErr::InputSliceTooSmall);
+        }
+
+        dst_slice[..4].copy_from_slice(&u32::to_le_bytes(self.consumer_id));
+        dst_slice[4..8].copy_from_slice(&u32::to_le_bytes(self.consumer_voltage_mv));
+        dst_slice[8..12].copy_from_slice(&u32::to_le_bytes(self.consumer_current_ma));
+        dst_slice[12..16].copy_from_slice(&u32::to_le_bytes(self.providers_mw));
+        dst_slice[16..20].copy_from_slice(&u32::to_le_bytes(self.charger_mw));
+        dst_slice[20..24].copy_from_slice(&u32::to_le_bytes(self.flags));
+        dst_slice[24..28].copy_from_slice(&u32::to_le_bytes(self.boot_state));
+        dst_slice[28] = self.provider_count;
+        for (i, provider) in self.providers.iter().enumerate() {
+            let start = Self::PROVIDERS_START_IDX + i * Self::PROVIDER_SIZE;
+            dst_slice[start] = provider.device_id;
+            dst_slice[start + 1..start + 3].copy_from_slice(&u16::to_le_bytes(provider.voltage_mv));
+            dst_slice[start + 3..start + 5].copy_from_slice(&u16::to_le_bytes(provider.current_ma));
+        }
+        dst_slice[Self::CHARGERS_START_IDX - 1] = self.charger_count;
+        for (i, charger) in self.chargers.iter().enumerate() {
+            let start = Self::CHARGERS_START_IDX + i * Self::CHARGER_SIZE;
+            dst_slice[start] = charger.charger_id;
+            dst_slice[start + 1] = charger.state;
+            dst_slice[start + 2..start + 4].copy_from_slice(&u16::to_le_bytes(charger.voltage_mv));
+            dst_slice[start + 4..start + 6].copy_from_slice(&u16::to_le_bytes(charger.current_ma));
+        }
+        Ok(Self::SIZE)
+    }
+
+    fn from_bytes<M: MctpMedium>(buffer: &[u8]) -> MctpPacketResult<Self, M> {
+        let provider_count = safe_get_u8(buffer, 28)?;
+        let charger_count = safe_get_u8(buffer, Self::CHARGERS_START_IDX - 1)?;
+        if provider_count as usize > POWER_POLICY_MAX_PROVIDERS || charger_count as usize > POWER_POLICY_MAX_CHARGERS {
+            return Err(MctpPacketError::HeaderParseError("too many power policy entries"));
+        }
+
+        let mut providers = [PowerPolicyProvider::default(); POWER_POLICY_MAX_PROVIDERS];
+        for (i, provider) in providers.iter_mut().enumerate() {
+            let start = Self::PROVIDERS_START_IDX + i * Self::PROVIDER_SIZE;
+            *provider = PowerPolicyProvider {
+                device_id: safe_get_u8(buffer, start)?,
+                voltage_mv: safe_get_u16(buffer, start + 1)?,
+                current_ma: safe_get_u16(buffer, start + 3)?,
+            };
+        }
+        let mut chargers = [PowerPolicyCharger::default(); POWER_POLICY_MAX_CHARGERS];
+        for (i, charger) in chargers.iter_mut().enumerate() {
+            let start = Self::CHARGERS_START_IDX + i * Self::CHARGER_SIZE;
+            *charger = PowerPolicyCharger {
+                charger_id: safe_get_u8(buffer, start)?,
+                state: safe_get_u8(buffer, start + 1)?,
+                voltage_mv: safe_get_u16(buffer, start + 2)?,
+                current_ma: safe_get_u16(buffer, start + 4)?,
+            };
+        }
+
+        Ok(Self {
+            consumer_id: safe_get_dword(buffer, 0)?,
+            consumer_voltage_mv: safe_get_dword(buffer, 4)?,
+            consumer_current_ma: safe_get_dword(buffer, 8)?,
+            providers_mw: safe_get_dword(buffer, 12)?,
+            charger_mw: safe_get_dword(buffer, 16)?,
+            flags: safe_get_dword(buffer, 20)?,
+            boot_state: safe_get_dword(buffer, 24)?,
+            provider_count,
+            providers,
+            charger_count,
+            chargers,
+        })
+    }
+}
+
 /// Standard 32-bit DWORD
 pub type Dword = u32;
 
@@ -478,6 +670,13 @@ pub enum Odp<
     BatteryGetStaRequest {
         battery_id: u8,
     },
+    BatterySetChargeLimitRequest {
+        battery_id: u8,
+        mode: Dword,
+        upper_pct: Dword,
+        lower_pct: Dword,
+        full_charge_delay_min: Dword,
+    },
     BatteryGetBixResponse {
         bix: BixFixedStrings<BIX_MODEL_SIZE, BIX_SERIAL_SIZE, BIX_BATTERY_SIZE, BIX_OEM_SIZE>,
     },
@@ -517,6 +716,9 @@ pub enum Odp<
     BatteryGetStaResponse {
         sta: embedded_batteries_async::acpi::StaReturn,
     },
+    BatterySetChargeLimitResponse {
+        status: Dword,
+    },
 
     ThermalGetTmpRequest {
         instance_id: u8,
@@ -548,6 +750,11 @@ pub enum Odp<
         set_var: Dword,
     },
     DebugGetMsgsRequest,
+    DebugDumpPdJournalRequest,
+    PowerGetPolicyRequest,
+    PowerSetSystemPowerStateRequest {
+        state: Dword,
+    },
 
     ThermalGetTmpResponse {
         temperature: DeciKelvin,
@@ -574,6 +781,13 @@ pub enum Odp<
     DebugGetMsgsResponse {
         debug_buf: [u8; DEBUG_BUF_SIZE],
     },
+    DebugDumpPdJournalResponse,
+    PowerGetPolicyResponse {
+        policy: PowerPolicyState,
+    },
+    PowerSetSystemPowerStateResponse {
+        status: Dword,
+    },
     ErrorResponse {},
 }
 
@@ -641,7 +855,7 @@ impl<
         DEBUG_BUF_SIZE,
     >
 {
-    const MESSAGE_TYPE: u8 = 0x7D;
+    const MESSAGE_TYPE: u8 = ODP_MESSAGE_TYPE;
     type Header = OdpHeader;
 
     fn serialize<M: MctpMedium>(self, buffer: &mut [u8]) -> MctpPacketResult<usize, M> {
@@ -702,6 +916,21 @@ impl<
                 Ok(5)
             }
             Self::BatteryGetStaRequest { battery_id } => write_to_buffer(buffer, [battery_id]),
+            Self::BatterySetChargeLimitRequest {
+                battery_id,
+                mode,
+                upper_pct,
+                lower_pct,
+                full_charge_delay_min,
+            } => {
+                buffer[0] = battery_id;
+                buffer[1..5].copy_from_slice(&u32::to_le_bytes(mode));
+                buffer[5..9].copy_from_slice(&u32::to_le_bytes(upper_pct));
+                buffer[9..13].copy_from_slice(&u32::to_le_bytes(lower_pct));
+                buffer[13..17].copy_from_slice(&u32::to_le_bytes(full_charge_delay_min));
+
+                Ok(17)
+            }
             Self::ThermalGetTmpRequest { instance_id } => write_to_buffer(buffer, [instance_id]),
             Self::ThermalSetThrsRequest {
                 instance_id,
@@ -755,6 +984,13 @@ impl<
                 Ok(23)
             }
             Self::DebugGetMsgsRequest => Ok(0),
+            Self::DebugDumpPdJournalRequest => Ok(0),
+            Self::PowerGetPolicyRequest => Ok(0),
+            Self::PowerSetSystemPowerStateRequest { state } => {
+                buffer[..4].copy_from_slice(&u32::to_le_bytes(state));
+
+                Ok(4)
+            }
             Self::BatteryGetBixResponse { bix } => bix
                 .to_bytes(buffer)
                 .map(|_| 100)
@@ -830,6 +1066,11 @@ impl<
 
                 Ok(STA_RETURN_SIZE_BYTES)
             }
+            Self::BatterySetChargeLimitResponse { status } => {
+                buffer[..4].copy_from_slice(&u32::to_le_bytes(status));
+
+                Ok(4)
+            }
             Self::ThermalGetTmpResponse { temperature } => {
                 buffer[..4].copy_from_slice(&u32::to_le_bytes(temperature));
 
@@ -873,6 +1114,15 @@ impl<
                 buffer[..debug_buf.len()].copy_from_slice(&debug_buf);
                 Ok(debug_buf.len())
             }
+            Self::DebugDumpPdJournalResponse => Ok(0),
+            Self::PowerGetPolicyResponse { policy } => policy
+                .to_bytes(buffer)
+                .map_err(|_| mctp_rs::MctpPacketError::SerializeError("buffer too small for odp message")),
+            Self::PowerSetSystemPowerStateResponse { status } => {
+                buffer[..4].copy_from_slice(&u32::to_le_bytes(status));
+
+                Ok(4)
+            }
             Self::ErrorResponse {} => Ok(0),
         }
     }
@@ -956,6 +1206,13 @@ impl<
             OdpCommandCode::BatteryGetStaRequest => Self::BatteryGetStaRequest {
                 battery_id: safe_get_u8(buffer, 0)?,
             },
+            OdpCommandCode::BatterySetChargeLimitRequest => Self::BatterySetChargeLimitRequest {
+                battery_id: safe_get_u8(buffer, 0)?,
+                mode: safe_get_dword(buffer, 1)?,
+                upper_pct: safe_get_dword(buffer, 5)?,
+                lower_pct: safe_get_dword(buffer, 9)?,
+                full_charge_delay_min: safe_get_dword(buffer, 13)?,
+            },
             OdpCommandCode::ThermalGetTmpRequest => Self::ThermalGetTmpRequest {
                 instance_id: safe_get_u8(buffer, 0)?,
             },
@@ -986,6 +1243,11 @@ impl<
                 set_var: safe_get_dword(buffer, 19)?,
             },
             OdpCommandCode::DebugGetMsgsRequest => Self::DebugGetMsgsRequest,
+            OdpCommandCode::DebugDumpPdJournalRequest => Self::DebugDumpPdJournalRequest,
+            OdpCommandCode::PowerGetPolicyRequest => Self::PowerGetPolicyRequest,
+            OdpCommandCode::PowerSetSystemPowerStateRequest => Self::PowerSetSystemPowerStateRequest {
+                state: safe_get_dword(buffer, 0)?,
+            },
             OdpCommandCode::BatteryGetBixResponse => Self::BatteryGetBixResponse {
                 bix: BixFixedStrings {
                     revision: safe_get_dword(buffer, 0)?,
@@ -1117,6 +1379,9 @@ impl<
             OdpCommandCode::BatteryGetStaResponse => Self::BatteryGetStaResponse {
                 sta: embedded_batteries_async::acpi::StaReturn::from_bits_retain(safe_get_dword(buffer, 0)?),
             },
+            OdpCommandCode::BatterySetChargeLimitResponse => Self::BatterySetChargeLimitResponse {
+                status: safe_get_dword(buffer, 0)?,
+            },
             OdpCommandCode::ThermalGetTmpResponse => Self::ThermalGetTmpResponse {
                 temperature: safe_get_dword(buffer, 0)?,
             },
@@ -1144,10 +1409,339 @@ impl<
                     .try_into()
                     .map_err(|_| MctpPacketError::HeaderParseError("MCTP buf not large enough"))?,
             },
+            OdpCommandCode::DebugDumpPdJournalResponse => Self::DebugDumpPdJournalResponse,
+            OdpCommandCode::PowerGetPolicyResponse => Self::PowerGetPolicyResponse {
+                policy: PowerPolicyState::from_bytes(buffer)?,
+            },
+            OdpCommandCode::PowerSetSystemPowerStateResponse => Self::PowerSetSystemPowerStateResponse {
+                status: safe_get_dword(buffer, 0)?,
+            },
         })
     }
 }
 
+/// MCTP control message type
+pub const CONTROL_MESSAGE_TYPE: u8 = 0x00;
+/// ODP vendor message type
+pub const ODP_MESSAGE_TYPE: u8 = 0x7D;
+/// Message type used by Get MCTP Version Support to query the base specification version
+pub const BASE_SPEC_MESSAGE_TYPE: u8 = 0xFF;
+/// MCTP base specification version supported, 1.3.1
+pub const MCTP_BASE_VERSION: u32 = 0xF1F3_F100;
+/// ODP message version supported, 1.0.0
+pub const ODP_VERSION: u32 = 0xF1F0_F000;
+/// Null endpoint ID, used before an endpoint ID is assigned
+pub const NULL_EID: u8 = 0x00;
+/// Broadcast endpoint ID
+pub const BROADCAST_EID: u8 = 0xFF;
+/// Maximum number of entries in a list carried by a control message
+pub const MAX_CONTROL_LIST_LEN: usize = 4;
+
+/// Get Endpoint ID endpoint type: simple endpoint using a dynamic endpoint ID
+pub const SIMPLE_ENDPOINT_DYNAMIC_EID: u8 = 0b0000_0000;
+/// Get MCTP Version Support completion code for an unknown message type
+pub const MESSAGE_TYPE_NOT_SUPPORTED: u8 = 0x80;
+// Set Endpoint ID assignment status
+const SET_EID_STATUS_MASK: u8 = 0b0011_0000;
+const SET_EID_REJECTED: u8 = 0b0001_0000;
+
+// 8 bits total
+#[derive(num_enum::IntoPrimitive, num_enum::FromPrimitive, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum ControlCommandCode {
+    SetEndpointId = 0x01,
+    GetEndpointId = 0x02,
+    GetMctpVersionSupport = 0x04,
+    GetMessageTypeSupport = 0x05,
+    #[num_enum(catch_all)]
+    Unsupported(u8),
+}
+
+// 2 bits total
+#[derive(num_enum::IntoPrimitive, num_enum::TryFromPrimitive, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SetEndpointIdOperation {
+    SetEid = 0b00,
+    ForceEid = 0b01,
+    ResetEid = 0b10,
+    SetDiscoveredFlag = 0b11,
+}
+
+// 2 byte header, responses are followed by the completion code
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ControlHeader {
+    pub request_bit: bool,                   // [15:15] (1 bit)
+    pub datagram_bit: bool,                  // [14:14] (1 bit)
+    pub instance_id: u8,                     // [8:12] (5 bits)
+    pub command_code: ControlCommandCode,    // [0:7] (8 bits)
+    pub completion_code: MctpCompletionCode, // responses only (8 bits)
+}
+
+/// MCTP control messages (DSP0236) supported by the EC
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MctpControl {
+    SetEndpointIdRequest {
+        operation: SetEndpointIdOperation,
+        eid: u8,
+    },
+    SetEndpointIdResponse {
+        accepted: bool,
+        eid: u8,
+        pool_size: u8,
+    },
+    GetEndpointIdRequest,
+    GetEndpointIdResponse {
+        eid: u8,
+        endpoint_type: u8,
+        medium_specific: u8,
+    },
+    GetMctpVersionSupportRequest {
+        message_type: u8,
+    },
+    GetMctpVersionSupportResponse {
+        count: u8,
+        versions: [u32; MAX_CONTROL_LIST_LEN],
+    },
+    GetMessageTypeSupportRequest,
+    GetMessageTypeSupportResponse {
+        count: u8,
+        message_types: [u8; MAX_CONTROL_LIST_LEN],
+    },
+    /// Request for a command the EC doesn't implement
+    UnsupportedRequest,
+    /// Response with a completion code other than success, carries no data
+    ErrorResponse,
+}
+
+impl MctpMessageHeaderTrait for ControlHeader {
+    fn serialize<M: MctpMedium>(self, buffer: &mut [u8]) -> MctpPacketResult<usize, M> {
+        let len = if self.request_bit { 2 } else { 3 };
+        if buffer.len() < len {
+            return Err(MctpPacketError::SerializeError("buffer too small for control header"));
+        }
+
+        buffer[0] = (self.request_bit as u8) << 7 | (self.datagram_bit as u8) << 6 | (self.instance_id & 0b0001_1111);
+        buffer[1] = self.command_code.into();
+        if !self.request_bit {
+            buffer[2] = self.completion_code.into();
+        }
+        Ok(len)
+    }
+
+    fn deserialize<M: MctpMedium>(buffer: &[u8]) -> MctpPacketResult<(Self, &[u8]), M> {
+        if buffer.len() < 2 {
+            return Err(MctpPacketError::HeaderParseError("buffer too small for control header"));
+        }
+        let request_bit = buffer[0] & 0b1000_0000 != 0;
+        let datagram_bit = buffer[0] & 0b0100_0000 != 0;
+        let instance_id = buffer[0] & 0b0001_1111;
+        let command_code = buffer[1].into();
+
+        let (completion_code, rest) = if request_bit {
+            (MctpCompletionCode::Success, &buffer[2..])
+        } else {
+            let completion_code = buffer
+                .get(2)
+                .ok_or(MctpPacketError::HeaderParseError("buffer too small for control header"))?;
+            let completion_code = (*completion_code)
+                .try_into()
+                .map_err(|_| MctpPacketError::HeaderParseError("invalid completion code"))?;
+            (completion_code, &buffer[3..])
+        };
+
+        Ok((
+            ControlHeader {
+                request_bit,
+                datagram_bit,
+                instance_id,
+                command_code,
+                completion_code,
+            },
+            rest,
+        ))
+    }
+}
+
+impl MctpMessageTrait<'_> for MctpControl {
+    const MESSAGE_TYPE: u8 = CONTROL_MESSAGE_TYPE;
+    type Header = ControlHeader;
+
+    fn serialize<M: MctpMedium>(self, buffer: &mut [u8]) -> MctpPacketResult<usize, M> {
+        match self {
+            Self::SetEndpointIdRequest { operation, eid } => write_to_buffer(buffer, [operation.into(), eid]),
+            Self::SetEndpointIdResponse {
+                accepted,
+                eid,
+                pool_size,
+            } => {
+                let status = if accepted { 0 } else { SET_EID_REJECTED };
+                write_to_buffer(buffer, [status, eid, pool_size])
+            }
+            Self::GetEndpointIdResponse {
+                eid,
+                endpoint_type,
+                medium_specific,
+            } => write_to_buffer(buffer, [eid, endpoint_type, medium_specific]),
+            Self::GetMctpVersionSupportRequest { message_type } => write_to_buffer(buffer, [message_type]),
+            Self::GetMctpVersionSupportResponse { count, versions } => {
+                let versions = versions
+                    .get(..count as usize)
+                    .ok_or(MctpPacketError::SerializeError("too many control list entries"))?;
+                let len = 1 + versions.len() * 4;
+                if buffer.len() < len {
+                    return Err(MctpPacketError::SerializeError("buffer too small for control message"));
+                }
+
+                buffer[0] = count;
+                // Version entries are big endian
+                for (entry, version) in buffer[1..len].chunks_exact_mut(4).zip(versions) {
+                    entry.copy_from_slice(&version.to_be_bytes());
+                }
+                Ok(len)
+            }
+            Self::GetMessageTypeSupportResponse { count, message_types } => {
+                let message_types = message_types
+                    .get(..count as usize)
+                    .ok_or(MctpPacketError::SerializeError("too many control list entries"))?;
+                let len = 1 + message_types.len();
+                if buffer.len() < len {
+                    return Err(MctpPacketError::SerializeError("buffer too small for control message"));
+                }
+
+                buffer[0] = count;
+                buffer[1..len].copy_from_slice(message_types);
+                Ok(len)
+            }
+            Self::GetEndpointIdRequest
+            | Self::GetMessageTypeSupportRequest
+            | Self::UnsupportedRequest
+            | Self::ErrorResponse => Ok(0),
+        }
+    }
+
+    fn deserialize<M: MctpMedium>(header: &Self::Header, buffer: &'_ [u8]) -> MctpPacketResult<Self, M> {
+        if !header.request_bit && header.completion_code != MctpCompletionCode::Success {
+            return Ok(Self::ErrorResponse);
+        }
+
+        Ok(match (header.command_code, header.request_bit) {
+            (ControlCommandCode::SetEndpointId, true) => Self::SetEndpointIdRequest {
+                operation: (safe_get_u8(buffer, 0)? & 0b0000_0011)
+                    .try_into()
+                    .map_err(|_| MctpPacketError::HeaderParseError("invalid set endpoint id operation"))?,
+                eid: safe_get_u8(buffer, 1)?,
+            },
+            (ControlCommandCode::SetEndpointId, false) => Self::SetEndpointIdResponse {
+                accepted: safe_get_u8(buffer, 0)? & SET_EID_STATUS_MASK == 0,
+                eid: safe_get_u8(buffer, 1)?,
+                pool_size: safe_get_u8(buffer, 2)?,
+            },
+            (ControlCommandCode::GetEndpointId, true) => Self::GetEndpointIdRequest,
+            (ControlCommandCode::GetEndpointId, false) => Self::GetEndpointIdResponse {
+                eid: safe_get_u8(buffer, 0)?,
+                endpoint_type: safe_get_u8(buffer, 1)?,
+                medium_specific: safe_get_u8(buffer, 2)?,
+            },
+            (ControlCommandCode::GetMctpVersionSupport, true) => Self::GetMctpVersionSupportRequest {
+                message_type: safe_get_u8(buffer, 0)?,
+            },
+            (ControlCommandCode::GetMctpVersionSupport, false) => {
+                let count = safe_get_control_list_len(buffer)?;
+                let mut versions = [0; MAX_CONTROL_LIST_LEN];
+                for (i, version) in versions.iter_mut().take(count as usize).enumerate() {
+                    // Version entries are big endian
+                    *version = safe_get_dword(buffer, 1 + 4 * i)?.swap_bytes();
+                }
+                Self::GetMctpVersionSupportResponse { count, versions }
+            }
+            (ControlCommandCode::GetMessageTypeSupport, true) => Self::GetMessageTypeSupportRequest,
+            (ControlCommandCode::GetMessageTypeSupport, false) => {
+                let count = safe_get_control_list_len(buffer)?;
+                let mut message_types = [0; MAX_CONTROL_LIST_LEN];
+                for (i, message_type) in message_types.iter_mut().take(count as usize).enumerate() {
+                    *message_type = safe_get_u8(buffer, 1 + i)?;
+                }
+                Self::GetMessageTypeSupportResponse { count, message_types }
+            }
+            (ControlCommandCode::Unsupported(_), true) => Self::UnsupportedRequest,
+            (ControlCommandCode::Unsupported(_), false) => {
+                return Err(MctpPacketError::HeaderParseError("unsupported control command"));
+            }
+        })
+    }
+}
+
+/// Build the response to an MCTP control request.
+/// `eid` is the current endpoint ID of the EC, Set Endpoint ID updates it.
+pub fn control_response(header: &ControlHeader, request: &MctpControl, eid: &mut u8) -> (ControlHeader, MctpControl) {
+    let mut completion_code = MctpCompletionCode::Success;
+    let response = match *request {
+        MctpControl::SetEndpointIdRequest {
+            operation: SetEndpointIdOperation::SetEid | SetEndpointIdOperation::ForceEid,
+            eid: new_eid,
+        } if new_eid != NULL_EID && new_eid != BROADCAST_EID => {
+            *eid = new_eid;
+            MctpControl::SetEndpointIdResponse {
+                accepted: true,
+                eid: new_eid,
+                pool_size: 0,
+            }
+        }
+        // Reserved endpoint IDs, there is also no static endpoint ID to reset to and the discovered flag is PCIe only
+        MctpControl::SetEndpointIdRequest { .. } => {
+            completion_code = MctpCompletionCode::ErrorInvalidData;
+            MctpControl::ErrorResponse
+        }
+        MctpControl::GetEndpointIdRequest => MctpControl::GetEndpointIdResponse {
+            eid: *eid,
+            endpoint_type: SIMPLE_ENDPOINT_DYNAMIC_EID,
+            medium_specific: 0,
+        },
+        MctpControl::GetMctpVersionSupportRequest { message_type } => {
+            let version = match message_type {
+                CONTROL_MESSAGE_TYPE | BASE_SPEC_MESSAGE_TYPE => Some(MCTP_BASE_VERSION),
+                ODP_MESSAGE_TYPE => Some(ODP_VERSION),
+                _ => None,
+            };
+
+            if let Some(version) = version {
+                let mut versions = [0; MAX_CONTROL_LIST_LEN];
+                versions[0] = version;
+                MctpControl::GetMctpVersionSupportResponse { count: 1, versions }
+            } else {
+                completion_code = MctpCompletionCode::CommandSpecific(MESSAGE_TYPE_NOT_SUPPORTED);
+                MctpControl::ErrorResponse
+            }
+        }
+        MctpControl::GetMessageTypeSupportRequest => {
+            // The control message type is implied and not part of the list
+            let mut message_types = [0; MAX_CONTROL_LIST_LEN];
+            message_types[0] = ODP_MESSAGE_TYPE;
+            MctpControl::GetMessageTypeSupportResponse {
+                count: 1,
+                message_types,
+            }
+        }
+        _ => {
+            completion_code = MctpCompletionCode::ErrorUnsupportedCmd;
+            MctpControl::ErrorResponse
+        }
+    };
+
+    (
+        ControlHeader {
+            request_bit: false,
+            completion_code,
+            ..*header
+        },
+        response,
+    )
+}
+
 fn safe_get_u8<M: MctpMedium>(buffer: &[u8], index: usize) -> MctpPacketResult<u8, M> {
     if buffer.len() < index + 1 {
         return Err(MctpPacketError::HeaderParseError("buffer too small for odp message"));
@@ -1179,6 +1773,14 @@ fn safe_get_uuid<M: MctpMedium>(buffer: &[u8], index: usize) -> MctpPacketResult
     Ok(buffer[index..index + 16].try_into().unwrap())
 }
 
+fn safe_get_control_list_len<M: MctpMedium>(buffer: &[u8]) -> MctpPacketResult<u8, M> {
+    let count = safe_get_u8(buffer, 0)?;
+    if count as usize > MAX_CONTROL_LIST_LEN {
+        return Err(MctpPacketError::HeaderParseError("too many control list entries"));
+    }
+    Ok(count)
+}
+
 fn write_to_buffer<M: MctpMedium, const N: usize>(buffer: &mut [u8], data: [u8; N]) -> MctpPacketResult<usize, M> {
     if buffer.len() < N {
         return Err(MctpPacketError::SerializeError("buffer too small for odp message"));
@@ -1298,6 +1900,128 @@ mod tests {
 
     use test_util::TestMedium;
 
+    #[test]
+    fn smbus_pec_check_value() {
+        assert_eq!(smbus_pec(b"123456789"), 0xF4);
+        assert_eq!(smbus_pec(&[]), 0);
+    }
+
+    #[test]
+    fn smbus_packet_finalize() {
+        // Destination, command code, byte count, source, header version, EIDs, flags with tag 5 and PEC
+        let mut packet = [0x02, 0x0F, 0x05, 0x03, 0x01, 0x08, 0x80, 0b1111_1101, 0x00];
+        assert_eq!(smbus_message_tag(&packet), Some(5));
+
+        assert_eq!(finalize_smbus_packet(&mut packet, 2, false), Some(()));
+        assert_eq!(packet[SMBUS_MCTP_FLAGS_OFFSET], 0b1110_0101);
+        assert_eq!(packet[8], smbus_pec(&packet[..8]));
+
+        assert_eq!(finalize_smbus_packet(&mut packet, 5, true), Some(()));
+        assert_eq!(
+            packet[SMBUS_MCTP_FLAGS_OFFSET] & (MCTP_SEQ_MASK | MCTP_TAG_OWNER),
+            0b0001_1000
+        );
+        assert_eq!(smbus_message_tag(&packet), Some(5));
+
+        assert_eq!(finalize_smbus_packet(&mut packet[..4], 0, false), None);
+    }
+
+    type TestOdp = Odp<8, 8, 8, 8, 8, 8, 8, 128>;
+
+    fn odp_header(command_code: OdpCommandCode) -> OdpHeader {
+        OdpHeader {
+            request_bit: false,
+            datagram_bit: false,
+            service: OdpService::Power,
+            command_code,
+            completion_code: MctpCompletionCode::Success,
+        }
+    }
+
+    #[test]
+    fn power_policy_request_roundtrip() {
+        let header = odp_header(OdpCommandCode::PowerGetPolicyRequest);
+        let mut buf = [0u8; 3];
+        header.serialize::<TestMedium>(&mut buf).unwrap();
+        assert_eq!(buf, [0b0001_0000, 0x61, 0]);
+
+        let size = TestOdp::PowerGetPolicyRequest
+            .serialize::<TestMedium>(&mut buf)
+            .unwrap();
+        assert_eq!(size, 0);
+        assert!(TestOdp::deserialize::<TestMedium>(&header, &[]).unwrap() == TestOdp::PowerGetPolicyRequest);
+    }
+
+    #[test]
+    fn power_system_power_state_request_roundtrip() {
+        let header = odp_header(OdpCommandCode::PowerSetSystemPowerStateRequest);
+        let request = TestOdp::PowerSetSystemPowerStateRequest { state: 3 };
+        let mut buf = [0u8; 4];
+        let size = request.serialize::<TestMedium>(&mut buf).unwrap();
+        assert_eq!(size, 4);
+        assert_eq!(buf, [3, 0, 0, 0]);
+        assert!(TestOdp::deserialize::<TestMedium>(&header, &buf).unwrap() == request);
+    }
+
+    #[test]
+    fn power_policy_response_roundtrip() {
+        let mut policy = PowerPolicyState {
+            consumer_id: 1,
+            consumer_voltage_mv: 20000,
+            consumer_current_ma: 3250,
+            providers_mw: 7500,
+            charger_mw: 45000,
+            flags: 0b10,
+            boot_state: 4,
+            provider_count: 1,
+            charger_count: 1,
+            ..Default::default()
+        };
+        policy.providers[0] = PowerPolicyProvider {
+            device_id: 2,
+            voltage_mv: 5000,
+            current_ma: 1500,
+        };
+        policy.chargers[0] = PowerPolicyCharger {
+            charger_id: 0,
+            state: 2,
+            voltage_mv: 20000,
+            current_ma: 3250,
+        };
+
+        let header = odp_header(OdpCommandCode::PowerGetPolicyResponse);
+        let mut header_buf = [0u8; 3];
+        header.serialize::<TestMedium>(&mut header_buf).unwrap();
+        assert_eq!(header_buf, [0b0001_0000, 0x71, 0]);
+
+        let mut buf = [0u8; PowerPolicyState::SIZE];
+        let size = TestOdp::PowerGetPolicyResponse { policy }
+            .serialize::<TestMedium>(&mut buf)
+            .unwrap();
+        assert_eq!(size, PowerPolicyState::SIZE);
+        assert_eq!(buf[..4], 1u32.to_le_bytes());
+        assert_eq!(buf[28], 1);
+        assert_eq!(buf[29..34], [2, 0x88, 0x13, 0xDC, 0x05]);
+        assert_eq!(buf[49], 1);
+        assert_eq!(buf[50..56], [0, 2, 0x20, 0x4E, 0xB2, 0x0C]);
+
+        let parsed = TestOdp::deserialize::<TestMedium>(&header, &buf).unwrap();
+        assert!(parsed == TestOdp::PowerGetPolicyResponse { policy });
+
+        // Truncated responses and too many entries are rejected
+        assert!(TestOdp::deserialize::<TestMedium>(&header, &buf[..size - 1]).is_err());
+        buf[28] = POWER_POLICY_MAX_PROVIDERS as u8 + 1;
+        assert!(TestOdp::deserialize::<TestMedium>(&header, &buf).is_err());
+
+        // Serializing into a buffer that's too small fails
+        let mut small = [0u8; PowerPolicyState::SIZE - 1];
+        assert!(
+            TestOdp::PowerGetPolicyResponse { policy }
+                .serialize::<TestMedium>(&mut small)
+                .is_err()
+        );
+    }
+
     #[rstest::rstest]
     #[case(OdpHeader {
         request_bit: true,
@@ -1364,4 +2088,176 @@ mod tests {
             other => panic!("unexpected error: {:?}", other),
         }
     }
+
+    #[rstest::rstest]
+    #[case(ControlHeader {
+        request_bit: true,
+        datagram_bit: false,
+        instance_id: 0x1F,
+        command_code: ControlCommandCode::SetEndpointId,
+        completion_code: MctpCompletionCode::Success,
+    }, 2)]
+    #[case(ControlHeader {
+        request_bit: false,
+        datagram_bit: false,
+        instance_id: 3,
+        command_code: ControlCommandCode::Unsupported(0x7F),
+        completion_code: MctpCompletionCode::ErrorUnsupportedCmd,
+    }, 3)]
+    fn control_header_roundtrip(#[case] header: ControlHeader, #[case] len: usize) {
+        let mut buf = [0u8; 3];
+        let size = header.serialize::<TestMedium>(&mut buf).unwrap();
+        assert_eq!(size, len);
+
+        let (parsed, rest) = ControlHeader::deserialize::<TestMedium>(&buf[..size]).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(rest.len(), 0);
+    }
+
+    #[test]
+    fn control_version_support_roundtrip() {
+        let header = ControlHeader {
+            request_bit: false,
+            datagram_bit: false,
+            instance_id: 0,
+            command_code: ControlCommandCode::GetMctpVersionSupport,
+            completion_code: MctpCompletionCode::Success,
+        };
+        let message = MctpControl::GetMctpVersionSupportResponse {
+            count: 2,
+            versions: [MCTP_BASE_VERSION, ODP_VERSION, 0, 0],
+        };
+
+        let mut buf = [0u8; 16];
+        let size = message.serialize::<TestMedium>(&mut buf).unwrap();
+        assert_eq!(buf[..size], [2, 0xF1, 0xF3, 0xF1, 0x00, 0xF1, 0xF0, 0xF0, 0x00]);
+        assert_eq!(
+            MctpControl::deserialize::<TestMedium>(&header, &buf[..size]).unwrap(),
+            message
+        );
+
+        // Lists longer than supported are rejected
+        buf[0] = MAX_CONTROL_LIST_LEN as u8 + 1;
+        assert!(MctpControl::deserialize::<TestMedium>(&header, &buf).is_err());
+    }
+
+    #[test]
+    fn control_response_endpoint_id() {
+        let mut eid = 0x80;
+        let header = ControlHeader {
+            request_bit: true,
+            datagram_bit: false,
+            instance_id: 5,
+            command_code: ControlCommandCode::SetEndpointId,
+            completion_code: MctpCompletionCode::Success,
+        };
+
+        // Assignment is accepted and used from then on
+        let (response_header, response) = control_response(
+            &header,
+            &MctpControl::SetEndpointIdRequest {
+                operation: SetEndpointIdOperation::SetEid,
+                eid: 0x42,
+            },
+            &mut eid,
+        );
+        assert!(!response_header.request_bit);
+        assert_eq!(response_header.instance_id, 5);
+        assert_eq!(response_header.completion_code, MctpCompletionCode::Success);
+        assert_eq!(
+            response,
+            MctpControl::SetEndpointIdResponse {
+                accepted: true,
+                eid: 0x42,
+                pool_size: 0
+            }
+        );
+        assert_eq!(eid, 0x42);
+
+        let header = ControlHeader {
+            command_code: ControlCommandCode::GetEndpointId,
+            ..header
+        };
+        let (_, response) = control_response(&header, &MctpControl::GetEndpointIdRequest, &mut eid);
+        assert_eq!(
+            response,
+            MctpControl::GetEndpointIdResponse {
+                eid: 0x42,
+                endpoint_type: SIMPLE_ENDPOINT_DYNAMIC_EID,
+                medium_specific: 0
+            }
+        );
+
+        // Reserved endpoint IDs are rejected
+        let header = ControlHeader {
+            command_code: ControlCommandCode::SetEndpointId,
+            ..header
+        };
+        for reserved in [NULL_EID, BROADCAST_EID] {
+            let (response_header, response) = control_response(
+                &header,
+                &MctpControl::SetEndpointIdRequest {
+                    operation: SetEndpointIdOperation::ForceEid,
+                    eid: reserved,
+                },
+                &mut eid,
+            );
+            assert_eq!(response_header.completion_code, MctpCompletionCode::ErrorInvalidData);
+            assert_eq!(response, MctpControl::ErrorResponse);
+        }
+        assert_eq!(eid, 0x42);
+    }
+
+    #[rstest::rstest]
+    #[case(
+        ControlCommandCode::GetMctpVersionSupport,
+        MctpControl::GetMctpVersionSupportRequest { message_type: BASE_SPEC_MESSAGE_TYPE },
+        MctpCompletionCode::Success,
+        MctpControl::GetMctpVersionSupportResponse { count: 1, versions: [MCTP_BASE_VERSION, 0, 0, 0] }
+    )]
+    #[case(
+        ControlCommandCode::GetMctpVersionSupport,
+        MctpControl::GetMctpVersionSupportRequest { message_type: ODP_MESSAGE_TYPE },
+        MctpCompletionCode::Success,
+        MctpControl::GetMctpVersionSupportResponse { count: 1, versions: [ODP_VERSION, 0, 0, 0] }
+    )]
+    #[case(
+        ControlCommandCode::GetMctpVersionSupport,
+        MctpControl::GetMctpVersionSupportRequest { message_type: 0x7E },
+        MctpCompletionCode::CommandSpecific(MESSAGE_TYPE_NOT_SUPPORTED),
+        MctpControl::ErrorResponse
+    )]
+    #[case(
+        ControlCommandCode::GetMessageTypeSupport,
+        MctpControl::GetMessageTypeSupportRequest,
+        MctpCompletionCode::Success,
+        MctpControl::GetMessageTypeSupportResponse { count: 1, message_types: [ODP_MESSAGE_TYPE, 0, 0, 0] }
+    )]
+    #[case(
+        ControlCommandCode::Unsupported(0x0A),
+        MctpControl::UnsupportedRequest,
+        MctpCompletionCode::ErrorUnsupportedCmd,
+        MctpControl::ErrorResponse
+    )]
+    fn control_response_discovery(
+        #[case] command_code: ControlCommandCode,
+        #[case] request: MctpControl,
+        #[case] completion_code: MctpCompletionCode,
+        #[case] expected: MctpControl,
+    ) {
+        let mut eid = 0x80;
+        let header = ControlHeader {
+            request_bit: true,
+            datagram_bit: false,
+            instance_id: 0,
+            command_code,
+            completion_code: MctpCompletionCode::Success,
+        };
+
+        let (response_header, response) = control_response(&header, &request, &mut eid);
+        assert_eq!(response_header.command_code, command_code);
+        assert_eq!(response_header.completion_code, completion_code);
+        assert_eq!(response, expected);
+        assert_eq!(eid, 0x80);
+    }
 }
@@ -13,3 +13,6 @@ pub mod mctp;
 
 /// MTPF (Modern Thermal and Power Framework).
 pub mod mptf;
+
+/// ODP Specific Power Policy Protocol.
+pub mod power;
@@ -0,0 +1,9 @@
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// ODP Specific Power Policy Commands
+pub enum PowerCmd {
+    /// Get the power policy state: consumer, providers, chargers and boot state
+    GetPolicy = 1,
+    /// Notify the EC of a host sleep state transition
+    SetSystemPowerState = 2,
+}
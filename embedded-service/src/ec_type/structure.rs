@@ -1,4 +1,5 @@
 //! EC Internal Data Structures
+use super::layout::{memory_map, memory_section};
 
 #[allow(missing_docs)]
 pub const EC_MEMMAP_VERSION: Version = Version {
@@ -10,7 +11,7 @@ pub const EC_MEMMAP_VERSION: Version = Version {
 
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
@@ -18,114 +19,129 @@ pub struct Version {
     pub res0: u8,
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Capabilities {
-    pub events: u32,
-    pub fw_version: Version,
-    pub secure_state: u8,
-    pub boot_status: u8,
-    pub fan_mask: u8,
-    pub battery_mask: u8,
-    pub temp_mask: u16,
-    pub key_mask: u16,
-    pub debug_mask: u16,
-    pub res0: u16,
+impl Version {
+    /// Returns true if a host asking for memory map version `requested` can use this version
+    ///
+    /// Minor versions only add sections, so older minor versions of the same major version are supported.
+    pub const fn supports(&self, requested: Version) -> bool {
+        self.major == requested.major && requested.minor <= self.minor
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct TimeAlarm {
-    pub events: u32,
-    pub capability: u32,
-    pub year: u16,
-    pub month: u8,
-    pub day: u8,
-    pub hour: u8,
-    pub minute: u8,
-    pub second: u8,
-    pub valid: u8,
-    pub daylight: u8,
-    pub res1: u8,
-    pub milli: u16,
-    pub time_zone: u16,
-    pub res2: u16,
-    pub alarm_status: u32,
-    pub ac_time_val: u32,
-    pub dc_time_val: u32,
+memory_section! {
+    #[allow(missing_docs)]
+    pub struct Capabilities => CapabilitiesMessage {
+        events: u32 => Events,
+        fw_version: Version => FwVersion,
+        secure_state: u8 => SecureState,
+        boot_status: u8 => BootStatus,
+        fan_mask: u8 => FanMask,
+        battery_mask: u8 => BatteryMask,
+        temp_mask: u16 => TempMask,
+        key_mask: u16 => KeyMask,
+        debug_mask: u16 => DebugMask,
+        res0: u16 => Res0,
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Battery {
-    pub events: u32,
-    pub status: u32,
-    pub last_full_charge: u32,
-    pub cycle_count: u32,
-    pub state: u32,
-    pub present_rate: u32,
-    pub remain_cap: u32,
-    pub present_volt: u32,
-    pub psr_state: u32,
-    pub psr_max_out: u32,
-    pub psr_max_in: u32,
-    pub peak_level: u32,
-    pub peak_power: u32,
-    pub sus_level: u32,
-    pub sus_power: u32,
-    pub peak_thres: u32,
-    pub sus_thres: u32,
-    pub trip_thres: u32,
-    pub bmc_data: u32,
-    pub bmd_data: u32,
-    pub bmd_flags: u32,
-    pub bmd_count: u32,
-    pub charge_time: u32,
-    pub run_time: u32,
-    pub sample_time: u32,
+memory_section! {
+    #[allow(missing_docs)]
+    pub struct TimeAlarm => TimeAlarmMessage {
+        events: u32 => Events,
+        capability: u32 => Capability,
+        year: u16 => Year,
+        month: u8 => Month,
+        day: u8 => Day,
+        hour: u8 => Hour,
+        minute: u8 => Minute,
+        second: u8 => Second,
+        valid: u8 => Valid,
+        daylight: u8 => Daylight,
+        res1: u8 => Res1,
+        milli: u16 => Milli,
+        time_zone: u16 => TimeZone,
+        res2: u16 => Res2,
+        alarm_status: u32 => AlarmStatus,
+        ac_time_val: u32 => AcTimeVal,
+        dc_time_val: u32 => DcTimeVal,
+    }
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Thermal {
-    pub events: u32,
-    pub cool_mode: u32,
-    pub dba_limit: u32,
-    pub sonne_limit: u32,
-    pub ma_limit: u32,
-    pub fan1_on_temp: u32,
-    pub fan1_ramp_temp: u32,
-    pub fan1_max_temp: u32,
-    pub fan1_crt_temp: u32,
-    pub fan1_hot_temp: u32,
-    pub fan1_max_rpm: u32,
-    pub fan1_cur_rpm: u32,
-    pub tmp1_val: u32,
-    pub tmp1_timeout: u32,
-    pub tmp1_low: u32,
-    pub tmp1_high: u32,
+memory_section! {
+    #[allow(missing_docs)]
+    pub struct Battery => BatteryMessage {
+        events: u32 => Events,
+        status: u32 => Status,
+        last_full_charge: u32 => LastFullCharge,
+        cycle_count: u32 => CycleCount,
+        state: u32 => State,
+        present_rate: u32 => PresentRate,
+        remain_cap: u32 => RemainCap,
+        present_volt: u32 => PresentVolt,
+        psr_state: u32 => PsrState,
+        psr_max_out: u32 => PsrMaxOut,
+        psr_max_in: u32 => PsrMaxIn,
+        peak_level: u32 => PeakLevel,
+        peak_power: u32 => PeakPower,
+        sus_level: u32 => SusLevel,
+        sus_power: u32 => SusPower,
+        peak_thres: u32 => PeakThres,
+        sus_thres: u32 => SusThres,
+        trip_thres: u32 => TripThres,
+        bmc_data: u32 => BmcData,
+        bmd_data: u32 => BmdData,
+        bmd_flags: u32 => BmdFlags,
+        bmd_count: u32 => BmdCount,
+        charge_time: u32 => ChargeTime,
+        run_time: u32 => RunTime,
+        sample_time: u32 => SampleTime,
+    }
+}
+
+memory_section! {
+    #[allow(missing_docs)]
+    pub struct Thermal => ThermalMessage {
+        events: u32 => Events,
+        cool_mode: u32 => CoolMode,
+        dba_limit: u32 => DbaLimit,
+        sonne_limit: u32 => SonneLimit,
+        ma_limit: u32 => MaLimit,
+        fan1_on_temp: u32 => Fan1OnTemp,
+        fan1_ramp_temp: u32 => Fan1RampTemp,
+        fan1_max_temp: u32 => Fan1MaxTemp,
+        fan1_crt_temp: u32 => Fan1CrtTemp,
+        fan1_hot_temp: u32 => Fan1HotTemp,
+        fan1_max_rpm: u32 => Fan1MaxRpm,
+        fan1_cur_rpm: u32 => Fan1CurRpm,
+        tmp1_val: u32 => Tmp1Val,
+        tmp1_timeout: u32 => Tmp1Timeout,
+        tmp1_low: u32 => Tmp1Low,
+        tmp1_high: u32 => Tmp1High,
+    }
 }
 
+/// Notifications sent to the host and not acknowledged yet
+///
+/// `event` is the offset of the notification to handle next and `service` the ODP service that raised it, `pending` is
+/// the number of unacknowledged notifications. The host acknowledges a notification by writing its offset to `event`.
 #[allow(missing_docs)]
 #[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Notifications {
     pub service: u16,
     pub event: u16,
+    pub pending: u16,
+    pub res0: u16,
 }
 
-#[allow(missing_docs)]
-#[repr(C, packed)]
-#[derive(Clone, Copy, Debug, Default)]
-pub struct ECMemory {
-    pub ver: Version,
-    pub caps: Capabilities,
-    pub notif: Notifications,
-    pub alarm: TimeAlarm,
-    pub batt: Battery,
-    pub therm: Thermal,
+memory_map! {
+    #[allow(missing_docs)]
+    pub struct ECMemory {
+        ver: Version => Version, ReadOnly, since 1;
+        caps: Capabilities => Capabilities(CapabilitiesMessage), ReadOnly, since 1;
+        notif: Notifications => Notifications, ReadWrite, since 1;
+        alarm: TimeAlarm => TimeAlarm(TimeAlarmMessage), ReadWrite, owner TimeAlarm, since 1;
+        batt: Battery => Battery(BatteryMessage), ReadWrite, owner Battery, since 1;
+        therm: Thermal => Thermal(ThermalMessage), ReadWrite, owner Thermal, since 1;
+    }
 }
@@ -0,0 +1,145 @@
+//! Device-agnostic firmware update interface
+//!
+//! Implemented by any component that can receive a new firmware image, e.g. PD controllers and retimers. The update
+//! protocol used by the host is handled separately, see [`crate::cfu::adapter`] for the CFU bridge.
+//!
+//! An update follows the sequence:
+//! 1. [`FwUpdate::validate_offer`] to check if the device can accept an update right now
+//! 2. [`FwUpdate::start_fw_update`] to enter update mode
+//! 3. [`FwUpdate::write_fw_contents`] for each chunk of the image
+//! 4. [`FwUpdate::verify_fw_update`] once all chunks have been written
+//! 5. [`FwUpdate::activate_fw_update`] to switch to the new image, this may reset the device
+//!
+//! [`FwUpdate::abort_fw_update`] can be called at any point to exit update mode. Devices that keep the previous image
+//! can switch back to it after activation with [`FwUpdate::rollback_fw_update`], this is optional and the CFU adapter
+//! doesn't rely on it.
+use core::future::Future;
+
+/// Firmware update error kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorKind {
+    /// Error communicating with the device
+    Bus,
+    /// Operation is not valid in the current update state, e.g. writing without starting an update
+    InvalidState,
+    /// Offset or length is outside of the image
+    InvalidOffset,
+    /// Image failed verification
+    Verification,
+    /// Operation is not supported by the device
+    Unsupported,
+    /// Other device-specific error
+    Other,
+}
+
+/// Firmware update error
+pub trait Error {
+    /// Convert to a generic error kind
+    fn kind(&self) -> ErrorKind;
+}
+
+impl Error for ErrorKind {
+    fn kind(&self) -> ErrorKind {
+        *self
+    }
+}
+
+impl<BE> Error for embedded_usb_pd::Error<BE> {
+    fn kind(&self) -> ErrorKind {
+        use embedded_usb_pd::PdError;
+
+        match self {
+            embedded_usb_pd::Error::Bus(_) => ErrorKind::Bus,
+            embedded_usb_pd::Error::Pd(PdError::InvalidMode) => ErrorKind::InvalidState,
+            embedded_usb_pd::Error::Pd(PdError::InvalidParams) => ErrorKind::InvalidOffset,
+            embedded_usb_pd::Error::Pd(PdError::UnrecognizedCommand) => ErrorKind::Unsupported,
+            embedded_usb_pd::Error::Pd(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// Error type for a firmware update implementation
+pub trait ErrorType {
+    /// Error type
+    type Error: Error;
+}
+
+/// Reason for rejecting an offer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RejectReason {
+    /// Offered image is older than the current image
+    OldFirmware,
+    /// Device is unable to swap images, e.g. a previous update is waiting for a reset
+    SwapPending,
+    /// Device-specific reason
+    Other,
+}
+
+/// Result of offer validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OfferStatus {
+    /// Device can accept the update
+    Accept,
+    /// Device will not accept the update
+    Reject(RejectReason),
+    /// Device is busy, the offer can be retried later
+    Busy,
+}
+
+/// Update state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum State {
+    /// No update in progress
+    #[default]
+    Idle,
+    /// Update started, image contents are being written
+    Writing,
+    /// All contents have been written and verified
+    Verified,
+}
+
+/// Update progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Progress {
+    /// Current state
+    pub state: State,
+    /// Number of image bytes written so far
+    pub bytes_written: usize,
+}
+
+/// Firmware update trait
+pub trait FwUpdate: ErrorType {
+    /// Get the version of the active firmware image
+    fn get_active_fw_version(&mut self) -> impl Future<Output = Result<u32, Self::Error>>;
+    /// Check if the device can accept an update right now
+    ///
+    /// Version policy is left to the update protocol, this only covers device-specific conditions.
+    fn validate_offer(&mut self) -> impl Future<Output = Result<OfferStatus, Self::Error>> {
+        async { Ok(OfferStatus::Accept) }
+    }
+    /// Enter update mode, abandoning any update already in progress
+    fn start_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Write a chunk of the image at the given offset
+    fn write_fw_contents(&mut self, offset: usize, data: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Verify the written image before activating it
+    fn verify_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        async { Ok(()) }
+    }
+    /// Switch to the new image, this may reset the device
+    fn activate_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Exit update mode without activating the new image
+    ///
+    /// Succeeds if no update is in progress.
+    fn abort_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Switch back to the previously active image
+    ///
+    /// Devices that can't switch back return an error of kind [`ErrorKind::Unsupported`].
+    fn rollback_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Get the progress of the current update
+    fn progress(&self) -> Progress;
+}
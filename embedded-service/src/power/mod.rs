@@ -1,3 +1,4 @@
 //! Module for anything power related
 #[allow(clippy::module_inception)]
 pub mod policy;
+pub mod system;
@@ -70,10 +70,20 @@ impl<'a, S: Kind> Policy<'a, S> {
     async fn connect_as_provider_internal_no_timeout(&self, capability: ProviderPowerCapability) -> Result<(), Error> {
         info!("Device {} connecting provider", self.device.id().0);
 
-        self.device
+        let capability = match self
+            .device
             .execute_device_command(device::CommandData::ConnectAsProvider(capability))
             .await?
-            .complete_or_err()?;
+        {
+            device::ResponseData::Complete => capability,
+            device::ResponseData::ProviderCapability(actual) => {
+                info!(
+                    "Device {} connected with a different provider capability",
+                    self.device.id().0
+                );
+                actual
+            }
+        };
 
         self.device
             .set_state(device::State::ConnectedProvider(capability))
@@ -0,0 +1,209 @@
+//! Battery as a power source
+//!
+//! Each battery registers a [`Device`] with the power policy through
+//! [`register_battery`](super::policy::register_battery). The battery service reports the battery state here so that
+//! the power policy can account for the power the batteries can deliver, e.g. when sourcing power to providers without
+//! external power, and sequence boot with depleted or missing batteries.
+//!
+//! Listeners are only notified of changes the power policy acts on. Temperature changes are only reported when they
+//! cross the limit set with [`set_temperature_limit`].
+use embassy_sync::{mutex::Mutex, signal::Signal};
+
+use crate::{GlobalRawMutex, intrusive_list};
+
+/// Battery ID new type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryId(pub u8);
+
+/// Battery power source status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status {
+    /// Battery is present
+    pub present: bool,
+    /// Relative state of charge
+    pub state_of_charge_pct: u8,
+    /// Power the battery can sustain, zero if unknown
+    pub sus_power_mw: u32,
+    /// Battery temperature in deci-Kelvin
+    pub temperature_dk: u16,
+}
+
+impl Status {
+    /// Combine the status of two batteries, the result describes them as a single power source
+    ///
+    /// The state of charge is the lowest and the temperature the highest of the present batteries.
+    pub fn combine(self, other: Status) -> Status {
+        match (self.present, other.present) {
+            (true, true) => Status {
+                present: true,
+                state_of_charge_pct: self.state_of_charge_pct.min(other.state_of_charge_pct),
+                sus_power_mw: if self.sus_power_mw == 0 || other.sus_power_mw == 0 {
+                    0
+                } else {
+                    self.sus_power_mw.saturating_add(other.sus_power_mw)
+                },
+                temperature_dk: self.temperature_dk.max(other.temperature_dk),
+            },
+            (true, false) => self,
+            (false, _) => other,
+        }
+    }
+
+    /// Returns true if the power policy needs to act on the change from `previous` to this status
+    fn is_relevant_change(&self, previous: Option<Status>, temperature_limit_dk: Option<u16>) -> bool {
+        let Some(previous) = previous else {
+            return true;
+        };
+
+        let over_limit = |status: &Status| temperature_limit_dk.is_some_and(|limit| status.temperature_dk > limit);
+        self.present != previous.present
+            || self.state_of_charge_pct != previous.state_of_charge_pct
+            || self.sus_power_mw != previous.sus_power_mw
+            || over_limit(self) != over_limit(&previous)
+    }
+}
+
+/// Battery device registered with the power policy
+pub struct Device {
+    /// Intrusive list node
+    node: intrusive_list::Node,
+    /// Battery ID
+    id: BatteryId,
+    /// Last reported status, `None` if unknown
+    status: Mutex<GlobalRawMutex, Option<Status>>,
+}
+
+impl Device {
+    /// Create a new battery device with an unknown status
+    pub fn new(id: BatteryId) -> Self {
+        Self {
+            node: intrusive_list::Node::uninit(),
+            id,
+            status: Mutex::new(None),
+        }
+    }
+
+    /// Get the battery ID
+    pub fn id(&self) -> BatteryId {
+        self.id
+    }
+
+    /// Get the last reported status, `None` if it's unknown
+    pub async fn status(&self) -> Option<Status> {
+        *self.status.lock().await
+    }
+
+    /// Update the battery status, `None` marks it unknown, e.g. before the battery has been probed
+    ///
+    /// Listeners are only notified if the change is relevant to the power policy.
+    pub async fn update(&self, status: Option<Status>) {
+        let mut current = self.status.lock().await;
+        let relevant = match status {
+            Some(status) => status.is_relevant_change(*current, *TEMPERATURE_LIMIT_DK.lock().await),
+            None => current.is_some(),
+        };
+
+        *current = status;
+        if relevant {
+            STATUS_CHANGED.signal(());
+        }
+    }
+}
+
+impl intrusive_list::NodeContainer for Device {
+    fn get_node(&self) -> &crate::Node {
+        &self.node
+    }
+}
+
+/// Trait for any container that holds a battery device
+pub trait BatteryContainer {
+    /// Get the underlying battery device
+    fn get_battery(&self) -> &Device;
+}
+
+impl BatteryContainer for Device {
+    fn get_battery(&self) -> &Device {
+        self
+    }
+}
+
+static TEMPERATURE_LIMIT_DK: Mutex<GlobalRawMutex, Option<u16>> = Mutex::new(None);
+static STATUS_CHANGED: Signal<GlobalRawMutex, ()> = Signal::new();
+
+/// Set the battery temperature the power policy acts on, `None` if temperature changes aren't relevant
+pub async fn set_temperature_limit(limit_dk: Option<u16>) {
+    *TEMPERATURE_LIMIT_DK.lock().await = limit_dk;
+}
+
+/// Wait for a relevant change to the status of any battery
+pub async fn wait_status_changed() {
+    STATUS_CHANGED.wait().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: Status = Status {
+        present: true,
+        state_of_charge_pct: 50,
+        sus_power_mw: 20000,
+        temperature_dk: 3000,
+    };
+
+    /// Test combining the status of multiple batteries
+    #[test]
+    fn test_combine() {
+        let other = Status {
+            state_of_charge_pct: 30,
+            sus_power_mw: 10000,
+            temperature_dk: 3100,
+            ..STATUS
+        };
+        assert_eq!(
+            STATUS.combine(other),
+            Status {
+                present: true,
+                state_of_charge_pct: 30,
+                sus_power_mw: 30000,
+                temperature_dk: 3100,
+            }
+        );
+
+        // Missing batteries don't contribute
+        assert_eq!(STATUS.combine(Status::default()), STATUS);
+        assert_eq!(Status::default().combine(STATUS), STATUS);
+
+        // Unknown sustainable power stays unknown
+        let unknown = Status {
+            sus_power_mw: 0,
+            ..STATUS
+        };
+        assert_eq!(STATUS.combine(unknown).sus_power_mw, 0);
+    }
+
+    /// Test that only changes the power policy acts on are relevant
+    #[test]
+    fn test_relevant_change() {
+        assert!(STATUS.is_relevant_change(None, None));
+        assert!(!STATUS.is_relevant_change(Some(STATUS), None));
+
+        let charged = Status {
+            state_of_charge_pct: 51,
+            ..STATUS
+        };
+        assert!(charged.is_relevant_change(Some(STATUS), None));
+
+        // Temperature changes are only relevant when crossing the limit
+        let warmer = Status {
+            temperature_dk: 3050,
+            ..STATUS
+        };
+        assert!(!warmer.is_relevant_change(Some(STATUS), None));
+        assert!(!warmer.is_relevant_change(Some(STATUS), Some(3100)));
+        assert!(warmer.is_relevant_change(Some(STATUS), Some(3000)));
+    }
+}
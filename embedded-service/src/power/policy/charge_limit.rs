@@ -0,0 +1,234 @@
+//! Charge limit modes
+//!
+//! Holds the battery below full charge to extend its life. The battery service keeps a [`Limiter`] per battery,
+//! evaluates it with the battery's state of charge and reports the result to the chargers with the rest of the charge
+//! regulation inputs.
+use embassy_time::Instant;
+
+/// State of charge held in storage mode
+pub const STORAGE_UPPER_PCT: u8 = 55;
+/// Charging resumes below this state of charge in storage mode
+pub const STORAGE_LOWER_PCT: u8 = 45;
+
+/// Charge limit mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Charge to full
+    #[default]
+    Disabled,
+    /// Stop charging at the upper state of charge and resume once it drops below the lower state of charge
+    Band {
+        /// Charging stops at this state of charge
+        upper_pct: u8,
+        /// Charging resumes below this state of charge
+        lower_pct: u8,
+    },
+    /// Hold the battery in a band until the given time, then charge to full, e.g. to be full before the user unplugs
+    Scheduled {
+        /// Charging stops at this state of charge until the full charge starts
+        upper_pct: u8,
+        /// Charging resumes below this state of charge until the full charge starts
+        lower_pct: u8,
+        /// Time to start the full charge
+        full_charge_at: Instant,
+    },
+    /// Hold the battery around half charge for long-term storage
+    Storage,
+}
+
+/// Charge limit mode error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidMode;
+
+impl Mode {
+    /// Returns the band to hold at the given time, `None` if charging isn't limited
+    fn band(&self, now: Instant) -> Option<(u8, u8)> {
+        match *self {
+            Mode::Disabled => None,
+            Mode::Band { upper_pct, lower_pct } => Some((upper_pct, lower_pct)),
+            Mode::Scheduled {
+                upper_pct,
+                lower_pct,
+                full_charge_at,
+            } => (now < full_charge_at).then_some((upper_pct, lower_pct)),
+            Mode::Storage => Some((STORAGE_UPPER_PCT, STORAGE_LOWER_PCT)),
+        }
+    }
+
+    /// Check that the band is within 0-100% and the lower bound is not above the upper bound
+    pub fn validate(&self) -> Result<(), InvalidMode> {
+        match *self {
+            Mode::Band { upper_pct, lower_pct }
+            | Mode::Scheduled {
+                upper_pct, lower_pct, ..
+            } if upper_pct > 100 || lower_pct > upper_pct => Err(InvalidMode),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Charge limit state machine
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limiter {
+    /// Active mode
+    mode: Mode,
+    /// The upper bound has been reached and charging is held until the lower bound
+    holding: bool,
+}
+
+impl Limiter {
+    /// Create a new limiter with charge limiting disabled
+    pub const fn new() -> Self {
+        Self {
+            mode: Mode::Disabled,
+            holding: false,
+        }
+    }
+
+    /// Active mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Set the mode, the hold state is re-evaluated on the next update
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), InvalidMode> {
+        mode.validate()?;
+        self.mode = mode;
+        self.holding = false;
+        Ok(())
+    }
+
+    /// Update with the current state of charge, returns true if the battery may be charged
+    pub fn update(&mut self, soc_pct: u8, now: Instant) -> bool {
+        self.holding = match self.mode.band(now) {
+            None => false,
+            Some((upper_pct, _)) if soc_pct >= upper_pct => true,
+            Some((_, lower_pct)) if soc_pct < lower_pct => false,
+            // Inside the band, keep doing what we were doing
+            Some(_) => self.holding,
+        };
+        !self.holding
+    }
+
+    /// Returns true if charging is currently held by the limit
+    pub fn is_limiting(&self) -> bool {
+        self.holding
+    }
+
+    /// Returns the time at which the limit changes on its own, i.e. when a scheduled full charge starts
+    ///
+    /// The limiter must be updated at this time, `None` if there is nothing pending.
+    pub fn deadline(&self, now: Instant) -> Option<Instant> {
+        match self.mode {
+            Mode::Scheduled { full_charge_at, .. } if now < full_charge_at => Some(full_charge_at),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// Test hysteresis in band mode
+    #[test]
+    fn test_band() {
+        let now = Instant::from_secs(0);
+        let mut limiter = Limiter::new();
+        limiter
+            .set_mode(Mode::Band {
+                upper_pct: 80,
+                lower_pct: 75,
+            })
+            .unwrap();
+
+        assert!(limiter.update(70, now));
+        assert!(limiter.update(79, now));
+        assert!(!limiter.update(80, now));
+        assert!(limiter.is_limiting());
+        // Stay limited inside the band
+        assert!(!limiter.update(76, now));
+        assert!(!limiter.update(75, now));
+        assert!(limiter.update(74, now));
+        assert!(!limiter.is_limiting());
+    }
+
+    /// Test that scheduled mode charges to full once the scheduled time is reached
+    #[test]
+    fn test_scheduled() {
+        let mut limiter = Limiter::new();
+        limiter
+            .set_mode(Mode::Scheduled {
+                upper_pct: 60,
+                lower_pct: 55,
+                full_charge_at: Instant::from_secs(3600),
+            })
+            .unwrap();
+
+        assert!(!limiter.update(60, Instant::from_secs(0)));
+        assert!(!limiter.update(58, Instant::from_secs(3599)));
+        assert!(limiter.update(58, Instant::from_secs(3600)));
+        assert!(limiter.update(99, Instant::from_secs(7200)));
+        assert!(!limiter.is_limiting());
+    }
+
+    /// Test that only a pending scheduled full charge has a deadline
+    #[test]
+    fn test_deadline() {
+        let mut limiter = Limiter::new();
+        assert_eq!(limiter.deadline(Instant::from_secs(0)), None);
+
+        limiter
+            .set_mode(Mode::Scheduled {
+                upper_pct: 60,
+                lower_pct: 55,
+                full_charge_at: Instant::from_secs(3600),
+            })
+            .unwrap();
+        assert_eq!(limiter.deadline(Instant::from_secs(0)), Some(Instant::from_secs(3600)));
+        assert_eq!(limiter.deadline(Instant::from_secs(3600)), None);
+
+        limiter.set_mode(Mode::Storage).unwrap();
+        assert_eq!(limiter.deadline(Instant::from_secs(0)), None);
+    }
+
+    /// Test storage mode and switching modes
+    #[test]
+    fn test_storage() {
+        let now = Instant::from_secs(0);
+        let mut limiter = Limiter::new();
+        assert!(limiter.update(100, now));
+
+        limiter.set_mode(Mode::Storage).unwrap();
+        assert!(!limiter.update(STORAGE_UPPER_PCT, now));
+        assert!(limiter.is_limiting());
+
+        limiter.set_mode(Mode::Disabled).unwrap();
+        assert!(!limiter.is_limiting());
+        assert!(limiter.update(STORAGE_UPPER_PCT, now));
+    }
+
+    /// Test mode validation
+    #[test]
+    fn test_invalid_mode() {
+        let mut limiter = Limiter::new();
+        assert_eq!(
+            limiter.set_mode(Mode::Band {
+                upper_pct: 101,
+                lower_pct: 50,
+            }),
+            Err(InvalidMode)
+        );
+        assert_eq!(
+            limiter.set_mode(Mode::Band {
+                upper_pct: 50,
+                lower_pct: 60,
+            }),
+            Err(InvalidMode)
+        );
+        assert_eq!(limiter.mode(), Mode::Disabled);
+    }
+}
@@ -1,11 +1,14 @@
 //! Charger device struct and controller
 use core::{future::Future, ops::DerefMut};
 
-use embassy_sync::{channel::Channel, mutex::Mutex};
+use embassy_sync::{channel::Channel, mutex::Mutex, signal::Signal};
 
 use crate::{
     GlobalRawMutex, intrusive_list,
-    power::{self, policy::ConsumerPowerCapability},
+    power::{
+        self,
+        policy::{ConsumerPowerCapability, battery::BatteryId},
+    },
 };
 
 /// Charger controller trait that device drivers may use to integrate with internal messaging system
@@ -154,6 +157,41 @@ pub struct InternalState {
     pub capability: Option<ConsumerPowerCapability>,
 }
 
+/// Battery measurements and fuel gauge charging request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryInputs {
+    /// Battery is present
+    pub present: bool,
+    /// Charging voltage requested by the fuel gauge
+    pub requested_voltage_mv: u16,
+    /// Charging current requested by the fuel gauge
+    pub requested_current_ma: u16,
+    /// Measured pack voltage
+    pub voltage_mv: u16,
+    /// Measured pack current, positive while charging
+    pub current_ma: i16,
+    /// Fuel gauge reports the battery as fully charged
+    pub fully_charged: bool,
+    /// Relative state of charge
+    pub state_of_charge_pct: u8,
+    /// Charging is held by the battery's [charge limit](super::charge_limit)
+    pub charge_limited: bool,
+}
+
+/// Charge regulation inputs reported by other services
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RegulationInputs {
+    /// Latest inputs of the battery charged by this charger
+    pub battery: BatteryInputs,
+    /// Charge current limit requested by thermal management, `None` if charging isn't throttled
+    pub thermal_limit_ma: Option<u16>,
+    /// Input power in mW the power policy leaves for charging after the system load and provider allocations,
+    /// `None` if it hasn't been computed yet
+    pub charge_budget_mw: Option<u32>,
+}
+
 /// Channel size for device requests
 pub const CHARGER_CHANNEL_SIZE: usize = 1;
 
@@ -163,26 +201,40 @@ pub struct Device {
     node: intrusive_list::Node,
     /// Device ID
     id: ChargerId,
+    /// Battery charged by this charger
+    battery: BatteryId,
     /// Current state of the device
     state: Mutex<GlobalRawMutex, InternalState>,
     /// Channel for requests to the device
     commands: Channel<GlobalRawMutex, PolicyEvent, CHARGER_CHANNEL_SIZE>,
     /// Channel for responses from the device
     response: Channel<GlobalRawMutex, ChargerResponse, CHARGER_CHANNEL_SIZE>,
+    /// Charge regulation inputs
+    regulation_inputs: Mutex<GlobalRawMutex, RegulationInputs>,
+    /// Signaled when the regulation inputs change
+    regulation_inputs_changed: Signal<GlobalRawMutex, ()>,
 }
 
 impl Device {
-    /// Create a new device
+    /// Create a new device charging the battery with the same ID
     pub fn new(id: ChargerId) -> Self {
+        Self::new_with_battery(id, BatteryId(id.0))
+    }
+
+    /// Create a new device charging the given battery
+    pub fn new_with_battery(id: ChargerId, battery: BatteryId) -> Self {
         Self {
             node: intrusive_list::Node::uninit(),
             id,
+            battery,
             state: Mutex::new(InternalState {
                 state: State::Unpowered,
                 capability: None,
             }),
             commands: Channel::new(),
             response: Channel::new(),
+            regulation_inputs: Mutex::new(RegulationInputs::default()),
+            regulation_inputs_changed: Signal::new(),
         }
     }
 
@@ -191,6 +243,11 @@ impl Device {
         self.id
     }
 
+    /// Get the ID of the battery charged by this charger
+    pub fn battery(&self) -> BatteryId {
+        self.battery
+    }
+
     /// Returns the current state of the device
     pub async fn state(&self) -> InternalState {
         *self.state.lock().await
@@ -218,6 +275,46 @@ impl Device {
         self.response.send(response).await
     }
 
+    /// Returns the current charge regulation inputs
+    pub async fn regulation_inputs(&self) -> RegulationInputs {
+        *self.regulation_inputs.lock().await
+    }
+
+    /// Update the battery inputs, the charger is only notified if they changed
+    pub async fn update_battery(&self, battery: BatteryInputs) {
+        let mut inputs = self.regulation_inputs.lock().await;
+        if inputs.battery != battery {
+            inputs.battery = battery;
+            self.regulation_inputs_changed.signal(());
+        }
+    }
+
+    /// Set the thermal charge current limit, `None` removes the limit
+    pub async fn set_thermal_limit(&self, limit_ma: Option<u16>) {
+        let mut inputs = self.regulation_inputs.lock().await;
+        if inputs.thermal_limit_ma != limit_ma {
+            inputs.thermal_limit_ma = limit_ma;
+            self.regulation_inputs_changed.signal(());
+        }
+    }
+
+    /// Set the input power left for charging, `None` removes the limit
+    ///
+    /// Unlike commands this doesn't wait for the charger, so the power policy can update it while holding its state.
+    pub async fn set_charge_budget(&self, charge_budget_mw: Option<u32>) {
+        let mut inputs = self.regulation_inputs.lock().await;
+        if inputs.charge_budget_mw != charge_budget_mw {
+            inputs.charge_budget_mw = charge_budget_mw;
+            self.regulation_inputs_changed.signal(());
+        }
+    }
+
+    /// Wait for the charge regulation inputs to change
+    pub async fn wait_regulation_inputs_changed(&self) -> RegulationInputs {
+        self.regulation_inputs_changed.wait().await;
+        self.regulation_inputs().await
+    }
+
     /// Send a command and wait for a response from the charger
     pub async fn execute_command(&self, policy_event: PolicyEvent) -> ChargerResponse {
         self.send_command(policy_event).await;
@@ -88,6 +88,9 @@ pub struct Command {
 pub enum ResponseData {
     /// The request was successful
     Complete,
+    /// The device is providing a different capability than requested, e.g. it can't change the capability it
+    /// advertises
+    ProviderCapability(ProviderPowerCapability),
 }
 
 impl ResponseData {
@@ -95,6 +98,7 @@ impl ResponseData {
     pub fn complete_or_err(self) -> Result<(), Error> {
         match self {
             ResponseData::Complete => Ok(()),
+            ResponseData::ProviderCapability(_) => Err(Error::InvalidResponse),
         }
     }
 }
@@ -1,9 +1,12 @@
 //! Power policy related data structures and messages
 pub mod action;
+pub mod battery;
+pub mod charge_limit;
 pub mod charger;
 pub mod device;
 pub mod flags;
 pub mod policy;
+pub mod snapshot;
 
 pub use policy::{init, register_device};
 
@@ -137,6 +140,46 @@ impl UnconstrainedState {
     }
 }
 
+/// Input power budget breakdown
+#[derive(Debug, Clone, Default, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PowerBudget {
+    /// Power available from the current consumer contract, zero when running from battery
+    pub input_mw: u32,
+    /// Power reserved for the rest of the system
+    pub system_load_mw: u32,
+    /// Power allocated to connected providers
+    pub providers_mw: u32,
+    /// Power left for the chargers
+    pub charger_mw: u32,
+}
+
+/// Boot power sequencing state
+#[derive(Debug, Clone, Default, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootState {
+    /// Waiting for the battery state
+    #[default]
+    Starting,
+    /// Battery is depleted, waiting for external power
+    DeadBattery,
+    /// No battery, waiting for external power
+    NoBattery,
+    /// External power connected, waiting for it to stabilize before loading it
+    Stabilizing,
+    /// Normal operation
+    Running,
+    /// No battery, external power is stable and charging is disabled
+    AcOnly,
+}
+
+impl BootState {
+    /// Returns true if the system can't boot until external power is connected
+    pub fn needs_power(&self) -> bool {
+        matches!(self, BootState::DeadBattery | BootState::NoBattery)
+    }
+}
+
 /// Data to send with the comms service
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -151,6 +194,10 @@ pub enum CommsData {
     ProviderConnected(DeviceId, ProviderPowerCapability),
     /// Unconstrained state changed
     Unconstrained(UnconstrainedState),
+    /// Power budget changed
+    Budget(PowerBudget),
+    /// Boot power sequencing state changed
+    Boot(BootState),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -3,12 +3,14 @@ use core::sync::atomic::{AtomicBool, Ordering};
 
 use crate::GlobalRawMutex;
 use crate::broadcaster::immediate as broadcaster;
-use crate::power::policy::{CommsMessage, ConsumerPowerCapability, ProviderPowerCapability};
+use crate::power::policy::{BootState, CommsMessage, ConsumerPowerCapability, ProviderPowerCapability};
 use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
 
 use super::charger::ChargerResponse;
 use super::device::{self};
-use super::{DeviceId, Error, action, charger};
+use super::snapshot::{self, Snapshot};
+use super::{DeviceId, Error, action, battery, charger};
 use crate::power::policy::charger::ChargerResponseData::Ack;
 use crate::{error, intrusive_list};
 
@@ -81,8 +83,14 @@ struct Context {
     policy_response: Channel<GlobalRawMutex, InternalResponseData, POLICY_CHANNEL_SIZE>,
     /// Registered chargers
     chargers: intrusive_list::IntrusiveList,
+    /// Registered batteries
+    batteries: intrusive_list::IntrusiveList,
     /// Message broadcaster
     broadcaster: broadcaster::Immediate<CommsMessage>,
+    /// Last published policy state
+    snapshot: Mutex<GlobalRawMutex, Snapshot>,
+    /// Boot power sequencing state, `None` until the power policy has started
+    boot_state: Mutex<GlobalRawMutex, Option<BootState>>,
 }
 
 impl Context {
@@ -90,9 +98,12 @@ impl Context {
         Self {
             devices: intrusive_list::IntrusiveList::new(),
             chargers: intrusive_list::IntrusiveList::new(),
+            batteries: intrusive_list::IntrusiveList::new(),
             policy_request: Channel::new(),
             policy_response: Channel::new(),
             broadcaster: broadcaster::Immediate::new(),
+            snapshot: Mutex::new(Snapshot::new()),
+            boot_state: Mutex::new(None),
         }
     }
 }
@@ -122,6 +133,33 @@ pub fn register_charger(device: &'static impl charger::ChargerContainer) -> Resu
     CONTEXT.chargers.push(device)
 }
 
+/// Register a battery with the power policy service
+pub fn register_battery(device: &'static impl battery::BatteryContainer) -> Result<(), intrusive_list::Error> {
+    let device = device.get_battery();
+    if CONTEXT
+        .batteries
+        .iter_only::<battery::Device>()
+        .any(|battery| battery.id() == device.id())
+    {
+        return Err(intrusive_list::Error::NodeAlreadyInList);
+    }
+
+    CONTEXT.batteries.push(device)
+}
+
+/// Combined status of all registered batteries, `None` if no battery status is known
+///
+/// Batteries with an unknown status, e.g. before they have been probed, are ignored.
+pub async fn battery_status() -> Option<battery::Status> {
+    let mut combined: Option<battery::Status> = None;
+    for battery in CONTEXT.batteries.iter_only::<battery::Device>() {
+        if let Some(status) = battery.status().await {
+            combined = Some(combined.map_or(status, |combined| combined.combine(status)));
+        }
+    }
+    combined
+}
+
 /// Find a device by its ID
 fn get_device(id: DeviceId) -> Option<&'static device::Device> {
     for device in &CONTEXT.devices {
@@ -150,6 +188,33 @@ pub async fn compute_total_provider_power_mw() -> u32 {
     total
 }
 
+/// Returns the last published power policy state along with the current charger states
+pub async fn snapshot() -> Snapshot {
+    let mut snapshot = CONTEXT.snapshot.lock().await.clone();
+    snapshot.chargers.clear();
+    for charger in CONTEXT.chargers.iter_only::<charger::Device>() {
+        if snapshot
+            .chargers
+            .push(snapshot::Charger {
+                id: charger.id(),
+                state: charger.state().await,
+            })
+            .is_err()
+        {
+            error!("Too many chargers for power policy snapshot");
+            break;
+        }
+    }
+    snapshot
+}
+
+/// Returns the boot power sequencing state, `None` until the power policy has started
+///
+/// Cheaper than [`snapshot`] for services that only need to know how the system is booting.
+pub async fn boot_state() -> Option<BootState> {
+    *CONTEXT.boot_state.lock().await
+}
+
 /// Find a device by its ID
 fn get_charger(id: charger::ChargerId) -> Option<&'static charger::Device> {
     for charger in &CONTEXT.chargers {
@@ -201,6 +266,22 @@ pub async fn check_chargers_ready() -> ChargerResponse {
     Ok(Ack)
 }
 
+/// Report the inputs of a battery for charge regulation to the chargers charging it
+pub async fn update_charger_battery(id: battery::BatteryId, inputs: charger::BatteryInputs) {
+    for charger in CONTEXT.chargers.iter_only::<charger::Device>() {
+        if charger.battery() == id {
+            charger.update_battery(inputs).await;
+        }
+    }
+}
+
+/// Set the thermal charge current limit of all chargers, `None` removes the limit
+pub async fn set_charger_thermal_limit(limit_ma: Option<u16>) {
+    for charger in CONTEXT.chargers.iter_only::<charger::Device>() {
+        charger.set_thermal_limit(limit_ma).await;
+    }
+}
+
 /// Register a message receiver for power policy messages
 pub fn register_message_receiver(
     receiver: &'static broadcaster::Receiver<'_, CommsMessage>,
@@ -280,4 +361,14 @@ impl ContextToken {
     pub async fn broadcast_message(&self, message: CommsMessage) {
         CONTEXT.broadcaster.broadcast(message).await;
     }
+
+    /// Publish the current policy state, see [`snapshot`]
+    pub async fn publish_snapshot(&self, snapshot: Snapshot) {
+        *CONTEXT.snapshot.lock().await = snapshot;
+    }
+
+    /// Publish the boot power sequencing state, see [`boot_state`]
+    pub async fn publish_boot_state(&self, state: BootState) {
+        *CONTEXT.boot_state.lock().await = Some(state);
+    }
 }
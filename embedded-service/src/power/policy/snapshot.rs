@@ -0,0 +1,91 @@
+//! Read-only view of the power policy state
+//!
+//! The power policy publishes its state after every change, other services and the host can query it with
+//! [`policy::snapshot`](super::policy::snapshot) without going through the policy request channel.
+use heapless::Vec;
+
+use super::charger::{self, ChargerId};
+use super::{BootState, ConsumerPowerCapability, DeviceId, PowerBudget, ProviderPowerCapability, UnconstrainedState};
+
+/// Maximum number of connected providers in a snapshot
+pub const MAX_PROVIDERS: usize = 4;
+/// Maximum number of chargers in a snapshot
+pub const MAX_CHARGERS: usize = 4;
+
+/// Currently connected consumer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Consumer {
+    /// Device ID
+    pub device_id: DeviceId,
+    /// Contract
+    pub capability: ConsumerPowerCapability,
+}
+
+/// Connected provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Provider {
+    /// Device ID
+    pub device_id: DeviceId,
+    /// Contract
+    pub capability: ProviderPowerCapability,
+}
+
+/// Registered charger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Charger {
+    /// Charger ID
+    pub id: ChargerId,
+    /// Charger state
+    pub state: charger::InternalState,
+}
+
+/// Power policy state snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Current consumer, if any
+    pub consumer: Option<Consumer>,
+    /// Connected providers
+    pub providers: Vec<Provider, MAX_PROVIDERS>,
+    /// True if the system can only provide limited power to providers
+    pub providers_limited: bool,
+    /// System unconstrained power
+    pub unconstrained: UnconstrainedState,
+    /// Input power budget
+    pub budget: PowerBudget,
+    /// Boot power sequencing state
+    pub boot: BootState,
+    /// Registered chargers, filled in when the snapshot is queried
+    pub chargers: Vec<Charger, MAX_CHARGERS>,
+}
+
+impl Snapshot {
+    /// Create an empty snapshot
+    pub const fn new() -> Self {
+        Self {
+            consumer: None,
+            providers: Vec::new(),
+            providers_limited: false,
+            unconstrained: UnconstrainedState {
+                unconstrained: false,
+                available: 0,
+            },
+            budget: PowerBudget {
+                input_mw: 0,
+                system_load_mw: 0,
+                providers_mw: 0,
+                charger_mw: 0,
+            },
+            boot: BootState::Starting,
+            chargers: Vec::new(),
+        }
+    }
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,335 @@
+//! System power state
+//!
+//! Tracks the host power state and coordinates transitions between services. Transitions are requested with
+//! [`request_transition`], usually by the platform code that handles the host sleep signals. Every registered [`Hook`]
+//! is asked to prepare for the transition first and can veto or delay it. Hooks that were already ready are notified if
+//! a later hook vetoes the transition, otherwise all hooks are notified once the new state has been entered. Waking up to
+//! [`SystemPowerState::S0`] can't be vetoed.
+//!
+//! The system is assumed to be running until the first transition.
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::task::Poll;
+
+use embassy_sync::{channel::Channel, mutex::Mutex, signal::Signal};
+use embassy_time::{Duration, Timer, with_timeout};
+
+use crate::{GlobalRawMutex, info, intrusive_list, warn};
+
+/// Time a hook has to respond to a transition request, hooks that don't respond in time are considered ready
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(1);
+/// Maximum time a single hook can delay a transition
+pub const MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// System power state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SystemPowerState {
+    /// S0 - System fully running
+    #[default]
+    S0,
+    /// S3 - Suspend to RAM
+    S3,
+    /// S4 - Hibernate
+    S4,
+    /// S5 - Soft off
+    S5,
+    /// S0ix - Modern standby / Connected standby
+    S0ix,
+}
+
+impl SystemPowerState {
+    /// Returns true if the host is running
+    pub fn is_running(self) -> bool {
+        self == Self::S0
+    }
+
+    /// Returns true if the host is hibernating or off
+    pub fn is_off(self) -> bool {
+        matches!(self, Self::S4 | Self::S5)
+    }
+}
+
+/// System power state transition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transition {
+    /// Current state
+    pub from: SystemPowerState,
+    /// Requested state
+    pub to: SystemPowerState,
+}
+
+/// Hook response to a transition request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Response {
+    /// Ready for the transition
+    Ready,
+    /// Block the transition
+    Veto,
+    /// Not ready yet, ask again after the given time
+    Delay(Duration),
+}
+
+/// Event delivered to a hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// A transition was requested, must be answered with [`Hook::respond`]
+    Prepare(Transition),
+    /// A transition this hook was ready for was vetoed by another hook
+    Cancelled(Transition),
+    /// A transition completed
+    Entered(Transition),
+}
+
+/// Transition error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The transition was vetoed by the named hook
+    Vetoed(&'static str),
+}
+
+/// Per-service transition hook
+pub struct Hook {
+    /// Intrusive list node
+    node: intrusive_list::Node,
+    /// Name used in logs
+    name: &'static str,
+    /// Transition requests
+    prepare: Channel<GlobalRawMutex, Transition, 1>,
+    /// Responses to transition requests
+    response: Channel<GlobalRawMutex, Response, 1>,
+    /// Last vetoed transition
+    cancelled: Signal<GlobalRawMutex, Transition>,
+    /// Last completed transition
+    entered: Signal<GlobalRawMutex, Transition>,
+}
+
+impl Hook {
+    /// Create a new hook
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            node: intrusive_list::Node::uninit(),
+            name,
+            prepare: Channel::new(),
+            response: Channel::new(),
+            cancelled: Signal::new(),
+            entered: Signal::new(),
+        }
+    }
+
+    /// Hook name
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Wait for the next event
+    pub async fn wait_event(&self) -> Event {
+        let mut prepare = pin!(self.prepare.receive());
+        let mut cancelled = pin!(self.cancelled.wait());
+        let mut entered = pin!(self.entered.wait());
+        poll_fn(|cx| {
+            if let Poll::Ready(transition) = prepare.as_mut().poll(cx) {
+                return Poll::Ready(Event::Prepare(transition));
+            }
+            if let Poll::Ready(transition) = cancelled.as_mut().poll(cx) {
+                return Poll::Ready(Event::Cancelled(transition));
+            }
+            entered.as_mut().poll(cx).map(Event::Entered)
+        })
+        .await
+    }
+
+    /// Respond to an [`Event::Prepare`]
+    pub async fn respond(&self, response: Response) {
+        self.response.send(response).await
+    }
+
+    /// Ask the hook to prepare for a transition, waiting out any delay
+    async fn request(&self, transition: Transition) -> Response {
+        let mut delayed = Duration::from_ticks(0);
+        loop {
+            // Drop anything left over from a request that timed out
+            self.prepare.clear();
+            self.response.clear();
+
+            let response = with_timeout(HOOK_TIMEOUT, async {
+                self.prepare.send(transition).await;
+                self.response.receive().await
+            })
+            .await;
+
+            match response {
+                Ok(Response::Delay(delay)) if delayed + delay <= MAX_DELAY => {
+                    info!("{}: Delaying system power transition", self.name);
+                    delayed += delay;
+                    Timer::after(delay).await;
+                }
+                Ok(Response::Delay(_)) => {
+                    warn!("{}: Maximum system power transition delay exceeded", self.name);
+                    return Response::Ready;
+                }
+                Ok(response) => return response,
+                Err(_) => {
+                    warn!("{}: No response to system power transition", self.name);
+                    return Response::Ready;
+                }
+            }
+        }
+    }
+}
+
+impl intrusive_list::NodeContainer for Hook {
+    fn get_node(&self) -> &intrusive_list::Node {
+        &self.node
+    }
+}
+
+/// System power state context
+struct Context {
+    /// Registered hooks
+    hooks: intrusive_list::IntrusiveList,
+    /// Current state
+    state: Mutex<GlobalRawMutex, SystemPowerState>,
+    /// Held while a transition is in progress
+    transition: Mutex<GlobalRawMutex, ()>,
+}
+
+static CONTEXT: Context = Context {
+    hooks: intrusive_list::IntrusiveList::new(),
+    state: Mutex::new(SystemPowerState::S0),
+    transition: Mutex::new(()),
+};
+
+/// Register a transition hook
+pub fn register_hook(hook: &'static Hook) -> intrusive_list::Result<()> {
+    CONTEXT.hooks.push(hook)
+}
+
+/// Current system power state
+pub async fn state() -> SystemPowerState {
+    *CONTEXT.state.lock().await
+}
+
+/// Transition to a new system power state
+///
+/// Returns once every hook is ready and the new state has been entered.
+pub async fn request_transition(to: SystemPowerState) -> Result<(), Error> {
+    let _transition = CONTEXT.transition.lock().await;
+    let from = state().await;
+    if from == to {
+        return Ok(());
+    }
+
+    let transition = Transition { from, to };
+    info!("System power transition requested: {:?} -> {:?}", from, to);
+    for hook in CONTEXT.hooks.iter_only::<Hook>() {
+        if hook.request(transition).await == Response::Veto {
+            if to.is_running() {
+                warn!("{}: Wake can't be vetoed", hook.name);
+            } else {
+                info!("{}: Vetoed system power transition", hook.name);
+                // Every hook before this one was ready
+                for ready in CONTEXT
+                    .hooks
+                    .iter_only::<Hook>()
+                    .take_while(|ready| !core::ptr::eq(*ready, hook))
+                {
+                    ready.cancelled.signal(transition);
+                }
+                return Err(Error::Vetoed(hook.name));
+            }
+        }
+    }
+
+    *CONTEXT.state.lock().await = to;
+    for hook in CONTEXT.hooks.iter_only::<Hook>() {
+        hook.entered.signal(transition);
+    }
+    info!("Entered system power state {:?}", to);
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    static HOOK: Hook = Hook::new("test");
+    static READY_HOOK: Hook = Hook::new("ready");
+
+    /// Test vetoing, delaying and completing transitions
+    #[tokio::test]
+    async fn test_transition() {
+        register_hook(&HOOK).unwrap();
+        // Hooks are asked in reverse registration order, this one is asked before the vetoing hook
+        register_hook(&READY_HOOK).unwrap();
+
+        let ready_hook = async {
+            // Ready for the first sleep request, which is then vetoed
+            let Event::Prepare(transition) = READY_HOOK.wait_event().await else {
+                return None;
+            };
+            READY_HOOK.respond(Response::Ready).await;
+            assert_eq!(READY_HOOK.wait_event().await, Event::Cancelled(transition));
+
+            // Ready for the sleep and wake transitions
+            for _ in 0..2 {
+                let Event::Prepare(transition) = READY_HOOK.wait_event().await else {
+                    return None;
+                };
+                READY_HOOK.respond(Response::Ready).await;
+                assert_eq!(READY_HOOK.wait_event().await, Event::Entered(transition));
+            }
+            Some(())
+        };
+
+        let hook = async {
+            // Veto the first sleep request
+            assert_eq!(
+                HOOK.wait_event().await,
+                Event::Prepare(Transition {
+                    from: SystemPowerState::S0,
+                    to: SystemPowerState::S3,
+                })
+            );
+            HOOK.respond(Response::Veto).await;
+
+            // Delay the second one once
+            let Event::Prepare(transition) = HOOK.wait_event().await else {
+                return None;
+            };
+            HOOK.respond(Response::Delay(Duration::from_millis(10))).await;
+            assert_eq!(HOOK.wait_event().await, Event::Prepare(transition));
+            HOOK.respond(Response::Ready).await;
+            assert_eq!(HOOK.wait_event().await, Event::Entered(transition));
+
+            // Wake can't be vetoed
+            assert!(matches!(HOOK.wait_event().await, Event::Prepare(_)));
+            HOOK.respond(Response::Veto).await;
+            HOOK.wait_event().await;
+            Some(())
+        };
+
+        let requests = async {
+            assert_eq!(
+                request_transition(SystemPowerState::S3).await,
+                Err(Error::Vetoed("test"))
+            );
+            assert_eq!(state().await, SystemPowerState::S0);
+
+            assert_eq!(request_transition(SystemPowerState::S3).await, Ok(()));
+            assert_eq!(state().await, SystemPowerState::S3);
+
+            assert_eq!(request_transition(SystemPowerState::S0).await, Ok(()));
+            assert_eq!(state().await, SystemPowerState::S0);
+        };
+
+        let (hook, ready_hook, _) = tokio::join!(hook, ready_hook, requests);
+        assert_eq!(hook, Some(()));
+        assert_eq!(ready_hook, Some(()));
+    }
+}
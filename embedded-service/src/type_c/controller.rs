@@ -16,6 +16,7 @@ use embedded_usb_pd::{
 use heapless::Vec;
 
 use super::{ATTN_VDM_LEN, ControllerId, OTHER_VDM_LEN, external};
+use crate::fw_update::FwUpdate;
 use crate::ipc::deferred;
 use crate::power::policy;
 use crate::type_c::Cached;
@@ -52,6 +53,10 @@ pub struct PortStatus {
     pub epr: bool,
     /// Port partner is unconstrained
     pub unconstrained_power: bool,
+    /// Raw PDO of the explicit contract, [`None`] for implicit contracts
+    pub active_pdo: Option<u32>,
+    /// Raw RDO of the explicit contract, [`None`] for implicit contracts
+    pub active_rdo: Option<u32>,
 }
 
 impl PortStatus {
@@ -70,6 +75,8 @@ impl PortStatus {
             power_path: PowerPathStatus::none(),
             epr: false,
             unconstrained_power: false,
+            active_pdo: None,
+            active_rdo: None,
         }
     }
 
@@ -242,6 +249,166 @@ pub struct TbtConfig {
     pub tbt_enabled: bool,
 }
 
+/// Data mode currently active on a port
+///
+/// Variants are ordered from least to most capable so they can be compared directly.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DataMode {
+    /// No data connection
+    #[default]
+    None,
+    /// USB 2.0 only
+    Usb2,
+    /// USB 3.x
+    Usb3,
+    /// DisplayPort alt-mode, with or without USB 3.x on the remaining lanes
+    DisplayPort,
+    /// Thunderbolt 3 alt-mode
+    Tbt3,
+    /// USB4
+    Usb4,
+}
+
+/// Battery reference used by the PD Get_Battery_Cap and Get_Battery_Status messages
+///
+/// References 0-3 are fixed batteries, 4-7 are hot-swappable batteries.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatteryRef(pub u8);
+
+impl BatteryRef {
+    /// Highest valid battery reference
+    pub const MAX: u8 = 7;
+    /// First hot-swappable battery reference
+    pub const FIRST_HOT_SWAPPABLE: u8 = 4;
+
+    /// Returns true if this is a valid battery reference
+    pub const fn is_valid(self) -> bool {
+        self.0 <= Self::MAX
+    }
+
+    /// Returns true if this references a hot-swappable battery
+    pub const fn is_hot_swappable(self) -> bool {
+        self.0 >= Self::FIRST_HOT_SWAPPABLE && self.is_valid()
+    }
+}
+
+/// Battery capabilities, contents of the PD Battery_Capabilities extended message
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatteryCapabilities {
+    /// Battery vendor ID
+    pub vid: u16,
+    /// Battery product ID
+    pub pid: u16,
+    /// Design capacity in units of 0.1 Wh, [`None`] if not available
+    pub design_capacity_dwh: Option<u16>,
+    /// Last full charge capacity in units of 0.1 Wh, [`None`] if not available
+    pub last_full_charge_capacity_dwh: Option<u16>,
+    /// The requested battery reference is not valid
+    pub invalid_battery_ref: bool,
+}
+
+impl BatteryCapabilities {
+    /// Response for a battery reference that doesn't exist
+    pub const fn invalid() -> Self {
+        Self {
+            vid: 0,
+            pid: 0,
+            design_capacity_dwh: None,
+            last_full_charge_capacity_dwh: None,
+            invalid_battery_ref: true,
+        }
+    }
+}
+
+/// Battery charging status reported in the PD Battery_Status message
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatteryChargingStatus {
+    /// Battery is charging
+    Charging,
+    /// Battery is discharging
+    Discharging,
+    /// Battery is neither charging nor discharging
+    #[default]
+    Idle,
+}
+
+/// Battery status, contents of the PD Battery_Status message
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatteryStatus {
+    /// Present capacity in units of 0.1 Wh, [`None`] if not available
+    pub present_capacity_dwh: Option<u16>,
+    /// Charging status
+    pub charging_status: BatteryChargingStatus,
+    /// Battery is present
+    pub present: bool,
+    /// The requested battery reference is not valid
+    pub invalid_battery_ref: bool,
+}
+
+impl BatteryStatus {
+    /// Response for a battery reference that doesn't exist
+    pub const fn invalid() -> Self {
+        Self {
+            present_capacity_dwh: None,
+            charging_status: BatteryChargingStatus::Idle,
+            present: false,
+            invalid_battery_ref: true,
+        }
+    }
+}
+
+/// Source port type reported in the PD Source_Info message
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SourcePortType {
+    /// Port power can change based on the power demands of other ports
+    #[default]
+    Managed,
+    /// Port power is guaranteed
+    Guaranteed,
+}
+
+/// Source information, contents of the PD Source_Info message
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceInfo {
+    /// Source port type
+    pub port_type: SourcePortType,
+    /// Maximum power the port is capable of in W
+    pub max_pdp_w: u8,
+    /// Power the port is currently capable of in W
+    pub present_pdp_w: u8,
+    /// Power the port is currently advertising in W
+    pub reported_pdp_w: u8,
+}
+
+/// Partner status, contents of the PD Status extended message
+///
+/// Flag fields are passed through as raw bytes from the status data block.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PdStatus {
+    /// Internal temperature in °C, [`None`] if not supported
+    pub internal_temp_c: Option<u8>,
+    /// Present input flags
+    pub present_input: u8,
+    /// Present battery input flags
+    pub present_battery_input: u8,
+    /// Event flags
+    pub event_flags: u8,
+    /// Temperature status flags
+    pub temperature_status: u8,
+    /// Power status flags
+    pub power_status: u8,
+    /// Power state change flags
+    pub power_state_change: u8,
+}
+
 /// PD state-machine configuration
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Default, Copy, PartialEq)]
@@ -389,6 +556,18 @@ pub enum PortCommandData {
     GetDiscoverIdentitySop,
     /// Get the response to a Discover Identity command sent to the given port with SOP'
     GetDiscoverIdentitySopPrime,
+    /// Request battery capabilities from the port partner
+    GetPartnerBatteryCapabilities(BatteryRef),
+    /// Request battery status from the port partner
+    GetPartnerBatteryStatus(BatteryRef),
+    /// Request source information from the port partner
+    GetPartnerSourceInfo,
+    /// Request status from the port partner
+    GetPartnerStatus,
+    /// Set the battery capabilities reported to the port partner
+    SetBatteryCapabilities(BatteryRef, BatteryCapabilities),
+    /// Set the battery status reported to the port partner
+    SetBatteryStatus(BatteryRef, BatteryStatus),
 }
 
 /// Port-specific commands
@@ -439,6 +618,14 @@ pub enum PortResponseData {
     DiscoverIdentitySop(embedded_usb_pd::vdm::structured::command::discover_identity::sop::ResponseVdos),
     /// Discover Identity SOP' response
     DiscoverIdentitySopPrime(embedded_usb_pd::vdm::structured::command::discover_identity::sop_prime::ResponseVdos),
+    /// Port partner battery capabilities
+    BatteryCapabilities(BatteryCapabilities),
+    /// Port partner battery status
+    BatteryStatus(BatteryStatus),
+    /// Port partner source information
+    SourceInfo(SourceInfo),
+    /// Port partner status
+    PdStatus(PdStatus),
 }
 
 impl PortResponseData {
@@ -458,20 +645,7 @@ pub type PortResponse = Result<PortResponseData, PdError>;
 ///
 /// Used to notify the PD controller of the current system power state,
 /// which triggers Application Configuration updates (e.g., crossbar reconfiguration).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum SystemPowerState {
-    /// S0 - System fully running
-    S0,
-    /// S3 - Suspend to RAM
-    S3,
-    /// S4 - Hibernate
-    S4,
-    /// S5 - Soft off
-    S5,
-    /// S0ix - Modern standby / Connected standby
-    S0ix,
-}
+pub use crate::power::system::SystemPowerState;
 
 /// PD controller command-specific data
 #[derive(Copy, Clone, Debug)]
@@ -623,7 +797,9 @@ impl DeviceContainer for Device<'_> {
 }
 
 /// PD controller trait that device drivers may use to integrate with internal messaging system
-pub trait Controller {
+///
+/// Firmware updates are provided through the [`FwUpdate`] supertrait.
+pub trait Controller: FwUpdate<Error = Error<<Self as Controller>::BusError>> {
     /// Type of error returned by the bus
     type BusError;
 
@@ -689,6 +865,17 @@ pub trait Controller {
         port: LocalPortId,
         voltage_mv: Option<u16>,
     ) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
+    /// Set the maximum capability advertised when sourcing power on the given port
+    ///
+    /// This may trigger a renegotiation. Controllers that can't change their source capabilities at runtime return
+    /// [`PdError::UnrecognizedCommand`].
+    fn set_source_capability(
+        &mut self,
+        _port: LocalPortId,
+        _capability: policy::PowerCapability,
+    ) -> impl Future<Output = Result<(), Error<Self::BusError>>> {
+        async { Err(Error::Pd(PdError::UnrecognizedCommand)) }
+    }
     /// Set port unconstrained status
     fn set_unconstrained_power(
         &mut self,
@@ -696,22 +883,6 @@ pub trait Controller {
         unconstrained: bool,
     ) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
 
-    // TODO: remove all these once we migrate to a generic FW update trait
-    // https://github.com/OpenDevicePartnership/embedded-services/issues/242
-    /// Get current FW version
-    fn get_active_fw_version(&mut self) -> impl Future<Output = Result<u32, Error<Self::BusError>>>;
-    /// Start a firmware update
-    fn start_fw_update(&mut self) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
-    /// Abort a firmware update
-    fn abort_fw_update(&mut self) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
-    /// Finalize a firmware update
-    fn finalize_fw_update(&mut self) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
-    /// Write firmware update contents
-    fn write_fw_contents(
-        &mut self,
-        offset: usize,
-        data: &[u8],
-    ) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
     /// Get the Rx Other VDM data for the given port
     fn get_other_vdm(&mut self, port: LocalPortId) -> impl Future<Output = Result<OtherVdm, Error<Self::BusError>>>;
     /// Get the Rx Attention VDM data for the given port
@@ -818,6 +989,48 @@ pub trait Controller {
             Error<Self::BusError>,
         >,
     >;
+
+    /// Send Get_Battery_Cap to the port partner and return the response
+    fn get_partner_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+    ) -> impl Future<Output = Result<BatteryCapabilities, Error<Self::BusError>>>;
+
+    /// Send Get_Battery_Status to the port partner and return the response
+    fn get_partner_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+    ) -> impl Future<Output = Result<BatteryStatus, Error<Self::BusError>>>;
+
+    /// Send Get_Source_Info to the port partner and return the response
+    fn get_partner_source_info(
+        &mut self,
+        port: LocalPortId,
+    ) -> impl Future<Output = Result<SourceInfo, Error<Self::BusError>>>;
+
+    /// Send Get_Status to the port partner and return the response
+    fn get_partner_status(
+        &mut self,
+        port: LocalPortId,
+    ) -> impl Future<Output = Result<PdStatus, Error<Self::BusError>>>;
+
+    /// Set the battery capabilities used to answer Get_Battery_Cap from the port partner
+    fn set_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+        capabilities: BatteryCapabilities,
+    ) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
+
+    /// Set the battery status used to answer Get_Battery_Status from the port partner
+    fn set_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+        status: BatteryStatus,
+    ) -> impl Future<Output = Result<(), Error<Self::BusError>>>;
 }
 
 /// Internal context for managing PD controllers
@@ -1464,6 +1677,91 @@ impl ContextToken {
         }
     }
 
+    /// Request battery capabilities from the port partner
+    pub async fn get_partner_battery_capabilities(
+        &self,
+        port: GlobalPortId,
+        battery: BatteryRef,
+    ) -> Result<BatteryCapabilities, PdError> {
+        match self
+            .send_port_command(port, PortCommandData::GetPartnerBatteryCapabilities(battery))
+            .await?
+        {
+            PortResponseData::BatteryCapabilities(capabilities) => Ok(capabilities),
+            r => {
+                error!("Invalid response: expected battery capabilities, got {:?}", r);
+                Err(PdError::InvalidResponse)
+            }
+        }
+    }
+
+    /// Request battery status from the port partner
+    pub async fn get_partner_battery_status(
+        &self,
+        port: GlobalPortId,
+        battery: BatteryRef,
+    ) -> Result<BatteryStatus, PdError> {
+        match self
+            .send_port_command(port, PortCommandData::GetPartnerBatteryStatus(battery))
+            .await?
+        {
+            PortResponseData::BatteryStatus(status) => Ok(status),
+            r => {
+                error!("Invalid response: expected battery status, got {:?}", r);
+                Err(PdError::InvalidResponse)
+            }
+        }
+    }
+
+    /// Request source information from the port partner
+    pub async fn get_partner_source_info(&self, port: GlobalPortId) -> Result<SourceInfo, PdError> {
+        match self
+            .send_port_command(port, PortCommandData::GetPartnerSourceInfo)
+            .await?
+        {
+            PortResponseData::SourceInfo(info) => Ok(info),
+            r => {
+                error!("Invalid response: expected source info, got {:?}", r);
+                Err(PdError::InvalidResponse)
+            }
+        }
+    }
+
+    /// Request status from the port partner
+    pub async fn get_partner_status(&self, port: GlobalPortId) -> Result<PdStatus, PdError> {
+        match self.send_port_command(port, PortCommandData::GetPartnerStatus).await? {
+            PortResponseData::PdStatus(status) => Ok(status),
+            r => {
+                error!("Invalid response: expected PD status, got {:?}", r);
+                Err(PdError::InvalidResponse)
+            }
+        }
+    }
+
+    /// Set the battery capabilities reported to the port partner
+    pub async fn set_battery_capabilities(
+        &self,
+        port: GlobalPortId,
+        battery: BatteryRef,
+        capabilities: BatteryCapabilities,
+    ) -> Result<(), PdError> {
+        self.send_port_command(port, PortCommandData::SetBatteryCapabilities(battery, capabilities))
+            .await?
+            .complete_or_err()
+    }
+
+    /// Set the battery status reported to the port partner
+    pub async fn set_battery_status(
+        &self,
+        port: GlobalPortId,
+        battery: BatteryRef,
+        status: BatteryStatus,
+    ) -> Result<(), PdError> {
+        self.send_port_command(port, PortCommandData::SetBatteryStatus(battery, status))
+            .await?
+            .complete_or_err()
+    }
+
     /// Broadcast a type-C message to all subscribers
     pub async fn broadcast_message(&self, message: CommsMessage) {
         CONTEXT.broadcaster.broadcast(message).await;
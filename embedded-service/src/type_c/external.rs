@@ -6,7 +6,8 @@ use embedded_usb_pd::{GlobalPortId, LocalPortId, PdError, ucsi};
 use crate::type_c::{
     Cached,
     controller::{
-        DiscoveredSvids, PdStateMachineConfig, SystemPowerState, TbtConfig, TypeCStateMachineState, UsbControlConfig,
+        BatteryCapabilities, BatteryRef, BatteryStatus, DataMode, DiscoveredSvids, PdStateMachineConfig, PdStatus,
+        SourceInfo, SystemPowerState, TbtConfig, TypeCStateMachineState, UsbControlConfig,
         execute_external_ucsi_command,
     },
 };
@@ -17,6 +18,7 @@ use super::{
         ControllerStatus, DpConfig, DpStatus, PortStatus, RetimerFwUpdateState, SendVdm,
         execute_external_controller_command, execute_external_port_command, lookup_controller, lookup_global_port,
     },
+    journal,
 };
 
 /// Data for controller-specific commands
@@ -111,6 +113,24 @@ pub enum PortCommandData {
     GetDiscoverIdentitySop,
     /// Get the response to a Discover Identity command sent to the given port with SOP'
     GetDiscoverIdentitySopPrime,
+    /// Get the data mode currently active on the given port
+    GetDataMode,
+    /// Request battery capabilities from the port partner
+    GetPartnerBatteryCapabilities(BatteryRef),
+    /// Request battery status from the port partner
+    GetPartnerBatteryStatus(BatteryRef),
+    /// Request source information from the port partner
+    GetPartnerSourceInfo,
+    /// Request status from the port partner
+    GetPartnerStatus,
+    /// Set the battery capabilities reported to the port partner
+    SetBatteryCapabilities(BatteryRef, BatteryCapabilities),
+    /// Set the battery status reported to the port partner
+    SetBatteryStatus(BatteryRef, BatteryStatus),
+    /// Get the oldest journal entry with a sequence number at or after the given one
+    GetJournalEntry(u32),
+    /// Clear the port's event journal
+    ClearJournal,
 }
 
 /// Port-specific commands
@@ -141,6 +161,18 @@ pub enum PortResponseData {
     DiscoverIdentitySop(embedded_usb_pd::vdm::structured::command::discover_identity::sop::ResponseVdos),
     /// Discover Identity response data for SOP'
     DiscoverIdentitySopPrime(embedded_usb_pd::vdm::structured::command::discover_identity::sop_prime::ResponseVdos),
+    /// Data mode currently active on the port
+    DataMode(DataMode),
+    /// Port partner battery capabilities
+    BatteryCapabilities(BatteryCapabilities),
+    /// Port partner battery status
+    BatteryStatus(BatteryStatus),
+    /// Port partner source information
+    SourceInfo(SourceInfo),
+    /// Port partner status
+    PdStatus(PdStatus),
+    /// Journal entry, [`None`] if there are no more entries
+    JournalEntry(Option<journal::Entry>),
 }
 
 /// Port-specific command response
@@ -577,3 +609,129 @@ pub async fn get_discover_identity_sop_prime_response(
         _ => Err(PdError::InvalidResponse),
     }
 }
+
+/// Get the data mode currently active on the given port.
+pub async fn get_data_mode(port: GlobalPortId) -> Result<DataMode, PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::GetDataMode,
+    }))
+    .await?
+    {
+        PortResponseData::DataMode(mode) => Ok(mode),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Request battery capabilities from the port partner on the given port.
+pub async fn get_partner_battery_capabilities(
+    port: GlobalPortId,
+    battery: BatteryRef,
+) -> Result<BatteryCapabilities, PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::GetPartnerBatteryCapabilities(battery),
+    }))
+    .await?
+    {
+        PortResponseData::BatteryCapabilities(capabilities) => Ok(capabilities),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Request battery status from the port partner on the given port.
+pub async fn get_partner_battery_status(port: GlobalPortId, battery: BatteryRef) -> Result<BatteryStatus, PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::GetPartnerBatteryStatus(battery),
+    }))
+    .await?
+    {
+        PortResponseData::BatteryStatus(status) => Ok(status),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Request source information from the port partner on the given port.
+pub async fn get_partner_source_info(port: GlobalPortId) -> Result<SourceInfo, PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::GetPartnerSourceInfo,
+    }))
+    .await?
+    {
+        PortResponseData::SourceInfo(info) => Ok(info),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Request status from the port partner on the given port.
+pub async fn get_partner_status(port: GlobalPortId) -> Result<PdStatus, PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::GetPartnerStatus,
+    }))
+    .await?
+    {
+        PortResponseData::PdStatus(status) => Ok(status),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Set the battery capabilities reported to the port partner on the given port.
+pub async fn set_battery_capabilities(
+    port: GlobalPortId,
+    battery: BatteryRef,
+    capabilities: BatteryCapabilities,
+) -> Result<(), PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::SetBatteryCapabilities(battery, capabilities),
+    }))
+    .await?
+    {
+        PortResponseData::Complete => Ok(()),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Set the battery status reported to the port partner on the given port.
+pub async fn set_battery_status(port: GlobalPortId, battery: BatteryRef, status: BatteryStatus) -> Result<(), PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::SetBatteryStatus(battery, status),
+    }))
+    .await?
+    {
+        PortResponseData::Complete => Ok(()),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Get the oldest journal entry on the given port with a sequence number at or after `sequence`.
+///
+/// Returns [`None`] if there are no more entries.
+pub async fn get_journal_entry(port: GlobalPortId, sequence: u32) -> Result<Option<journal::Entry>, PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::GetJournalEntry(sequence),
+    }))
+    .await?
+    {
+        PortResponseData::JournalEntry(entry) => Ok(entry),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
+
+/// Clear the event journal of the given port.
+pub async fn clear_journal(port: GlobalPortId) -> Result<(), PdError> {
+    match execute_external_port_command(Command::Port(PortCommand {
+        port,
+        data: PortCommandData::ClearJournal,
+    }))
+    .await?
+    {
+        PortResponseData::Complete => Ok(()),
+        _ => Err(PdError::InvalidResponse),
+    }
+}
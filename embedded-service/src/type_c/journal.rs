@@ -0,0 +1,218 @@
+//! Per-port PD event journal
+//!
+//! A bounded record of what happened on a port, used to debug field failures. Each entry has a sequence number that
+//! increases by one for each event so that readers can page through entries even while older entries are being
+//! discarded. Sequence numbers wrap around and are compared by their wrapping difference, so paging keeps working
+//! across the wrap.
+use embassy_time::Instant;
+use embedded_usb_pd::{PdError, PowerRole};
+use heapless::Deque;
+
+use crate::power::policy::PowerCapability;
+use crate::type_c::controller::DataMode;
+use crate::type_c::event::PortNotificationSingle;
+
+/// Default number of entries kept per port
+pub const DEFAULT_JOURNAL_LEN: usize = 16;
+
+/// Journal event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// Port partner attached
+    Attach {
+        /// Partner is a debug accessory
+        debug_accessory: bool,
+    },
+    /// Port partner detached
+    Detach,
+    /// New power contract
+    Contract {
+        /// Power role of the port
+        role: PowerRole,
+        /// Negotiated capability
+        capability: PowerCapability,
+        /// Partner is unconstrained
+        unconstrained: bool,
+        /// EPR mode active
+        epr: bool,
+        /// Partner supports dual-power roles
+        dual_power: bool,
+        /// Raw PDO of the contract, [`None`] for implicit contracts
+        pdo: Option<u32>,
+        /// Raw RDO of the contract, [`None`] for implicit contracts
+        rdo: Option<u32>,
+    },
+    /// PD hard reset
+    HardReset,
+    /// Alternate mode entered
+    AltModeEntered,
+    /// Data mode entered by the data mode policy
+    DataModeEntered(DataMode),
+    /// Port notification, e.g. PD alerts and VDMs
+    Notification(PortNotificationSingle),
+    /// Error while processing a port event
+    Error(PdError),
+}
+
+/// Journal entry
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Entry {
+    /// Sequence number, increases by one for each recorded event
+    pub sequence: u32,
+    /// Time the event was recorded, in milliseconds since boot
+    pub timestamp_ms: u64,
+    /// Recorded event
+    pub event: Event,
+}
+
+/// Returns true if sequence number `a` is at or after `b`, accounting for wraparound
+///
+/// Valid as long as the two sequence numbers are less than 2^31 apart, far more than any journal holds.
+fn sequence_at_or_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) >= 0
+}
+
+/// Bounded event journal, the oldest entries are discarded when full
+#[derive(Clone, Debug)]
+pub struct Journal<const N: usize = DEFAULT_JOURNAL_LEN> {
+    entries: Deque<Entry, N>,
+    next_sequence: u32,
+}
+
+impl<const N: usize> Journal<N> {
+    /// Create a new empty journal
+    pub const fn new() -> Self {
+        Self {
+            entries: Deque::new(),
+            next_sequence: 0,
+        }
+    }
+
+    /// Record an event with the current time
+    pub fn record(&mut self, event: Event) {
+        self.record_at(Instant::now().as_millis(), event);
+    }
+
+    /// Record an event with the given timestamp
+    pub fn record_at(&mut self, timestamp_ms: u64, event: Event) {
+        if self.entries.is_full() {
+            let _ = self.entries.pop_front();
+        }
+
+        // Can't fail, we just made space
+        let _ = self.entries.push_back(Entry {
+            sequence: self.next_sequence,
+            timestamp_ms,
+            event,
+        });
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+    }
+
+    /// Returns the oldest entry with a sequence number at or after `sequence`
+    ///
+    /// Start with a sequence number of zero and continue with the returned sequence number plus one (wrapping) to read
+    /// all entries.
+    pub fn get(&self, sequence: u32) -> Option<Entry> {
+        self.entries
+            .iter()
+            .find(|entry| sequence_at_or_after(entry.sequence, sequence))
+            .copied()
+    }
+
+    /// Iterate over all entries, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    /// Number of entries in the journal
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the journal is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove all entries, sequence numbers continue from where they left off
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<const N: usize> Default for Journal<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// Test that the oldest entries are discarded when the journal is full
+    #[test]
+    fn test_wraparound() {
+        let mut journal = Journal::<2>::new();
+        journal.record_at(1, Event::Attach { debug_accessory: false });
+        journal.record_at(2, Event::HardReset);
+        journal.record_at(3, Event::Detach);
+
+        assert_eq!(journal.len(), 2);
+        let entries: heapless::Vec<_, 2> = journal.iter().copied().collect();
+        assert_eq!(
+            entries.as_slice(),
+            &[
+                Entry {
+                    sequence: 1,
+                    timestamp_ms: 2,
+                    event: Event::HardReset,
+                },
+                Entry {
+                    sequence: 2,
+                    timestamp_ms: 3,
+                    event: Event::Detach,
+                },
+            ]
+        );
+    }
+
+    /// Test paging through entries by sequence number
+    #[test]
+    fn test_get() {
+        let mut journal = Journal::<2>::new();
+        journal.record_at(1, Event::Attach { debug_accessory: true });
+        journal.record_at(2, Event::HardReset);
+        journal.record_at(3, Event::Detach);
+
+        // Entry 0 was discarded, get the oldest remaining entry instead
+        assert_eq!(journal.get(0).unwrap().sequence, 1);
+        assert_eq!(journal.get(2).unwrap().event, Event::Detach);
+        assert_eq!(journal.get(3), None);
+
+        journal.clear();
+        assert!(journal.is_empty());
+        journal.record_at(4, Event::Error(PdError::Failed));
+        assert_eq!(journal.get(0).unwrap().sequence, 3);
+    }
+
+    /// Test paging through entries across a sequence number wraparound
+    #[test]
+    fn test_get_wraparound() {
+        let mut journal = Journal::<4>::new();
+        journal.next_sequence = u32::MAX - 1;
+        journal.record_at(1, Event::Attach { debug_accessory: false });
+        journal.record_at(2, Event::HardReset);
+        journal.record_at(3, Event::Detach);
+
+        assert_eq!(journal.get(u32::MAX).unwrap().event, Event::HardReset);
+        // Entries after the wrap are newer than entries before it
+        assert_eq!(journal.get(u32::MAX.wrapping_add(1)).unwrap().event, Event::Detach);
+        assert_eq!(journal.get(1), None);
+        // Reading from before the oldest entry returns the oldest entry
+        assert_eq!(journal.get(u32::MAX - 10).unwrap().sequence, u32::MAX - 1);
+    }
+}
@@ -9,6 +9,7 @@ pub mod comms;
 pub mod controller;
 pub mod event;
 pub mod external;
+pub mod journal;
 
 /// Controller ID
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -1,53 +1,284 @@
-use core::mem::offset_of;
-use core::slice;
-
 use core::borrow::BorrowMut;
-use embassy_imxrt::espi;
+use embassy_futures::select::select_array;
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::once_lock::OnceLock;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_services::buffer::OwnedRef;
 use embedded_services::comms::{self, EndpointID, External, Internal};
-use embedded_services::ec_type::message::{HostMsg, NotificationMsg, StdHostMsg, StdHostPayload, StdHostRequest};
+use embedded_services::ec_type::layout::{self, Access, OemSection, Section};
+use embedded_services::ec_type::message::{
+    HostMsg, NotificationMsg, OEM_MESSAGE_MAX_LEN, OdpCommand, OemMessage, StdHostMsg, StdHostPayload, StdHostRequest,
+};
 use embedded_services::ec_type::protocols::mctp;
-use embedded_services::{GlobalRawMutex, debug, ec_type, error, info, trace};
+use embedded_services::ec_type::structure::{EC_MEMMAP_VERSION, ECMemory, SectionId, SectionMessage, Version};
+use embedded_services::{GlobalRawMutex, debug, ec_type, error, info, trace, warn};
+use mctp_rs::mctp_completion_code::MctpCompletionCode;
 use mctp_rs::smbus_espi::SmbusEspiMedium;
 use mctp_rs::smbus_espi::SmbusEspiReplyContext;
 
-const HOST_TX_QUEUE_SIZE: usize = 5;
+use crate::notification::NotificationQueue;
+use crate::transport::{self, Event, Transport};
 
-// OOB port number for NXP IMXRT
-// REVISIT: When adding support for other platforms, refactor this as they don't have a notion of port IDs
-const OOB_PORT_ID: usize = 1;
+// Battery, thermal, debug and power get their own queues, other services share the last one
+const SERVICE_COUNT: usize = 5;
+// Responses and notifications queued per service
+const SERVICE_TX_QUEUE_SIZE: usize = 2;
+// Requests that can be waiting for a response from a single service
+const MAX_IN_FLIGHT_PER_SERVICE: usize = 4;
 
 // Should be as large as the largest possible MCTP packet and it's metadata.
 const ASSEMBLY_BUF_SIZE: usize = 256;
+// Should be as large as a single OOB packet, including the PEC
+const PACKET_BUF_SIZE: usize = 100;
+// OOB requests received while a response is being sent
+const OOB_QUEUE_SIZE: usize = 2;
+
+// Offsets in an MCTP over SMBus packet
+const SMBUS_BYTE_COUNT_OFFSET: usize = 2;
+const SMBUS_HEADER_LEN: usize = 3;
 
 embedded_services::define_static_buffer!(assembly_buf, u8, [0u8; ASSEMBLY_BUF_SIZE]);
 
 type HostMsgInternal = (EndpointID, StdHostMsg);
+type ReplyContext = mctp_rs::MctpReplyContext<SmbusEspiMedium>;
+type OobPacket = heapless::Vec<u8, PACKET_BUF_SIZE>;
+
+/// Request forwarded to a service and waiting for its response
+struct PendingRequest {
+    endpoint: EndpointID,
+    /// MCTP message tag, responses are matched to their request with it
+    tag: u8,
+    command: OdpCommand,
+    /// `None` once the request timed out, its late response is dropped
+    reply_context: Option<ReplyContext>,
+    deadline: Instant,
+}
+
+/// Requests in flight to a service, oldest first
+type InFlight = heapless::Vec<PendingRequest, MAX_IN_FLIGHT_PER_SERVICE>;
+
+/// Memory map shared with the host
+struct MemoryMap<'a> {
+    ec: &'a mut ECMemory,
+    /// OEM sections, after the standard ones
+    oem: &'a mut [u8],
+    /// Version negotiated with the host
+    version: Version,
+}
+
+/// Request received from the host
+enum HostRequest {
+    /// ODP request, forwarded to a service
+    Odp(EndpointID, StdHostRequest),
+    /// MCTP control request, answered by the eSPI service
+    Control(mctp::ControlHeader, mctp::MctpControl),
+}
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     Serialize,
     Buffer(embedded_services::buffer::Error),
+    Transport(transport::Error),
+    /// Packet error code mismatch
+    Pec,
+}
+
+/// eSPI service configuration
+///
+/// Responses echo the endpoint IDs of the request they answer, these are only used for messages that aren't a reply
+/// to a request.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// EC endpoint ID, until the host assigns one with Set Endpoint ID
+    pub ec_eid: u8,
+    /// Host endpoint ID for battery messages
+    pub battery_eid: u8,
+    /// Host endpoint ID for thermal messages
+    pub thermal_eid: u8,
+    /// Host endpoint ID for debug messages
+    pub debug_eid: u8,
+    /// Host endpoint ID for power policy messages
+    pub power_eid: u8,
+    /// Time a service has to answer a request before the host gets an error response
+    pub request_timeout: Duration,
+    /// OEM sections placed after the standard sections of the memory map
+    pub oem_sections: &'static [OemSection],
+    /// Time informational notifications are held to coalesce duplicates
+    pub notification_window: Duration,
+    /// Reject OOB packets without a PEC, some hosts leave it out
+    pub require_pec: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ec_eid: 0x80,
+            battery_eid: 8,
+            thermal_eid: 9,
+            debug_eid: 10,
+            power_eid: 11,
+            request_timeout: Duration::from_secs(2),
+            oem_sections: &[],
+            notification_window: Duration::from_millis(20),
+            require_pec: true,
+        }
+    }
 }
 
 pub struct Service<'a> {
     endpoint: comms::Endpoint,
-    ec_memory: Mutex<GlobalRawMutex, &'a mut ec_type::structure::ECMemory>,
-    host_tx_queue: Channel<GlobalRawMutex, HostMsgInternal, HOST_TX_QUEUE_SIZE>,
+    config: Config,
+    /// Current EC endpoint ID
+    eid: Mutex<GlobalRawMutex, u8>,
+    memory: Mutex<GlobalRawMutex, MemoryMap<'a>>,
+    /// Responses and notifications from each service
+    host_tx_queues: [Channel<GlobalRawMutex, HostMsgInternal, SERVICE_TX_QUEUE_SIZE>; SERVICE_COUNT],
     assembly_buf_owned_ref: OwnedRef<'a, u8>,
+    /// Requests in flight to each service
+    in_flight: Mutex<GlobalRawMutex, [InFlight; SERVICE_COUNT]>,
+    /// Notifications waiting to be sent or acknowledged by the host
+    notifications: Mutex<GlobalRawMutex, NotificationQueue>,
+    /// OOB requests waiting to be processed
+    oob_queue: Channel<GlobalRawMutex, OobPacket, OOB_QUEUE_SIZE>,
 }
 
 impl Service<'_> {
-    pub fn new(ec_memory: &'static mut ec_type::structure::ECMemory) -> Self {
+    pub fn new(ec_memory: &'static mut ECMemory, oem_memory: &'static mut [u8], config: Config) -> Self {
+        ec_memory.ver = EC_MEMMAP_VERSION;
         Service {
             endpoint: comms::Endpoint::uninit(EndpointID::External(External::Host)),
-            ec_memory: Mutex::new(ec_memory),
-            host_tx_queue: Channel::new(),
+            config,
+            eid: Mutex::new(config.ec_eid),
+            memory: Mutex::new(MemoryMap {
+                ec: ec_memory,
+                oem: oem_memory,
+                version: EC_MEMMAP_VERSION,
+            }),
+            host_tx_queues: [const { Channel::new() }; SERVICE_COUNT],
             assembly_buf_owned_ref: assembly_buf::get_mut().unwrap(),
+            in_flight: Mutex::new(Default::default()),
+            notifications: Mutex::new(NotificationQueue::new(config.notification_window)),
+            oob_queue: Channel::new(),
+        }
+    }
+
+    /// Track a request forwarded to a service, gives the reply context back if too many are already in flight
+    ///
+    /// A request reusing the tag of one in flight replaces it, the host no longer expects the older response. Requests
+    /// that timed out make room for new ones.
+    async fn track_request(
+        &self,
+        endpoint: EndpointID,
+        tag: u8,
+        command: OdpCommand,
+        reply_context: ReplyContext,
+    ) -> Result<(), ReplyContext> {
+        let mut in_flight = self.in_flight.lock().await;
+        let in_flight = &mut in_flight[service_index(endpoint)];
+        in_flight.retain(|request| request.tag != tag);
+        if in_flight.is_full() {
+            let Some(index) = in_flight.iter().position(|request| request.reply_context.is_none()) else {
+                return Err(reply_context);
+            };
+            in_flight.remove(index);
+        }
+
+        // There's room for the request after the checks above
+        let _ = in_flight.push(PendingRequest {
+            endpoint,
+            tag,
+            command,
+            reply_context: Some(reply_context),
+            deadline: Instant::now() + self.config.request_timeout,
+        });
+        Ok(())
+    }
+
+    /// Reply context for a response from a service
+    ///
+    /// The response is matched to its request by message tag. Falls back to the configured endpoint IDs for
+    /// unsolicited messages. Also returns true if the EC owns the message tag. Returns `None` for late responses to
+    /// requests that timed out.
+    async fn take_reply_context(&self, endpoint: EndpointID, tag: u8) -> Result<Option<(ReplyContext, bool)>, Error> {
+        let mut in_flight = self.in_flight.lock().await;
+        let in_flight = &mut in_flight[service_index(endpoint)];
+        if let Some(index) = in_flight.iter().position(|request| request.tag == tag) {
+            return Ok(in_flight
+                .remove(index)
+                .reply_context
+                .map(|reply_context| (reply_context, false)));
+        }
+
+        let host_eid = match endpoint {
+            EndpointID::Internal(Internal::Battery) => self.config.battery_eid,
+            EndpointID::Internal(Internal::Thermal) => self.config.thermal_eid,
+            EndpointID::Internal(Internal::Power) => self.config.power_eid,
+            _ => self.config.debug_eid,
+        };
+        let reply_context = mctp_rs::MctpReplyContext {
+            source_endpoint_id: mctp_rs::EndpointId::Id(*self.eid.lock().await),
+            destination_endpoint_id: mctp_rs::EndpointId::Id(host_eid),
+            packet_sequence_number: mctp_rs::MctpSequenceNumber::new(0),
+            message_tag: mctp_rs::MctpMessageTag::try_from(0).map_err(|e| {
+                error!("take_reply_context: {:?}", e);
+                Error::Serialize
+            })?,
+            medium_context: SmbusEspiReplyContext {
+                destination_slave_address: 1,
+                source_slave_address: 0,
+            },
+        };
+        Ok(Some((reply_context, true)))
+    }
+
+    /// Wait for the oldest request in flight to time out, returns the service it was sent to
+    pub(crate) async fn wait_for_request_timeout(&self) -> EndpointID {
+        let oldest = {
+            let in_flight = self.in_flight.lock().await;
+            in_flight
+                .iter()
+                .flatten()
+                .filter(|request| request.reply_context.is_some())
+                .min_by_key(|request| request.deadline)
+                .map(|request| (request.endpoint, request.deadline))
+        };
+
+        match oldest {
+            Some((endpoint, deadline)) => {
+                Timer::at(deadline).await;
+                endpoint
+            }
+            // Requests are only added from the task loop, which starts a new wait afterwards
+            None => core::future::pending().await,
+        }
+    }
+
+    /// Answer the requests in flight to a service that timed out with an error
+    pub(crate) async fn process_request_timeout(&self, transport: &mut impl Transport, endpoint: EndpointID) {
+        loop {
+            let now = Instant::now();
+            let timed_out = {
+                let mut in_flight = self.in_flight.lock().await;
+                // The request stays in flight without its reply context, so that a late response is dropped
+                in_flight[service_index(endpoint)]
+                    .iter_mut()
+                    .filter(|request| request.deadline <= now)
+                    .find_map(|request| Some((request.tag, request.command, request.reply_context.take()?)))
+            };
+            let Some((tag, command, reply_context)) = timed_out else {
+                return;
+            };
+
+            warn!("Request to {:?} with tag {} timed out", endpoint, tag);
+            if let Err(e) = self
+                .send_error_response(transport, endpoint, command, reply_context, MctpCompletionCode::Error)
+                .await
+            {
+                error!("Failed to send timeout response: {:?}", e);
+            }
         }
     }
 
@@ -55,218 +286,351 @@ impl Service<'_> {
         let mut offset = offset;
         let mut length = length;
 
-        if offset + length > size_of::<ec_type::structure::ECMemory>() {
+        let memory_len = {
+            let memory = self.memory.lock().await;
+            size_of::<ECMemory>() + memory.oem.len()
+        };
+        if offset + length > memory_len {
             return Err(ec_type::Error::InvalidLocation);
         }
 
         while length > 0 {
-            if (offset >= offset_of!(ec_type::structure::ECMemory, ver)
-                && offset < offset_of!(ec_type::structure::ECMemory, ver) + size_of::<ec_type::structure::Version>())
-                || (offset >= offset_of!(ec_type::structure::ECMemory, caps)
-                    && offset
-                        < offset_of!(ec_type::structure::ECMemory, caps)
-                            + size_of::<ec_type::structure::Capabilities>())
-            {
-                // This is a read-only section. eSPI master should not write to it.
+            let section =
+                layout::find_section(self.config.oem_sections, offset).ok_or(ec_type::Error::InvalidLocation)?;
+
+            let handled_by_ec = match section.id {
+                SectionId::Version => {
+                    self.negotiate_version().await?;
+                    true
+                }
+                SectionId::Notifications => {
+                    self.acknowledge_notification().await;
+                    true
+                }
+                _ => false,
+            };
+            if handled_by_ec {
+                // The whole section is handled at once
+                let end = section.offset + section.size;
+                length = length.saturating_sub(end - offset);
+                offset = end;
+                continue;
+            }
+
+            if section.access == Access::ReadOnly || !section.available_in(self.memory.lock().await.version) {
+                // eSPI master should not write to this section
                 return Err(ec_type::Error::InvalidLocation);
-            } else if offset >= offset_of!(ec_type::structure::ECMemory, batt)
-                && offset < offset_of!(ec_type::structure::ECMemory, batt) + size_of::<ec_type::structure::Battery>()
-            {
-                self.route_to_battery_service(&mut offset, &mut length).await?;
-            } else if offset >= offset_of!(ec_type::structure::ECMemory, therm)
-                && offset < offset_of!(ec_type::structure::ECMemory, therm) + size_of::<ec_type::structure::Thermal>()
-            {
-                self.route_to_thermal_service(&mut offset, &mut length).await?;
-            } else if offset >= offset_of!(ec_type::structure::ECMemory, alarm)
-                && offset < offset_of!(ec_type::structure::ECMemory, alarm) + size_of::<ec_type::structure::TimeAlarm>()
-            {
-                self.route_to_time_alarm_service(&mut offset, &mut length).await?;
+            }
+
+            match section.id {
+                SectionId::Oem(_) => self.route_to_oem_service(&section, &mut offset, &mut length).await?,
+                _ => self.route_to_owner(&section, &mut offset, &mut length).await?,
             }
         }
 
         Ok(())
     }
 
-    async fn route_to_battery_service(&self, offset: &mut usize, length: &mut usize) -> Result<(), ec_type::Error> {
-        let msg = {
-            let memory_map = self
-                .ec_memory
-                .try_lock()
-                .expect("Messages handled one after another, should be infallible.");
-            ec_type::mem_map_to_battery_msg(&memory_map, offset, length)?
-        };
-
-        comms::send(
-            EndpointID::External(External::Host),
-            EndpointID::Internal(Internal::Battery),
-            &msg,
-        )
-        .await
-        .unwrap();
+    /// Switch to the memory map version the host wrote in the version section
+    ///
+    /// The version section is restored if the requested version isn't supported.
+    async fn negotiate_version(&self) -> Result<(), ec_type::Error> {
+        let mut memory = self.memory.lock().await;
+        let requested = memory.ec.ver;
+        if EC_MEMMAP_VERSION.supports(requested) {
+            info!(
+                "Host selected memory map version {}.{}",
+                requested.major, requested.minor
+            );
+            memory.version = requested;
+            Ok(())
+        } else {
+            memory.ec.ver = memory.version;
+            Err(ec_type::Error::UnsupportedVersion)
+        }
+    }
 
-        Ok(())
+    /// Acknowledge the notification the host wrote in the notifications section
+    async fn acknowledge_notification(&self) {
+        let mut memory = self.memory.lock().await;
+        let mut notifications = self.notifications.lock().await;
+        let event = memory.ec.notif.event;
+        if !u8::try_from(event).is_ok_and(|offset| notifications.acknowledge(offset)) {
+            warn!("Host acknowledged notification {} that isn't pending", event);
+        }
+        memory.ec.notif = notifications.section();
     }
 
-    async fn route_to_thermal_service(&self, offset: &mut usize, length: &mut usize) -> Result<(), ec_type::Error> {
+    /// Forward a write to a standard section to the service that owns it
+    async fn route_to_owner(
+        &self,
+        section: &Section,
+        offset: &mut usize,
+        length: &mut usize,
+    ) -> Result<(), ec_type::Error> {
+        let owner = section.owner.ok_or(ec_type::Error::InvalidLocation)?;
         let msg = {
-            let memory_map = self
-                .ec_memory
+            let memory = self
+                .memory
                 .try_lock()
                 .expect("Messages handled one after another, should be infallible.");
-            ec_type::mem_map_to_thermal_msg(&memory_map, offset, length)?
+            memory.ec.field_message(section.id, offset, length)?
         };
 
-        comms::send(
-            EndpointID::External(External::Host),
-            EndpointID::Internal(Internal::Thermal),
-            &msg,
-        )
-        .await
-        .unwrap();
+        msg.send(EndpointID::External(External::Host), owner).await.unwrap();
 
         Ok(())
     }
 
-    async fn route_to_time_alarm_service(&self, offset: &mut usize, length: &mut usize) -> Result<(), ec_type::Error> {
+    /// Forward a write to an OEM section to the service that owns it
+    async fn route_to_oem_service(
+        &self,
+        section: &Section,
+        offset: &mut usize,
+        length: &mut usize,
+    ) -> Result<(), ec_type::Error> {
+        let (SectionId::Oem(id), Some(owner)) = (section.id, section.owner) else {
+            return Err(ec_type::Error::InvalidLocation);
+        };
+
+        let section_offset = *offset - section.offset;
+        let len = (*length).min(section.size - section_offset).min(OEM_MESSAGE_MAX_LEN);
         let msg = {
-            let memory_map = self
-                .ec_memory
+            let memory = self
+                .memory
                 .try_lock()
                 .expect("Messages handled one after another, should be infallible.");
-            ec_type::mem_map_to_time_alarm_msg(&memory_map, offset, length)?
+            let start = *offset - size_of::<ECMemory>();
+            let data = memory
+                .oem
+                .get(start..start + len)
+                .ok_or(ec_type::Error::InvalidLocation)?;
+            OemMessage {
+                section: id,
+                offset: section_offset,
+                data: heapless::Vec::from_slice(data).map_err(|_| ec_type::Error::InvalidLocation)?,
+            }
         };
+        *offset += len;
+        *length -= len;
 
-        comms::send(
-            EndpointID::External(External::Host),
-            EndpointID::Internal(Internal::TimeAlarm),
-            &msg,
-        )
-        .await
-        .unwrap();
+        comms::send(EndpointID::External(External::Host), owner, &msg)
+            .await
+            .unwrap();
 
         Ok(())
     }
 
     pub(crate) async fn wait_for_subsystem_msg(&self) -> HostMsgInternal {
-        self.host_tx_queue.receive().await
+        select_array(self.host_tx_queues.each_ref().map(|queue| queue.receive()))
+            .await
+            .0
     }
 
-    pub(crate) async fn process_subsystem_msg(&self, espi: &mut espi::Espi<'static>, host_msg: HostMsgInternal) {
+    pub(crate) async fn process_subsystem_msg(&self, transport: &mut impl Transport, host_msg: HostMsgInternal) {
         let (endpoint, host_msg) = host_msg;
         match host_msg {
-            HostMsg::Notification(notification_msg) => self.process_notification_to_host(espi, &notification_msg).await,
-            HostMsg::Response(acpi_msg_comms) => self.process_response_to_host(espi, &acpi_msg_comms, endpoint).await,
+            HostMsg::Notification(notification_msg) => {
+                self.process_notification_to_host(transport, endpoint, &notification_msg)
+                    .await
+            }
+            HostMsg::Response(acpi_msg_comms) => {
+                self.process_response_to_host(transport, &acpi_msg_comms, endpoint)
+                    .await
+            }
+        }
+    }
+
+    async fn process_notification_to_host(
+        &self,
+        transport: &mut impl Transport,
+        endpoint: EndpointID,
+        notification: &NotificationMsg,
+    ) {
+        let queued = self
+            .notifications
+            .lock()
+            .await
+            .push(*notification, odp_service(endpoint), Instant::now());
+        if queued.is_err() {
+            warn!("Too many notifications pending, dropping id {}", notification.offset);
+        }
+
+        // Critical notifications don't wait for the next pass of the task loop
+        self.send_notifications(transport).await;
+    }
+
+    /// Wait until a queued notification has to be sent
+    pub(crate) async fn wait_for_notification(&self) {
+        let due = self.notifications.lock().await.next_due();
+        match due {
+            Some(due) => Timer::at(due).await,
+            // Notifications are only queued from the task loop, which starts a new wait afterwards
+            None => core::future::pending().await,
         }
     }
 
-    async fn process_notification_to_host(&self, espi: &mut espi::Espi<'_>, notification: &NotificationMsg) {
-        espi.irq_push(notification.offset).await;
-        info!("espi: Notification id {} sent to Host!", notification.offset);
+    /// Send the notifications that are due to the host
+    pub(crate) async fn send_notifications(&self, transport: &mut impl Transport) {
+        let mut sent = false;
+        loop {
+            let offset = self.notifications.lock().await.take_due(Instant::now());
+            let Some(offset) = offset else {
+                break;
+            };
+            transport.notify(offset).await;
+            info!("espi: Notification id {} sent to Host!", offset);
+            sent = true;
+        }
+
+        if sent {
+            let section = self.notifications.lock().await.section();
+            self.memory.lock().await.ec.notif = section;
+        }
     }
 
     async fn serialize_packet_from_subsystem(
         &self,
-        espi: &mut espi::Espi<'static>,
+        transport: &mut impl Transport,
         response: &StdHostRequest,
         endpoint: EndpointID,
     ) -> Result<(), Error> {
-        let mut assembly_buf_access = self.assembly_buf_owned_ref.borrow_mut().map_err(Error::Buffer)?;
-        let pkt_ctx_buf = assembly_buf_access.borrow_mut();
-        let mut mctp_ctx = mctp_rs::MctpPacketContext::new(mctp_rs::smbus_espi::SmbusEspiMedium, pkt_ctx_buf);
-
-        let reply_context: mctp_rs::MctpReplyContext<SmbusEspiMedium> = mctp_rs::MctpReplyContext {
-            source_endpoint_id: mctp_rs::EndpointId::Id(0x80),
-            destination_endpoint_id: match endpoint {
-                EndpointID::Internal(Internal::Battery) => mctp_rs::EndpointId::Id(8),
-                EndpointID::Internal(Internal::Thermal) => mctp_rs::EndpointId::Id(9),
-                EndpointID::Internal(Internal::Debug) => mctp_rs::EndpointId::Id(10),
-                _ => mctp_rs::EndpointId::Id(0x80),
-            },
-            packet_sequence_number: mctp_rs::MctpSequenceNumber::new(0),
-            message_tag: mctp_rs::MctpMessageTag::try_from(3).map_err(|e| {
-                error!("serialize_packet_from_subsystem: {:?}", e);
-                Error::Serialize
-            })?,
-            medium_context: SmbusEspiReplyContext {
-                destination_slave_address: 1,
-                source_slave_address: 0,
-            }, // Medium-specific context
+        let Some((reply_context, tag_owner)) = self.take_reply_context(endpoint, response.tag).await? else {
+            warn!("Dropping late response from {:?}", endpoint);
+            return Ok(());
         };
 
         let header = mctp::OdpHeader {
             request_bit: false,
             datagram_bit: false,
-            service: match endpoint {
-                EndpointID::Internal(Internal::Battery) => mctp::OdpService::Battery,
-                EndpointID::Internal(Internal::Thermal) => mctp::OdpService::Thermal,
-                EndpointID::Internal(Internal::Debug) => mctp::OdpService::Debug,
-                _ => mctp::OdpService::Debug,
-            },
+            service: odp_service(endpoint),
             command_code: response.command.into(),
             completion_code: Default::default(),
         };
 
-        let mut packet_state = mctp_ctx
-            .serialize_packet(reply_context, (header, response.payload))
-            .map_err(|e| {
-                error!("serialize_packet_from_subsystem: {:?}", e);
-                Error::Serialize
-            })?;
+        self.send_message(transport, reply_context, tag_owner, (header, response.payload))
+            .await
+    }
+
+    /// Answer a request with an ODP error response
+    async fn send_error_response(
+        &self,
+        transport: &mut impl Transport,
+        endpoint: EndpointID,
+        command: OdpCommand,
+        reply_context: ReplyContext,
+        completion_code: MctpCompletionCode,
+    ) -> Result<(), Error> {
+        let header = mctp::OdpHeader {
+            request_bit: false,
+            datagram_bit: false,
+            service: odp_service(endpoint),
+            command_code: command.into(),
+            completion_code,
+        };
+
+        self.send_message(
+            transport,
+            reply_context,
+            false,
+            (header, StdHostPayload::ErrorResponse {}),
+        )
+        .await
+    }
+
+    /// Serialize a message and send it to the host one packet at a time
+    async fn send_message<'m, P: mctp_rs::MctpMessageTrait<'m>>(
+        &self,
+        transport: &mut impl Transport,
+        mut reply_context: ReplyContext,
+        tag_owner: bool,
+        message: (P::Header, P),
+    ) -> Result<(), Error> {
+        // Requests may be addressed to the null EID, always answer from the current one
+        reply_context.source_endpoint_id = mctp_rs::EndpointId::Id(*self.eid.lock().await);
+
+        let mut assembly_buf_access = self.assembly_buf_owned_ref.borrow_mut().map_err(Error::Buffer)?;
+        let pkt_ctx_buf = assembly_buf_access.borrow_mut();
+        let mut mctp_ctx = mctp_rs::MctpPacketContext::new(mctp_rs::smbus_espi::SmbusEspiMedium, pkt_ctx_buf);
+
+        let mut packet_state = mctp_ctx.serialize_packet(reply_context, message).map_err(|e| {
+            error!("send_message: {:?}", e);
+            Error::Serialize
+        })?;
         // Send each packet
+        let mut sequence = 0;
         while let Some(packet_result) = packet_state.next() {
             let packet = packet_result.map_err(|e| {
-                error!("serialize_packet_from_subsystem: {:?}", e);
+                error!("send_message: {:?}", e);
                 Error::Serialize
             })?;
-            // Last byte is PEC, ignore for now
-            let packet = &packet[..packet.len() - 1];
+
+            let mut buf = [0u8; PACKET_BUF_SIZE];
+            let buf = buf.get_mut(..packet.len()).ok_or(Error::Serialize)?;
+            buf.copy_from_slice(packet);
+            mctp::finalize_smbus_packet(buf, sequence, tag_owner).ok_or(Error::Serialize)?;
+            sequence = sequence.wrapping_add(1);
+
+            let packet = &*buf;
             #[cfg(feature = "defmt")]
             trace!("Sending MCTP response: {:?}", packet);
 
-            self.write_to_hw(espi, packet).map_err(|e| {
-                error!("serialize_packet_from_subsystem: {:?}", e);
-                Error::Serialize
+            transport.oob_write(packet).map_err(|e| {
+                error!("send_message: {:?}", e);
+                Error::Transport(e)
             })?;
 
-            // Immediately service the packet with the ESPI HAL
-            let event = espi.wait_for_event().await;
-            process_controller_event(espi, self, event).await?;
+            // Immediately service the packet with the transport
+            let event = transport.wait_event().await;
+            process_link_event(transport, self, event).await;
         }
         Ok(())
     }
 
-    fn write_to_hw(&self, espi: &mut espi::Espi<'static>, packet: &[u8]) -> Result<(), embassy_imxrt::espi::Error> {
-        // Send packet via your transport medium
-        // SAFETY: Safe as the access to espi is protected by a mut reference.
-        let dest_slice = unsafe { espi.oob_get_write_buffer(OOB_PORT_ID)? };
-        dest_slice[..packet.len()].copy_from_slice(&packet[..packet.len()]);
+    /// Answer an MCTP control request
+    async fn process_control_request(
+        &self,
+        transport: &mut impl Transport,
+        header: mctp::ControlHeader,
+        request: mctp::MctpControl,
+        reply_context: ReplyContext,
+    ) -> Result<(), Error> {
+        // Set Endpoint ID is already answered from the new endpoint ID
+        let (header, response) = mctp::control_response(&header, &request, &mut *self.eid.lock().await);
+        info!(
+            "MCTP control request {:?}, completion {:?}",
+            header.command_code, header.completion_code
+        );
 
-        // Write response over OOB
-        espi.oob_write_data(OOB_PORT_ID, packet.len() as u8)
+        self.send_message(transport, reply_context, false, (header, response))
+            .await
     }
 
-    fn send_mctp_error_response(&self, endpoint: EndpointID, espi: &mut espi::Espi<'static>) {
+    fn send_mctp_error_response(&self, endpoint: EndpointID, transport: &mut impl Transport) {
         // SAFETY: Unwrap is safe here as battery will always be supported.
         // Data is ACPI payload [version, instance, reserved (error status), command]
         let (final_packet, final_packet_size) = mctp::build_mctp_header(&[0, 0, 0, 1], 4, endpoint, true, true)
             .expect("Unexpected error building MCTP header");
 
-        if let Err(e) = self.write_to_hw(espi, &final_packet[..final_packet_size]) {
+        if let Err(e) = transport.oob_write(&final_packet[..final_packet_size]) {
             error!("Critical error sending error response: {:?}", e);
         }
     }
 
     async fn process_response_to_host(
         &self,
-        espi: &mut espi::Espi<'static>,
+        transport: &mut impl Transport,
         response: &StdHostRequest,
         endpoint: EndpointID,
     ) {
-        match self.serialize_packet_from_subsystem(espi, response, endpoint).await {
+        match self
+            .serialize_packet_from_subsystem(transport, response, endpoint)
+            .await
+        {
             Err(e) => {
                 error!("Packet serialize error {:?}", e);
 
-                self.send_mctp_error_response(endpoint, espi);
+                self.send_mctp_error_response(endpoint, transport);
             }
             Ok(()) => {
                 trace!("Full packet successfully sent to host!")
@@ -284,22 +648,23 @@ impl comms::MailboxDelegate for Service<'_> {
         if let Some(msg) = message.data.get::<StdHostMsg>() {
             let host_msg = (message.from, *msg);
             debug!("Espi service: recvd acpi response");
-            if self.host_tx_queue.try_send(host_msg).is_err() {
+            if self.host_tx_queues[service_index(message.from)]
+                .try_send(host_msg)
+                .is_err()
+            {
                 return Err(comms::MailboxDelegateError::BufferFull);
             }
         } else {
-            let mut memory_map = self
-                .ec_memory
+            let mut memory = self
+                .memory
                 .try_lock()
                 .expect("Messages handled one after another, should be infallible.");
-            if let Some(msg) = message.data.get::<ec_type::message::CapabilitiesMessage>() {
-                ec_type::update_capabilities_section(msg, &mut memory_map);
-            } else if let Some(msg) = message.data.get::<ec_type::message::BatteryMessage>() {
-                ec_type::update_battery_section(msg, &mut memory_map);
-            } else if let Some(msg) = message.data.get::<ec_type::message::ThermalMessage>() {
-                ec_type::update_thermal_section(msg, &mut memory_map);
-            } else if let Some(msg) = message.data.get::<ec_type::message::TimeAlarmMessage>() {
-                ec_type::update_time_alarm_section(msg, &mut memory_map);
+            if let Some(msg) = SectionMessage::from_data(&message.data) {
+                memory.ec.update(&msg);
+            } else if let Some(msg) = message.data.get::<OemMessage>() {
+                let memory = &mut *memory;
+                layout::update_oem_section(self.config.oem_sections, memory.oem, msg)
+                    .map_err(|_| comms::MailboxDelegateError::InvalidData)?;
             } else {
                 return Err(comms::MailboxDelegateError::MessageNotFound);
             }
@@ -311,159 +676,1022 @@ impl comms::MailboxDelegate for Service<'_> {
 
 pub(crate) static ESPI_SERVICE: OnceLock<Service> = OnceLock::new();
 
+/// Index of the queues used for a service
+fn service_index(endpoint: EndpointID) -> usize {
+    match endpoint {
+        EndpointID::Internal(Internal::Battery) => 0,
+        EndpointID::Internal(Internal::Thermal) => 1,
+        EndpointID::Internal(Internal::Debug) => 2,
+        EndpointID::Internal(Internal::Power) => 3,
+        _ => 4,
+    }
+}
+
+/// ODP service of an endpoint
+fn odp_service(endpoint: EndpointID) -> mctp::OdpService {
+    match endpoint {
+        EndpointID::Internal(Internal::Battery) => mctp::OdpService::Battery,
+        EndpointID::Internal(Internal::Thermal) => mctp::OdpService::Thermal,
+        EndpointID::Internal(Internal::Power) => mctp::OdpService::Power,
+        _ => mctp::OdpService::Debug,
+    }
+}
+
+/// Validate the PEC of a received packet, appending it if the host left it out and it isn't required
+///
+/// `buf` must have room for the PEC after the `len` bytes received. Returns the length of the packet including the PEC.
+fn check_pec(buf: &mut [u8], len: usize, required: bool) -> Result<usize, Error> {
+    let byte_count = *buf.get(SMBUS_BYTE_COUNT_OFFSET).ok_or(Error::Serialize)?;
+    let packet_len = SMBUS_HEADER_LEN + usize::from(byte_count);
+    if len < packet_len {
+        return Err(Error::Serialize);
+    }
+
+    let (packet, rest) = buf.split_at_mut_checked(packet_len).ok_or(Error::Serialize)?;
+    let pec = rest.first_mut().ok_or(Error::Serialize)?;
+    if len == packet_len {
+        if required {
+            return Err(Error::Pec);
+        }
+        *pec = mctp::smbus_pec(packet);
+    } else if *pec != mctp::smbus_pec(packet) {
+        return Err(Error::Pec);
+    }
+
+    // Anything after the PEC is padding
+    Ok(packet_len + 1)
+}
+
 pub(crate) async fn process_controller_event(
-    espi: &mut espi::Espi<'static>,
+    transport: &mut impl Transport,
     espi_service: &Service<'_>,
-    event: Result<embassy_imxrt::espi::Event, embassy_imxrt::espi::Error>,
+    event: Result<Event, transport::Error>,
 ) -> Result<(), Error> {
     match event {
-        Ok(espi::Event::PeripheralEvent(port_event)) => {
-            info!(
-                "eSPI PeripheralEvent Port: {}, direction: {}, address: {}, offset: {}, length: {}",
-                port_event.port, port_event.direction, port_event.offset, port_event.base_addr, port_event.length,
-            );
+        Ok(Event::OobPacket { .. }) => process_oob_packet(transport, espi_service).await,
+        event => {
+            process_link_event(transport, espi_service, event).await;
+            Ok(())
+        }
+    }
+}
 
-            // If it is a peripheral channel write, then we need to notify the service
-            if port_event.direction {
-                let res = espi_service
-                    .route_to_service(port_event.offset, port_event.length)
-                    .await;
-
-                if res.is_err() {
-                    error!(
-                        "eSPI master send invalid offset: {} length: {}",
-                        port_event.offset, port_event.length
-                    );
-                }
+/// Handle an event other than a request from the host
+async fn process_link_event(
+    transport: &mut impl Transport,
+    espi_service: &Service<'_>,
+    event: Result<Event, transport::Error>,
+) {
+    match event {
+        Ok(Event::MemoryWrite { offset, length }) => {
+            // Peripheral channel write, notify the service that owns the section
+            let res = espi_service.route_to_service(offset, length).await;
+
+            if res.is_err() {
+                error!("eSPI master send invalid offset: {} length: {}", offset, length);
             }
 
-            espi.complete_port(port_event.port);
+            transport.complete();
         }
-        Ok(espi::Event::OOBEvent(port_event)) => {
-            info!(
-                "eSPI OOBEvent Port: {}, direction: {}, address: {}, offset: {}, length: {}",
-                port_event.port, port_event.direction, port_event.offset, port_event.base_addr, port_event.length,
+        Ok(Event::MemoryRead { .. }) | Ok(Event::OobSent) => {
+            transport.complete();
+        }
+        Ok(Event::OobPacket { .. }) => {
+            // Only seen here while a response is being sent, processed once the response is out
+            match read_oob_packet(transport) {
+                Ok(packet) => {
+                    if espi_service.oob_queue.try_send(packet).is_err() {
+                        error!("eSPI OOB queue full, packet dropped");
+                    }
+                }
+                Err(e) => error!("eSPI OOB read error: {:?}", e),
+            }
+        }
+        Ok(Event::Port80) => {
+            info!("eSPI Port 80");
+        }
+        Ok(Event::WireChange) => {
+            info!("eSPI WireChange");
+        }
+        Err(e) => {
+            error!("eSPI Failed with error: {:?}", e);
+        }
+    }
+}
+
+/// Read the pending OOB packet and release the channel back to the host
+fn read_oob_packet(transport: &mut impl Transport) -> Result<OobPacket, Error> {
+    let mut buf = [0u8; PACKET_BUF_SIZE];
+    // Leave room for the PEC if the host left it out
+    let result = transport.oob_read(&mut buf[..PACKET_BUF_SIZE - 1]);
+    transport.complete();
+    let len = result.map_err(Error::Transport)?;
+    OobPacket::from_slice(&buf[..len]).map_err(|_| Error::Serialize)
+}
+
+/// Process the OOB requests received while a response was being sent
+pub(crate) async fn process_queued_oob_packets(
+    transport: &mut impl Transport,
+    espi_service: &Service<'_>,
+) -> Result<(), Error> {
+    while let Ok(packet) = espi_service.oob_queue.try_receive() {
+        process_oob_request(transport, espi_service, &packet).await?;
+    }
+    Ok(())
+}
+
+/// Handle a request from the host
+async fn process_oob_packet(transport: &mut impl Transport, espi_service: &Service<'_>) -> Result<(), Error> {
+    let packet = read_oob_packet(transport).inspect_err(|e| error!("eSPI OOB read error: {:?}", e))?;
+    process_oob_request(transport, espi_service, &packet).await
+}
+
+/// Handle an OOB request that was already read from the transport
+async fn process_oob_request(
+    transport: &mut impl Transport,
+    espi_service: &Service<'_>,
+    packet: &[u8],
+) -> Result<(), Error> {
+    let mut buf = [0u8; PACKET_BUF_SIZE];
+    let len = packet.len();
+    buf[..len].copy_from_slice(packet);
+
+    #[cfg(feature = "defmt")]
+    debug!("OOB message: {:02X}", &buf[..len]);
+
+    let with_pec = match check_pec(&mut buf, len, espi_service.config.require_pec) {
+        Ok(len) => &buf[..len],
+        Err(e) => {
+            error!("eSPI OOB packet rejected: {:?}", e);
+
+            // REVISIT: We don't know what subsystem the message was meant for, hardcode Debug.
+            espi_service.send_mctp_error_response(
+                EndpointID::Internal(embedded_services::comms::Internal::Debug),
+                transport,
             );
+            return Err(e);
+        }
+    };
 
-            if port_event.direction {
-                let src_slice = unsafe { slice::from_raw_parts(port_event.base_addr as *const u8, port_event.length) };
+    let request: HostRequest;
+    let reply_context: ReplyContext;
 
-                // TODO: This is a workaround because mctp_rs expects a PEC byte, so we hardcode a 0 at the end.
-                // We should add functionality to mctp_rs to disable PEC.
-                let mut with_pec = [0u8; 100];
-                with_pec[..src_slice.len()].copy_from_slice(src_slice);
-                with_pec[src_slice.len()] = 0;
-                let with_pec = &with_pec[..=src_slice.len()];
+    {
+        let mut assembly_access = espi_service
+            .assembly_buf_owned_ref
+            .borrow_mut()
+            .map_err(Error::Buffer)?;
+        let mut mctp_ctx =
+            mctp_rs::MctpPacketContext::<SmbusEspiMedium>::new(SmbusEspiMedium, assembly_access.borrow_mut());
 
+        match mctp_ctx.deserialize_packet(with_pec) {
+            Ok(Some(message)) => {
                 #[cfg(feature = "defmt")]
-                debug!("OOB message: {:02X}", &src_slice[0..]);
-
-                let host_request: StdHostRequest;
-                let endpoint: EndpointID;
-
-                {
-                    let mut assembly_access = espi_service
-                        .assembly_buf_owned_ref
-                        .borrow_mut()
-                        .map_err(Error::Buffer)?;
-                    // let mut comms_access = espi_service.comms_buf_owned_ref.borrow_mut();
-                    let mut mctp_ctx = mctp_rs::MctpPacketContext::<SmbusEspiMedium>::new(
-                        SmbusEspiMedium,
-                        assembly_access.borrow_mut(),
-                    );
-
-                    match mctp_ctx.deserialize_packet(with_pec) {
-                        Ok(Some(message)) => {
-                            #[cfg(feature = "defmt")]
-                            trace!("MCTP packet successfully deserialized");
-
-                            match message.parse_as::<StdHostPayload>() {
-                                Ok((header, body)) => {
-                                    host_request = StdHostRequest {
-                                        command: header.command_code.into(),
-                                        status: header.completion_code.into(),
-                                        payload: body,
-                                    };
-                                    endpoint = match header.service {
-                                        mctp::OdpService::Battery => {
-                                            EndpointID::Internal(embedded_services::comms::Internal::Battery)
-                                        }
-                                        mctp::OdpService::Thermal => {
-                                            EndpointID::Internal(embedded_services::comms::Internal::Thermal)
-                                        }
-                                        mctp::OdpService::Debug => {
-                                            EndpointID::Internal(embedded_services::comms::Internal::Debug)
-                                        }
-                                    };
-                                    #[cfg(feature = "defmt")]
-                                    trace!(
-                                        "Host Request: Service {:?}, Command {:?}, Status {:?}",
-                                        endpoint, host_request.command, host_request.status,
-                                    );
+                trace!("MCTP packet successfully deserialized");
+
+                if let Ok((header, control)) = message.parse_as::<mctp::MctpControl>() {
+                    if !header.request_bit {
+                        // The EC doesn't send control requests, so there's nothing to match a response to
+                        warn!("Unexpected MCTP control response {:?}, dropped", header.command_code);
+                        return Ok(());
+                    }
+                    request = HostRequest::Control(header, control);
+                    reply_context = message.reply_context;
+                } else {
+                    match message.parse_as::<StdHostPayload>() {
+                        Ok((header, body)) => {
+                            let host_request = StdHostRequest {
+                                command: header.command_code.into(),
+                                status: header.completion_code.into(),
+                                tag: mctp::smbus_message_tag(with_pec).unwrap_or_default(),
+                                payload: body,
+                            };
+                            let endpoint = match header.service {
+                                mctp::OdpService::Battery => {
+                                    EndpointID::Internal(embedded_services::comms::Internal::Battery)
                                 }
-                                Err(_e) => {
-                                    #[cfg(feature = "defmt")]
-                                    error!("MCTP ODP type malformed");
-                                    espi.complete_port(port_event.port);
-
-                                    // REVISIT: An error here means that we couldn't decode the incoming message,
-                                    // thus we don't know what subsystem the message was meant for. For now,
-                                    // hardcode Debug but we might need a special endpoint for error.
-                                    espi_service.send_mctp_error_response(
-                                        EndpointID::Internal(embedded_services::comms::Internal::Debug),
-                                        espi,
-                                    );
-                                    return Err(Error::Serialize);
+                                mctp::OdpService::Thermal => {
+                                    EndpointID::Internal(embedded_services::comms::Internal::Thermal)
                                 }
-                            }
-                        }
-                        Ok(None) => {
-                            // Partial message, waiting for more packets
-                            error!("Partial msg, should not happen");
-                            espi.complete_port(OOB_PORT_ID);
-
-                            // REVISIT: An error here means that we couldn't decode the incoming message,
-                            // thus we don't know what subsystem the message was meant for. For now,
-                            // hardcode Debug but we might need a special endpoint for error.
-                            espi_service.send_mctp_error_response(
-                                EndpointID::Internal(embedded_services::comms::Internal::Debug),
-                                espi,
+                                mctp::OdpService::Debug => {
+                                    EndpointID::Internal(embedded_services::comms::Internal::Debug)
+                                }
+                                mctp::OdpService::Power => {
+                                    EndpointID::Internal(embedded_services::comms::Internal::Power)
+                                }
+                            };
+                            #[cfg(feature = "defmt")]
+                            trace!(
+                                "Host Request: Service {:?}, Command {:?}, Status {:?}",
+                                endpoint, host_request.command, host_request.status,
                             );
-                            return Err(Error::Serialize);
+                            request = HostRequest::Odp(endpoint, host_request);
+                            reply_context = message.reply_context;
                         }
                         Err(_e) => {
-                            // Handle protocol or medium error
-                            error!("MCTP packet malformed");
-                            espi.complete_port(OOB_PORT_ID);
+                            #[cfg(feature = "defmt")]
+                            error!("MCTP ODP type malformed");
 
                             // REVISIT: An error here means that we couldn't decode the incoming message,
                             // thus we don't know what subsystem the message was meant for. For now,
                             // hardcode Debug but we might need a special endpoint for error.
                             espi_service.send_mctp_error_response(
                                 EndpointID::Internal(embedded_services::comms::Internal::Debug),
-                                espi,
+                                transport,
                             );
                             return Err(Error::Serialize);
                         }
                     }
                 }
+            }
+            Ok(None) => {
+                // Partial message, waiting for more packets
+                error!("Partial msg, should not happen");
 
-                espi.complete_port(port_event.port);
-                espi_service.endpoint.send(endpoint, &host_request).await.unwrap();
-                info!("MCTP packet forwarded to service: {:?}", endpoint);
-            } else {
-                espi.complete_port(port_event.port);
+                // REVISIT: An error here means that we couldn't decode the incoming message,
+                // thus we don't know what subsystem the message was meant for. For now,
+                // hardcode Debug but we might need a special endpoint for error.
+                espi_service.send_mctp_error_response(
+                    EndpointID::Internal(embedded_services::comms::Internal::Debug),
+                    transport,
+                );
+                return Err(Error::Serialize);
+            }
+            Err(_e) => {
+                // Handle protocol or medium error
+                error!("MCTP packet malformed");
+
+                // REVISIT: An error here means that we couldn't decode the incoming message,
+                // thus we don't know what subsystem the message was meant for. For now,
+                // hardcode Debug but we might need a special endpoint for error.
+                espi_service.send_mctp_error_response(
+                    EndpointID::Internal(embedded_services::comms::Internal::Debug),
+                    transport,
+                );
+                return Err(Error::Serialize);
             }
         }
-        Ok(espi::Event::Port80) => {
-            info!("eSPI Port 80");
+    }
+
+    match request {
+        HostRequest::Odp(endpoint, host_request) => {
+            if let Err(reply_context) = espi_service
+                .track_request(endpoint, host_request.tag, host_request.command, reply_context)
+                .await
+            {
+                error!("Too many requests in flight to {:?}", endpoint);
+                espi_service
+                    .send_error_response(
+                        transport,
+                        endpoint,
+                        host_request.command,
+                        reply_context,
+                        MctpCompletionCode::ErrorNotReady,
+                    )
+                    .await?;
+                return Ok(());
+            }
+
+            espi_service.endpoint.send(endpoint, &host_request).await.unwrap();
+            info!("MCTP packet forwarded to service: {:?}", endpoint);
+            Ok(())
         }
-        Ok(espi::Event::WireChange(_)) => {
-            info!("eSPI WireChange");
+        HostRequest::Control(header, control) => {
+            espi_service
+                .process_control_request(transport, header, control, reply_context)
+                .await
         }
-        Err(e) => {
-            error!("eSPI Failed with error: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::offset_of;
+
+    use super::*;
+    use crate::transport::loopback::{Host, Loopback, Packet};
+    use embedded_services::ec_type::message::{NotificationPriority, ThermalMessage};
+    use embedded_services::ec_type::protocols::acpi::BatteryCmd;
+    use embedded_services::ec_type::protocols::mctp::{ControlCommandCode, ControlHeader, MctpControl};
+    use embedded_services::ec_type::protocols::mptf::ThermalCmd;
+    use embedded_services::ec_type::structure::{Notifications, Thermal};
+    use static_cell::StaticCell;
+
+    /// Records messages sent to a service
+    struct Recorder<T> {
+        endpoint: comms::Endpoint,
+        messages: Channel<GlobalRawMutex, T, 4>,
+    }
+
+    impl<T> Recorder<T> {
+        const fn new(id: Internal) -> Self {
+            Self {
+                endpoint: comms::Endpoint::uninit(EndpointID::Internal(id)),
+                messages: Channel::new(),
+            }
         }
     }
-    Ok(())
+
+    impl<T: Clone + 'static> comms::MailboxDelegate for Recorder<T> {
+        fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+            let msg = message
+                .data
+                .get::<T>()
+                .ok_or(comms::MailboxDelegateError::MessageNotFound)?;
+            self.messages
+                .try_send(msg.clone())
+                .map_err(|_| comms::MailboxDelegateError::BufferFull)
+        }
+    }
+
+    static BATTERY: Recorder<StdHostRequest> = Recorder::new(Internal::Battery);
+    static THERMAL: Recorder<ThermalMessage> = Recorder::new(Internal::Thermal);
+    static OEM: Recorder<OemMessage> = Recorder::new(Internal::Oem(0));
+    static OEM_SECTIONS: [OemSection; 2] = [
+        OemSection {
+            id: 0,
+            offset: 0,
+            size: 4,
+            access: Access::ReadOnly,
+            owner: EndpointID::Internal(Internal::Oem(0)),
+        },
+        OemSection {
+            id: 1,
+            offset: 4,
+            size: 8,
+            access: Access::ReadWrite,
+            owner: EndpointID::Internal(Internal::Oem(0)),
+        },
+    ];
+    static SERVICE: OnceLock<Service> = OnceLock::new();
+    /// Tests exchanging OOB packets share the pending reply contexts and endpoint ID
+    static OOB_LOCK: Mutex<GlobalRawMutex, ()> = Mutex::new(());
+
+    async fn init() -> &'static Service<'static> {
+        static INIT: Mutex<GlobalRawMutex, bool> = Mutex::new(false);
+        static MEMORY: StaticCell<ECMemory> = StaticCell::new();
+        static OEM_MEMORY: StaticCell<[u8; 12]> = StaticCell::new();
+
+        let mut init = INIT.lock().await;
+        if !*init {
+            embedded_services::init().await;
+            comms::register_endpoint(&BATTERY, &BATTERY.endpoint).await.unwrap();
+            comms::register_endpoint(&THERMAL, &THERMAL.endpoint).await.unwrap();
+            comms::register_endpoint(&OEM, &OEM.endpoint).await.unwrap();
+            let config = Config {
+                request_timeout: Duration::from_millis(50),
+                oem_sections: &OEM_SECTIONS,
+                ..Default::default()
+            };
+            SERVICE.get_or_init(|| Service::new(MEMORY.init(ECMemory::default()), OEM_MEMORY.init([0; 12]), config));
+            *init = true;
+        }
+
+        SERVICE.get().await
+    }
+
+    /// Test routing memory window writes
+    #[tokio::test]
+    async fn test_memory_write() {
+        let service = init().await;
+        let host = Host::new();
+        let mut transport = Loopback::new(&host);
+
+        // Thermal section is forwarded to the thermal service
+        host.write_memory(offset_of!(ECMemory, therm) + offset_of!(Thermal, events), 4)
+            .await;
+        let event = transport.wait_event().await;
+        process_controller_event(&mut transport, service, event).await.unwrap();
+        assert_eq!(THERMAL.messages.try_receive(), Ok(ThermalMessage::Events(0)));
+
+        // Read-only and out of range writes are rejected
+        assert_eq!(
+            service.route_to_service(offset_of!(ECMemory, caps), 4).await,
+            Err(ec_type::Error::InvalidLocation)
+        );
+        assert_eq!(
+            service.route_to_service(size_of::<ECMemory>(), 4).await,
+            Err(ec_type::Error::InvalidLocation)
+        );
+        assert_eq!(
+            service.route_to_service(size_of::<ECMemory>() + 12, 4).await,
+            Err(ec_type::Error::InvalidLocation)
+        );
+    }
+
+    /// Test negotiating the memory map version through the version section
+    #[tokio::test]
+    async fn test_version_negotiation() {
+        let service = init().await;
+
+        // Other major versions are rejected and the version section is restored
+        service.memory.lock().await.ec.ver.major = EC_MEMMAP_VERSION.major + 1;
+        assert_eq!(
+            service.route_to_service(offset_of!(ECMemory, ver), 4).await,
+            Err(ec_type::Error::UnsupportedVersion)
+        );
+        assert_eq!(service.memory.lock().await.ec.ver, EC_MEMMAP_VERSION);
+
+        // The supported version is accepted
+        assert_eq!(service.route_to_service(offset_of!(ECMemory, ver), 4).await, Ok(()));
+        assert_eq!(service.memory.lock().await.version, EC_MEMMAP_VERSION);
+    }
+
+    /// Test routing writes to OEM sections and updating them from services
+    #[tokio::test]
+    async fn test_oem_sections() {
+        let service = init().await;
+        let oem_base = size_of::<ECMemory>();
+
+        service.memory.lock().await.oem[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(service.route_to_service(oem_base + 5, 2).await, Ok(()));
+        assert_eq!(
+            OEM.messages.try_receive(),
+            Ok(OemMessage {
+                section: 1,
+                offset: 1,
+                data: heapless::Vec::from_slice(&[2, 3]).unwrap(),
+            })
+        );
+
+        // Read-only OEM section
+        assert_eq!(
+            service.route_to_service(oem_base, 4).await,
+            Err(ec_type::Error::InvalidLocation)
+        );
+
+        let msg = OemMessage {
+            section: 0,
+            offset: 0,
+            data: heapless::Vec::from_slice(&[5, 6, 7, 8]).unwrap(),
+        };
+        let message = comms::Message {
+            from: EndpointID::Internal(Internal::Oem(0)),
+            to: EndpointID::External(External::Host),
+            data: comms::Data::new(&msg),
+        };
+        assert!(comms::MailboxDelegate::receive(service, &message).is_ok());
+        assert_eq!(service.memory.lock().await.oem[..4], [5, 6, 7, 8]);
+    }
+
+    /// Test forwarding OOB requests to services and sending responses back
+    #[tokio::test]
+    async fn test_oob() {
+        let _lock = OOB_LOCK.lock().await;
+        let service = init().await;
+        let host = Host::new();
+        let mut transport = Loopback::new(&host);
+
+        // Build a request the way the host would
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = mctp_rs::MctpPacketContext::new(SmbusEspiMedium, &mut buf);
+        let context: mctp_rs::MctpReplyContext<SmbusEspiMedium> = mctp_rs::MctpReplyContext {
+            source_endpoint_id: mctp_rs::EndpointId::Id(8),
+            destination_endpoint_id: mctp_rs::EndpointId::Id(0x80),
+            packet_sequence_number: mctp_rs::MctpSequenceNumber::new(0),
+            message_tag: mctp_rs::MctpMessageTag::try_from(3).unwrap(),
+            medium_context: SmbusEspiReplyContext {
+                destination_slave_address: 0,
+                source_slave_address: 1,
+            },
+        };
+        let header = mctp::OdpHeader {
+            request_bit: true,
+            datagram_bit: false,
+            service: mctp::OdpService::Battery,
+            command_code: mctp::OdpCommandCode::BatterySetChargeLimitRequest,
+            completion_code: Default::default(),
+        };
+        let mut packets = mctp_ctx
+            .serialize_packet(context, (header, charge_limit_request(1)))
+            .unwrap();
+        let packet = packets.next().unwrap().unwrap();
+        let data = &packet[..packet.len() - 1];
+        let mut request = [0u8; PACKET_BUF_SIZE];
+        request[..data.len()].copy_from_slice(data);
+        request[data.len()] = mctp::smbus_pec(data);
+        let request = &request[..=data.len()];
+
+        // A corrupted PEC is rejected
+        let mut corrupted = [0u8; PACKET_BUF_SIZE];
+        corrupted[..request.len()].copy_from_slice(request);
+        corrupted[request.len() - 1] ^= 0xFF;
+        host.send_oob(&corrupted[..request.len()]).await.unwrap();
+        let event = transport.wait_event().await;
+        assert!(matches!(
+            process_controller_event(&mut transport, service, event).await,
+            Err(Error::Pec)
+        ));
+        assert!(!host.receive_oob().await.is_empty());
+        assert_eq!(transport.wait_event().await, Ok(Event::OobSent));
+        assert!(BATTERY.messages.try_receive().is_err());
+
+        // So is a missing one
+        host.send_oob(&request[..request.len() - 1]).await.unwrap();
+        let event = transport.wait_event().await;
+        assert!(matches!(
+            process_controller_event(&mut transport, service, event).await,
+            Err(Error::Pec)
+        ));
+        assert!(!host.receive_oob().await.is_empty());
+        assert_eq!(transport.wait_event().await, Ok(Event::OobSent));
+        assert!(BATTERY.messages.try_receive().is_err());
+
+        host.send_oob(request).await.unwrap();
+        let event = transport.wait_event().await;
+        process_controller_event(&mut transport, service, event).await.unwrap();
+        let request = BATTERY.messages.try_receive().unwrap();
+        assert!(matches!(
+            request.command,
+            OdpCommand::Battery(BatteryCmd::SetChargeLimit)
+        ));
+        assert!(request.payload == charge_limit_request(1));
+        assert_eq!(request.tag, 3);
+
+        // Response goes back to the host over OOB
+        let response = StdHostRequest {
+            command: OdpCommand::Battery(BatteryCmd::SetChargeLimit),
+            status: 0,
+            tag: 3,
+            payload: StdHostPayload::BatterySetChargeLimitResponse { status: 0 },
+        };
+        service
+            .process_subsystem_msg(
+                &mut transport,
+                (EndpointID::Internal(Internal::Battery), HostMsg::Response(response)),
+            )
+            .await;
+        let packet = host.receive_oob().await;
+        let (pec, data) = packet.split_last().unwrap();
+        assert_eq!(mctp::smbus_pec(data), *pec);
+
+        // Tag and endpoint IDs are echoed from the request, sequence numbering starts over
+        assert_eq!(packet[5], 8);
+        assert_eq!(packet[6], 0x80);
+        assert_eq!(mctp::smbus_message_tag(&packet), Some(3));
+        assert_eq!(
+            packet[mctp::SMBUS_MCTP_FLAGS_OFFSET] & (mctp::MCTP_SEQ_MASK | mctp::MCTP_TAG_OWNER),
+            0
+        );
+
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = mctp_rs::MctpPacketContext::new(SmbusEspiMedium, &mut buf);
+        let message = mctp_ctx.deserialize_packet(&packet).unwrap().unwrap();
+        let (header, _) = message.parse_as::<StdHostPayload>().unwrap();
+        assert!(!header.request_bit);
+        assert_eq!(header.service, mctp::OdpService::Battery);
+
+        // Malformed requests are answered with an error
+        host.send_oob(&[0xFF; 8]).await.unwrap();
+        let event = transport.wait_event().await;
+        assert!(matches!(
+            process_controller_event(&mut transport, service, event).await,
+            Err(Error::Serialize)
+        ));
+        let packet = host.receive_oob().await;
+        assert!(!packet.is_empty());
+        assert!(BATTERY.messages.try_receive().is_err());
+    }
+
+    /// Charge limit request used as a generic battery request
+    fn charge_limit_request(battery_id: u8) -> StdHostPayload {
+        StdHostPayload::BatterySetChargeLimitRequest {
+            battery_id,
+            mode: 1,
+            upper_pct: 80,
+            lower_pct: 75,
+            full_charge_delay_min: 0,
+        }
+    }
+
+    /// Serialize a single packet request from the host to `eid` with the given tag
+    fn host_packet<'m, P: mctp_rs::MctpMessageTrait<'m>>(eid: u8, tag: u8, message: (P::Header, P)) -> Packet {
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = mctp_rs::MctpPacketContext::new(SmbusEspiMedium, &mut buf);
+        let context: mctp_rs::MctpReplyContext<SmbusEspiMedium> = mctp_rs::MctpReplyContext {
+            source_endpoint_id: mctp_rs::EndpointId::Id(8),
+            destination_endpoint_id: mctp_rs::EndpointId::Id(eid),
+            packet_sequence_number: mctp_rs::MctpSequenceNumber::new(0),
+            message_tag: mctp_rs::MctpMessageTag::try_from(tag).unwrap(),
+            medium_context: SmbusEspiReplyContext {
+                destination_slave_address: 0,
+                source_slave_address: 1,
+            },
+        };
+        let mut packets = mctp_ctx.serialize_packet(context, message).unwrap();
+        let packet = packets.next().unwrap().unwrap();
+        let data = &packet[..packet.len() - 1];
+        let mut request = Packet::from_slice(data).unwrap();
+        request.push(mctp::smbus_pec(data)).unwrap();
+        request
+    }
+
+    /// Send an ODP request to a service
+    async fn odp_request(
+        service: &Service<'_>,
+        host: &Host,
+        transport: &mut Loopback<'_>,
+        tag: u8,
+        header: mctp::OdpHeader,
+        request: StdHostPayload,
+    ) {
+        host.send_oob(&host_packet(0x80, tag, (header, request))).await.unwrap();
+        let event = transport.wait_event().await;
+        process_controller_event(transport, service, event).await.unwrap();
+    }
+
+    /// Send an MCTP control request to `eid` and return the response packet along with its decoded content
+    async fn control_request(
+        service: &Service<'_>,
+        host: &Host,
+        transport: &mut Loopback<'_>,
+        eid: u8,
+        command_code: ControlCommandCode,
+        request: MctpControl,
+    ) -> (Packet, ControlHeader, MctpControl) {
+        let header = ControlHeader {
+            request_bit: true,
+            datagram_bit: false,
+            instance_id: 1,
+            command_code,
+            completion_code: MctpCompletionCode::Success,
+        };
+        host.send_oob(&host_packet(eid, 1, (header, request))).await.unwrap();
+        let event = transport.wait_event().await;
+        process_controller_event(transport, service, event).await.unwrap();
+
+        let packet = host.receive_oob().await;
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = mctp_rs::MctpPacketContext::new(SmbusEspiMedium, &mut buf);
+        let message = mctp_ctx.deserialize_packet(&packet).unwrap().unwrap();
+        let (header, response) = message.parse_as::<MctpControl>().unwrap();
+        (packet, header, response)
+    }
+
+    /// Test processing OOB requests received while a response is being sent and dropping control responses
+    #[tokio::test]
+    async fn test_oob_queue() {
+        let _lock = OOB_LOCK.lock().await;
+        let service = init().await;
+        let host = Host::new();
+        let mut transport = Loopback::new(&host);
+
+        // A request arriving while a response is being sent is queued
+        let header = mctp::OdpHeader {
+            request_bit: true,
+            datagram_bit: false,
+            service: mctp::OdpService::Battery,
+            command_code: mctp::OdpCommandCode::BatterySetChargeLimitRequest,
+            completion_code: Default::default(),
+        };
+        host.send_oob(&host_packet(0x80, 4, (header, charge_limit_request(2))))
+            .await
+            .unwrap();
+        let event = transport.wait_event().await;
+        process_link_event(&mut transport, service, event).await;
+        assert!(BATTERY.messages.try_receive().is_err());
+
+        // And processed once the response is out
+        process_queued_oob_packets(&mut transport, service).await.unwrap();
+        let request = BATTERY.messages.try_receive().unwrap();
+        assert!(request.payload == charge_limit_request(2));
+        assert_eq!(request.tag, 4);
+
+        let response = StdHostRequest {
+            command: OdpCommand::Battery(BatteryCmd::SetChargeLimit),
+            status: 0,
+            tag: 4,
+            payload: StdHostPayload::BatterySetChargeLimitResponse { status: 0 },
+        };
+        service
+            .process_subsystem_msg(
+                &mut transport,
+                (EndpointID::Internal(Internal::Battery), HostMsg::Response(response)),
+            )
+            .await;
+        let packet = host.receive_oob().await;
+        assert_eq!(mctp::smbus_message_tag(&packet), Some(4));
+
+        // The EC doesn't send control requests, control responses are dropped
+        let header = ControlHeader {
+            request_bit: false,
+            datagram_bit: false,
+            instance_id: 1,
+            command_code: ControlCommandCode::GetEndpointId,
+            completion_code: MctpCompletionCode::Success,
+        };
+        let response = MctpControl::GetEndpointIdResponse {
+            eid: 0x10,
+            endpoint_type: mctp::SIMPLE_ENDPOINT_DYNAMIC_EID,
+            medium_specific: 0,
+        };
+        host.send_oob(&host_packet(0x80, 1, (header, response))).await.unwrap();
+        let event = transport.wait_event().await;
+        process_controller_event(&mut transport, service, event).await.unwrap();
+        assert!(host.try_receive_oob().is_none());
+    }
+
+    /// Test answering MCTP control requests and using the assigned endpoint ID
+    #[tokio::test]
+    async fn test_control() {
+        let _lock = OOB_LOCK.lock().await;
+        let service = init().await;
+        let host = Host::new();
+        let mut transport = Loopback::new(&host);
+
+        // Discovery happens before the EC has an endpoint ID assigned
+        let (packet, header, response) = control_request(
+            service,
+            &host,
+            &mut transport,
+            mctp::NULL_EID,
+            ControlCommandCode::GetEndpointId,
+            MctpControl::GetEndpointIdRequest,
+        )
+        .await;
+        assert!(!header.request_bit);
+        assert_eq!(header.instance_id, 1);
+        assert_eq!(header.completion_code, MctpCompletionCode::Success);
+        assert_eq!(
+            response,
+            MctpControl::GetEndpointIdResponse {
+                eid: 0x80,
+                endpoint_type: mctp::SIMPLE_ENDPOINT_DYNAMIC_EID,
+                medium_specific: 0,
+            }
+        );
+        assert_eq!(packet[5], 8);
+        assert_eq!(packet[6], 0x80);
+        assert_eq!(mctp::smbus_message_tag(&packet), Some(1));
+        assert_eq!(packet[mctp::SMBUS_MCTP_FLAGS_OFFSET] & mctp::MCTP_TAG_OWNER, 0);
+
+        // The assigned endpoint ID is used for every message from then on
+        let (packet, _, response) = control_request(
+            service,
+            &host,
+            &mut transport,
+            mctp::NULL_EID,
+            ControlCommandCode::SetEndpointId,
+            MctpControl::SetEndpointIdRequest {
+                operation: mctp::SetEndpointIdOperation::SetEid,
+                eid: 0x42,
+            },
+        )
+        .await;
+        assert_eq!(
+            response,
+            MctpControl::SetEndpointIdResponse {
+                accepted: true,
+                eid: 0x42,
+                pool_size: 0,
+            }
+        );
+        assert_eq!(packet[6], 0x42);
+
+        service
+            .process_subsystem_msg(
+                &mut transport,
+                (
+                    EndpointID::Internal(Internal::Battery),
+                    HostMsg::Response(StdHostRequest {
+                        command: OdpCommand::Battery(BatteryCmd::SetChargeLimit),
+                        status: 0,
+                        tag: 0,
+                        payload: StdHostPayload::BatterySetChargeLimitResponse { status: 0 },
+                    }),
+                ),
+            )
+            .await;
+        let packet = host.receive_oob().await;
+        assert_eq!(packet[5], 8);
+        assert_eq!(packet[6], 0x42);
+
+        // Unknown message types and commands are reported in the completion code
+        let (_, header, response) = control_request(
+            service,
+            &host,
+            &mut transport,
+            0x42,
+            ControlCommandCode::GetMctpVersionSupport,
+            MctpControl::GetMctpVersionSupportRequest { message_type: 0x7E },
+        )
+        .await;
+        assert_eq!(
+            header.completion_code,
+            MctpCompletionCode::CommandSpecific(mctp::MESSAGE_TYPE_NOT_SUPPORTED)
+        );
+        assert_eq!(response, MctpControl::ErrorResponse);
+
+        let (_, header, _) = control_request(
+            service,
+            &host,
+            &mut transport,
+            0x42,
+            ControlCommandCode::Unsupported(0x0A),
+            MctpControl::UnsupportedRequest,
+        )
+        .await;
+        assert_eq!(header.completion_code, MctpCompletionCode::ErrorUnsupportedCmd);
+
+        // Restore the default endpoint ID for the other tests
+        control_request(
+            service,
+            &host,
+            &mut transport,
+            0x42,
+            ControlCommandCode::SetEndpointId,
+            MctpControl::SetEndpointIdRequest {
+                operation: mctp::SetEndpointIdOperation::SetEid,
+                eid: 0x80,
+            },
+        )
+        .await;
+    }
+
+    /// Test requests in flight to several services and answering requests that time out
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let _lock = OOB_LOCK.lock().await;
+        let service = init().await;
+        let host = Host::new();
+        let mut transport = Loopback::new(&host);
+
+        // The thermal request is never answered
+        let header = mctp::OdpHeader {
+            request_bit: true,
+            datagram_bit: false,
+            service: mctp::OdpService::Thermal,
+            command_code: mctp::OdpCommandCode::ThermalGetTmpRequest,
+            completion_code: Default::default(),
+        };
+        odp_request(
+            service,
+            &host,
+            &mut transport,
+            1,
+            header,
+            StdHostPayload::ThermalGetTmpRequest { instance_id: 0 },
+        )
+        .await;
+
+        let header = mctp::OdpHeader {
+            service: mctp::OdpService::Battery,
+            command_code: mctp::OdpCommandCode::BatterySetChargeLimitRequest,
+            ..header
+        };
+        odp_request(service, &host, &mut transport, 2, header, charge_limit_request(0)).await;
+        assert!(BATTERY.messages.try_receive().is_ok());
+
+        // Battery answers without waiting for thermal
+        let response = StdHostRequest {
+            command: OdpCommand::Battery(BatteryCmd::SetChargeLimit),
+            status: 0,
+            tag: 2,
+            payload: StdHostPayload::BatterySetChargeLimitResponse { status: 0 },
+        };
+        service
+            .process_subsystem_msg(
+                &mut transport,
+                (EndpointID::Internal(Internal::Battery), HostMsg::Response(response)),
+            )
+            .await;
+        let packet = host.receive_oob().await;
+        assert_eq!(mctp::smbus_message_tag(&packet), Some(2));
+
+        // Thermal gets an error response once its request times out
+        let endpoint = service.wait_for_request_timeout().await;
+        assert_eq!(endpoint, EndpointID::Internal(Internal::Thermal));
+        service.process_request_timeout(&mut transport, endpoint).await;
+        let packet = host.receive_oob().await;
+        assert_eq!(mctp::smbus_message_tag(&packet), Some(1));
+
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = mctp_rs::MctpPacketContext::new(SmbusEspiMedium, &mut buf);
+        let message = mctp_ctx.deserialize_packet(&packet).unwrap().unwrap();
+        let (header, _) = message.parse_as::<StdHostPayload>().unwrap();
+        assert_eq!(header.service, mctp::OdpService::Thermal);
+        assert_eq!(header.completion_code, MctpCompletionCode::Error);
+
+        // A late response is dropped
+        let response = StdHostRequest {
+            command: OdpCommand::Thermal(ThermalCmd::GetTmp),
+            status: 0,
+            tag: 1,
+            payload: StdHostPayload::ThermalGetTmpResponse { temperature: 3000 },
+        };
+        service
+            .process_subsystem_msg(
+                &mut transport,
+                (EndpointID::Internal(Internal::Thermal), HostMsg::Response(response)),
+            )
+            .await;
+        assert!(host.try_receive_oob().is_none());
+    }
+
+    /// Test matching responses to their requests by message tag
+    #[tokio::test]
+    async fn test_out_of_order_responses() {
+        let _lock = OOB_LOCK.lock().await;
+        let service = init().await;
+        let host = Host::new();
+        let mut transport = Loopback::new(&host);
+
+        let header = mctp::OdpHeader {
+            request_bit: true,
+            datagram_bit: false,
+            service: mctp::OdpService::Battery,
+            command_code: mctp::OdpCommandCode::BatterySetChargeLimitRequest,
+            completion_code: Default::default(),
+        };
+        for tag in [5, 6] {
+            odp_request(service, &host, &mut transport, tag, header, charge_limit_request(0)).await;
+            assert!(BATTERY.messages.try_receive().is_ok());
+        }
+
+        // The battery answers the newest request first
+        for tag in [6, 5] {
+            let response = StdHostRequest {
+                command: OdpCommand::Battery(BatteryCmd::SetChargeLimit),
+                status: 0,
+                tag,
+                payload: StdHostPayload::BatterySetChargeLimitResponse { status: 0 },
+            };
+            service
+                .process_subsystem_msg(
+                    &mut transport,
+                    (EndpointID::Internal(Internal::Battery), HostMsg::Response(response)),
+                )
+                .await;
+            let packet = host.receive_oob().await;
+            assert_eq!(mctp::smbus_message_tag(&packet), Some(tag));
+            assert_eq!(packet[mctp::SMBUS_MCTP_FLAGS_OFFSET] & mctp::MCTP_TAG_OWNER, 0);
+        }
+    }
+
+    /// Test sending notifications to the host and acknowledging them
+    #[tokio::test]
+    async fn test_notification() {
+        let service = init().await;
+        let host = Host::new();
+        let mut transport = Loopback::new(&host);
+
+        // Informational notifications are coalesced
+        for _ in 0..2 {
+            service
+                .process_subsystem_msg(
+                    &mut transport,
+                    (
+                        EndpointID::Internal(Internal::Battery),
+                        HostMsg::Notification(NotificationMsg {
+                            offset: 5,
+                            priority: NotificationPriority::Info,
+                        }),
+                    ),
+                )
+                .await;
+        }
+        // Critical notifications are sent right away
+        service
+            .process_subsystem_msg(
+                &mut transport,
+                (
+                    EndpointID::Internal(Internal::Thermal),
+                    HostMsg::Notification(NotificationMsg {
+                        offset: 6,
+                        priority: NotificationPriority::Critical,
+                    }),
+                ),
+            )
+            .await;
+        assert_eq!(host.wait_notification().await, 6);
+
+        service.wait_for_notification().await;
+        service.send_notifications(&mut transport).await;
+        assert_eq!(host.wait_notification().await, 5);
+        assert_eq!(
+            service.memory.lock().await.ec.notif,
+            Notifications {
+                service: 2,
+                event: 6,
+                pending: 2,
+                res0: 0,
+            }
+        );
+
+        // The host acknowledges notifications through the notifications section
+        service.memory.lock().await.ec.notif.event = 6;
+        assert_eq!(service.route_to_service(offset_of!(ECMemory, notif), 4).await, Ok(()));
+        assert_eq!(
+            service.memory.lock().await.ec.notif,
+            Notifications {
+                service: 1,
+                event: 5,
+                pending: 1,
+                res0: 0,
+            }
+        );
+        service.memory.lock().await.ec.notif.event = 5;
+        assert_eq!(service.route_to_service(offset_of!(ECMemory, notif), 4).await, Ok(()));
+        assert_eq!(service.memory.lock().await.ec.notif, Notifications::default());
+    }
 }
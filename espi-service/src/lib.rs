@@ -5,6 +5,9 @@
 #![allow(clippy::unwrap_used)]
 
 mod espi_service;
+mod notification;
 pub mod task;
+pub mod transport;
 
 pub use espi_service::*;
+pub use transport::Transport;
@@ -0,0 +1,287 @@
+//! Notifications from services to the host
+//!
+//! Informational notifications are held for a short window so bursts of the same event reach the host as a single
+//! interrupt, critical ones are sent right away and ahead of anything else pending. Notifications stay pending until
+//! the host acknowledges them through the notifications section of the memory map. An event raised again before the
+//! host acknowledges it is sent once more after the acknowledgement.
+use embassy_time::{Duration, Instant};
+use embedded_services::ec_type::message::{NotificationMsg, NotificationPriority};
+use embedded_services::ec_type::protocols::mctp::OdpService;
+use embedded_services::ec_type::structure::Notifications;
+
+/// Notifications that can be pending at once
+pub(crate) const MAX_PENDING_NOTIFICATIONS: usize = 8;
+
+/// Notification waiting to be sent or acknowledged
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    offset: u8,
+    /// Service that raised the notification
+    service: OdpService,
+    priority: NotificationPriority,
+    /// Time the notification should be sent
+    due: Instant,
+    /// The host was notified and hasn't acknowledged it yet
+    sent: bool,
+    /// Raised again after it was sent, sent once more when the host acknowledges it
+    resend: bool,
+}
+
+/// Pending notifications, oldest first
+pub(crate) struct NotificationQueue {
+    pending: heapless::Vec<Pending, MAX_PENDING_NOTIFICATIONS>,
+    /// Time informational notifications are held to coalesce duplicates
+    window: Duration,
+}
+
+impl NotificationQueue {
+    pub(crate) const fn new(window: Duration) -> Self {
+        Self {
+            pending: heapless::Vec::new(),
+            window,
+        }
+    }
+
+    /// Queue a notification from `service`, coalescing it with a pending one for the same offset
+    ///
+    /// Makes room by dropping the oldest notification the host hasn't acknowledged if needed, critical notifications
+    /// also replace the oldest informational one still to be sent. Gives the notification back if there's no room.
+    pub(crate) fn push(
+        &mut self,
+        notification: NotificationMsg,
+        service: OdpService,
+        now: Instant,
+    ) -> Result<(), NotificationMsg> {
+        let due = match notification.priority {
+            NotificationPriority::Critical => now,
+            NotificationPriority::Info => now + self.window,
+        };
+
+        if let Some(pending) = self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.offset == notification.offset)
+        {
+            if pending.sent && !pending.resend {
+                // The host may have handled the offset before this event, notify it again
+                pending.resend = true;
+                pending.due = due;
+            } else {
+                pending.due = pending.due.min(due);
+            }
+            pending.priority = pending.priority.max(notification.priority);
+            return Ok(());
+        }
+
+        if self.pending.is_full() {
+            let index = self
+                .pending
+                .iter()
+                .position(|pending| pending.sent)
+                .or_else(|| match notification.priority {
+                    NotificationPriority::Critical => self
+                        .pending
+                        .iter()
+                        .position(|pending| pending.priority == NotificationPriority::Info),
+                    NotificationPriority::Info => None,
+                })
+                .ok_or(notification)?;
+            self.pending.remove(index);
+        }
+
+        self.pending
+            .push(Pending {
+                offset: notification.offset,
+                service,
+                priority: notification.priority,
+                due,
+                sent: false,
+                resend: false,
+            })
+            .map_err(|_| notification)
+    }
+
+    /// Earliest time a notification has to be sent
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.pending
+            .iter()
+            .filter(|pending| !pending.sent)
+            .map(|pending| pending.due)
+            .min()
+    }
+
+    /// Take the next notification to send at `now`, critical ones first
+    pub(crate) fn take_due(&mut self, now: Instant) -> Option<u8> {
+        let pending = self
+            .pending
+            .iter_mut()
+            .filter(|pending| !pending.sent && pending.due <= now)
+            .reduce(|next, pending| {
+                if pending.priority > next.priority {
+                    pending
+                } else {
+                    next
+                }
+            })?;
+        pending.sent = true;
+        Some(pending.offset)
+    }
+
+    /// Acknowledge a notification sent to the host, returns false if it wasn't waiting for acknowledgement
+    pub(crate) fn acknowledge(&mut self, offset: u8) -> bool {
+        match self
+            .pending
+            .iter()
+            .position(|pending| pending.sent && pending.offset == offset)
+        {
+            Some(index) => {
+                if let Some(pending) = self.pending.get_mut(index).filter(|pending| pending.resend) {
+                    pending.sent = false;
+                    pending.resend = false;
+                } else {
+                    self.pending.remove(index);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Contents of the notifications section of the memory map
+    pub(crate) fn section(&self) -> Notifications {
+        let sent = self.pending.iter().filter(|pending| pending.sent);
+        let next = sent.clone().reduce(|next, pending| {
+            if pending.priority > next.priority {
+                pending
+            } else {
+                next
+            }
+        });
+
+        Notifications {
+            service: next.map_or(0, |pending| u16::from(u8::from(pending.service))),
+            event: next.map_or(0, |pending| u16::from(pending.offset)),
+            pending: sent.count() as u16,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(10);
+    const SERVICE: OdpService = OdpService::Battery;
+
+    fn info(offset: u8) -> NotificationMsg {
+        NotificationMsg {
+            offset,
+            priority: NotificationPriority::Info,
+        }
+    }
+
+    fn critical(offset: u8) -> NotificationMsg {
+        NotificationMsg {
+            offset,
+            priority: NotificationPriority::Critical,
+        }
+    }
+
+    fn section(service: OdpService, event: u8, pending: u16) -> Notifications {
+        Notifications {
+            service: u8::from(service).into(),
+            event: event.into(),
+            pending,
+            res0: 0,
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let now = Instant::from_millis(0);
+        let mut queue = NotificationQueue::new(WINDOW);
+
+        queue.push(info(1), SERVICE, now).unwrap();
+        queue.push(info(1), SERVICE, now + Duration::from_millis(5)).unwrap();
+        assert_eq!(queue.next_due(), Some(now + WINDOW));
+        assert_eq!(queue.take_due(now), None);
+        assert_eq!(queue.take_due(now + WINDOW), Some(1));
+        assert_eq!(queue.take_due(now + WINDOW), None);
+        assert_eq!(queue.section(), section(SERVICE, 1, 1));
+
+        assert!(queue.acknowledge(1));
+        assert!(!queue.acknowledge(1));
+        assert_eq!(queue.section(), Notifications::default());
+    }
+
+    #[test]
+    fn test_resend() {
+        let now = Instant::from_millis(0);
+        let mut queue = NotificationQueue::new(WINDOW);
+
+        queue.push(critical(1), SERVICE, now).unwrap();
+        assert_eq!(queue.take_due(now), Some(1));
+
+        // Events raised before the host acknowledges the notification are sent once it does
+        queue.push(info(1), SERVICE, now).unwrap();
+        queue.push(info(1), SERVICE, now + Duration::from_millis(5)).unwrap();
+        assert_eq!(queue.next_due(), None);
+        assert_eq!(queue.section(), section(SERVICE, 1, 1));
+
+        assert!(queue.acknowledge(1));
+        assert_eq!(queue.section(), Notifications::default());
+        assert_eq!(queue.next_due(), Some(now + WINDOW));
+        assert_eq!(queue.take_due(now + WINDOW), Some(1));
+
+        assert!(queue.acknowledge(1));
+        assert_eq!(queue.next_due(), None);
+        assert!(!queue.acknowledge(1));
+    }
+
+    #[test]
+    fn test_priority() {
+        let now = Instant::from_millis(0);
+        let mut queue = NotificationQueue::new(WINDOW);
+
+        queue.push(info(1), SERVICE, now).unwrap();
+        queue.push(critical(2), OdpService::Thermal, now).unwrap();
+        assert_eq!(queue.next_due(), Some(now));
+        assert_eq!(queue.take_due(now), Some(2));
+
+        // Critical duplicate of a pending informational notification is sent right away
+        queue.push(critical(1), SERVICE, now).unwrap();
+        assert_eq!(queue.take_due(now), Some(1));
+
+        queue.push(info(3), SERVICE, now).unwrap();
+        assert_eq!(queue.take_due(now + WINDOW), Some(3));
+        assert_eq!(queue.section(), section(OdpService::Thermal, 2, 3));
+    }
+
+    #[test]
+    fn test_full() {
+        let now = Instant::from_millis(0);
+        let mut queue = NotificationQueue::new(WINDOW);
+
+        for offset in 0..MAX_PENDING_NOTIFICATIONS as u8 {
+            queue.push(info(offset), SERVICE, now).unwrap();
+        }
+        assert_eq!(queue.push(info(100), SERVICE, now), Err(info(100)));
+
+        // Critical notifications replace the oldest informational one
+        queue.push(critical(101), SERVICE, now).unwrap();
+        assert_eq!(queue.take_due(now), Some(101));
+        assert_eq!(queue.take_due(now + WINDOW), Some(1));
+
+        // The oldest notification waiting for acknowledgement makes room
+        queue.push(info(100), SERVICE, now).unwrap();
+        assert_eq!(queue.section(), section(SERVICE, 101, 1));
+
+        // Nothing makes room for critical notifications when all pending ones are critical
+        let mut queue = NotificationQueue::new(WINDOW);
+        for offset in 0..MAX_PENDING_NOTIFICATIONS as u8 {
+            queue.push(critical(offset), SERVICE, now).unwrap();
+        }
+        assert_eq!(queue.push(critical(100), SERVICE, now), Err(critical(100)));
+    }
+}
@@ -1,12 +1,13 @@
-use embassy_futures::select::select;
-use embassy_imxrt::espi;
+use embassy_futures::select::{Either4, select4};
 use embedded_services::{comms, ec_type, info};
 
-use crate::{ESPI_SERVICE, Service, process_controller_event};
+use crate::transport::Transport;
+use crate::{Config, ESPI_SERVICE, Service, process_controller_event, process_queued_oob_packets};
 
 pub async fn espi_service(
-    mut espi: espi::Espi<'static>,
+    mut transport: impl Transport,
     memory_map_buffer: &'static mut [u8],
+    config: Config,
 ) -> Result<embedded_services::Never, crate::espi_service::Error> {
     info!("Reserved eSPI memory map buffer size: {}", memory_map_buffer.len());
     info!("eSPI MemoryMap size: {}", size_of::<ec_type::structure::ECMemory>());
@@ -17,32 +18,41 @@ pub async fn espi_service(
 
     memory_map_buffer.fill(0);
 
+    let (memory_map_buffer, oem_memory) = memory_map_buffer.split_at_mut(size_of::<ec_type::structure::ECMemory>());
+    if ec_type::layout::validate_oem_sections(config.oem_sections, oem_memory.len()).is_err() {
+        panic!("eSPI OEM sections don't fit in reserved memory buffer!!!");
+    }
+
     let memory_map: &mut ec_type::structure::ECMemory =
         unsafe { &mut *(memory_map_buffer.as_mut_ptr() as *mut ec_type::structure::ECMemory) };
 
-    espi.wait_for_plat_reset().await;
+    transport.wait_for_reset().await;
 
     info!("Initializing memory map");
-    memory_map.ver.major = ec_type::structure::EC_MEMMAP_VERSION.major;
-    memory_map.ver.minor = ec_type::structure::EC_MEMMAP_VERSION.minor;
-    memory_map.ver.spin = ec_type::structure::EC_MEMMAP_VERSION.spin;
-    memory_map.ver.res0 = ec_type::structure::EC_MEMMAP_VERSION.res0;
-
-    let espi_service = ESPI_SERVICE.get_or_init(|| Service::new(memory_map));
+    let espi_service = ESPI_SERVICE.get_or_init(|| Service::new(memory_map, oem_memory, config));
     comms::register_endpoint(espi_service, espi_service.endpoint())
         .await
         .unwrap();
 
     loop {
-        let event = select(espi.wait_for_event(), espi_service.wait_for_subsystem_msg()).await;
+        // Requests that arrived while a response was being sent
+        process_queued_oob_packets(&mut transport, espi_service).await?;
+
+        let event = select4(
+            transport.wait_event(),
+            espi_service.wait_for_subsystem_msg(),
+            espi_service.wait_for_request_timeout(),
+            espi_service.wait_for_notification(),
+        )
+        .await;
 
         match event {
-            embassy_futures::select::Either::First(controller_event) => {
-                process_controller_event(&mut espi, espi_service, controller_event).await?
-            }
-            embassy_futures::select::Either::Second(host_msg) => {
-                espi_service.process_subsystem_msg(&mut espi, host_msg).await
+            Either4::First(controller_event) => {
+                process_controller_event(&mut transport, espi_service, controller_event).await?
             }
+            Either4::Second(host_msg) => espi_service.process_subsystem_msg(&mut transport, host_msg).await,
+            Either4::Third(endpoint) => espi_service.process_request_timeout(&mut transport, endpoint).await,
+            Either4::Fourth(()) => espi_service.send_notifications(&mut transport).await,
         }
     }
 }
@@ -0,0 +1,124 @@
+//! NXP iMXRT eSPI transport
+use core::slice;
+
+use embassy_imxrt::espi;
+use embedded_services::{error, info};
+
+use super::{Error, Event};
+
+// OOB port number for NXP IMXRT
+const OOB_PORT_ID: usize = 1;
+
+/// iMXRT eSPI transport
+pub struct Espi<'d> {
+    espi: espi::Espi<'d>,
+    /// Port of the last event, completed by [`super::Transport::complete`]
+    port: Option<usize>,
+    /// Location and length of the last OOB packet received
+    oob: Option<(*const u8, usize)>,
+}
+
+impl<'d> Espi<'d> {
+    /// Create a new transport
+    pub fn new(espi: espi::Espi<'d>) -> Self {
+        Self {
+            espi,
+            port: None,
+            oob: None,
+        }
+    }
+}
+
+impl super::Transport for Espi<'_> {
+    async fn wait_for_reset(&mut self) {
+        self.espi.wait_for_plat_reset().await;
+    }
+
+    async fn wait_event(&mut self) -> Result<Event, Error> {
+        self.port = None;
+        self.oob = None;
+
+        match self.espi.wait_for_event().await {
+            Ok(espi::Event::PeripheralEvent(port_event)) => {
+                info!(
+                    "eSPI PeripheralEvent Port: {}, direction: {}, address: {}, offset: {}, length: {}",
+                    port_event.port, port_event.direction, port_event.base_addr, port_event.offset, port_event.length,
+                );
+
+                self.port = Some(port_event.port);
+                Ok(if port_event.direction {
+                    Event::MemoryWrite {
+                        offset: port_event.offset,
+                        length: port_event.length,
+                    }
+                } else {
+                    Event::MemoryRead {
+                        offset: port_event.offset,
+                        length: port_event.length,
+                    }
+                })
+            }
+            Ok(espi::Event::OOBEvent(port_event)) => {
+                info!(
+                    "eSPI OOBEvent Port: {}, direction: {}, address: {}, offset: {}, length: {}",
+                    port_event.port, port_event.direction, port_event.base_addr, port_event.offset, port_event.length,
+                );
+
+                self.port = Some(port_event.port);
+                if port_event.direction {
+                    self.oob = Some((port_event.base_addr as *const u8, port_event.length));
+                    Ok(Event::OobPacket {
+                        length: port_event.length,
+                    })
+                } else {
+                    Ok(Event::OobSent)
+                }
+            }
+            Ok(espi::Event::Port80) => Ok(Event::Port80),
+            Ok(espi::Event::WireChange(_)) => Ok(Event::WireChange),
+            Err(e) => {
+                error!("eSPI Failed with error: {:?}", e);
+                Err(Error::Hardware)
+            }
+        }
+    }
+
+    fn complete(&mut self) {
+        if let Some(port) = self.port.take() {
+            self.espi.complete_port(port);
+        }
+    }
+
+    fn oob_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let (base_addr, length) = self.oob.ok_or(Error::NoPacket)?;
+        // SAFETY: The packet stays valid in the OOB buffer until the port is completed
+        let src_slice = unsafe { slice::from_raw_parts(base_addr, length) };
+        buf.get_mut(..length)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(src_slice);
+        Ok(length)
+    }
+
+    fn oob_write(&mut self, packet: &[u8]) -> Result<(), Error> {
+        // SAFETY: Safe as the access to espi is protected by a mut reference.
+        let dest_slice = unsafe { self.espi.oob_get_write_buffer(OOB_PORT_ID) }.map_err(|e| {
+            error!("eSPI OOB write buffer error: {:?}", e);
+            Error::Hardware
+        })?;
+        dest_slice
+            .get_mut(..packet.len())
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(packet);
+
+        // Write response over OOB
+        let length = u8::try_from(packet.len()).map_err(|_| Error::BufferTooSmall)?;
+        self.espi.oob_write_data(OOB_PORT_ID, length).map_err(|e| {
+            error!("eSPI OOB write error: {:?}", e);
+            Error::Hardware
+        })
+    }
+
+    async fn notify(&mut self, offset: u8) {
+        self.espi.irq_push(offset).await;
+    }
+}
@@ -0,0 +1,141 @@
+//! In-memory loopback transport
+//!
+//! [`Host`] plays the role of the host side of the link, for tests and simulation on targets without an eSPI
+//! controller.
+use embassy_sync::channel::Channel;
+use embedded_services::GlobalRawMutex;
+
+use super::{Error, Event};
+
+/// Maximum OOB packet size
+pub const MAX_PACKET_SIZE: usize = 128;
+/// Number of requests and responses that can be queued in each direction
+const QUEUE_SIZE: usize = 4;
+
+/// OOB packet
+pub type Packet = heapless::Vec<u8, MAX_PACKET_SIZE>;
+
+/// Host request
+enum Request {
+    /// Memory window write
+    MemoryWrite { offset: usize, length: usize },
+    /// OOB packet
+    Oob(Packet),
+}
+
+/// Host side of the loopback link
+pub struct Host {
+    /// Requests from the host
+    requests: Channel<GlobalRawMutex, Request, QUEUE_SIZE>,
+    /// OOB packets sent to the host
+    responses: Channel<GlobalRawMutex, Packet, QUEUE_SIZE>,
+    /// Notifications sent to the host
+    notifications: Channel<GlobalRawMutex, u8, QUEUE_SIZE>,
+}
+
+impl Host {
+    /// Create a new host
+    pub const fn new() -> Self {
+        Self {
+            requests: Channel::new(),
+            responses: Channel::new(),
+            notifications: Channel::new(),
+        }
+    }
+
+    /// Signal a write of `length` bytes at `offset` in the memory window
+    pub async fn write_memory(&self, offset: usize, length: usize) {
+        self.requests.send(Request::MemoryWrite { offset, length }).await;
+    }
+
+    /// Send an OOB packet
+    pub async fn send_oob(&self, packet: &[u8]) -> Result<(), Error> {
+        let packet = Packet::from_slice(packet).map_err(|_| Error::BufferTooSmall)?;
+        self.requests.send(Request::Oob(packet)).await;
+        Ok(())
+    }
+
+    /// Wait for an OOB packet from the service
+    pub async fn receive_oob(&self) -> Packet {
+        self.responses.receive().await
+    }
+
+    /// Take an OOB packet from the service if one was sent
+    pub fn try_receive_oob(&self) -> Option<Packet> {
+        self.responses.try_receive().ok()
+    }
+
+    /// Wait for a notification from the service
+    pub async fn wait_notification(&self) -> u8 {
+        self.notifications.receive().await
+    }
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Service side of the loopback link
+pub struct Loopback<'a> {
+    host: &'a Host,
+    /// OOB packet received with the last event
+    pending: Option<Packet>,
+    /// A packet was sent to the host and hasn't been reported as consumed yet
+    sent: bool,
+}
+
+impl<'a> Loopback<'a> {
+    /// Create a new loopback transport connected to `host`
+    pub fn new(host: &'a Host) -> Self {
+        Self {
+            host,
+            pending: None,
+            sent: false,
+        }
+    }
+}
+
+impl super::Transport for Loopback<'_> {
+    async fn wait_for_reset(&mut self) {}
+
+    async fn wait_event(&mut self) -> Result<Event, Error> {
+        self.pending = None;
+        if self.sent {
+            // The host consumes packets as soon as they are queued
+            self.sent = false;
+            return Ok(Event::OobSent);
+        }
+
+        match self.host.requests.receive().await {
+            Request::MemoryWrite { offset, length } => Ok(Event::MemoryWrite { offset, length }),
+            Request::Oob(packet) => {
+                let length = packet.len();
+                self.pending = Some(packet);
+                Ok(Event::OobPacket { length })
+            }
+        }
+    }
+
+    fn complete(&mut self) {}
+
+    fn oob_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let packet = self.pending.as_ref().ok_or(Error::NoPacket)?;
+        buf.get_mut(..packet.len())
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(packet);
+        Ok(packet.len())
+    }
+
+    fn oob_write(&mut self, packet: &[u8]) -> Result<(), Error> {
+        let packet = Packet::from_slice(packet).map_err(|_| Error::BufferTooSmall)?;
+        self.host.responses.try_send(packet).map_err(|_| Error::Busy)?;
+        self.sent = true;
+        Ok(())
+    }
+
+    async fn notify(&mut self, offset: u8) {
+        self.host.notifications.send(offset).await;
+    }
+}
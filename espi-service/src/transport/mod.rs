@@ -0,0 +1,75 @@
+//! Host transport abstraction
+//!
+//! The service only needs three things from the host interface: a shared memory window the host writes to, an
+//! out-of-band (OOB) channel carrying MCTP packets and a way to notify the host. [`Transport`] covers those so the
+//! routing logic doesn't depend on a particular eSPI controller.
+#[cfg(feature = "imxrt")]
+pub mod imxrt;
+pub mod loopback;
+
+/// Transport error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Error reported by the underlying hardware
+    Hardware,
+    /// Packet doesn't fit in the provided buffer
+    BufferTooSmall,
+    /// No OOB packet pending
+    NoPacket,
+    /// Transport can't accept more data right now
+    Busy,
+}
+
+/// Transport event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The host wrote to the memory window
+    MemoryWrite {
+        /// Offset into the memory window
+        offset: usize,
+        /// Number of bytes written
+        length: usize,
+    },
+    /// The host read from the memory window
+    MemoryRead {
+        /// Offset into the memory window
+        offset: usize,
+        /// Number of bytes read
+        length: usize,
+    },
+    /// The host sent an OOB packet, read it with [`Transport::oob_read`]
+    OobPacket {
+        /// Packet length
+        length: usize,
+    },
+    /// A packet sent with [`Transport::oob_write`] was consumed by the host
+    OobSent,
+    /// Port 80 write
+    Port80,
+    /// Virtual wire change
+    WireChange,
+}
+
+/// Host transport
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    /// Wait for the host to come out of reset
+    async fn wait_for_reset(&mut self);
+
+    /// Wait for the next event
+    async fn wait_event(&mut self) -> Result<Event, Error>;
+
+    /// Acknowledge the last event returned by [`Transport::wait_event`], releasing the channel back to the host
+    fn complete(&mut self);
+
+    /// Copy the pending OOB packet into `buf`, returns the packet length
+    fn oob_read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Send an OOB packet to the host
+    fn oob_write(&mut self, packet: &[u8]) -> Result<(), Error>;
+
+    /// Notify the host
+    async fn notify(&mut self, offset: u8);
+}
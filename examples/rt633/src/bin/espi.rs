@@ -98,7 +98,12 @@ unsafe extern "C" {
 
 #[embassy_executor::task]
 async fn espi_service_task(espi: embassy_imxrt::espi::Espi<'static>, memory_map_buffer: &'static mut [u8]) -> ! {
-    let Err(e) = espi_service::task::espi_service(espi, memory_map_buffer).await;
+    let Err(e) = espi_service::task::espi_service(
+        espi_service::transport::imxrt::Espi::new(espi),
+        memory_map_buffer,
+        Default::default(),
+    )
+    .await;
     panic!("espi_service_task error: {e:?}");
 }
 
@@ -164,6 +164,7 @@ mod espi_service {
                         embedded_services::ec_type::protocols::debug::DebugCmd::GetMsgs,
                     ),
                     status: 0,
+                    tag: 0,
                     payload: StdHostPayload::DebugGetMsgsRequest,
                 },
             )
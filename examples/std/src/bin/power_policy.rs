@@ -241,6 +241,11 @@ async fn power_policy_task(config: power_policy_service::config::Config) {
         .expect("Failed to start power policy service task");
 }
 
+#[embassy_executor::task]
+async fn system_power_task() {
+    power_policy_service::task::system_power_task().await;
+}
+
 fn main() {
     env_logger::builder().filter_level(log::LevelFilter::Trace).init();
 
@@ -250,5 +255,6 @@ fn main() {
         spawner.spawn(power_policy_task(power_policy_service::config::Config::default()).unwrap());
         spawner.spawn(run(spawner).unwrap());
         spawner.spawn(receiver_task().unwrap());
+        spawner.spawn(system_power_task().unwrap());
     });
 }
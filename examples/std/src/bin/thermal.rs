@@ -349,6 +349,12 @@ async fn handle_requests() -> ! {
     unreachable!()
 }
 
+#[embassy_executor::task]
+async fn system_power() -> ! {
+    ts::task::system_power_task().await;
+    unreachable!()
+}
+
 #[embassy_executor::task]
 async fn run(spawner: Spawner) {
     embedded_services::init().await;
@@ -356,6 +362,7 @@ async fn run(spawner: Spawner) {
     spawner.spawn(host().unwrap());
     spawner.spawn(handle_alerts().unwrap());
     spawner.spawn(handle_requests().unwrap());
+    spawner.spawn(system_power().unwrap());
 }
 
 fn main() {
@@ -6,9 +6,9 @@ use embedded_services::{
     power::policy::PowerCapability,
     type_c::{
         controller::{
-            AttnVdm, ControllerStatus, DiscoveredSvids, DpConfig, DpPinConfig, DpStatus, OtherVdm,
-            PdStateMachineConfig, PortStatus, RetimerFwUpdateState, SendVdm, SystemPowerState, TbtConfig,
-            TypeCStateMachineState, UsbControlConfig,
+            AttnVdm, BatteryCapabilities, BatteryRef, BatteryStatus, ControllerStatus, DiscoveredSvids, DpConfig,
+            DpPinConfig, DpStatus, OtherVdm, PdStateMachineConfig, PdStatus, PortStatus, RetimerFwUpdateState, SendVdm,
+            SourceInfo, SystemPowerState, TbtConfig, TypeCStateMachineState, UsbControlConfig,
         },
         event::PortEvent,
     },
@@ -201,26 +201,6 @@ impl embedded_services::type_c::controller::Controller for Controller<'_> {
         Ok(())
     }
 
-    async fn get_active_fw_version(&mut self) -> Result<u32, Error<Self::BusError>> {
-        Ok(0)
-    }
-
-    async fn start_fw_update(&mut self) -> Result<(), Error<Self::BusError>> {
-        Ok(())
-    }
-
-    async fn abort_fw_update(&mut self) -> Result<(), Error<Self::BusError>> {
-        Ok(())
-    }
-
-    async fn finalize_fw_update(&mut self) -> Result<(), Error<Self::BusError>> {
-        Ok(())
-    }
-
-    async fn write_fw_contents(&mut self, _offset: usize, _data: &[u8]) -> Result<(), Error<Self::BusError>> {
-        Ok(())
-    }
-
     async fn set_max_sink_voltage(
         &mut self,
         port: LocalPortId,
@@ -230,6 +210,15 @@ impl embedded_services::type_c::controller::Controller for Controller<'_> {
         Ok(())
     }
 
+    async fn set_source_capability(
+        &mut self,
+        port: LocalPortId,
+        capability: PowerCapability,
+    ) -> Result<(), Error<Self::BusError>> {
+        debug!("Set source capability for port {}: {:?}", port.0, capability);
+        Ok(())
+    }
+
     async fn reconfigure_retimer(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
         debug!("reconfigure_retimer(port: {port:?})");
         Ok(())
@@ -371,6 +360,88 @@ impl embedded_services::type_c::controller::Controller for Controller<'_> {
         debug!("Get Discover Identity SOP' response for port {port:?}");
         Err(Error::Pd(PdError::Failed))
     }
+
+    async fn get_partner_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+    ) -> Result<BatteryCapabilities, Error<Self::BusError>> {
+        debug!("Get partner battery capabilities for port {port:?}: {battery:?}");
+        Ok(BatteryCapabilities::invalid())
+    }
+
+    async fn get_partner_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+    ) -> Result<BatteryStatus, Error<Self::BusError>> {
+        debug!("Get partner battery status for port {port:?}: {battery:?}");
+        Ok(BatteryStatus::invalid())
+    }
+
+    async fn get_partner_source_info(&mut self, port: LocalPortId) -> Result<SourceInfo, Error<Self::BusError>> {
+        debug!("Get partner source info for port {port:?}");
+        Ok(SourceInfo::default())
+    }
+
+    async fn get_partner_status(&mut self, port: LocalPortId) -> Result<PdStatus, Error<Self::BusError>> {
+        debug!("Get partner status for port {port:?}");
+        Ok(PdStatus::default())
+    }
+
+    async fn set_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+        capabilities: BatteryCapabilities,
+    ) -> Result<(), Error<Self::BusError>> {
+        debug!("Set battery capabilities for port {port:?}: {battery:?}: {capabilities:?}");
+        Ok(())
+    }
+
+    async fn set_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+        status: BatteryStatus,
+    ) -> Result<(), Error<Self::BusError>> {
+        debug!("Set battery status for port {port:?}: {battery:?}: {status:?}");
+        Ok(())
+    }
+}
+
+impl embedded_services::fw_update::ErrorType for Controller<'_> {
+    type Error = Error<()>;
+}
+
+impl embedded_services::fw_update::FwUpdate for Controller<'_> {
+    async fn get_active_fw_version(&mut self) -> Result<u32, Self::Error> {
+        Ok(0)
+    }
+
+    async fn start_fw_update(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn write_fw_contents(&mut self, _offset: usize, _data: &[u8]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn activate_fw_update(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn abort_fw_update(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn rollback_fw_update(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn progress(&self) -> embedded_services::fw_update::Progress {
+        Default::default()
+    }
 }
 
 pub struct Validator;
@@ -51,6 +51,11 @@ impl EmbeddedCrc<u32> {
         self.current_crc.unwrap_or(self.algorithm.init)
     }
 
+    /// Discard the current CRC to start a new calculation
+    pub fn reset(&mut self) {
+        self.current_crc = None;
+    }
+
     // Reverses the digest finalize operation to use as another CRC input
     fn un_finalize(&self, crc: u32) -> u32 {
         let mut out: u32 = crc ^ self.algorithm.xorout;
@@ -101,6 +106,11 @@ impl EmbeddedCrc<u16> {
         self.current_crc.unwrap_or(self.algorithm.init)
     }
 
+    /// Discard the current CRC to start a new calculation
+    pub fn reset(&mut self) {
+        self.current_crc = None;
+    }
+
     // Reverses the digest finalize operation to use as another CRC input
     fn un_finalize(&self, crc: u16) -> u16 {
         let mut out: u16 = crc ^ self.algorithm.xorout;
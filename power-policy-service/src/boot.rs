@@ -0,0 +1,314 @@
+//! Boot power sequencing
+//!
+//! Orchestrates startup with a depleted or missing battery. Until external power has been connected for
+//! [stabilize_time](Config::stabilize_time), chargers are left unconfigured and providers aren't connected so that the
+//! system doesn't brown out. While waiting for external power, consumers are connected without waiting for the selection
+//! hold time and regardless of [min_consumer_threshold_mw](super::Config::min_consumer_threshold_mw), and the type-C
+//! wrapper reports sink contracts as soon as they're accepted instead of waiting for the sink ready event.
+//! Once power is stable the sequencer enters [`BootState::Running`] or [`BootState::AcOnly`], which the type-C service
+//! uses to clear dead battery flags.
+//!
+//! The sequencer stays in [`BootState::Starting`] until the battery service reports the battery status through
+//! [`battery::Device::update`]. It's disabled by default, in which case the power policy starts in
+//! [`BootState::Running`].
+use embassy_time::Duration;
+use embedded_services::power::policy::battery;
+
+use super::*;
+
+/// Boot sequencer configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// At or below this state of charge the battery is treated as depleted
+    pub dead_battery_soc_pct: u8,
+    /// External power must stay connected this long before it's loaded
+    pub stabilize_time: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dead_battery_soc_pct: 2,
+            stabilize_time: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Boot sequencer state machine
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Sequencer {
+    /// Current state
+    state: BootState,
+    /// Time external power is considered stable, set while stabilizing
+    stable_at: Option<Instant>,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl Sequencer {
+    /// Create a new sequencer, a disabled sequencer starts in [`BootState::Running`]
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            state: if enabled {
+                BootState::Starting
+            } else {
+                BootState::Running
+            },
+            stable_at: None,
+        }
+    }
+
+    /// Current state
+    pub fn state(&self) -> BootState {
+        self.state
+    }
+
+    /// Returns true if external power is stable and can be loaded by chargers and providers
+    pub fn power_stable(&self) -> bool {
+        matches!(self.state, BootState::Running | BootState::AcOnly)
+    }
+
+    /// Returns true if the system needs external power as soon as possible
+    pub fn needs_power(&self) -> bool {
+        self.state.needs_power()
+    }
+
+    /// Time at which the sequencer needs to be updated
+    pub fn deadline(&self) -> Option<Instant> {
+        self.stable_at
+    }
+
+    /// State while waiting for external power
+    fn waiting_state(battery: battery::Status, config: &Config) -> BootState {
+        if !battery.present {
+            BootState::NoBattery
+        } else if battery.state_of_charge_pct <= config.dead_battery_soc_pct {
+            BootState::DeadBattery
+        } else {
+            BootState::Running
+        }
+    }
+
+    /// State once external power is stable
+    fn stable_state(battery: battery::Status) -> BootState {
+        if battery.present {
+            BootState::Running
+        } else {
+            BootState::AcOnly
+        }
+    }
+
+    /// Advance the state machine, returns the new state if it changed
+    pub fn update(
+        &mut self,
+        battery: Option<battery::Status>,
+        consumer_connected: bool,
+        now: Instant,
+        config: &Config,
+    ) -> Option<BootState> {
+        let Some(battery) = battery else {
+            // Nothing to do until we know the battery status
+            return None;
+        };
+
+        let next = match self.state {
+            // Only battery changes matter once power is stable
+            BootState::Running | BootState::AcOnly => Self::stable_state(battery),
+            BootState::Stabilizing => {
+                if !consumer_connected {
+                    Self::waiting_state(battery, config)
+                } else if self.stable_at.is_some_and(|stable_at| now >= stable_at) {
+                    Self::stable_state(battery)
+                } else {
+                    BootState::Stabilizing
+                }
+            }
+            BootState::Starting | BootState::DeadBattery | BootState::NoBattery => {
+                match Self::waiting_state(battery, config) {
+                    // Battery can carry the system, no need to wait
+                    BootState::Running => BootState::Running,
+                    _ if consumer_connected => BootState::Stabilizing,
+                    waiting => waiting,
+                }
+            }
+        };
+
+        if next != BootState::Stabilizing {
+            self.stable_at = None;
+        } else if self.state != BootState::Stabilizing {
+            self.stable_at = Some(now + config.stabilize_time);
+        }
+
+        if next == self.state {
+            None
+        } else {
+            self.state = next;
+            Some(next)
+        }
+    }
+}
+
+impl PowerPolicy {
+    /// Advance the boot sequencer and broadcast state changes
+    pub(super) async fn update_boot(&self, state: &mut InternalState) -> Result<(), Error> {
+        let Some(config) = self.config.boot else {
+            return Ok(());
+        };
+
+        let was_stable = state.boot.power_stable();
+        let Some(boot_state) = state.boot.update(
+            state.battery,
+            state.current_consumer_state.is_some(),
+            Instant::now(),
+            &config,
+        ) else {
+            return Ok(());
+        };
+
+        info!("Boot state changed: {:?}", boot_state);
+        self.context.publish_boot_state(boot_state).await;
+        if !was_stable
+            && state.boot.power_stable()
+            && let Some(consumer) = state.current_consumer_state
+        {
+            // Providers are handled by the budget update
+            info!("Power stable, configuring chargers");
+            self.configure_chargers(&consumer).await?;
+        }
+
+        self.comms_notify(CommsMessage {
+            data: CommsData::Boot(boot_state),
+        })
+        .await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEAD: Option<battery::Status> = Some(battery::Status {
+        present: true,
+        state_of_charge_pct: 0,
+        sus_power_mw: 0,
+        temperature_dk: 2980,
+    });
+    const HEALTHY: Option<battery::Status> = Some(battery::Status {
+        present: true,
+        state_of_charge_pct: 50,
+        sus_power_mw: 0,
+        temperature_dk: 2980,
+    });
+    const NO_BATTERY: Option<battery::Status> = Some(battery::Status {
+        present: false,
+        state_of_charge_pct: 0,
+        sus_power_mw: 0,
+        temperature_dk: 0,
+    });
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    /// Test booting with a depleted battery
+    #[test]
+    fn test_dead_battery() {
+        let config = Config::default();
+        let mut sequencer = Sequencer::new(true);
+        assert_eq!(sequencer.update(None, false, at(0), &config), None);
+        assert_eq!(sequencer.state(), BootState::Starting);
+
+        assert_eq!(
+            sequencer.update(DEAD, false, at(0), &config),
+            Some(BootState::DeadBattery)
+        );
+        assert!(sequencer.needs_power());
+
+        assert_eq!(
+            sequencer.update(DEAD, true, at(100), &config),
+            Some(BootState::Stabilizing)
+        );
+        assert_eq!(sequencer.deadline(), Some(at(1100)));
+        assert!(!sequencer.power_stable());
+
+        // Still stabilizing, even if the battery picked up some charge
+        assert_eq!(sequencer.update(HEALTHY, true, at(500), &config), None);
+        assert_eq!(sequencer.deadline(), Some(at(1100)));
+
+        assert_eq!(
+            sequencer.update(HEALTHY, true, at(1100), &config),
+            Some(BootState::Running)
+        );
+        assert!(sequencer.power_stable());
+        assert_eq!(sequencer.deadline(), None);
+    }
+
+    /// Test losing external power while stabilizing
+    #[test]
+    fn test_consumer_lost() {
+        let config = Config::default();
+        let mut sequencer = Sequencer::new(true);
+        assert_eq!(
+            sequencer.update(DEAD, true, at(0), &config),
+            Some(BootState::Stabilizing)
+        );
+        assert_eq!(
+            sequencer.update(DEAD, false, at(500), &config),
+            Some(BootState::DeadBattery)
+        );
+        assert_eq!(sequencer.deadline(), None);
+
+        // Stabilization starts over
+        assert_eq!(
+            sequencer.update(DEAD, true, at(600), &config),
+            Some(BootState::Stabilizing)
+        );
+        assert_eq!(sequencer.deadline(), Some(at(1600)));
+    }
+
+    /// Test running without a battery and inserting one later
+    #[test]
+    fn test_no_battery() {
+        let config = Config::default();
+        let mut sequencer = Sequencer::new(true);
+        assert_eq!(
+            sequencer.update(NO_BATTERY, false, at(0), &config),
+            Some(BootState::NoBattery)
+        );
+        assert_eq!(
+            sequencer.update(NO_BATTERY, true, at(0), &config),
+            Some(BootState::Stabilizing)
+        );
+        assert_eq!(
+            sequencer.update(NO_BATTERY, true, at(1000), &config),
+            Some(BootState::AcOnly)
+        );
+        assert!(sequencer.power_stable());
+
+        assert_eq!(
+            sequencer.update(HEALTHY, true, at(2000), &config),
+            Some(BootState::Running)
+        );
+    }
+
+    /// Test booting with a healthy battery and with the sequencer disabled
+    #[test]
+    fn test_healthy_battery() {
+        let config = Config::default();
+        let mut sequencer = Sequencer::new(true);
+        assert_eq!(
+            sequencer.update(HEALTHY, false, at(0), &config),
+            Some(BootState::Running)
+        );
+
+        let sequencer = Sequencer::default();
+        assert_eq!(sequencer.state(), BootState::Running);
+        assert!(sequencer.power_stable());
+    }
+}
@@ -0,0 +1,121 @@
+//! This file implements the input power budget shared by the system, providers and chargers.
+//! While running from external power the consumer contract is split between the
+//! [system load](super::Config::system_load_mw), connected providers and the chargers, in that order.
+//! Chargers are given whatever is left. If providers would leave less than [min_charge_mw](super::Config::min_charge_mw)
+//! for charging, for example because the consumer contract shrank, provider power is reallocated, see [provider](super::provider).
+use embedded_services::trace;
+
+use super::*;
+
+/// Compute the budget breakdown
+fn compute_budget(input_mw: u32, providers_mw: u32, config: &Config) -> PowerBudget {
+    PowerBudget {
+        input_mw,
+        system_load_mw: config.system_load_mw,
+        providers_mw,
+        charger_mw: input_mw
+            .saturating_sub(config.system_load_mw)
+            .saturating_sub(providers_mw),
+    }
+}
+
+/// Maximum power providers can draw from the consumer contract, `None` when running from battery
+pub(super) fn provider_headroom_mw(input_mw: u32, config: &Config) -> Option<u32> {
+    (input_mw > 0).then(|| {
+        input_mw
+            .saturating_sub(config.system_load_mw)
+            .saturating_sub(config.min_charge_mw)
+    })
+}
+
+impl PowerPolicy {
+    /// Power available from the current consumer contract
+    pub(super) fn input_power_mw(state: &InternalState) -> u32 {
+        state.current_consumer_state.map_or(0, |consumer| {
+            consumer.consumer_power_capability.capability.max_power_mw()
+        })
+    }
+
+    /// Total power currently allocated to providers
+    async fn total_provider_power_mw(&self) -> u32 {
+        let mut total_power_mw = 0;
+        for device in self.context.devices().iter_only::<device::Device>() {
+            total_power_mw += device
+                .provider_capability()
+                .await
+                .map_or(0, |cap| cap.capability.max_power_mw());
+        }
+        total_power_mw
+    }
+
+    /// Recompute the power budget, reallocating provider power if needed, and notify chargers and listeners of changes
+    ///
+    /// Also publishes the policy state snapshot.
+    pub(super) async fn update_budget(&self, state: &mut InternalState) -> Result<(), Error> {
+        let input_mw = Self::input_power_mw(state);
+        self.update_providers(state).await;
+        let providers_mw = self.total_provider_power_mw().await;
+
+        let budget = compute_budget(input_mw, providers_mw, &self.config);
+        if budget == state.budget {
+            trace!("Power budget unchanged");
+        } else {
+            info!("Power budget changed: {:?}", budget);
+            state.budget = budget;
+            self.comms_notify(CommsMessage {
+                data: CommsData::Budget(budget),
+            })
+            .await;
+        }
+
+        // The split between chargers also depends on the consumer port
+        self.update_charger_budgets(state).await?;
+
+        // Every state change ends up here
+        self.publish_snapshot(state).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            system_load_mw: 10000,
+            min_charge_mw: 5000,
+            ..Default::default()
+        }
+    }
+
+    /// Test the budget breakdown
+    #[test]
+    fn test_compute_budget() {
+        let config = config();
+        assert_eq!(
+            compute_budget(60000, 15000, &config),
+            PowerBudget {
+                input_mw: 60000,
+                system_load_mw: 10000,
+                providers_mw: 15000,
+                charger_mw: 35000,
+            }
+        );
+
+        // Input contract too small for everything
+        assert_eq!(compute_budget(15000, 15000, &config).charger_mw, 0);
+
+        // Running from battery
+        assert_eq!(compute_budget(0, 7500, &config).charger_mw, 0);
+    }
+
+    /// Test provider headroom
+    #[test]
+    fn test_provider_headroom() {
+        let config = config();
+        assert_eq!(provider_headroom_mw(60000, &config), Some(45000));
+        assert_eq!(provider_headroom_mw(12000, &config), Some(0));
+        assert_eq!(provider_headroom_mw(0, &config), None);
+    }
+}
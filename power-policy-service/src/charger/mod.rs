@@ -1,7 +1,7 @@
 use embassy_sync::mutex::Mutex;
 use embedded_services::GlobalRawMutex;
 
-use embassy_futures::select::select;
+use embassy_futures::select::{Either3, select3};
 use embedded_services::{
     debug, error, info,
     power::policy::charger::{
@@ -10,12 +10,18 @@ use embedded_services::{
     trace, warn,
 };
 
+pub mod regulation;
+
 pub struct Wrapper<'a, C: ChargeController>
 where
     charger::ChargerError: From<<C as ChargeController>::ChargeControllerError>,
 {
     charger_policy_state: &'a charger::Device,
     controller: Mutex<GlobalRawMutex, C>,
+    /// Charge current and voltage regulation
+    regulator: Mutex<GlobalRawMutex, regulation::Regulator>,
+    /// Setpoint last programmed into the charger, `None` if it needs to be reprogrammed
+    programmed: Mutex<GlobalRawMutex, Option<regulation::Setpoint>>,
 }
 
 impl<'a, C: ChargeController> Wrapper<'a, C>
@@ -23,12 +29,27 @@ where
     charger::ChargerError: From<<C as ChargeController>::ChargeControllerError>,
 {
     pub fn new(charger_policy_state: &'a charger::Device, controller: C) -> Self {
+        Self::new_with_regulation_config(charger_policy_state, controller, Default::default())
+    }
+
+    pub fn new_with_regulation_config(
+        charger_policy_state: &'a charger::Device,
+        controller: C,
+        regulation_config: regulation::Config,
+    ) -> Self {
         Self {
             charger_policy_state,
             controller: Mutex::new(controller),
+            regulator: Mutex::new(regulation::Regulator::new(regulation_config)),
+            programmed: Mutex::new(None),
         }
     }
 
+    /// Returns the last computed regulation setpoint
+    pub async fn setpoint(&self) -> regulation::Setpoint {
+        self.regulator.lock().await.setpoint()
+    }
+
     pub async fn get_state(&self) -> charger::InternalState {
         self.charger_policy_state.state().await
     }
@@ -251,20 +272,86 @@ where
         self.charger_policy_state.send_response(res).await;
     }
 
+    /// Recompute the regulation setpoint and program the charger if it changed
+    async fn regulate(&self, controller: &mut C) {
+        let state = self.get_state().await;
+        let inputs = self.charger_policy_state.regulation_inputs().await;
+        let setpoint = {
+            let mut regulator = self.regulator.lock().await;
+            regulator.set_battery(inputs.battery);
+            regulator.set_thermal_limit(inputs.thermal_limit_ma);
+            regulator.set_charge_budget(inputs.charge_budget_mw);
+            // Only charge from an attached PSU with an active contract
+            regulator.set_input_power(match (state.state, state.capability) {
+                (State::Powered(PoweredSubstate::PsuAttached), Some(capability)) => {
+                    capability.capability.max_power_mw()
+                }
+                _ => 0,
+            });
+            regulator.regulate()
+        };
+
+        match state.state {
+            State::Powered(PoweredSubstate::PsuAttached) | State::Powered(PoweredSubstate::PsuDetached) => (),
+            // Charger can't be programmed yet, do it once initialized
+            _ => {
+                *self.programmed.lock().await = None;
+                return;
+            }
+        }
+
+        let mut programmed = self.programmed.lock().await;
+        if *programmed == Some(setpoint) {
+            return;
+        }
+
+        if programmed.map(|p| p.phase) != Some(setpoint.phase) {
+            info!("Charge phase: {:?}", setpoint.phase);
+        }
+        debug!(
+            "Programming charger: {}mV, {}mA",
+            setpoint.voltage_mv, setpoint.current_ma
+        );
+
+        // Program the voltage first so the current is never applied with a stale voltage
+        // Leave the voltage alone when not charging, zero current is enough to stop charging
+        let voltage_ok = matches!(setpoint.phase, regulation::Phase::Idle | regulation::Phase::Limited)
+            || controller.charging_voltage(setpoint.voltage_mv).await.is_ok();
+        if !voltage_ok || controller.charging_current(setpoint.current_ma).await.is_err() {
+            error!("Failed to program charger setpoint");
+            // Retry on the next regulation pass
+            *programmed = None;
+            return;
+        }
+
+        *programmed = Some(setpoint);
+    }
+
     pub async fn process(&self) {
         let mut controller = self.controller.lock().await;
         loop {
-            let res = select(controller.wait_event(), self.wait_policy_command()).await;
+            let res = select3(
+                controller.wait_event(),
+                self.wait_policy_command(),
+                self.charger_policy_state.wait_regulation_inputs_changed(),
+            )
+            .await;
             match res {
-                embassy_futures::select::Either::First(event) => {
+                Either3::First(event) => {
                     trace!("New charger device event.");
                     self.process_controller_event(&mut controller, event).await;
                 }
-                embassy_futures::select::Either::Second(event) => {
+                Either3::Second(event) => {
                     trace!("New charger policy command.");
                     self.process_policy_command(&mut controller, event).await;
                 }
+                Either3::Third(inputs) => {
+                    trace!("Charge regulation inputs changed: {:?}", inputs);
+                }
             };
+
+            // Any event can change the regulation inputs
+            self.regulate(&mut controller).await;
         }
     }
 }
@@ -0,0 +1,480 @@
+//! Charge current and voltage regulation
+//!
+//! Combines the fuel gauge charging request, the available input power, thermal throttling and the charge phase into
+//! the voltage and current programmed into the charger. This module contains no I/O so it can be driven by the charger
+//! wrapper as well as by simulations.
+pub use embedded_services::power::policy::charger::BatteryInputs;
+
+/// Regulation configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Below this pack voltage the battery is deeply discharged and only charged with the trickle current
+    pub trickle_voltage_mv: u16,
+    /// Charge current used in the trickle phase
+    pub trickle_current_ma: u16,
+    /// Below this pack voltage the battery is charged with the pre-charge current
+    pub precharge_voltage_mv: u16,
+    /// Charge current used in the pre-charge phase
+    pub precharge_current_ma: u16,
+    /// Constant voltage is entered once the pack voltage is within this margin of the charge voltage
+    pub cv_margin_mv: u16,
+    /// Charging terminates once the measured current drops below this value in constant voltage
+    pub termination_current_ma: u16,
+    /// Charging restarts after termination once the pack voltage drops this far below the charge voltage
+    pub recharge_hysteresis_mv: u16,
+    /// Maximum charge current supported by the charger hardware
+    pub max_current_ma: u16,
+    /// Charger conversion efficiency in percent, used to convert input power into charge current
+    pub efficiency_percent: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        // 2S Li-ion pack
+        Self {
+            trickle_voltage_mv: 5000,
+            trickle_current_ma: 64,
+            precharge_voltage_mv: 6000,
+            precharge_current_ma: 256,
+            cv_margin_mv: 50,
+            termination_current_ma: 128,
+            recharge_hysteresis_mv: 200,
+            max_current_ma: 5000,
+            efficiency_percent: 90,
+        }
+    }
+}
+
+/// Charge phase
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phase {
+    /// Not charging, no battery, no input power or no charge requested
+    #[default]
+    Idle,
+    /// Battery is deeply discharged
+    Trickle,
+    /// Battery is below the pre-charge threshold
+    PreCharge,
+    /// Fast charge at the requested current
+    ConstantCurrent,
+    /// Pack voltage has reached the charge voltage, current tapers off
+    ConstantVoltage,
+    /// Charging complete
+    Terminated,
+    /// Charging held by the charge limit
+    Limited,
+}
+
+/// Values to program into the charger
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Setpoint {
+    /// Charge phase
+    pub phase: Phase,
+    /// Charge voltage
+    pub voltage_mv: u16,
+    /// Charge current
+    pub current_ma: u16,
+}
+
+/// Charge regulator
+#[derive(Debug, Clone, Copy)]
+pub struct Regulator {
+    /// Configuration
+    config: Config,
+    /// Latest battery inputs
+    battery: BatteryInputs,
+    /// Power available from the current consumer contract, zero if there is none
+    input_power_mw: u32,
+    /// Input power the power policy leaves for charging
+    charge_budget_mw: Option<u32>,
+    /// Charge current limit requested by thermal management
+    thermal_limit_ma: Option<u16>,
+    /// Last computed setpoint
+    setpoint: Setpoint,
+    /// Current limit applied by the regulator itself in the last setpoint, i.e. excluding the fuel gauge request
+    limit_ma: u16,
+}
+
+impl Regulator {
+    /// Create a new regulator
+    pub const fn new(config: Config) -> Self {
+        Self {
+            config,
+            battery: BatteryInputs {
+                present: false,
+                requested_voltage_mv: 0,
+                requested_current_ma: 0,
+                voltage_mv: 0,
+                current_ma: 0,
+                fully_charged: false,
+                state_of_charge_pct: 0,
+                charge_limited: false,
+            },
+            input_power_mw: 0,
+            charge_budget_mw: None,
+            thermal_limit_ma: None,
+            setpoint: Setpoint {
+                phase: Phase::Idle,
+                voltage_mv: 0,
+                current_ma: 0,
+            },
+            limit_ma: 0,
+        }
+    }
+
+    /// Current charge phase
+    pub fn phase(&self) -> Phase {
+        self.setpoint.phase
+    }
+
+    /// Last computed setpoint
+    pub fn setpoint(&self) -> Setpoint {
+        self.setpoint
+    }
+
+    /// Latest battery inputs
+    pub fn battery(&self) -> BatteryInputs {
+        self.battery
+    }
+
+    /// Update the battery measurements and fuel gauge request
+    pub fn set_battery(&mut self, battery: BatteryInputs) {
+        self.battery = battery;
+    }
+
+    /// Set the power available from the current consumer contract
+    pub fn set_input_power(&mut self, input_power_mw: u32) {
+        self.input_power_mw = input_power_mw;
+    }
+
+    /// Set the input power the power policy leaves for charging, `None` removes the limit
+    pub fn set_charge_budget(&mut self, charge_budget_mw: Option<u32>) {
+        self.charge_budget_mw = charge_budget_mw;
+    }
+
+    /// Set the thermal charge current limit, `None` removes the limit
+    pub fn set_thermal_limit(&mut self, limit_ma: Option<u16>) {
+        self.thermal_limit_ma = limit_ma;
+    }
+
+    /// Maximum charge current the input power can sustain at the given voltage
+    fn budget_current_ma(&self, voltage_mv: u16) -> u16 {
+        if voltage_mv == 0 {
+            return 0;
+        }
+
+        // The charge budget already excludes the system load and provider allocations
+        let input_mw = self.input_power_mw.min(self.charge_budget_mw.unwrap_or(u32::MAX));
+        let available_mw = input_mw as u64 * self.config.efficiency_percent as u64 / 100;
+        u16::try_from(available_mw * 1000 / voltage_mv as u64).unwrap_or(u16::MAX)
+    }
+
+    /// Determine the charge phase from the current inputs
+    fn next_phase(&self) -> Phase {
+        let battery = &self.battery;
+        if !battery.present || self.input_power_mw == 0 || battery.requested_voltage_mv == 0 {
+            return Phase::Idle;
+        }
+
+        if battery.charge_limited {
+            return Phase::Limited;
+        }
+
+        if battery.fully_charged || battery.requested_current_ma == 0 {
+            return Phase::Terminated;
+        }
+
+        let target_mv = battery.requested_voltage_mv;
+        let recharge_mv = target_mv.saturating_sub(self.config.recharge_hysteresis_mv);
+        match self.setpoint.phase {
+            // Wait for the pack to relax before starting another cycle
+            Phase::Terminated if battery.voltage_mv >= recharge_mv => return Phase::Terminated,
+            Phase::ConstantVoltage if battery.voltage_mv >= recharge_mv => {
+                // Only terminate if the taper is caused by the battery and not by our own current limit
+                let taper = battery.current_ma >= 0 && (battery.current_ma as u16) < self.config.termination_current_ma;
+                return if taper && self.limit_ma > self.config.termination_current_ma {
+                    Phase::Terminated
+                } else {
+                    Phase::ConstantVoltage
+                };
+            }
+            _ => (),
+        }
+
+        if battery.voltage_mv < self.config.trickle_voltage_mv {
+            Phase::Trickle
+        } else if battery.voltage_mv < self.config.precharge_voltage_mv {
+            Phase::PreCharge
+        } else if battery.voltage_mv.saturating_add(self.config.cv_margin_mv) >= target_mv {
+            Phase::ConstantVoltage
+        } else {
+            Phase::ConstantCurrent
+        }
+    }
+
+    /// Compute the next setpoint from the current inputs
+    pub fn regulate(&mut self) -> Setpoint {
+        let phase = self.next_phase();
+        let voltage_mv = match phase {
+            Phase::Idle => 0,
+            _ => self.battery.requested_voltage_mv,
+        };
+
+        let phase_limit_ma = match phase {
+            Phase::Idle | Phase::Terminated | Phase::Limited => 0,
+            Phase::Trickle => self.config.trickle_current_ma,
+            Phase::PreCharge => self.config.precharge_current_ma,
+            Phase::ConstantCurrent | Phase::ConstantVoltage => self.config.max_current_ma,
+        };
+
+        self.limit_ma = phase_limit_ma
+            .min(self.config.max_current_ma)
+            .min(self.thermal_limit_ma.unwrap_or(u16::MAX))
+            .min(self.budget_current_ma(voltage_mv));
+
+        self.setpoint = Setpoint {
+            phase,
+            voltage_mv,
+            current_ma: self.battery.requested_current_ma.min(self.limit_ma),
+        };
+        self.setpoint
+    }
+}
+
+impl Default for Regulator {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simple simulated battery
+    ///
+    /// Pack voltage rises linearly with charge and the fuel gauge tapers the requested current in constant voltage.
+    struct SimBattery {
+        /// Charge in mAh
+        charge_mah: u32,
+        /// Capacity in mAh
+        capacity_mah: u32,
+        /// Empty pack voltage
+        empty_mv: u16,
+        /// Full pack voltage
+        full_mv: u16,
+        /// Current flowing into the battery
+        current_ma: u16,
+    }
+
+    impl SimBattery {
+        fn voltage_mv(&self) -> u16 {
+            let range = (self.full_mv - self.empty_mv) as u32;
+            self.empty_mv + (range * self.charge_mah.min(self.capacity_mah) / self.capacity_mah) as u16
+        }
+
+        fn inputs(&self) -> BatteryInputs {
+            let voltage_mv = self.voltage_mv();
+            // Taper the request over the last 100mV
+            let headroom_mv = self.full_mv.saturating_sub(voltage_mv).min(100) as u32;
+            BatteryInputs {
+                present: true,
+                requested_voltage_mv: self.full_mv,
+                requested_current_ma: (3000 * headroom_mv / 100).max(50) as u16,
+                voltage_mv,
+                current_ma: self.current_ma as i16,
+                fully_charged: self.charge_mah >= self.capacity_mah,
+                state_of_charge_pct: (self.charge_mah.min(self.capacity_mah) * 100 / self.capacity_mah) as u8,
+                charge_limited: false,
+            }
+        }
+
+        /// Apply a setpoint from the charger for one minute
+        fn step(&mut self, setpoint: &Setpoint) {
+            self.current_ma = setpoint.current_ma;
+            self.charge_mah += setpoint.current_ma as u32 / 60;
+        }
+    }
+
+    fn battery(voltage_mv: u16) -> BatteryInputs {
+        BatteryInputs {
+            present: true,
+            requested_voltage_mv: 8400,
+            requested_current_ma: 3000,
+            voltage_mv,
+            current_ma: 0,
+            fully_charged: false,
+            state_of_charge_pct: 50,
+            charge_limited: false,
+        }
+    }
+
+    /// Test phase selection from the pack voltage
+    #[test]
+    fn test_phase_selection() {
+        let mut regulator = Regulator::default();
+        regulator.set_input_power(65000);
+
+        regulator.set_battery(battery(4500));
+        let setpoint = regulator.regulate();
+        assert_eq!(setpoint.phase, Phase::Trickle);
+        assert_eq!(setpoint.current_ma, 64);
+        assert_eq!(setpoint.voltage_mv, 8400);
+
+        regulator.set_battery(battery(5500));
+        let setpoint = regulator.regulate();
+        assert_eq!(setpoint.phase, Phase::PreCharge);
+        assert_eq!(setpoint.current_ma, 256);
+
+        regulator.set_battery(battery(7400));
+        let setpoint = regulator.regulate();
+        assert_eq!(setpoint.phase, Phase::ConstantCurrent);
+        assert_eq!(setpoint.current_ma, 3000);
+
+        regulator.set_battery(battery(8360));
+        assert_eq!(regulator.regulate().phase, Phase::ConstantVoltage);
+    }
+
+    /// Test that the charge current is limited by input power and thermal requests
+    #[test]
+    fn test_limits() {
+        let mut regulator = Regulator::default();
+        regulator.set_battery(battery(7400));
+
+        // No input power
+        assert_eq!(regulator.regulate(), Setpoint::default());
+
+        // 15W at 90% efficiency, 1607mA at 8.4V
+        regulator.set_input_power(15000);
+        assert_eq!(regulator.regulate().current_ma, 1607);
+
+        // System load and providers leave nothing for charging
+        regulator.set_charge_budget(Some(0));
+        assert_eq!(regulator.regulate().current_ma, 0);
+        regulator.set_charge_budget(None);
+
+        regulator.set_input_power(100000);
+        regulator.set_thermal_limit(Some(500));
+        assert_eq!(regulator.regulate().current_ma, 500);
+
+        regulator.set_thermal_limit(None);
+        assert_eq!(regulator.regulate().current_ma, 3000);
+
+        // Policy budget of 10W at 90% efficiency, 1071mA at 8.4V
+        regulator.set_charge_budget(Some(10000));
+        assert_eq!(regulator.regulate().current_ma, 1071);
+        regulator.set_charge_budget(None);
+        assert_eq!(regulator.regulate().current_ma, 3000);
+    }
+
+    /// Test that a budget-limited current doesn't terminate charging
+    #[test]
+    fn test_no_false_termination() {
+        let mut regulator = Regulator::default();
+        regulator.set_input_power(65000);
+        regulator.set_battery(BatteryInputs {
+            current_ma: 3000,
+            ..battery(8380)
+        });
+        assert_eq!(regulator.regulate().phase, Phase::ConstantVoltage);
+
+        // Thermal limit below the termination current
+        regulator.set_thermal_limit(Some(100));
+        assert_eq!(regulator.regulate().phase, Phase::ConstantVoltage);
+        regulator.set_battery(BatteryInputs {
+            current_ma: 100,
+            ..battery(8380)
+        });
+        assert_eq!(regulator.regulate().phase, Phase::ConstantVoltage);
+
+        // Battery tapers below the termination current on its own
+        regulator.set_thermal_limit(None);
+        assert_eq!(regulator.regulate().phase, Phase::ConstantVoltage);
+        regulator.set_battery(BatteryInputs {
+            current_ma: 100,
+            ..battery(8380)
+        });
+        assert_eq!(regulator.regulate().phase, Phase::Terminated);
+    }
+
+    /// Test recharge after termination
+    #[test]
+    fn test_recharge() {
+        let mut regulator = Regulator::default();
+        regulator.set_input_power(65000);
+        regulator.set_battery(BatteryInputs {
+            fully_charged: true,
+            ..battery(8400)
+        });
+        let setpoint = regulator.regulate();
+        assert_eq!(setpoint.phase, Phase::Terminated);
+        assert_eq!(setpoint.current_ma, 0);
+
+        // Within hysteresis
+        regulator.set_battery(battery(8300));
+        assert_eq!(regulator.regulate().phase, Phase::Terminated);
+
+        regulator.set_battery(battery(8100));
+        assert_eq!(regulator.regulate().phase, Phase::ConstantCurrent);
+    }
+
+    /// Test that the charge limit holds charging
+    #[test]
+    fn test_charge_limited() {
+        let mut regulator = Regulator::default();
+        regulator.set_input_power(65000);
+        regulator.set_battery(BatteryInputs {
+            charge_limited: true,
+            ..battery(7400)
+        });
+        let setpoint = regulator.regulate();
+        assert_eq!(setpoint.phase, Phase::Limited);
+        assert_eq!(setpoint.current_ma, 0);
+
+        regulator.set_battery(battery(7400));
+        assert_eq!(regulator.regulate().phase, Phase::ConstantCurrent);
+    }
+
+    /// Charge a simulated battery from empty to full
+    #[test]
+    fn test_full_charge_cycle() {
+        let mut regulator = Regulator::default();
+        regulator.set_input_power(45000);
+        let mut sim = SimBattery {
+            charge_mah: 0,
+            capacity_mah: 3000,
+            empty_mv: 4800,
+            full_mv: 8400,
+            current_ma: 0,
+        };
+
+        let mut phases: heapless::Vec<Phase, 8> = heapless::Vec::new();
+        for _ in 0..1000 {
+            regulator.set_battery(sim.inputs());
+            let setpoint = regulator.regulate();
+            assert!(setpoint.current_ma <= regulator.config.max_current_ma);
+            if phases.last() != Some(&setpoint.phase) {
+                assert!(phases.push(setpoint.phase).is_ok());
+            }
+
+            if setpoint.phase == Phase::Terminated {
+                break;
+            }
+            sim.step(&setpoint);
+        }
+
+        assert_eq!(
+            phases.as_slice(),
+            &[
+                Phase::Trickle,
+                Phase::PreCharge,
+                Phase::ConstantCurrent,
+                Phase::ConstantVoltage,
+                Phase::Terminated
+            ]
+        );
+    }
+}
@@ -1,19 +1,64 @@
 //! Configuration types for the power policy service
 
-use embedded_services::power::policy::PowerCapability;
+use embedded_services::power::policy::charger::ChargerId;
+use embedded_services::power::policy::{DeviceId, PowerCapability};
+
+use crate::boot;
+use crate::selection::{ConsumerSelection, MaxPower};
+
+/// Provider configuration of a single port
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProviderPort {
+    /// Device ID of the port
+    pub device_id: DeviceId,
+    /// Maximum power capability provided on this port
+    pub max: PowerCapability,
+    /// Ports with a higher priority are given their full capability first
+    pub priority: u8,
+}
+
+/// Routing of consumer ports to a single charger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChargerRoute {
+    /// Charger ID
+    pub charger_id: ChargerId,
+    /// Consumer ports that feed this charger
+    pub ports: &'static [DeviceId],
+    /// Maximum input current the charger can draw, [`None`] if only limited by the consumer contract
+    pub max_input_current_ma: Option<u16>,
+}
 
 #[derive(Clone, Copy)]
 pub struct Config {
-    /// Above this threshold, the system is in limited power mode
+    /// Total power that can be provided, above this threshold the system is in limited power mode
     pub limited_power_threshold_mw: u32,
-    /// Power capability of every provider in normal power mode
+    /// Power capability of providers without a [`ProviderPort`] entry in normal power mode
     pub provider_unlimited: PowerCapability,
-    /// Power capability of every provider in limited power mode
+    /// Power capability guaranteed to every provider, used in limited power mode
     pub provider_limited: PowerCapability,
+    /// Per-port provider configuration, ports not listed use [`provider_unlimited`](Self::provider_unlimited) and the lowest priority
+    pub provider_ports: &'static [ProviderPort],
+    /// Below this battery state of charge, providers are disabled while running from battery
+    pub provider_min_soc_pct: u8,
+    /// Above this battery temperature in deci-Kelvin, providers only get [`provider_limited`](Self::provider_limited)
+    /// while running from battery
+    pub provider_max_battery_temp_dk: Option<u16>,
     /// Minimum power threshold to consume power from.
     ///
     /// If [`None`], the service will consume from providers, regardless of how much power they provide.
     pub min_consumer_threshold_mw: Option<u32>,
+    /// Estimated power drawn by the rest of the system, reserved from the consumer contract before providers and chargers
+    pub system_load_mw: u32,
+    /// Power kept available for charging, providers are throttled rather than dropping below this
+    pub min_charge_mw: u32,
+    /// Per-charger routing, chargers not listed are fed from every port
+    pub charger_routes: &'static [ChargerRoute],
+    /// Strategy used to select the consumer to connect to
+    pub consumer_selection: &'static dyn ConsumerSelection,
+    /// Boot power sequencing, [`None`] to start in normal operation
+    pub boot: Option<boot::Config>,
 }
 
 impl Default for Config {
@@ -31,8 +76,22 @@ impl Default for Config {
                 voltage_mv: 5000,
                 current_ma: 1500,
             },
+            // All ports equal
+            provider_ports: &[],
+            // Providers are always allowed on battery
+            provider_min_soc_pct: 0,
+            provider_max_battery_temp_dk: None,
             // No minimum threshold
             min_consumer_threshold_mw: None,
+            // Nothing reserved, chargers use whatever providers leave
+            system_load_mw: 0,
+            min_charge_mw: 0,
+            // Every charger is fed from every port
+            charger_routes: &[],
+            // Highest power wins
+            consumer_selection: &MaxPower,
+            // Battery is assumed to carry the system at boot
+            boot: None,
         }
     }
 }
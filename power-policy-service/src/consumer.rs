@@ -2,10 +2,9 @@ use core::cmp::Ordering;
 use embedded_services::debug;
 use embedded_services::power::policy::charger::Device as ChargerDevice;
 use embedded_services::power::policy::charger::PolicyEvent;
-use embedded_services::power::policy::policy::check_chargers_ready;
-use embedded_services::power::policy::policy::init_chargers;
 
 use super::*;
+use crate::selection::{self, Candidate};
 
 /// State of the current consumer
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,20 +16,6 @@ pub struct AvailableConsumer {
     pub consumer_power_capability: ConsumerPowerCapability,
 }
 
-/// Compare two consumer capabilities to determine which one is better
-///
-/// This is not part of the `Ord` implementation for `ConsumerPowerCapability`, because it's specific to this implementation.
-/// *_is_current indicate if the device with that capability is the currently connected consumer. This is used to make the
-/// implementation stick so as to avoid switching between otherwise equivalent consumers.
-fn cmp_consumer_capability(
-    a: &ConsumerPowerCapability,
-    a_is_current: bool,
-    b: &ConsumerPowerCapability,
-    b_is_current: bool,
-) -> Ordering {
-    (a.capability, a_is_current).cmp(&(b.capability, b_is_current))
-}
-
 impl PowerPolicy {
     /// Iterate over all devices to determine what is best power port provides the highest power
     async fn find_best_consumer(&self, state: &InternalState) -> Result<Option<AvailableConsumer>, Error> {
@@ -41,10 +26,11 @@ impl PowerPolicy {
             let device = node.data::<Device>().ok_or(Error::InvalidDevice)?;
 
             let consumer_capability = device.consumer_capability().await;
-            // Don't consider consumers below minimum threshold
-            if consumer_capability
-                .zip(self.config.min_consumer_threshold_mw)
-                .is_some_and(|(cap, min)| cap.capability.max_power_mw() < min)
+            // Don't consider consumers below minimum threshold, unless we need power to boot
+            if !state.boot.needs_power()
+                && consumer_capability
+                    .zip(self.config.min_consumer_threshold_mw)
+                    .is_some_and(|(cap, min)| cap.capability.max_power_mw() < min)
             {
                 info!(
                     "Device{}: Not considering consumer, power capability is too low",
@@ -66,12 +52,19 @@ impl PowerPolicy {
                 (Some(_), None) => best_consumer,
                 // Existing consumer, new available consumer
                 (Some(best), Some(available)) => {
-                    if cmp_consumer_capability(
-                        &available,
+                    if selection::cmp_candidates(
+                        self.config.consumer_selection,
+                        &Candidate {
+                            device_id: device.id(),
+                            capability: available,
+                        },
                         Some(device.id()) == current_consumer_id,
-                        &best.consumer_power_capability,
+                        &Candidate {
+                            device_id: best.device_id,
+                            capability: best.consumer_power_capability,
+                        },
                         Some(best.device_id) == current_consumer_id,
-                    ) == core::cmp::Ordering::Greater
+                    ) == Ordering::Greater
                     {
                         Some(AvailableConsumer {
                             device_id: device.id(),
@@ -123,31 +116,16 @@ impl PowerPolicy {
         connected_consumer: AvailableConsumer,
     ) -> Result<(), Error> {
         state.current_consumer_state = Some(connected_consumer);
-        // todo: review the delay time
-        embassy_time::Timer::after_millis(800).await;
+        state.consumer_connected_at = Some(Instant::now());
 
-        // If no chargers are registered, they won't receive the new power capability.
-        for node in self.context.chargers() {
-            let device = node.data::<ChargerDevice>().ok_or(Error::InvalidDevice)?;
-            // Chargers should be powered at this point, but in case they are not...
-            if let embedded_services::power::policy::charger::ChargerResponseData::UnpoweredAck = device
-                .execute_command(PolicyEvent::PolicyConfiguration(
-                    connected_consumer.consumer_power_capability,
-                ))
-                .await?
-            {
-                // Force charger CheckReady and InitRequest to get it into an initialized state.
-                // This condition can get hit if we did not have a previous consumer and the charger is unpowered.
-                info!("Charger is unpowered, forcing charger CheckReady and Init sequence");
-                check_chargers_ready().await?;
-                init_chargers().await?;
-                device
-                    .execute_command(PolicyEvent::PolicyConfiguration(
-                        connected_consumer.consumer_power_capability,
-                    ))
-                    .await?;
-            }
+        if state.boot.power_stable() {
+            // todo: review the delay time
+            embassy_time::Timer::after_millis(800).await;
+            self.configure_chargers(&connected_consumer).await?;
+        } else {
+            info!("Power not stable yet, deferring charger configuration");
         }
+
         self.comms_notify(CommsMessage {
             data: CommsData::ConsumerConnected(
                 connected_consumer.device_id,
@@ -250,6 +228,24 @@ impl PowerPolicy {
         Ok(())
     }
 
+    /// Returns the time until which the current consumer is held if switching to `best` should be deferred
+    async fn consumer_hold_until(&self, state: &InternalState, best: &AvailableConsumer) -> Option<Instant> {
+        let current = state.current_consumer_state?;
+        if current.device_id == best.device_id || state.boot.needs_power() {
+            return None;
+        }
+
+        // The hold time doesn't apply once the current consumer stops offering power
+        self.context
+            .get_device(current.device_id)
+            .ok()?
+            .consumer_capability()
+            .await?;
+
+        let hold_until = state.consumer_connected_at? + self.config.consumer_selection.hold_time();
+        (Instant::now() < hold_until).then_some(hold_until)
+    }
+
     /// Determines and connects the best external power
     pub(super) async fn update_current_consumer(&self) -> Result<(), Error> {
         let mut guard = self.state.lock().await;
@@ -259,10 +255,16 @@ impl PowerPolicy {
             state.current_consumer_state
         );
 
+        state.reevaluate_at = None;
         let best_consumer = self.find_best_consumer(state).await?;
         info!("Best consumer: {:#?}", best_consumer);
         if let Some(best_consumer) = best_consumer {
-            self.connect_new_consumer(state, best_consumer).await?;
+            if let Some(hold_until) = self.consumer_hold_until(state, &best_consumer).await {
+                info!("Holding current consumer until the selection hold time expires");
+                state.reevaluate_at = Some(hold_until);
+            } else {
+                self.connect_new_consumer(state, best_consumer).await?;
+            }
         } else {
             // Notify disconnect if recently detached consumer was previously attached.
             if let Some(consumer_state) = state.current_consumer_state {
@@ -276,31 +278,8 @@ impl PowerPolicy {
             state.current_consumer_state = None;
         }
 
-        self.update_unconstrained_state(state).await
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    const P0: PowerCapability = PowerCapability {
-        voltage_mv: 5000,
-        current_ma: 1000,
-    };
-    const P1: PowerCapability = PowerCapability {
-        voltage_mv: 5000,
-        current_ma: 1500,
-    };
-
-    /// Tests the [`cmp_consumer_capability`] without any flags set.
-    #[test]
-    fn test_cmp_consumer_capability_no_flags() {
-        let p0 = P0.into();
-        let p1 = P1.into();
-
-        assert_eq!(cmp_consumer_capability(&p0, false, &p1, false), Ordering::Less);
-        assert_eq!(cmp_consumer_capability(&p1, false, &p1, false), Ordering::Equal);
-        assert_eq!(cmp_consumer_capability(&p1, false, &p0, false), Ordering::Greater);
+        self.update_unconstrained_state(state).await?;
+        self.update_boot(state).await?;
+        self.update_budget(state).await
     }
 }
@@ -0,0 +1,267 @@
+//! Host queries of the power policy state
+//!
+//! The host reads the power policy state with the ODP power service [`PowerCmd::GetPolicy`] command. The response
+//! carries the consumer, the connected providers and the registered chargers, see [`mctp::PowerPolicyState`].
+//!
+//! The host also reports its sleep state transitions with [`PowerCmd::SetSystemPowerState`], these are requested from
+//! the [system power state](system_power) manager by [`system_power_task`](crate::task::system_power_task).
+//!
+//! [`PowerCmd::GetPolicy`]: embedded_services::ec_type::protocols::power::PowerCmd::GetPolicy
+//! [`PowerCmd::SetSystemPowerState`]: embedded_services::ec_type::protocols::power::PowerCmd::SetSystemPowerState
+use embedded_services::ec_type::message::{StdHostMsg, StdHostRequest};
+use embedded_services::ec_type::protocols::mctp;
+use embedded_services::power::policy::snapshot::Snapshot;
+use embedded_services::power::policy::{BootState, charger, policy};
+use embedded_services::power::system::{self as system_power, SystemPowerState};
+use embedded_services::{comms, error, info, trace};
+
+use crate::PowerPolicy;
+
+/// Providers can only get limited power
+pub const FLAG_PROVIDERS_LIMITED: u32 = 1 << 0;
+/// The system is running unconstrained
+pub const FLAG_UNCONSTRAINED: u32 = 1 << 1;
+
+/// Encode the boot state for the host
+fn encode_boot_state(state: BootState) -> u32 {
+    match state {
+        BootState::Starting => 0,
+        BootState::DeadBattery => 1,
+        BootState::NoBattery => 2,
+        BootState::Stabilizing => 3,
+        BootState::Running => 4,
+        BootState::AcOnly => 5,
+    }
+}
+
+/// Decode the system power state sent by the host, S0ix is sent as `0x10`
+fn decode_system_power_state(state: u32) -> Option<SystemPowerState> {
+    match state {
+        0 => Some(SystemPowerState::S0),
+        3 => Some(SystemPowerState::S3),
+        4 => Some(SystemPowerState::S4),
+        5 => Some(SystemPowerState::S5),
+        0x10 => Some(SystemPowerState::S0ix),
+        _ => None,
+    }
+}
+
+/// Encode a charger state for the host
+fn encode_charger_state(state: charger::State) -> u8 {
+    match state {
+        charger::State::Unpowered => 0,
+        charger::State::Powered(charger::PoweredSubstate::Init) => 1,
+        charger::State::Powered(charger::PoweredSubstate::PsuAttached) => 2,
+        charger::State::Powered(charger::PoweredSubstate::PsuDetached) => 3,
+    }
+}
+
+/// Encode a power policy snapshot for the host
+pub fn encode(snapshot: &Snapshot) -> mctp::PowerPolicyState {
+    let consumer = snapshot.consumer.map(|consumer| consumer.capability.capability);
+    let mut flags = 0;
+    if snapshot.providers_limited {
+        flags |= FLAG_PROVIDERS_LIMITED;
+    }
+    if snapshot.unconstrained.unconstrained {
+        flags |= FLAG_UNCONSTRAINED;
+    }
+
+    let mut state = mctp::PowerPolicyState {
+        // No consumer is reported as all ones
+        consumer_id: snapshot
+            .consumer
+            .map_or(u32::MAX, |consumer| consumer.device_id.0.into()),
+        consumer_voltage_mv: consumer.map_or(0, |capability| capability.voltage_mv.into()),
+        consumer_current_ma: consumer.map_or(0, |capability| capability.current_ma.into()),
+        providers_mw: snapshot.budget.providers_mw,
+        charger_mw: snapshot.budget.charger_mw,
+        flags,
+        boot_state: encode_boot_state(snapshot.boot),
+        ..Default::default()
+    };
+
+    for (entry, provider) in state.providers.iter_mut().zip(&snapshot.providers) {
+        *entry = mctp::PowerPolicyProvider {
+            device_id: provider.device_id.0,
+            voltage_mv: provider.capability.capability.voltage_mv,
+            current_ma: provider.capability.capability.current_ma,
+        };
+        state.provider_count += 1;
+    }
+
+    for (entry, charger) in state.chargers.iter_mut().zip(&snapshot.chargers) {
+        let capability = charger.state.capability.map(|capability| capability.capability);
+        *entry = mctp::PowerPolicyCharger {
+            charger_id: charger.id.0,
+            state: encode_charger_state(charger.state.state),
+            voltage_mv: capability.map_or(0, |capability| capability.voltage_mv),
+            current_ma: capability.map_or(0, |capability| capability.current_ma),
+        };
+        state.charger_count += 1;
+    }
+
+    state
+}
+
+impl PowerPolicy {
+    /// Answer a host request
+    pub(super) async fn process_host_request(&self, mut request: StdHostRequest) {
+        match request.payload {
+            mctp::Odp::PowerGetPolicyRequest => {
+                trace!("Power policy: got host policy request");
+                request.payload = mctp::Odp::PowerGetPolicyResponse {
+                    policy: encode(&policy::snapshot().await),
+                };
+                request.status = 0;
+            }
+            _ => {
+                error!("Power policy: unsupported host request");
+                request.payload = mctp::Odp::ErrorResponse {};
+                request.status = 1;
+            }
+        }
+
+        self.send_host_response(request).await;
+    }
+
+    /// Request the system power state transition reported by the host
+    ///
+    /// Must not run from the policy loop, the transition waits for the policy's own hook to respond.
+    pub(super) async fn process_system_power_request(&self, mut request: StdHostRequest) {
+        let mctp::Odp::PowerSetSystemPowerStateRequest { state } = request.payload else {
+            error!("Power policy: unexpected system power request");
+            return;
+        };
+
+        let status: u8 = match decode_system_power_state(state) {
+            Some(state) => match system_power::request_transition(state).await {
+                Ok(()) => 0,
+                Err(e) => {
+                    info!("Power policy: host system power transition failed: {:?}", e);
+                    1
+                }
+            },
+            None => {
+                error!("Power policy: invalid host system power state {}", state);
+                1
+            }
+        };
+
+        request.payload = mctp::Odp::PowerSetSystemPowerStateResponse { status: status.into() };
+        request.status = status;
+        self.send_host_response(request).await;
+    }
+
+    /// Send a response to a host request
+    async fn send_host_response(&self, request: StdHostRequest) {
+        if self
+            .tp
+            .send(
+                comms::EndpointID::External(comms::External::Host),
+                &StdHostMsg::Response(request),
+            )
+            .await
+            .is_err()
+        {
+            error!("Power policy: failed to send host response");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_services::power::policy::snapshot;
+    use embedded_services::power::policy::{DeviceId, PowerCapability};
+
+    use super::*;
+
+    /// Test encoding the consumer, providers and chargers for the host
+    #[test]
+    fn test_encode() {
+        let mut snapshot = Snapshot {
+            consumer: Some(snapshot::Consumer {
+                device_id: DeviceId(1),
+                capability: PowerCapability {
+                    voltage_mv: 20000,
+                    current_ma: 3250,
+                }
+                .into(),
+            }),
+            providers_limited: true,
+            boot: BootState::Running,
+            ..Default::default()
+        };
+        snapshot.budget.providers_mw = 7500;
+        snapshot.budget.charger_mw = 45000;
+        assert!(
+            snapshot
+                .providers
+                .push(snapshot::Provider {
+                    device_id: DeviceId(2),
+                    capability: PowerCapability {
+                        voltage_mv: 5000,
+                        current_ma: 1500,
+                    }
+                    .into(),
+                })
+                .is_ok()
+        );
+        assert!(
+            snapshot
+                .chargers
+                .push(snapshot::Charger {
+                    id: charger::ChargerId(0),
+                    state: charger::InternalState {
+                        state: charger::State::Powered(charger::PoweredSubstate::PsuAttached),
+                        capability: None,
+                    },
+                })
+                .is_ok()
+        );
+
+        let state = encode(&snapshot);
+        assert_eq!(state.consumer_id, 1);
+        assert_eq!(state.consumer_voltage_mv, 20000);
+        assert_eq!(state.consumer_current_ma, 3250);
+        assert_eq!(state.providers_mw, 7500);
+        assert_eq!(state.charger_mw, 45000);
+        assert_eq!(state.flags, FLAG_PROVIDERS_LIMITED);
+        assert_eq!(state.boot_state, 4);
+        assert_eq!(state.provider_count, 1);
+        assert_eq!(
+            state.providers.first(),
+            Some(&mctp::PowerPolicyProvider {
+                device_id: 2,
+                voltage_mv: 5000,
+                current_ma: 1500,
+            })
+        );
+        assert_eq!(state.charger_count, 1);
+        assert_eq!(
+            state.chargers.first(),
+            Some(&mctp::PowerPolicyCharger {
+                charger_id: 0,
+                state: 2,
+                voltage_mv: 0,
+                current_ma: 0,
+            })
+        );
+
+        // No consumer is reported as all ones
+        let state = encode(&Snapshot::default());
+        assert_eq!(state.consumer_id, u32::MAX);
+        assert_eq!(state.provider_count, 0);
+        assert_eq!(state.charger_count, 0);
+    }
+
+    /// Test decoding the system power states sent by the host
+    #[test]
+    fn test_decode_system_power_state() {
+        assert_eq!(decode_system_power_state(0), Some(SystemPowerState::S0));
+        assert_eq!(decode_system_power_state(3), Some(SystemPowerState::S3));
+        assert_eq!(decode_system_power_state(5), Some(SystemPowerState::S5));
+        assert_eq!(decode_system_power_state(0x10), Some(SystemPowerState::S0ix));
+        assert_eq!(decode_system_power_state(1), None);
+    }
+}
@@ -1,22 +1,35 @@
 #![no_std]
 use core::ops::DerefMut;
+use embassy_futures::select::{Either, Either4, select, select4};
 use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Instant, Timer};
 use embedded_services::GlobalRawMutex;
+use embedded_services::ec_type::message::StdHostRequest;
+use embedded_services::power::policy::battery;
+use embedded_services::power::policy::charger::ChargerId;
 use embedded_services::power::policy::device::Device;
+use embedded_services::power::policy::snapshot::{self, Snapshot};
 use embedded_services::power::policy::{action, policy, *};
+use embedded_services::power::system as system_power;
 use embedded_services::{comms, error, info};
 
+pub mod boot;
+mod budget;
 pub mod config;
 pub mod consumer;
+pub mod host;
 pub mod provider;
+mod routing;
+pub mod selection;
+mod system_power_state;
 pub mod task;
 
 pub use config::Config;
 
-use crate::provider::PowerState;
 pub mod charger;
 
-const MAX_CONNECTED_PROVIDERS: usize = 4;
+const MAX_CONNECTED_PROVIDERS: usize = snapshot::MAX_PROVIDERS;
 
 #[derive(Clone, Default)]
 struct InternalState {
@@ -28,6 +41,22 @@ struct InternalState {
     unconstrained: UnconstrainedState,
     /// Connected providers
     connected_providers: heapless::index_set::FnvIndexSet<DeviceId, MAX_CONNECTED_PROVIDERS>,
+    /// Current power budget
+    budget: PowerBudget,
+    /// Time the current consumer was connected
+    consumer_connected_at: Option<Instant>,
+    /// Time to re-evaluate the consumer selection, set while a switch is held off by the selection hold time
+    reevaluate_at: Option<Instant>,
+    /// Boot power sequencer
+    boot: boot::Sequencer,
+    /// Last reported battery status
+    battery: Option<battery::Status>,
+    /// Providers are currently allowed
+    providers_enabled: bool,
+    /// Host is hibernating or off
+    system_off: bool,
+    /// Charge budget last sent to each charger
+    charger_budgets: heapless::Vec<(ChargerId, u32), snapshot::MAX_CHARGERS>,
 }
 
 /// Power policy state
@@ -38,6 +67,12 @@ pub struct PowerPolicy {
     state: Mutex<GlobalRawMutex, InternalState>,
     /// Comms endpoint
     tp: comms::Endpoint,
+    /// Pending host request
+    host_request: Signal<GlobalRawMutex, StdHostRequest>,
+    /// Pending host system power state request, see [`task::system_power_task`]
+    system_power_request: Signal<GlobalRawMutex, StdHostRequest>,
+    /// System power state transition hook
+    system_power_hook: system_power::Hook,
     /// Config
     config: config::Config,
 }
@@ -47,8 +82,15 @@ impl PowerPolicy {
     pub fn create(config: config::Config) -> Option<Self> {
         Some(Self {
             context: policy::ContextToken::create()?,
-            state: Mutex::new(InternalState::default()),
+            state: Mutex::new(InternalState {
+                boot: boot::Sequencer::new(config.boot.is_some()),
+                providers_enabled: config.boot.is_none(),
+                ..Default::default()
+            }),
             tp: comms::Endpoint::uninit(comms::EndpointID::Internal(comms::Internal::Power)),
+            host_request: Signal::new(),
+            system_power_request: Signal::new(),
+            system_power_hook: system_power::Hook::new("power-policy"),
             config,
         })
     }
@@ -116,33 +158,50 @@ impl PowerPolicy {
     ///
     /// Returns true if the device was operating as a provider
     async fn remove_connected_provider(&self, device_id: DeviceId) -> bool {
-        if self.state.lock().await.connected_providers.remove(&device_id) {
-            // Determine total requested power draw
-            let mut total_power_mw = 0;
-            for device in self.context.devices().iter_only::<device::Device>() {
-                total_power_mw += device
-                    .provider_capability()
-                    .await
-                    .map_or(0, |cap| cap.capability.max_power_mw());
-            }
-
-            self.state.lock().await.current_provider_state.state =
-                if total_power_mw > self.config.limited_power_threshold_mw {
-                    PowerState::Limited
-                } else {
-                    PowerState::Unlimited
-                };
-
+        let mut state = self.state.lock().await;
+        if state.connected_providers.remove(&device_id) {
             self.comms_notify(CommsMessage {
                 data: CommsData::ProviderDisconnected(device_id),
             })
             .await;
+
+            // Remaining providers may be upgraded with the freed power
+            if let Err(e) = self.update_budget(&mut state).await {
+                error!("Failed to update power budget, {:#?}", e);
+            }
             true
         } else {
             false
         }
     }
 
+    /// Publish the policy state for queries from other services and the host
+    async fn publish_snapshot(&self, state: &InternalState) {
+        let mut snapshot = Snapshot {
+            consumer: state.current_consumer_state.map(|consumer| snapshot::Consumer {
+                device_id: consumer.device_id,
+                capability: consumer.consumer_power_capability,
+            }),
+            providers_limited: state.current_provider_state.state == provider::PowerState::Limited,
+            unconstrained: state.unconstrained,
+            budget: state.budget,
+            boot: state.boot.state(),
+            ..Default::default()
+        };
+
+        for device_id in state.connected_providers.iter().copied() {
+            let Ok(device) = self.context.get_device(device_id) else {
+                continue;
+            };
+            if let Some(capability) = device.provider_capability().await {
+                // Can't fail, the snapshot holds as many providers as the policy
+                let _ = snapshot.providers.push(snapshot::Provider { device_id, capability });
+            }
+        }
+
+        self.context.publish_snapshot(snapshot).await;
+    }
+
     async fn wait_request(&self) -> policy::Request {
         self.context.wait_request().await
     }
@@ -184,9 +243,78 @@ impl PowerPolicy {
 
     /// Top-level event loop function
     pub async fn process(&self) -> Result<(), Error> {
-        let request = self.wait_request().await;
-        self.process_request(request).await
+        let (reevaluate_at, boot_deadline) = {
+            let state = self.state.lock().await;
+            (state.reevaluate_at, state.boot.deadline())
+        };
+        let deadline = match (reevaluate_at, boot_deadline) {
+            (Some(reevaluate_at), Some(boot_deadline)) => Some(reevaluate_at.min(boot_deadline)),
+            (reevaluate_at, boot_deadline) => reevaluate_at.or(boot_deadline),
+        };
+
+        let timer = async {
+            match deadline {
+                Some(deadline) => Timer::at(deadline).await,
+                None => core::future::pending().await,
+            }
+        };
+
+        match select4(
+            self.wait_request(),
+            timer,
+            battery::wait_status_changed(),
+            select(self.host_request.wait(), self.system_power_hook.wait_event()),
+        )
+        .await
+        {
+            Either4::First(request) => self.process_request(request).await,
+            Either4::Second(_) => {
+                if reevaluate_at.is_some_and(|reevaluate_at| Instant::now() >= reevaluate_at) {
+                    info!("Consumer hold time expired, re-evaluating");
+                    self.update_current_consumer().await
+                } else {
+                    self.process_power_update(None).await
+                }
+            }
+            Either4::Third(()) => {
+                let battery = policy::battery_status().await;
+                info!("Battery status changed: {:?}", battery);
+                self.process_power_update(battery).await
+            }
+            Either4::Fourth(Either::First(request)) => {
+                self.process_host_request(request).await;
+                Ok(())
+            }
+            Either4::Fourth(Either::Second(event)) => self.process_system_power_event(event).await,
+        }
+    }
+
+    /// Process a battery status change or boot sequencer deadline, keeps the last known battery status if `None`
+    async fn process_power_update(&self, battery: Option<battery::Status>) -> Result<(), Error> {
+        let mut guard = self.state.lock().await;
+        let state = guard.deref_mut();
+        if battery.is_some() {
+            state.battery = battery;
+        }
+
+        self.update_boot(state).await?;
+        self.update_budget(state).await
     }
 }
 
-impl comms::MailboxDelegate for PowerPolicy {}
+impl comms::MailboxDelegate for PowerPolicy {
+    fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+        if let Some(request) = message.data.get::<StdHostRequest>() {
+            if matches!(
+                request.payload,
+                embedded_services::ec_type::protocols::mctp::Odp::PowerSetSystemPowerStateRequest { .. }
+            ) {
+                self.system_power_request.signal(*request);
+            } else {
+                self.host_request.signal(*request);
+            }
+        }
+
+        Ok(())
+    }
+}
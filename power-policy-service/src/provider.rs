@@ -1,11 +1,24 @@
 //! This file implements logic to determine how much power to provide to each connected device.
-//! When total provided power is below [limited_power_threshold_mw](super::Config::limited_power_threshold_mw)
-//! the system is in unlimited power state. In this mode up to [provider_unlimited](super::Config::provider_unlimited)
-//! is provided to each device. Above this threshold, the system is in limited power state.
-//! In this mode [provider_limited](super::Config::provider_limited) is provided to each device
+//! Every provider is guaranteed up to [provider_limited](super::Config::provider_limited). The remaining provider budget,
+//! [limited_power_threshold_mw](super::Config::limited_power_threshold_mw) or less if the input power budget doesn't
+//! allow it, is used to upgrade providers to their full capability in priority order, see
+//! [provider_ports](super::Config::provider_ports). Ports without a configuration are upgraded up to
+//! [provider_unlimited](super::Config::provider_unlimited). If any provider can't be upgraded, the system is in
+//! limited power state.
+//!
+//! Allocations are recomputed whenever a provider connects or disconnects, or the input power budget changes. Already
+//! connected providers are renegotiated if their allocation changed.
+//!
+//! While running from battery, providers share the power the battery can sustain and only get
+//! [provider_limited](super::Config::provider_limited) above
+//! [provider_max_battery_temp_dk](super::Config::provider_max_battery_temp_dk). Below
+//! [provider_min_soc_pct](super::Config::provider_min_soc_pct) providers are disconnected until the battery recovers or
+//! external power is connected. Providers are also held off until boot power sequencing completes, see [boot](super::boot).
+use embedded_services::power::policy::battery;
 use embedded_services::{debug, trace};
 
 use super::*;
+use crate::config::ProviderPort;
 
 /// Current system provider power state
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -25,98 +38,345 @@ pub(super) struct State {
     pub state: PowerState,
 }
 
+/// Provider allocation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Allocation {
+    /// Device ID
+    device_id: DeviceId,
+    /// Capability requested by the device
+    requested: ProviderPowerCapability,
+    /// Port configuration
+    port: ProviderPort,
+    /// Allocated capability
+    allocated: ProviderPowerCapability,
+}
+
+impl Allocation {
+    fn new(device_id: DeviceId, requested: ProviderPowerCapability, port: ProviderPort) -> Self {
+        Self {
+            device_id,
+            requested,
+            port,
+            allocated: requested,
+        }
+    }
+
+    /// Returns `capability` with the requested flags, or the request itself if it's lower
+    fn cap_to(&self, capability: PowerCapability) -> ProviderPowerCapability {
+        if self.requested.capability.max_power_mw() <= capability.max_power_mw() {
+            // Don't auto upgrade to a higher contract
+            self.requested
+        } else {
+            ProviderPowerCapability {
+                capability,
+                flags: self.requested.flags,
+            }
+        }
+    }
+}
+
+/// Allocate power to providers, returns true if any provider didn't get its full capability
+fn allocate(allocations: &mut [Allocation], floor: PowerCapability, budget_mw: u32) -> bool {
+    // Highest priority first, then by device ID to keep allocations stable
+    allocations.sort_unstable_by(|a, b| {
+        b.port
+            .priority
+            .cmp(&a.port.priority)
+            .then(a.device_id.0.cmp(&b.device_id.0))
+    });
+
+    // Everyone gets the floor
+    let mut used_mw = 0;
+    for allocation in allocations.iter_mut() {
+        let full = allocation.cap_to(allocation.port.max);
+        allocation.allocated = if full.capability.max_power_mw() <= floor.max_power_mw() {
+            full
+        } else {
+            allocation.cap_to(floor)
+        };
+        used_mw += allocation.allocated.capability.max_power_mw();
+    }
+
+    // Upgrade in priority order with what's left
+    let mut remaining_mw = budget_mw.saturating_sub(used_mw);
+    let mut limited = false;
+    for allocation in allocations.iter_mut() {
+        let full = allocation.cap_to(allocation.port.max);
+        let extra_mw = full
+            .capability
+            .max_power_mw()
+            .saturating_sub(allocation.allocated.capability.max_power_mw());
+        if extra_mw <= remaining_mw {
+            allocation.allocated = full;
+            remaining_mw -= extra_mw;
+        } else {
+            limited = true;
+        }
+    }
+
+    limited
+}
+
+/// Maximum power providers can draw from the battery, `None` if not limited
+fn battery_headroom_mw(battery: Option<battery::Status>, config: &Config) -> Option<u32> {
+    let battery = battery.filter(|battery| battery.present)?;
+    if config
+        .provider_max_battery_temp_dk
+        .is_some_and(|max_temp_dk| battery.temperature_dk > max_temp_dk)
+    {
+        // Providers only get the floor
+        return Some(0);
+    }
+
+    // Zero means the fuel gauge doesn't report it
+    (battery.sus_power_mw > 0).then(|| battery.sus_power_mw.saturating_sub(config.system_load_mw))
+}
+
+/// Returns true if the battery can source power to providers
+fn battery_allows_providers(battery: Option<battery::Status>, config: &Config) -> bool {
+    battery.is_none_or(|battery| !battery.present || battery.state_of_charge_pct >= config.provider_min_soc_pct)
+}
+
 impl PowerPolicy {
+    /// Configuration of the given provider port
+    fn provider_port(&self, device_id: DeviceId) -> ProviderPort {
+        self.config
+            .provider_ports
+            .iter()
+            .find(|port| port.device_id == device_id)
+            .copied()
+            .unwrap_or(ProviderPort {
+                device_id,
+                max: self.config.provider_unlimited,
+                priority: 0,
+            })
+    }
+
+    /// Total power that can be allocated to providers
+    fn provider_budget_mw(&self, state: &InternalState) -> u32 {
+        let headroom_mw = budget::provider_headroom_mw(Self::input_power_mw(state), &self.config)
+            .or_else(|| battery_headroom_mw(state.battery, &self.config));
+        self.config
+            .limited_power_threshold_mw
+            .min(headroom_mw.unwrap_or(u32::MAX))
+    }
+
+    /// Returns true if providers can be connected
+    fn providers_allowed(&self, state: &InternalState) -> bool {
+        let running_from_battery = Self::input_power_mw(state) == 0;
+        !state.system_off
+            && state.boot.power_stable()
+            && (!running_from_battery || battery_allows_providers(state.battery, &self.config))
+    }
+
     /// Attempt to connect the requester as a provider
     pub(super) async fn connect_provider(&self, requester_id: DeviceId) {
         trace!("Device{}: Attempting to connect as provider", requester_id.0);
-        let requester = match self.context.get_device(requester_id) {
-            Ok(device) => device,
-            Err(_) => {
-                error!("Device{}: Invalid device", requester_id.0);
-                return;
+        let mut state = self.state.lock().await;
+        if !self.providers_allowed(&state) {
+            // Connected once providers are allowed again
+            info!("Device{}: Providers not allowed, deferring", requester_id.0);
+            return;
+        }
+
+        self.rebalance_providers(&mut state, Some(requester_id)).await;
+        if let Err(e) = self.update_budget(&mut state).await {
+            error!("Failed to update power budget, {:#?}", e);
+        }
+    }
+
+    /// Update providers after a change to the power budget, battery, boot or system power state
+    pub(super) async fn update_providers(&self, state: &mut InternalState) {
+        let allowed = self.providers_allowed(state);
+        if !allowed {
+            if state.providers_enabled {
+                info!("Disabling providers");
             }
-        };
-        let requested_power_capability = match requester.requested_provider_capability().await {
-            Some(cap) => cap,
-            // Requester is no longer requesting power
-            _ => {
-                info!("Device{}: No-longer requesting power", requester.id().0);
-                return;
+            self.disconnect_providers(state).await;
+        } else if !state.providers_enabled {
+            info!("Enabling providers");
+            self.connect_pending_providers(state).await;
+        } else {
+            self.rebalance_providers(state, None).await;
+        }
+        state.providers_enabled = allowed;
+    }
+
+    /// Disconnect all providers
+    async fn disconnect_providers(&self, state: &mut InternalState) {
+        state.current_provider_state.state = PowerState::Limited;
+
+        let providers = state.connected_providers.clone();
+        for provider_id in providers.iter().copied() {
+            if let Ok(action) = self
+                .context
+                .try_policy_action::<action::ConnectedProvider>(provider_id)
+                .await
+                && let Err(e) = action.disconnect().await
+            {
+                error!("Device{}: Failed to disconnect provider, {:#?}", provider_id.0, e);
+                continue;
             }
-        };
-        let mut state = self.state.lock().await;
-        let mut total_power_mw = 0;
 
-        // Determine total requested power draw
-        for device in self.context.devices().iter_only::<device::Device>() {
-            let target_provider_cap = if device.id() == requester_id {
-                // Use the requester's requested power capability
-                // this handles both new connections and upgrade requests
-                Some(requested_power_capability)
-            } else {
-                // Use the device's current working provider capability
-                device.provider_capability().await
-            };
-            total_power_mw += target_provider_cap.map_or(0, |cap| cap.capability.max_power_mw());
+            state.connected_providers.remove(&provider_id);
+            self.comms_notify(CommsMessage {
+                data: CommsData::ProviderDisconnected(provider_id),
+            })
+            .await;
+        }
+    }
 
-            if total_power_mw > self.config.limited_power_threshold_mw {
-                state.current_provider_state.state = PowerState::Limited;
-            } else {
-                state.current_provider_state.state = PowerState::Unlimited;
+    /// Connect providers that requested power while providers weren't allowed
+    async fn connect_pending_providers(&self, state: &mut InternalState) {
+        for device in self.context.devices().iter_only::<device::Device>() {
+            if !state.connected_providers.contains(&device.id())
+                && device.requested_provider_capability().await.is_some()
+            {
+                debug!("Device{}: Connecting deferred provider", device.id().0);
+                self.rebalance_providers(state, Some(device.id())).await;
             }
         }
+    }
 
-        debug!("New power state: {:?}", state.current_provider_state.state);
+    /// Recompute provider allocations, connecting `requester` and renegotiating providers whose allocation changed
+    async fn rebalance_providers(&self, state: &mut InternalState, requester_id: Option<DeviceId>) {
+        let mut allocations: heapless::Vec<Allocation, MAX_CONNECTED_PROVIDERS> = heapless::Vec::new();
 
-        let target_power = match state.current_provider_state.state {
-            PowerState::Limited => ProviderPowerCapability {
-                capability: self.config.provider_limited,
-                flags: requested_power_capability.flags,
-            },
-            PowerState::Unlimited => {
-                if requested_power_capability.capability.max_power_mw() < self.config.provider_unlimited.max_power_mw()
-                {
-                    // Don't auto upgrade to a higher contract
-                    requested_power_capability
-                } else {
-                    ProviderPowerCapability {
-                        capability: self.config.provider_unlimited,
-                        flags: requested_power_capability.flags,
-                    }
+        if let Some(requester_id) = requester_id {
+            let requester = match self.context.get_device(requester_id) {
+                Ok(device) => device,
+                Err(_) => {
+                    error!("Device{}: Invalid device", requester_id.0);
+                    return;
+                }
+            };
+            match requester.requested_provider_capability().await {
+                // This handles both new connections and upgrade requests
+                Some(requested) => {
+                    let _ = allocations.push(Allocation::new(
+                        requester_id,
+                        requested,
+                        self.provider_port(requester_id),
+                    ));
                 }
+                // Requester is no longer requesting power
+                None => {
+                    info!("Device{}: No-longer requesting power", requester_id.0);
+                    return;
+                }
+            }
+        }
+
+        for provider_id in state.connected_providers.iter().copied() {
+            if Some(provider_id) == requester_id {
+                continue;
+            }
+
+            let Ok(device) = self.context.get_device(provider_id) else {
+                continue;
+            };
+            // Use the original request so that downgraded providers can be upgraded again
+            let requested = match device.requested_provider_capability().await {
+                Some(requested) => Some(requested),
+                None => device.provider_capability().await,
+            };
+            if let Some(requested) = requested
+                && allocations
+                    .push(Allocation::new(provider_id, requested, self.provider_port(provider_id)))
+                    .is_err()
+            {
+                error!("Device{}: Too many providers", provider_id.0);
             }
+        }
+
+        let limited = allocate(
+            &mut allocations,
+            self.config.provider_limited,
+            self.provider_budget_mw(state),
+        );
+        state.current_provider_state.state = if limited {
+            PowerState::Limited
+        } else {
+            PowerState::Unlimited
         };
+        debug!("New power state: {:?}", state.current_provider_state.state);
 
-        let connected = if let Ok(action) = self.context.try_policy_action::<action::Idle>(requester.id()).await {
-            if let Err(e) = action.connect_provider(target_power).await {
-                error!("Device{}: Failed to connect as provider, {:#?}", requester.id().0, e);
+        for allocation in allocations.iter() {
+            if Some(allocation.device_id) == requester_id {
+                self.connect_requester(state, allocation.device_id, allocation.allocated)
+                    .await;
             } else {
-                self.post_provider_connected(&mut state, requester.id(), target_power)
+                self.renegotiate_provider(allocation.device_id, allocation.allocated)
                     .await;
             }
+        }
+    }
+
+    /// Connect a new provider or apply an upgrade request
+    async fn connect_requester(
+        &self,
+        state: &mut InternalState,
+        requester_id: DeviceId,
+        target_power: ProviderPowerCapability,
+    ) {
+        let connected = if let Ok(action) = self.context.try_policy_action::<action::Idle>(requester_id).await {
+            match action.connect_provider(target_power).await {
+                Ok(action) => self.post_provider_connected(state, requester_id, action).await,
+                Err(e) => error!("Device{}: Failed to connect as provider, {:#?}", requester_id.0, e),
+            }
             Ok(())
         } else if let Ok(action) = self
             .context
-            .try_policy_action::<action::ConnectedProvider>(requester.id())
+            .try_policy_action::<action::ConnectedProvider>(requester_id)
             .await
         {
-            if let Err(e) = action.connect_provider(target_power).await {
-                error!("Device{}: Failed to connect as provider, {:#?}", requester.id().0, e);
-            } else {
-                self.post_provider_connected(&mut state, requester.id(), target_power)
-                    .await;
+            match action.connect_provider(target_power).await {
+                Ok(action) => self.post_provider_connected(state, requester_id, action).await,
+                Err(e) => error!("Device{}: Failed to connect as provider, {:#?}", requester_id.0, e),
             }
             Ok(())
         } else {
-            Err(Error::InvalidState(
-                device::StateKind::Idle,
-                requester.state().await.kind(),
-            ))
+            match self.context.get_device(requester_id) {
+                Ok(requester) => Err(Error::InvalidState(
+                    device::StateKind::Idle,
+                    requester.state().await.kind(),
+                )),
+                Err(e) => Err(e),
+            }
         };
 
         // Don't need to do anything special, the device is responsible for attempting to reconnect
         if let Err(e) = connected {
-            error!("Device{}: Failed to connect as provider, {:#?}", requester.id().0, e);
+            error!("Device{}: Failed to connect as provider, {:#?}", requester_id.0, e);
+        }
+    }
+
+    /// Renegotiate an already connected provider if its allocation changed
+    async fn renegotiate_provider(&self, provider_id: DeviceId, target_power: ProviderPowerCapability) {
+        let Ok(action) = self
+            .context
+            .try_policy_action::<action::ConnectedProvider>(provider_id)
+            .await
+        else {
+            return;
+        };
+
+        if action.power_capability().await == Some(target_power) {
+            return;
+        }
+
+        info!("Device{}: Renegotiating provider: {:#?}", provider_id.0, target_power);
+        match action.connect_provider(target_power).await {
+            // The device may keep providing a different capability, notify the one actually provided
+            Ok(action) => {
+                if let Some(capability) = action.power_capability().await {
+                    self.comms_notify(CommsMessage {
+                        data: CommsData::ProviderConnected(provider_id, capability),
+                    })
+                    .await;
+                }
+            }
+            Err(e) => error!("Device{}: Failed to renegotiate provider, {:#?}", provider_id.0, e),
         }
     }
 
@@ -125,12 +385,139 @@ impl PowerPolicy {
         &self,
         state: &mut InternalState,
         provider_id: DeviceId,
-        target_power: ProviderPowerCapability,
+        action: action::policy::Policy<'_, action::ConnectedProvider>,
     ) {
         let _ = state.connected_providers.insert(provider_id);
-        self.comms_notify(CommsMessage {
-            data: CommsData::ProviderConnected(provider_id, target_power),
-        })
-        .await;
+        // The device may be providing a different capability than allocated, notify the one actually provided
+        if let Some(capability) = action.power_capability().await {
+            self.comms_notify(CommsMessage {
+                data: CommsData::ProviderConnected(provider_id, capability),
+            })
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITED: PowerCapability = PowerCapability {
+        voltage_mv: 5000,
+        current_ma: 1500,
+    };
+    const FULL: PowerCapability = PowerCapability {
+        voltage_mv: 5000,
+        current_ma: 3000,
+    };
+
+    fn allocation(id: u8, requested: PowerCapability, priority: u8) -> Allocation {
+        Allocation::new(
+            DeviceId(id),
+            requested.into(),
+            ProviderPort {
+                device_id: DeviceId(id),
+                max: FULL,
+                priority,
+            },
+        )
+    }
+
+    fn allocated(allocations: &[Allocation], id: u8) -> PowerCapability {
+        allocations
+            .iter()
+            .find(|a| a.device_id == DeviceId(id))
+            .map(|a| a.allocated.capability)
+            .unwrap_or(PowerCapability {
+                voltage_mv: 0,
+                current_ma: 0,
+            })
+    }
+
+    /// Test that higher priority ports are upgraded first
+    #[test]
+    fn test_priority() {
+        let mut allocations = [allocation(0, FULL, 0), allocation(1, FULL, 1)];
+        // Enough for one full and one limited port
+        assert!(allocate(&mut allocations, LIMITED, 22500));
+        assert_eq!(allocated(&allocations, 0), LIMITED);
+        assert_eq!(allocated(&allocations, 1), FULL);
+
+        // Enough for both
+        assert!(!allocate(&mut allocations, LIMITED, 30000));
+        assert_eq!(allocated(&allocations, 0), FULL);
+        assert_eq!(allocated(&allocations, 1), FULL);
+    }
+
+    /// Test that the floor is always granted and requests are never upgraded
+    #[test]
+    fn test_floor() {
+        let low = PowerCapability {
+            voltage_mv: 5000,
+            current_ma: 500,
+        };
+        let mut allocations = [allocation(0, FULL, 0), allocation(1, low, 0)];
+        assert!(allocate(&mut allocations, LIMITED, 0));
+        assert_eq!(allocated(&allocations, 0), LIMITED);
+        assert_eq!(allocated(&allocations, 1), low);
+    }
+
+    /// Test provider limits while running from battery
+    #[test]
+    fn test_battery_limits() {
+        let config = Config {
+            system_load_mw: 5000,
+            provider_min_soc_pct: 10,
+            provider_max_battery_temp_dk: Some(3180),
+            ..Default::default()
+        };
+        let battery = battery::Status {
+            present: true,
+            state_of_charge_pct: 50,
+            sus_power_mw: 20000,
+            temperature_dk: 2980,
+        };
+
+        assert_eq!(battery_headroom_mw(Some(battery), &config), Some(15000));
+        assert!(battery_allows_providers(Some(battery), &config));
+
+        // Unknown sustainable power or battery status
+        let unknown = battery::Status {
+            sus_power_mw: 0,
+            ..battery
+        };
+        assert_eq!(battery_headroom_mw(Some(unknown), &config), None);
+        assert_eq!(battery_headroom_mw(None, &config), None);
+        assert!(battery_allows_providers(None, &config));
+
+        // Too hot
+        let hot = battery::Status {
+            temperature_dk: 3200,
+            ..battery
+        };
+        assert_eq!(battery_headroom_mw(Some(hot), &config), Some(0));
+
+        // Below the state of charge floor
+        let low = battery::Status {
+            state_of_charge_pct: 9,
+            ..battery
+        };
+        assert!(!battery_allows_providers(Some(low), &config));
+    }
+
+    /// Test per-port maximum capability
+    #[test]
+    fn test_port_max() {
+        let mut allocations = [Allocation::new(
+            DeviceId(0),
+            FULL.into(),
+            ProviderPort {
+                device_id: DeviceId(0),
+                max: LIMITED,
+                priority: 0,
+            },
+        )];
+        assert!(!allocate(&mut allocations, LIMITED, 100000));
+        assert_eq!(allocated(&allocations, 0), LIMITED);
     }
 }
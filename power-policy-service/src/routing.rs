@@ -0,0 +1,447 @@
+//! This file implements the routing of the consumer contract to chargers.
+//! Each charger is fed from the consumer ports listed in its [route](super::config::ChargerRoute), chargers without a
+//! route are fed from every port. The contract current is split evenly between the chargers fed from the current
+//! consumer port, capped to the maximum input current of each charger. Current a charger can't take because of its cap
+//! is shared between the other chargers. The charge budget is split in proportion to the input power assigned to each
+//! charger.
+//!
+//! When switching consumers every charger is detached before the new consumer is connected, only the chargers fed from
+//! the new port are attached afterwards.
+use embedded_services::power::policy::charger::{ChargerResponseData, Device as ChargerDevice, PolicyEvent};
+use embedded_services::power::policy::policy::{check_chargers_ready, init_chargers};
+use embedded_services::power::policy::snapshot::MAX_CHARGERS;
+use embedded_services::trace;
+use heapless::Vec;
+
+use super::*;
+use crate::config::ChargerRoute;
+use crate::consumer::AvailableConsumer;
+
+/// Input assigned to a charger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Assignment {
+    /// Charger ID
+    pub charger_id: ChargerId,
+    /// Assigned input, `None` if the charger isn't fed from the current consumer
+    pub capability: Option<ConsumerPowerCapability>,
+}
+
+/// Find the route of a charger
+fn route(routes: &[ChargerRoute], charger_id: ChargerId) -> Option<&ChargerRoute> {
+    routes.iter().find(|route| route.charger_id == charger_id)
+}
+
+/// Split `total_ma` evenly between chargers with the given input current caps
+///
+/// Chargers capped below the even share get their cap and the rest is shared between the others.
+fn split_current(total_ma: u16, caps: &[Option<u16>]) -> Vec<u16, MAX_CHARGERS> {
+    let mut currents: Vec<Option<u16>, MAX_CHARGERS> = caps.iter().take(MAX_CHARGERS).map(|_| None).collect();
+    let mut remaining_ma = total_ma;
+    loop {
+        let open = currents.iter().filter(|current| current.is_none()).count() as u16;
+        if open == 0 {
+            break;
+        }
+
+        let share_ma = remaining_ma / open;
+        let mut capped = false;
+        for (current, cap) in currents.iter_mut().zip(caps) {
+            if current.is_none()
+                && let Some(cap_ma) = *cap
+                && cap_ma < share_ma
+            {
+                *current = Some(cap_ma);
+                remaining_ma = remaining_ma.saturating_sub(cap_ma);
+                capped = true;
+            }
+        }
+
+        if !capped {
+            for current in currents.iter_mut().filter(|current| current.is_none()) {
+                *current = Some(share_ma);
+            }
+        }
+    }
+
+    currents.into_iter().map(|current| current.unwrap_or(0)).collect()
+}
+
+/// Split the consumer contract between the chargers fed from its port
+pub(super) fn assign(
+    routes: &[ChargerRoute],
+    chargers: &[ChargerId],
+    consumer: Option<&AvailableConsumer>,
+) -> Vec<Assignment, MAX_CHARGERS> {
+    let fed: Vec<ChargerId, MAX_CHARGERS> = chargers
+        .iter()
+        .copied()
+        .filter(|&charger_id| {
+            consumer.is_some_and(|consumer| {
+                route(routes, charger_id).is_none_or(|route| route.ports.contains(&consumer.device_id))
+            })
+        })
+        .take(MAX_CHARGERS)
+        .collect();
+    let caps: Vec<Option<u16>, MAX_CHARGERS> = fed
+        .iter()
+        .map(|&charger_id| route(routes, charger_id).and_then(|route| route.max_input_current_ma))
+        .collect();
+    let currents = consumer.map_or_else(Vec::new, |consumer| {
+        split_current(consumer.consumer_power_capability.capability.current_ma, &caps)
+    });
+
+    chargers
+        .iter()
+        .take(MAX_CHARGERS)
+        .map(|&charger_id| Assignment {
+            charger_id,
+            capability: consumer.and_then(|consumer| {
+                let index = fed.iter().position(|&fed_id| fed_id == charger_id)?;
+                let mut capability = consumer.consumer_power_capability;
+                capability.capability.current_ma = *currents.get(index)?;
+                Some(capability)
+            }),
+        })
+        .collect()
+}
+
+/// Split the charge budget in proportion to the input power assigned to each charger
+pub(super) fn split_budget(assignments: &[Assignment], charger_mw: u32) -> Vec<(ChargerId, u32), MAX_CHARGERS> {
+    let input_mw = |assignment: &Assignment| {
+        assignment
+            .capability
+            .map_or(0, |capability| u64::from(capability.capability.max_power_mw()))
+    };
+    let total_mw: u64 = assignments.iter().map(input_mw).sum();
+
+    assignments
+        .iter()
+        .map(|assignment| {
+            let share_mw = (u64::from(charger_mw) * input_mw(assignment))
+                .checked_div(total_mw)
+                .unwrap_or(0);
+            (assignment.charger_id, share_mw as u32)
+        })
+        .collect()
+}
+
+/// Send the assigned input to a charger, a charger without input is detached if it was attached
+async fn apply(device: &ChargerDevice, capability: Option<ConsumerPowerCapability>) -> Result<(), Error> {
+    let Some(capability) = capability else {
+        if device.state().await.capability.is_some() {
+            info!(
+                "Charger {}: Not fed from the current consumer, detaching",
+                device.id().0
+            );
+            device
+                .execute_command(PolicyEvent::PolicyConfiguration(ConsumerPowerCapability {
+                    capability: PowerCapability {
+                        voltage_mv: 0,
+                        current_ma: 0,
+                    },
+                    flags: flags::Consumer::none(),
+                }))
+                .await?;
+        }
+        return Ok(());
+    };
+
+    // Chargers should be powered at this point, but in case they are not...
+    if let ChargerResponseData::UnpoweredAck = device
+        .execute_command(PolicyEvent::PolicyConfiguration(capability))
+        .await?
+    {
+        // Force charger CheckReady and InitRequest to get it into an initialized state.
+        // This condition can get hit if we did not have a previous consumer and the charger is unpowered.
+        info!("Charger is unpowered, forcing charger CheckReady and Init sequence");
+        check_chargers_ready().await?;
+        init_chargers().await?;
+        device
+            .execute_command(PolicyEvent::PolicyConfiguration(capability))
+            .await?;
+    }
+
+    Ok(())
+}
+
+impl PowerPolicy {
+    /// IDs of the registered chargers
+    fn charger_ids(&self) -> Vec<ChargerId, MAX_CHARGERS> {
+        self.context
+            .chargers()
+            .iter_only::<ChargerDevice>()
+            .map(|device| device.id())
+            .take(MAX_CHARGERS)
+            .collect()
+    }
+
+    /// Send the consumer contract to the chargers fed from its port
+    pub(super) async fn configure_chargers(&self, consumer: &AvailableConsumer) -> Result<(), Error> {
+        // If no chargers are registered, they won't receive the new power capability.
+        for assignment in assign(self.config.charger_routes, &self.charger_ids(), Some(consumer)) {
+            let device = self.context.get_charger(assignment.charger_id)?;
+            apply(device, assignment.capability).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Give each charger its share of the charge budget if it changed
+    pub(super) async fn update_charger_budgets(&self, state: &mut InternalState) -> Result<(), Error> {
+        let assignments = assign(
+            self.config.charger_routes,
+            &self.charger_ids(),
+            state.current_consumer_state.as_ref(),
+        );
+
+        let budgets = split_budget(&assignments, state.budget.charger_mw);
+        for &(charger_id, budget_mw) in &budgets {
+            let previous_mw = state
+                .charger_budgets
+                .iter()
+                .find(|(id, _)| *id == charger_id)
+                .map_or(0, |(_, budget_mw)| *budget_mw);
+            if previous_mw != budget_mw {
+                trace!("Charger {}: Charge budget {}mW", charger_id.0, budget_mw);
+                self.context
+                    .get_charger(charger_id)?
+                    .set_charge_budget(Some(budget_mw))
+                    .await;
+            }
+        }
+
+        state.charger_budgets = budgets;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::pending;
+
+    use embassy_futures::block_on;
+    use embassy_futures::join::join;
+    use embassy_futures::select::select;
+    use embedded_batteries_async::charger::{Charger, ErrorKind, ErrorType, MilliAmps, MilliVolts};
+    use embedded_services::power::policy::charger::{
+        ChargeController, ChargerError, ChargerEvent, InternalState as ChargerState, PoweredSubstate, State,
+    };
+
+    use super::*;
+    use crate::charger::Wrapper;
+
+    const ROUTES: &[ChargerRoute] = &[
+        ChargerRoute {
+            charger_id: ChargerId(0),
+            ports: &[DeviceId(0), DeviceId(1)],
+            max_input_current_ma: None,
+        },
+        ChargerRoute {
+            charger_id: ChargerId(1),
+            ports: &[DeviceId(1)],
+            max_input_current_ma: Some(1000),
+        },
+    ];
+
+    fn consumer(device_id: u8) -> AvailableConsumer {
+        AvailableConsumer {
+            device_id: DeviceId(device_id),
+            consumer_power_capability: PowerCapability {
+                voltage_mv: 20000,
+                current_ma: 3000,
+            }
+            .into(),
+        }
+    }
+
+    fn capability(current_ma: u16) -> Option<ConsumerPowerCapability> {
+        Some(
+            PowerCapability {
+                voltage_mv: 20000,
+                current_ma,
+            }
+            .into(),
+        )
+    }
+
+    #[derive(Debug)]
+    struct MockError;
+
+    impl embedded_batteries_async::charger::Error for MockError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    impl From<MockError> for ChargerError {
+        fn from(_: MockError) -> Self {
+            ChargerError::BusError
+        }
+    }
+
+    /// Charge controller that accepts every request
+    struct MockController;
+
+    impl ErrorType for MockController {
+        type Error = MockError;
+    }
+
+    impl Charger for MockController {
+        async fn charging_current(&mut self, current: MilliAmps) -> Result<MilliAmps, Self::Error> {
+            Ok(current)
+        }
+
+        async fn charging_voltage(&mut self, voltage: MilliVolts) -> Result<MilliVolts, Self::Error> {
+            Ok(voltage)
+        }
+    }
+
+    impl ChargeController for MockController {
+        type ChargeControllerError = MockError;
+
+        async fn wait_event(&mut self) -> ChargerEvent {
+            pending().await
+        }
+
+        async fn init_charger(&mut self) -> Result<(), Self::ChargeControllerError> {
+            Ok(())
+        }
+
+        async fn is_psu_attached(&mut self) -> Result<bool, Self::ChargeControllerError> {
+            Ok(true)
+        }
+
+        async fn attach_handler(&mut self, _: ConsumerPowerCapability) -> Result<(), Self::ChargeControllerError> {
+            Ok(())
+        }
+
+        async fn detach_handler(&mut self) -> Result<(), Self::ChargeControllerError> {
+            Ok(())
+        }
+    }
+
+    /// Test routing the consumer contract to chargers
+    #[test]
+    fn test_assign() {
+        let chargers = [ChargerId(0), ChargerId(1), ChargerId(2)];
+
+        // Port 0 only feeds charger 0 and the unrouted charger 2
+        let assignments = assign(ROUTES, &chargers, Some(&consumer(0)));
+        assert_eq!(
+            assignments.iter().map(|a| a.capability).collect::<Vec<_, 3>>(),
+            [capability(1500), None, capability(1500)]
+        );
+
+        // Port 1 feeds every charger, the even share is within the cap of charger 1
+        let assignments = assign(ROUTES, &chargers, Some(&consumer(1)));
+        assert_eq!(
+            assignments.iter().map(|a| a.capability).collect::<Vec<_, 3>>(),
+            [capability(1000), capability(1000), capability(1000)]
+        );
+
+        // Current charger 1 can't take because of its cap goes to charger 0
+        let assignments = assign(ROUTES, &[ChargerId(0), ChargerId(1)], Some(&consumer(1)));
+        assert_eq!(
+            assignments.iter().map(|a| a.capability).collect::<Vec<_, 2>>(),
+            [capability(2000), capability(1000)]
+        );
+
+        // Nothing to assign without a consumer
+        let assignments = assign(ROUTES, &chargers, None);
+        assert!(assignments.iter().all(|a| a.capability.is_none()));
+
+        // Without routes a single charger gets the whole contract
+        let assignments = assign(&[], &[ChargerId(0)], Some(&consumer(5)));
+        assert_eq!(
+            assignments.iter().map(|a| a.capability).collect::<Vec<_, 1>>(),
+            [capability(3000)]
+        );
+    }
+
+    /// Test redistributing current capped by the maximum input current
+    #[test]
+    fn test_split_current() {
+        assert_eq!(split_current(3000, &[None, None]), [1500, 1500]);
+        assert_eq!(split_current(3000, &[None, Some(1000)]), [2000, 1000]);
+        assert_eq!(split_current(3000, &[Some(500), Some(800), None]), [500, 800, 1700]);
+        // Everything capped leaves current unused
+        assert_eq!(split_current(3000, &[Some(1000), Some(1000)]), [1000, 1000]);
+        assert!(split_current(3000, &[]).is_empty());
+    }
+
+    /// Test splitting the charge budget
+    #[test]
+    fn test_split_budget() {
+        let assignments = [
+            Assignment {
+                charger_id: ChargerId(0),
+                capability: capability(2000),
+            },
+            Assignment {
+                charger_id: ChargerId(1),
+                capability: capability(1000),
+            },
+            Assignment {
+                charger_id: ChargerId(2),
+                capability: None,
+            },
+        ];
+        assert_eq!(
+            split_budget(&assignments, 30000),
+            [(ChargerId(0), 20000), (ChargerId(1), 10000), (ChargerId(2), 0)]
+        );
+        assert_eq!(
+            split_budget(
+                &[Assignment {
+                    charger_id: ChargerId(0),
+                    capability: None,
+                }],
+                30000
+            ),
+            [(ChargerId(0), 0)]
+        );
+    }
+
+    /// Test switching consumers with chargers driven by mock controllers
+    #[test]
+    fn test_switch_consumer() {
+        let charger0 = ChargerDevice::new(ChargerId(0));
+        let charger1 = ChargerDevice::new(ChargerId(1));
+        let wrapper0 = Wrapper::new(&charger0, MockController);
+        let wrapper1 = Wrapper::new(&charger1, MockController);
+        let devices = [&charger0, &charger1];
+        let chargers = [ChargerId(0), ChargerId(1)];
+
+        let attached = ChargerState {
+            state: State::Powered(PoweredSubstate::PsuAttached),
+            capability: None,
+        };
+        block_on(charger0.set_state(attached));
+        block_on(charger1.set_state(attached));
+
+        let switch = async {
+            for (port, [expected0, expected1], [budget0, budget1]) in [
+                // Both chargers share port 1, charger 0 gets the current charger 1 can't take
+                (1, [capability(2000), capability(1000)], [20000, 10000]),
+                // Charger 1 isn't fed from port 0 and is detached
+                (0, [capability(3000), None], [30000, 0]),
+            ] {
+                let assignments = assign(ROUTES, &chargers, Some(&consumer(port)));
+                for (assignment, device) in assignments.iter().zip(devices) {
+                    assert_eq!(apply(device, assignment.capability).await, Ok(()));
+                }
+                for ((_, budget_mw), device) in split_budget(&assignments, 30000).into_iter().zip(devices) {
+                    device.set_charge_budget(Some(budget_mw)).await;
+                }
+
+                assert_eq!(charger0.state().await.capability, expected0);
+                assert_eq!(charger1.state().await.capability, expected1);
+                assert_eq!(charger0.regulation_inputs().await.charge_budget_mw, Some(budget0));
+                assert_eq!(charger1.regulation_inputs().await.charge_budget_mw, Some(budget1));
+            }
+        };
+
+        // Wrappers run forever, the test is done once the switch completes
+        block_on(select(switch, join(wrapper0.process(), wrapper1.process())));
+    }
+}
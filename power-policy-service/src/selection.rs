@@ -0,0 +1,246 @@
+//! Consumer selection strategies
+//!
+//! A strategy ranks the available consumers and the power policy connects to the highest ranked one. Strategies can be
+//! chained with [`Then`] so that later strategies break ties of earlier ones. Remaining ties go to the current consumer
+//! to avoid switching between otherwise equivalent consumers.
+use core::cmp::Ordering;
+
+use embassy_time::Duration;
+use embedded_services::power::policy::{ConsumerPowerCapability, DeviceId, flags::PsuType};
+
+/// Consumer being considered for selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Candidate {
+    /// Device ID
+    pub device_id: DeviceId,
+    /// Power capability offered by the device
+    pub capability: ConsumerPowerCapability,
+}
+
+/// Consumer selection strategy
+pub trait ConsumerSelection: Sync {
+    /// Compare two candidates, returns [`Ordering::Greater`] if `a` is preferred over `b`
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering;
+
+    /// Minimum time to stay connected to a consumer before switching to a preferred one
+    ///
+    /// Doesn't apply if the current consumer stops offering power.
+    fn hold_time(&self) -> Duration {
+        Duration::from_ticks(0)
+    }
+}
+
+/// Prefer the consumer with the highest power
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaxPower;
+
+impl ConsumerSelection for MaxPower {
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        a.capability.capability.cmp(&b.capability.capability)
+    }
+}
+
+/// Prefer unconstrained consumers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreferUnconstrained;
+
+impl ConsumerSelection for PreferUnconstrained {
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        a.capability
+            .flags
+            .unconstrained_power()
+            .cmp(&b.capability.flags.unconstrained_power())
+    }
+}
+
+/// Returns the rank of `item` in `priority`, lower is better, items not in the list rank last
+fn rank<T: PartialEq>(priority: &[T], item: &T) -> usize {
+    priority.iter().position(|p| p == item).unwrap_or(priority.len())
+}
+
+/// Prefer PSU types that come first in the list, e.g. DC barrel jack over Type-C
+#[derive(Debug, Clone, Copy)]
+pub struct PsuTypePriority(pub &'static [PsuType]);
+
+impl ConsumerSelection for PsuTypePriority {
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        rank(self.0, &b.capability.flags.psu_type()).cmp(&rank(self.0, &a.capability.flags.psu_type()))
+    }
+}
+
+/// Prefer devices that come first in the list
+#[derive(Debug, Clone, Copy)]
+pub struct PortPriority(pub &'static [DeviceId]);
+
+impl ConsumerSelection for PortPriority {
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        rank(self.0, &b.device_id).cmp(&rank(self.0, &a.device_id))
+    }
+}
+
+/// Use `first`, break ties with `next`
+#[derive(Debug, Clone, Copy)]
+pub struct Then<A, B> {
+    first: A,
+    next: B,
+}
+
+impl<A: ConsumerSelection, B: ConsumerSelection> Then<A, B> {
+    /// Create a new chained strategy
+    pub const fn new(first: A, next: B) -> Self {
+        Self { first, next }
+    }
+}
+
+impl<A: ConsumerSelection, B: ConsumerSelection> ConsumerSelection for Then<A, B> {
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        self.first.compare(a, b).then_with(|| self.next.compare(a, b))
+    }
+
+    fn hold_time(&self) -> Duration {
+        self.first.hold_time().max(self.next.hold_time())
+    }
+}
+
+/// Stay connected to a consumer for at least `hold_time` before switching
+#[derive(Debug, Clone, Copy)]
+pub struct HoldTime<S> {
+    inner: S,
+    hold_time: Duration,
+}
+
+impl<S: ConsumerSelection> HoldTime<S> {
+    /// Create a new strategy with a hold time
+    pub const fn new(inner: S, hold_time: Duration) -> Self {
+        Self { inner, hold_time }
+    }
+}
+
+impl<S: ConsumerSelection> ConsumerSelection for HoldTime<S> {
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        self.inner.compare(a, b)
+    }
+
+    fn hold_time(&self) -> Duration {
+        self.hold_time.max(self.inner.hold_time())
+    }
+}
+
+/// Compare two candidates using `strategy`, ties go to the current consumer
+pub(super) fn cmp_candidates(
+    strategy: &dyn ConsumerSelection,
+    a: &Candidate,
+    a_is_current: bool,
+    b: &Candidate,
+    b_is_current: bool,
+) -> Ordering {
+    strategy.compare(a, b).then(a_is_current.cmp(&b_is_current))
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_services::power::policy::{PowerCapability, flags};
+
+    use super::*;
+
+    const P0: PowerCapability = PowerCapability {
+        voltage_mv: 5000,
+        current_ma: 1000,
+    };
+    const P1: PowerCapability = PowerCapability {
+        voltage_mv: 5000,
+        current_ma: 1500,
+    };
+
+    fn candidate(id: u8, capability: PowerCapability, flags: flags::Consumer) -> Candidate {
+        Candidate {
+            device_id: DeviceId(id),
+            capability: ConsumerPowerCapability { capability, flags },
+        }
+    }
+
+    /// Tests [`MaxPower`] without any flags set
+    #[test]
+    fn test_max_power_no_flags() {
+        let p0 = candidate(0, P0, flags::Consumer::none());
+        let p1 = candidate(1, P1, flags::Consumer::none());
+
+        assert_eq!(cmp_candidates(&MaxPower, &p0, false, &p1, false), Ordering::Less);
+        assert_eq!(cmp_candidates(&MaxPower, &p1, false, &p1, false), Ordering::Equal);
+        assert_eq!(cmp_candidates(&MaxPower, &p1, false, &p0, false), Ordering::Greater);
+    }
+
+    /// Tests that ties go to the current consumer
+    #[test]
+    fn test_tie_goes_to_current() {
+        let a = candidate(0, P1, flags::Consumer::none());
+        let b = candidate(1, P1, flags::Consumer::none());
+
+        assert_eq!(cmp_candidates(&MaxPower, &a, true, &b, false), Ordering::Greater);
+        assert_eq!(cmp_candidates(&MaxPower, &a, false, &b, true), Ordering::Less);
+    }
+
+    /// Tests preferring DC barrel jacks over Type-C, falling back to max power
+    #[test]
+    fn test_psu_type_priority() {
+        let strategy = Then::new(PsuTypePriority(&[PsuType::DcJack, PsuType::TypeC]), MaxPower);
+        let jack = candidate(0, P0, flags::Consumer::none().with_psu_type(PsuType::DcJack));
+        let type_c = candidate(1, P1, flags::Consumer::none().with_psu_type(PsuType::TypeC));
+        let type_c_low = candidate(2, P0, flags::Consumer::none().with_psu_type(PsuType::TypeC));
+        let unknown = candidate(3, P1, flags::Consumer::none());
+
+        assert_eq!(
+            cmp_candidates(&strategy, &jack, false, &type_c, true),
+            Ordering::Greater
+        );
+        assert_eq!(
+            cmp_candidates(&strategy, &type_c, false, &type_c_low, false),
+            Ordering::Greater
+        );
+        assert_eq!(
+            cmp_candidates(&strategy, &unknown, false, &type_c_low, false),
+            Ordering::Less
+        );
+    }
+
+    /// Tests preferring unconstrained consumers
+    #[test]
+    fn test_prefer_unconstrained() {
+        let strategy = Then::new(PreferUnconstrained, MaxPower);
+        let unconstrained = candidate(0, P0, flags::Consumer::none().with_unconstrained_power());
+        let constrained = candidate(1, P1, flags::Consumer::none());
+
+        assert_eq!(
+            cmp_candidates(&strategy, &unconstrained, false, &constrained, false),
+            Ordering::Greater
+        );
+        assert_eq!(
+            cmp_candidates(&MaxPower, &unconstrained, false, &constrained, false),
+            Ordering::Less
+        );
+    }
+
+    /// Tests per-port priority and hold time propagation
+    #[test]
+    fn test_port_priority() {
+        let strategy = HoldTime::new(
+            Then::new(PortPriority(&[DeviceId(2), DeviceId(0)]), MaxPower),
+            Duration::from_secs(5),
+        );
+        let port0 = candidate(0, P1, flags::Consumer::none());
+        let port1 = candidate(1, P1, flags::Consumer::none());
+        let port2 = candidate(2, P0, flags::Consumer::none());
+
+        assert_eq!(
+            cmp_candidates(&strategy, &port2, false, &port0, false),
+            Ordering::Greater
+        );
+        assert_eq!(
+            cmp_candidates(&strategy, &port0, false, &port1, false),
+            Ordering::Greater
+        );
+        assert_eq!(strategy.hold_time(), Duration::from_secs(5));
+        assert_eq!(MaxPower.hold_time(), Duration::from_ticks(0));
+    }
+}
@@ -0,0 +1,34 @@
+//! System power state handling
+//!
+//! Providers are disabled while the host is hibernating or off, see [`SystemPowerState::is_off`], and enabled again
+//! once it resumes.
+//!
+//! [`SystemPowerState::is_off`]: embedded_services::power::system::SystemPowerState::is_off
+use embedded_services::power::system as system_power;
+
+use super::*;
+
+impl PowerPolicy {
+    /// Process system power state events
+    pub(super) async fn process_system_power_event(&self, event: system_power::Event) -> Result<(), Error> {
+        match event {
+            system_power::Event::Prepare(_) => {
+                // Nothing to prepare, providers are updated once the state is entered
+                self.system_power_hook.respond(system_power::Response::Ready).await;
+                Ok(())
+            }
+            system_power::Event::Cancelled(transition) => {
+                // Nothing was prepared, providers stay as they are
+                info!("System power transition to {:?} cancelled", transition.to);
+                Ok(())
+            }
+            system_power::Event::Entered(transition) => {
+                info!("Entered system power state {:?}", transition.to);
+                let mut guard = self.state.lock().await;
+                let state = guard.deref_mut();
+                state.system_off = transition.to.is_off();
+                self.update_budget(state).await
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use embassy_sync::once_lock::OnceLock;
+use embedded_services::power::policy::battery;
+use embedded_services::power::system as system_power;
 use embedded_services::{comms, error, info};
 
 use crate::{PowerPolicy, config};
@@ -12,9 +14,10 @@ pub enum InitError {
     RegistrationFailed,
 }
 
+static POLICY: OnceLock<PowerPolicy> = OnceLock::new();
+
 pub async fn task(config: config::Config) -> Result<embedded_services::Never, InitError> {
     info!("Starting power policy task");
-    static POLICY: OnceLock<PowerPolicy> = OnceLock::new();
     let policy = if let Some(policy) = PowerPolicy::create(config) {
         POLICY.get_or_init(|| policy)
     } else {
@@ -27,9 +30,29 @@ pub async fn task(config: config::Config) -> Result<embedded_services::Never, In
         return Err(InitError::RegistrationFailed);
     }
 
+    if system_power::register_hook(&policy.system_power_hook).is_err() {
+        error!("Failed to register system power hook");
+        return Err(InitError::RegistrationFailed);
+    }
+
+    battery::set_temperature_limit(policy.config.provider_max_battery_temp_dk).await;
+    let boot_state = policy.state.lock().await.boot.state();
+    policy.context.publish_boot_state(boot_state).await;
+
     loop {
         if let Err(e) = policy.process().await {
             error!("Error processing request: {:?}", e);
         }
     }
 }
+
+/// Request the system power state transitions reported by the host
+///
+/// Runs separately from [`task`] because a transition waits for every hook to respond, including the power policy's.
+pub async fn system_power_task() -> embedded_services::Never {
+    let policy = POLICY.get().await;
+    loop {
+        let request = policy.system_power_request.wait().await;
+        policy.process_system_power_request(request).await;
+    }
+}
@@ -0,0 +1,11 @@
+//! MCTP over SMBus/I2C host transport
+//!
+//! Carries the same MCTP/ODP requests as the eSPI OOB channel for platforms where the EC is connected to the SoC
+//! over SMBus. Following DSP0237 the host sends requests as SMBus block writes to the EC, and the EC becomes bus master
+//! to write responses back to the host. Notifications are sent with SMBus host notify.
+#![no_std]
+
+mod smbus_service;
+pub mod task;
+
+pub use smbus_service::*;
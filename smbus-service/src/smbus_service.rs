@@ -0,0 +1,807 @@
+use core::convert::Infallible;
+
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::once_lock::OnceLock;
+use embassy_time::{Duration, with_timeout};
+use embedded_hal_async::i2c::{Error as _, ErrorKind, I2c};
+use embedded_services::comms::{self, EndpointID, External, Internal};
+use embedded_services::ec_type::message::{HostMsg, NotificationMsg, StdHostMsg, StdHostPayload, StdHostRequest};
+use embedded_services::ec_type::protocols::mctp;
+use embedded_services::{GlobalRawMutex, debug, error, info, trace, warn};
+use hid_service::i2c::I2cSlaveAsync;
+use mctp_rs::smbus_espi::{SmbusEspiMedium, SmbusEspiReplyContext};
+
+const HOST_TX_QUEUE_SIZE: usize = 5;
+// Requests that can be waiting for a response, one per message tag
+const MAX_IN_FLIGHT: usize = 8;
+
+/// SMBus command code for MCTP
+const MCTP_COMMAND_CODE: u8 = 0x0F;
+
+/// SMBus host address, target of host notify messages
+const SMBUS_HOST_ADDRESS: u8 = 0x08;
+
+/// Largest SMBus packet: destination address, command code, byte count, payload and PEC
+pub const MAX_PACKET_SIZE: usize = mctp::MAX_MCTP_PACKET_LEN + 1;
+
+// Should be as large as the largest possible MCTP message and it's metadata.
+pub(crate) const ASSEMBLY_BUF_SIZE: usize = 256;
+
+type HostMsgInternal = (EndpointID, StdHostMsg);
+type Packet = heapless::Vec<u8, MAX_PACKET_SIZE>;
+type ReplyContext = mctp_rs::MctpReplyContext<SmbusEspiMedium>;
+
+/// MCTP packet context used to reassemble requests
+pub(crate) type AssemblyContext<'a> = mctp_rs::MctpPacketContext<'a, SmbusEspiMedium>;
+
+/// Request received from the host
+enum HostRequest {
+    /// ODP request, forwarded to a service
+    Odp(EndpointID, StdHostRequest),
+    /// MCTP control request, answered by the SMBus service
+    Control(mctp::ControlHeader, mctp::MctpControl),
+}
+
+/// Request forwarded to a service and waiting for its response
+struct PendingRequest {
+    tag: u8,
+    reply_context: ReplyContext,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<B> {
+    /// Error from the I2C target
+    Bus(B),
+    /// Error writing to the host as I2C master
+    Master(ErrorKind),
+    /// Bus operation didn't complete in time
+    Timeout,
+    /// Comms endpoint registration failed
+    Registration,
+    /// MCTP serialization or deserialization error
+    Serialize,
+    /// Packet isn't a valid MCTP over SMBus packet
+    InvalidPacket,
+    /// Packet error code mismatch
+    Pec,
+}
+
+/// SMBus service configuration
+///
+/// Responses echo the endpoint IDs of the request they answer, the host endpoint IDs are only used for messages that
+/// aren't a reply to a request.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// EC 7-bit target address
+    pub address: u8,
+    /// Host 7-bit address, used as the destination of responses
+    pub host_address: u8,
+    /// Time a single bus operation can take
+    pub bus_timeout: Duration,
+    /// EC endpoint ID, until the host assigns one with Set Endpoint ID
+    pub ec_eid: u8,
+    /// Host endpoint ID for battery messages
+    pub battery_eid: u8,
+    /// Host endpoint ID for thermal messages
+    pub thermal_eid: u8,
+    /// Host endpoint ID for debug messages
+    pub debug_eid: u8,
+    /// Host endpoint ID for power policy messages
+    pub power_eid: u8,
+}
+
+pub struct Service {
+    endpoint: comms::Endpoint,
+    config: Config,
+    /// Current EC endpoint ID
+    eid: Mutex<GlobalRawMutex, u8>,
+    host_tx_queue: Channel<GlobalRawMutex, HostMsgInternal, HOST_TX_QUEUE_SIZE>,
+    /// Requests waiting for a response, by message tag
+    in_flight: Mutex<GlobalRawMutex, heapless::Vec<PendingRequest, MAX_IN_FLIGHT>>,
+}
+
+impl Service {
+    pub fn new(config: Config) -> Self {
+        Service {
+            endpoint: comms::Endpoint::uninit(EndpointID::External(External::Host)),
+            config,
+            eid: Mutex::new(config.ec_eid),
+            host_tx_queue: Channel::new(),
+            in_flight: Mutex::new(heapless::Vec::new()),
+        }
+    }
+
+    pub(crate) fn endpoint(&self) -> &comms::Endpoint {
+        &self.endpoint
+    }
+
+    pub(crate) async fn wait_for_subsystem_msg(&self) -> HostMsgInternal {
+        self.host_tx_queue.receive().await
+    }
+
+    /// Receive part of a block write from the host, timeout if the host stops sending so we don't get stuck here
+    async fn read_bus<B: I2cSlaveAsync>(&self, bus: &mut B, buf: &mut [u8]) -> Result<(), Error<B::Error>> {
+        with_timeout(self.config.bus_timeout, bus.respond_to_write(buf))
+            .await
+            .map_err(|_| {
+                error!("SMBus write timeout");
+                Error::Timeout
+            })?
+            .map_err(Error::Bus)
+    }
+
+    /// Write to a device as bus master
+    async fn write_bus<E, M: I2c>(&self, bus: &mut M, address: u8, data: &[u8]) -> Result<(), Error<E>> {
+        with_timeout(self.config.bus_timeout, bus.write(address, data))
+            .await
+            .map_err(|_| {
+                error!("SMBus master write timeout");
+                Error::Timeout
+            })?
+            .map_err(|e| Error::Master(e.kind()))
+    }
+
+    /// Receive a block write from the host
+    pub(crate) async fn process_write<B: I2cSlaveAsync, M: I2c>(
+        &self,
+        bus: &mut B,
+        master: &mut M,
+        mctp_ctx: &mut AssemblyContext<'_>,
+    ) -> Result<(), Error<B::Error>> {
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        let (address, rest) = buf.split_first_mut().ok_or(Error::InvalidPacket)?;
+        // The target address isn't part of the data received from the bus, add it back for the PEC and mctp-rs
+        *address = self.config.address << 1;
+        // Command code, byte count, payload and PEC
+        self.read_bus(bus, rest).await?;
+
+        let [_, command_code, byte_count, ..] = buf;
+        if command_code != MCTP_COMMAND_CODE || usize::from(byte_count) > mctp::MAX_MCTP_BYTE_COUNT {
+            error!(
+                "Invalid SMBus header, command {} byte count {}",
+                command_code, byte_count
+            );
+            return Err(Error::InvalidPacket);
+        }
+
+        let packet = buf.get(..usize::from(byte_count) + 4).ok_or(Error::InvalidPacket)?;
+        let (request, reply_context) = match self.process_packet::<B::Error>(mctp_ctx, packet)? {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+
+        match request {
+            HostRequest::Odp(endpoint, host_request) => {
+                self.track_request(host_request.tag, reply_context).await;
+                if let Err(e) = self.endpoint.send(endpoint, &host_request).await {
+                    error!("Failed to forward MCTP request to service {:?}: {:?}", endpoint, e);
+                    // No response is coming for this request
+                    self.in_flight
+                        .lock()
+                        .await
+                        .retain(|request| request.tag != host_request.tag);
+                } else {
+                    info!("MCTP packet forwarded to service: {:?}", endpoint);
+                }
+            }
+            HostRequest::Control(header, control) => {
+                self.process_control_request(master, header, control, reply_context)
+                    .await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify and reassemble a packet, returns the request once all its packets have been received
+    fn process_packet<E>(
+        &self,
+        mctp_ctx: &mut AssemblyContext<'_>,
+        packet: &[u8],
+    ) -> Result<Option<(HostRequest, ReplyContext)>, Error<E>> {
+        let (received_pec, data) = packet.split_last().ok_or(Error::InvalidPacket)?;
+        if mctp::smbus_pec(data) != *received_pec {
+            error!("SMBus PEC mismatch");
+            return Err(Error::Pec);
+        }
+
+        #[cfg(feature = "defmt")]
+        debug!("SMBus packet: {:02X}", packet);
+
+        let tag = mctp::smbus_message_tag(packet).ok_or(Error::InvalidPacket)?;
+        let message = match mctp_ctx.deserialize_packet(packet) {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                trace!("Partial MCTP message, waiting for more packets");
+                return Ok(None);
+            }
+            Err(_e) => {
+                error!("MCTP packet malformed");
+                return Err(Error::Serialize);
+            }
+        };
+
+        if let Ok((header, control)) = message.parse_as::<mctp::MctpControl>() {
+            if !header.request_bit {
+                // The EC doesn't send control requests, so there's nothing to match a response to
+                warn!("Unexpected MCTP control response {:?}, dropped", header.command_code);
+                return Ok(None);
+            }
+            return Ok(Some((HostRequest::Control(header, control), message.reply_context)));
+        }
+
+        match message.parse_as::<StdHostPayload>() {
+            Ok((header, body)) => {
+                let endpoint = match header.service {
+                    mctp::OdpService::Battery => EndpointID::Internal(Internal::Battery),
+                    mctp::OdpService::Thermal => EndpointID::Internal(Internal::Thermal),
+                    mctp::OdpService::Debug => EndpointID::Internal(Internal::Debug),
+                    mctp::OdpService::Power => EndpointID::Internal(Internal::Power),
+                };
+                let host_request = StdHostRequest {
+                    command: header.command_code.into(),
+                    status: header.completion_code.into(),
+                    tag,
+                    payload: body,
+                };
+                Ok(Some((HostRequest::Odp(endpoint, host_request), message.reply_context)))
+            }
+            Err(_e) => {
+                error!("MCTP ODP type malformed");
+                Err(Error::Serialize)
+            }
+        }
+    }
+
+    /// Answer an MCTP control request
+    async fn process_control_request<M: I2c>(
+        &self,
+        bus: &mut M,
+        header: mctp::ControlHeader,
+        request: mctp::MctpControl,
+        reply_context: ReplyContext,
+    ) {
+        // Set Endpoint ID is already answered from the new endpoint ID
+        let (header, response) = mctp::control_response(&header, &request, &mut *self.eid.lock().await);
+        info!(
+            "MCTP control request {:?}, completion {:?}",
+            header.command_code, header.completion_code
+        );
+
+        if let Err(e) = self.send_message(bus, reply_context, false, (header, response)).await {
+            error!("Failed to send MCTP control response: {:?}", e);
+        }
+    }
+
+    /// Track a request forwarded to a service, replacing a request with the same tag the host gave up on
+    async fn track_request(&self, tag: u8, reply_context: ReplyContext) {
+        let mut in_flight = self.in_flight.lock().await;
+        in_flight.retain(|request| request.tag != tag);
+        // Can't fail, there's room for a request per tag
+        let _ = in_flight.push(PendingRequest { tag, reply_context });
+    }
+
+    /// Reply context for a response from a service
+    ///
+    /// Responses are matched to their request by message tag. Falls back to the configured endpoint IDs for
+    /// unsolicited messages. Also returns true if the EC owns the message tag.
+    async fn take_reply_context(
+        &self,
+        tag: u8,
+        endpoint: EndpointID,
+    ) -> Result<(ReplyContext, bool), Error<Infallible>> {
+        let pending = {
+            let mut in_flight = self.in_flight.lock().await;
+            in_flight
+                .iter()
+                .position(|request| request.tag == tag)
+                .map(|index| in_flight.swap_remove(index))
+        };
+        if let Some(request) = pending {
+            return Ok((request.reply_context, false));
+        }
+
+        let host_eid = match endpoint {
+            EndpointID::Internal(Internal::Battery) => self.config.battery_eid,
+            EndpointID::Internal(Internal::Thermal) => self.config.thermal_eid,
+            EndpointID::Internal(Internal::Power) => self.config.power_eid,
+            _ => self.config.debug_eid,
+        };
+        let reply_context = mctp_rs::MctpReplyContext {
+            source_endpoint_id: mctp_rs::EndpointId::Id(*self.eid.lock().await),
+            destination_endpoint_id: mctp_rs::EndpointId::Id(host_eid),
+            packet_sequence_number: mctp_rs::MctpSequenceNumber::new(0),
+            message_tag: mctp_rs::MctpMessageTag::try_from(0).map_err(|_| Error::Serialize)?,
+            medium_context: SmbusEspiReplyContext {
+                destination_slave_address: self.config.host_address,
+                source_slave_address: self.config.address,
+            },
+        };
+        Ok((reply_context, true))
+    }
+
+    /// Respond to a read from the host
+    ///
+    /// Responses are written to the host by the EC as bus master, reads aren't part of MCTP over SMBus.
+    pub(crate) async fn process_read<B: I2cSlaveAsync>(&self, bus: &mut B) -> Result<(), Error<B::Error>> {
+        debug!("Unexpected SMBus read from host");
+        with_timeout(self.config.bus_timeout, bus.respond_to_read(&[]))
+            .await
+            .map_err(|_| Error::Timeout)?
+            .map_err(Error::Bus)
+    }
+
+    pub(crate) async fn process_subsystem_msg<M: I2c>(&self, bus: &mut M, host_msg: HostMsgInternal) {
+        let (endpoint, host_msg) = host_msg;
+        match host_msg {
+            HostMsg::Notification(notification) => match self.send_notification(bus, &notification).await {
+                Ok(()) => info!("smbus: Notification id {} sent to Host!", notification.offset),
+                Err(e) => error!("Failed to send notification: {:?}", e),
+            },
+            HostMsg::Response(response) => match self.send_response(bus, &response, endpoint).await {
+                Ok(()) => trace!("Response sent to host"),
+                Err(e) => error!("Failed to send response: {:?}", e),
+            },
+        }
+    }
+
+    /// Notify the host with an SMBus host notify message, the data word carries the notification id
+    async fn send_notification<M: I2c>(
+        &self,
+        bus: &mut M,
+        notification: &NotificationMsg,
+    ) -> Result<(), Error<Infallible>> {
+        let message = [self.config.address << 1, notification.offset, 0];
+        self.write_bus(bus, SMBUS_HOST_ADDRESS, &message).await
+    }
+
+    /// Serialize a response and write its packets to the host
+    async fn send_response<M: I2c>(
+        &self,
+        bus: &mut M,
+        response: &StdHostRequest,
+        endpoint: EndpointID,
+    ) -> Result<(), Error<Infallible>> {
+        let (reply_context, tag_owner) = self.take_reply_context(response.tag, endpoint).await?;
+        let header = mctp::OdpHeader {
+            request_bit: false,
+            datagram_bit: false,
+            service: match endpoint {
+                EndpointID::Internal(Internal::Battery) => mctp::OdpService::Battery,
+                EndpointID::Internal(Internal::Thermal) => mctp::OdpService::Thermal,
+                EndpointID::Internal(Internal::Power) => mctp::OdpService::Power,
+                _ => mctp::OdpService::Debug,
+            },
+            command_code: response.command.into(),
+            completion_code: Default::default(),
+        };
+
+        self.send_message(bus, reply_context, tag_owner, (header, response.payload))
+            .await
+    }
+
+    /// Serialize a message and write its packets to the host
+    async fn send_message<'m, M: I2c, P: mctp_rs::MctpMessageTrait<'m>>(
+        &self,
+        bus: &mut M,
+        mut reply_context: ReplyContext,
+        tag_owner: bool,
+        message: (P::Header, P),
+    ) -> Result<(), Error<Infallible>> {
+        // Requests may be addressed to the null EID, always answer from the current one
+        reply_context.source_endpoint_id = mctp_rs::EndpointId::Id(*self.eid.lock().await);
+
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut buf);
+
+        let mut packet_state = mctp_ctx
+            .serialize_packet(reply_context, message)
+            .map_err(|_| Error::Serialize)?;
+        let mut sequence = 0;
+        while let Some(packet_result) = packet_state.next() {
+            let mut packet =
+                Packet::from_slice(packet_result.map_err(|_| Error::Serialize)?).map_err(|_| Error::Serialize)?;
+            // The PEC covers the address the packet is written to
+            let address = packet.first_mut().ok_or(Error::Serialize)?;
+            *address = self.config.host_address << 1;
+            mctp::finalize_smbus_packet(&mut packet, sequence, tag_owner).ok_or(Error::Serialize)?;
+            sequence = sequence.wrapping_add(1);
+
+            // The destination address is sent in the address phase of the write
+            let (_, data) = packet.split_first().ok_or(Error::Serialize)?;
+            self.write_bus(bus, self.config.host_address, data).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl comms::MailboxDelegate for Service {
+    fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+        let msg = message
+            .data
+            .get::<StdHostMsg>()
+            .ok_or(comms::MailboxDelegateError::MessageNotFound)?;
+
+        debug!("SMBus service: recvd host message");
+        self.host_tx_queue
+            .try_send((message.from, *msg))
+            .map_err(|_| comms::MailboxDelegateError::BufferFull)
+    }
+}
+
+pub(crate) static SMBUS_SERVICE: OnceLock<Service> = OnceLock::new();
+
+#[cfg(test)]
+#[allow(clippy::indexing_slicing)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use embedded_hal_async::i2c::{ErrorType, Operation};
+    use embedded_services::ec_type::message::OdpCommand;
+    use embedded_services::ec_type::protocols::acpi::BatteryCmd;
+    use embedded_services::ec_type::protocols::mctp::{
+        ControlCommandCode, ControlHeader, MctpCompletionCode, MctpControl,
+    };
+    use hid_service::i2c::Command;
+
+    const CONFIG: Config = Config {
+        address: 0x02,
+        host_address: 0x01,
+        bus_timeout: Duration::from_millis(10),
+        ec_eid: 0x80,
+        battery_eid: 8,
+        thermal_eid: 9,
+        debug_eid: 10,
+        power_eid: 11,
+    };
+
+    /// Target bus that serves a single write, the rest of the buffer is left untouched
+    struct MockTarget<'a> {
+        write: &'a [u8],
+    }
+
+    impl I2cSlaveAsync for MockTarget<'_> {
+        type Error = ();
+
+        async fn listen(&mut self) -> Result<Command, Self::Error> {
+            Ok(Command::Write)
+        }
+
+        async fn respond_to_write(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+            let len = buf.len().min(self.write.len());
+            buf[..len].copy_from_slice(&self.write[..len]);
+            self.write = &self.write[len..];
+            Ok(())
+        }
+
+        async fn respond_to_read(&mut self, _buf: &[u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// Master bus that records the writes
+    #[derive(Default)]
+    struct MockMaster {
+        writes: heapless::Vec<(u8, Packet), 4>,
+    }
+
+    impl ErrorType for MockMaster {
+        type Error = Infallible;
+    }
+
+    impl I2c for MockMaster {
+        async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let Operation::Write(data) = operation {
+                    self.writes.push((address, Packet::from_slice(data).unwrap())).unwrap();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Records requests sent to the battery service
+    struct Battery {
+        endpoint: comms::Endpoint,
+        requests: Channel<GlobalRawMutex, StdHostRequest, 1>,
+    }
+
+    impl comms::MailboxDelegate for Battery {
+        fn receive(&self, message: &comms::Message) -> Result<(), comms::MailboxDelegateError> {
+            let msg = message
+                .data
+                .get::<StdHostRequest>()
+                .ok_or(comms::MailboxDelegateError::MessageNotFound)?;
+            self.requests
+                .try_send(*msg)
+                .map_err(|_| comms::MailboxDelegateError::BufferFull)
+        }
+    }
+
+    static BATTERY: Battery = Battery {
+        endpoint: comms::Endpoint::uninit(EndpointID::Internal(Internal::Battery)),
+        requests: Channel::new(),
+    };
+
+    /// Charge limit request used as a generic battery request
+    fn charge_limit_request(battery_id: u8) -> StdHostPayload {
+        StdHostPayload::BatterySetChargeLimitRequest {
+            battery_id,
+            mode: 1,
+            upper_pct: 80,
+            lower_pct: 75,
+            full_charge_delay_min: 0,
+        }
+    }
+
+    /// Build a single packet request to `eid` with the given tag the way the host would, without the target address
+    fn host_packet<'m, P: mctp_rs::MctpMessageTrait<'m>>(eid: u8, tag: u8, message: (P::Header, P)) -> Packet {
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut buf);
+        let context: mctp_rs::MctpReplyContext<SmbusEspiMedium> = mctp_rs::MctpReplyContext {
+            source_endpoint_id: mctp_rs::EndpointId::Id(8),
+            destination_endpoint_id: mctp_rs::EndpointId::Id(eid),
+            packet_sequence_number: mctp_rs::MctpSequenceNumber::new(0),
+            message_tag: mctp_rs::MctpMessageTag::try_from(tag).unwrap(),
+            medium_context: SmbusEspiReplyContext {
+                destination_slave_address: CONFIG.address,
+                source_slave_address: CONFIG.host_address,
+            },
+        };
+        let mut packets = mctp_ctx.serialize_packet(context, message).unwrap();
+        let packet = packets.next().unwrap().unwrap();
+
+        let data = &packet[..packet.len() - 1];
+        let mut request = Packet::from_slice(&data[1..]).unwrap();
+        request.push(mctp::smbus_pec(data)).unwrap();
+        request
+    }
+
+    /// Build a battery request with the given tag
+    fn build_request(tag: u8) -> Packet {
+        let header = mctp::OdpHeader {
+            request_bit: true,
+            datagram_bit: false,
+            service: mctp::OdpService::Battery,
+            command_code: mctp::OdpCommandCode::BatterySetChargeLimitRequest,
+            completion_code: Default::default(),
+        };
+        host_packet(CONFIG.ec_eid, tag, (header, charge_limit_request(1)))
+    }
+
+    /// Send an MCTP control request to `eid` and return the response packet along with its decoded content
+    async fn control_request(
+        service: &Service,
+        master: &mut MockMaster,
+        eid: u8,
+        command_code: ControlCommandCode,
+        request: MctpControl,
+    ) -> (heapless::Vec<u8, { MAX_PACKET_SIZE + 1 }>, ControlHeader, MctpControl) {
+        let header = ControlHeader {
+            request_bit: true,
+            datagram_bit: false,
+            instance_id: 1,
+            command_code,
+            completion_code: MctpCompletionCode::Success,
+        };
+        let request = host_packet(eid, 1, (header, request));
+        let mut assembly_buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut assembly_buf);
+        let mut target = MockTarget { write: &request };
+        service.process_write(&mut target, master, &mut mctp_ctx).await.unwrap();
+
+        let (_, write) = master.writes.last().unwrap();
+        let packet = with_address(write);
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut buf);
+        let message = mctp_ctx.deserialize_packet(&packet).unwrap().unwrap();
+        let (header, response) = message.parse_as::<MctpControl>().unwrap();
+        (packet, header, response)
+    }
+
+    /// Send a battery response with the given tag
+    async fn send_response(service: &Service, master: &mut MockMaster, tag: u8) {
+        service
+            .process_subsystem_msg(
+                master,
+                (
+                    EndpointID::Internal(Internal::Battery),
+                    HostMsg::Response(StdHostRequest {
+                        command: OdpCommand::Battery(BatteryCmd::SetChargeLimit),
+                        status: 0,
+                        tag,
+                        payload: StdHostPayload::BatterySetChargeLimitResponse { status: 0 },
+                    }),
+                ),
+            )
+            .await;
+    }
+
+    /// Add the host address back to a packet written to the host and check its PEC, which covers the address
+    fn with_address(write: &[u8]) -> heapless::Vec<u8, { MAX_PACKET_SIZE + 1 }> {
+        let mut packet = heapless::Vec::new();
+        packet.push(CONFIG.host_address << 1).unwrap();
+        packet.extend_from_slice(write).unwrap();
+        let (received_pec, data) = packet.split_last().unwrap();
+        assert_eq!(mctp::smbus_pec(data), *received_pec);
+        assert_eq!(packet[1], MCTP_COMMAND_CODE);
+        packet
+    }
+
+    /// Test a request and response round trip
+    #[tokio::test]
+    async fn test_request_response() {
+        embedded_services::init().await;
+        comms::register_endpoint(&BATTERY, &BATTERY.endpoint).await.unwrap();
+
+        let service = Service::new(CONFIG);
+        let mut assembly_buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut assembly_buf);
+
+        let request = build_request(3);
+        let mut target = MockTarget { write: &request };
+        let mut master = MockMaster::default();
+        service
+            .process_write(&mut target, &mut master, &mut mctp_ctx)
+            .await
+            .unwrap();
+        assert!(master.writes.is_empty());
+        let request = BATTERY.requests.try_receive().unwrap();
+        assert!(matches!(
+            request.command,
+            OdpCommand::Battery(BatteryCmd::SetChargeLimit)
+        ));
+        assert!(request.payload == charge_limit_request(1));
+        assert_eq!(request.tag, 3);
+
+        // The response is written to the host as bus master
+        send_response(&service, &mut master, request.tag).await;
+        assert_eq!(master.writes.len(), 1);
+        let (address, write) = master.writes.first().unwrap();
+        assert_eq!(*address, CONFIG.host_address);
+        let response = with_address(write);
+
+        // Tag and endpoint IDs are echoed from the request
+        assert_eq!(response[5], 8);
+        assert_eq!(response[6], CONFIG.ec_eid);
+        assert_eq!(mctp::smbus_message_tag(&response), Some(3));
+        assert_eq!(
+            response[mctp::SMBUS_MCTP_FLAGS_OFFSET] & (mctp::MCTP_SEQ_MASK | mctp::MCTP_TAG_OWNER),
+            0
+        );
+
+        let mut buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut buf);
+        let message = mctp_ctx.deserialize_packet(&response).unwrap().unwrap();
+        let (header, _) = message.parse_as::<StdHostPayload>().unwrap();
+        assert!(!header.request_bit);
+        assert_eq!(header.service, mctp::OdpService::Battery);
+
+        // A response that doesn't match a request in flight is sent from the EC with the configured endpoint IDs
+        send_response(&service, &mut master, 3).await;
+        let (_, write) = master.writes.get(1).unwrap();
+        let response = with_address(write);
+        assert_eq!(response[5], CONFIG.battery_eid);
+        assert_eq!(response[6], CONFIG.ec_eid);
+        assert_eq!(
+            response[mctp::SMBUS_MCTP_FLAGS_OFFSET] & mctp::MCTP_TAG_OWNER,
+            mctp::MCTP_TAG_OWNER
+        );
+    }
+
+    /// Test answering MCTP control requests and using the assigned endpoint ID
+    #[tokio::test]
+    async fn test_control() {
+        let service = Service::new(CONFIG);
+        let mut master = MockMaster::default();
+
+        // Discovery happens before the EC has an endpoint ID assigned
+        let (packet, header, response) = control_request(
+            &service,
+            &mut master,
+            mctp::NULL_EID,
+            ControlCommandCode::GetEndpointId,
+            MctpControl::GetEndpointIdRequest,
+        )
+        .await;
+        assert!(!header.request_bit);
+        assert_eq!(header.instance_id, 1);
+        assert_eq!(header.completion_code, MctpCompletionCode::Success);
+        assert_eq!(
+            response,
+            MctpControl::GetEndpointIdResponse {
+                eid: CONFIG.ec_eid,
+                endpoint_type: mctp::SIMPLE_ENDPOINT_DYNAMIC_EID,
+                medium_specific: 0,
+            }
+        );
+        assert_eq!(packet[5], 8);
+        assert_eq!(packet[6], CONFIG.ec_eid);
+        assert_eq!(mctp::smbus_message_tag(&packet), Some(1));
+        assert_eq!(packet[mctp::SMBUS_MCTP_FLAGS_OFFSET] & mctp::MCTP_TAG_OWNER, 0);
+
+        // The assigned endpoint ID is used for every message from then on
+        let (packet, _, response) = control_request(
+            &service,
+            &mut master,
+            mctp::NULL_EID,
+            ControlCommandCode::SetEndpointId,
+            MctpControl::SetEndpointIdRequest {
+                operation: mctp::SetEndpointIdOperation::SetEid,
+                eid: 0x42,
+            },
+        )
+        .await;
+        assert_eq!(
+            response,
+            MctpControl::SetEndpointIdResponse {
+                accepted: true,
+                eid: 0x42,
+                pool_size: 0,
+            }
+        );
+        assert_eq!(packet[6], 0x42);
+
+        send_response(&service, &mut master, 0).await;
+        let (_, write) = master.writes.last().unwrap();
+        let response = with_address(write);
+        assert_eq!(response[5], CONFIG.battery_eid);
+        assert_eq!(response[6], 0x42);
+    }
+
+    /// Test sending notifications with SMBus host notify
+    #[tokio::test]
+    async fn test_notification() {
+        let service = Service::new(CONFIG);
+        let mut master = MockMaster::default();
+        service
+            .process_subsystem_msg(
+                &mut master,
+                (
+                    EndpointID::Internal(Internal::Battery),
+                    HostMsg::Notification(NotificationMsg {
+                        offset: 5,
+                        priority: Default::default(),
+                    }),
+                ),
+            )
+            .await;
+
+        let (address, write) = master.writes.first().unwrap();
+        assert_eq!(*address, SMBUS_HOST_ADDRESS);
+        assert_eq!(write.as_slice(), &[CONFIG.address << 1, 5, 0]);
+    }
+
+    /// Test rejecting packets with a bad PEC or header
+    #[tokio::test]
+    async fn test_invalid_packet() {
+        let service = Service::new(CONFIG);
+        let mut assembly_buf = [0u8; ASSEMBLY_BUF_SIZE];
+        let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut assembly_buf);
+
+        let mut request = build_request(3);
+        if let Some(pec) = request.last_mut() {
+            *pec ^= 0xFF;
+        }
+        let mut target = MockTarget { write: &request };
+        assert!(matches!(
+            service
+                .process_write(&mut target, &mut MockMaster::default(), &mut mctp_ctx)
+                .await,
+            Err(Error::Pec)
+        ));
+
+        let mut target = MockTarget {
+            write: &[0x01, 0x04, 0, 0, 0, 0, 0],
+        };
+        assert!(matches!(
+            service
+                .process_write(&mut target, &mut MockMaster::default(), &mut mctp_ctx)
+                .await,
+            Err(Error::InvalidPacket)
+        ));
+    }
+}
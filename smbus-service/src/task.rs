@@ -0,0 +1,50 @@
+use embassy_futures::select::{Either, select};
+use embedded_hal_async::i2c::I2c;
+use embedded_services::{comms, error};
+use hid_service::i2c::{Command, I2cSlaveAsync};
+use mctp_rs::smbus_espi::SmbusEspiMedium;
+
+use crate::{ASSEMBLY_BUF_SIZE, AssemblyContext, Config, Error, SMBUS_SERVICE, Service};
+
+/// Run the SMBus service
+///
+/// Requests are received on `target`, responses and notifications are written to the host on `master`.
+pub async fn smbus_service<B: I2cSlaveAsync, M: I2c>(
+    mut target: B,
+    mut master: M,
+    config: Config,
+) -> Result<embedded_services::Never, Error<B::Error>> {
+    let smbus_service = SMBUS_SERVICE.get_or_init(|| Service::new(config));
+    if comms::register_endpoint(smbus_service, smbus_service.endpoint())
+        .await
+        .is_err()
+    {
+        error!("Failed to register SMBus service endpoint");
+        return Err(Error::Registration);
+    }
+
+    let mut assembly_buf = [0u8; ASSEMBLY_BUF_SIZE];
+    let mut mctp_ctx = AssemblyContext::new(SmbusEspiMedium, &mut assembly_buf);
+
+    loop {
+        match select(target.listen(), smbus_service.wait_for_subsystem_msg()).await {
+            Either::First(Ok(Command::Probe)) => {}
+            Either::First(Ok(Command::Write)) => {
+                // Other errors are logged and the request dropped, the host will retry
+                if let Err(Error::Bus(e)) = smbus_service
+                    .process_write(&mut target, &mut master, &mut mctp_ctx)
+                    .await
+                {
+                    return Err(Error::Bus(e));
+                }
+            }
+            Either::First(Ok(Command::Read)) => {
+                if let Err(Error::Bus(e)) = smbus_service.process_read(&mut target).await {
+                    return Err(Error::Bus(e));
+                }
+            }
+            Either::First(Err(e)) => return Err(Error::Bus(e)),
+            Either::Second(host_msg) => smbus_service.process_subsystem_msg(&mut master, host_msg).await,
+        }
+    }
+}
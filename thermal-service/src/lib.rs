@@ -7,6 +7,8 @@ use embassy_sync::once_lock::OnceLock;
 use embedded_sensors_hal_async::temperature::DegreesCelsius;
 use embedded_services::buffer::OwnedRef;
 use embedded_services::ec_type::message::StdHostRequest;
+use embedded_services::power::policy;
+use embedded_services::power::system as system_power;
 use embedded_services::{comms, error, info, intrusive_list};
 
 mod context;
@@ -38,6 +40,7 @@ pub enum Event {
 struct Service<'a> {
     context: context::Context<'a>,
     endpoint: comms::Endpoint,
+    system_power_hook: system_power::Hook,
 }
 
 impl<'a> Service<'a> {
@@ -45,6 +48,7 @@ impl<'a> Service<'a> {
         Self {
             context: context::Context::new(),
             endpoint: comms::Endpoint::uninit(comms::EndpointID::Internal(comms::Internal::Thermal)),
+            system_power_hook: system_power::Hook::new("thermal"),
         }
     }
 }
@@ -77,6 +81,9 @@ pub async fn init() -> Result<(), Error> {
     if comms::register_endpoint(service, &service.endpoint).await.is_err() {
         error!("Failed to register thermal service endpoint");
         Err(Error)
+    } else if system_power::register_hook(&service.system_power_hook).is_err() {
+        error!("Failed to register thermal service system power hook");
+        Err(Error)
     } else {
         Ok(())
     }
@@ -141,6 +148,19 @@ pub async fn execute_sensor_request(id: sensor::DeviceId, request: sensor::Reque
     SERVICE.get().await.context.execute_sensor_request(id, request).await
 }
 
+/// Throttle charging to the lowest charge current limit requested by the sensors
+pub(crate) async fn update_charge_limit() {
+    let mut limit_ma: Option<u16> = None;
+    for sensor in sensors().await.iter_only::<sensor::Device>() {
+        if let Some(sensor_limit_ma) = sensor.charge_limit_ma().await {
+            limit_ma = Some(limit_ma.map_or(sensor_limit_ma, |limit_ma| limit_ma.min(sensor_limit_ma)));
+        }
+    }
+
+    info!("Thermal charge current limit: {:?}", limit_ma);
+    policy::policy::set_charger_thermal_limit(limit_ma).await;
+}
+
 /// Register a fan with the thermal service
 pub async fn register_fan(fan: &'static fan::Device) -> Result<(), intrusive_list::Error> {
     SERVICE.get().await.context.register_fan(fan)
@@ -1,6 +1,7 @@
 //! Sensor Device
 use crate::utils::SampleBuf;
 use crate::{Event, send_event};
+use embassy_futures::select::select;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
 use embassy_time::Timer;
@@ -8,6 +9,7 @@ use embedded_sensors_hal_async::temperature::{DegreesCelsius, TemperatureSensor,
 use embedded_services::GlobalRawMutex;
 use embedded_services::error;
 use embedded_services::ipc::deferred as ipc;
+use embedded_services::power::system as system_power;
 use embedded_services::{Node, intrusive_list};
 
 // Timeout period (in ms) for physical bus access
@@ -152,6 +154,10 @@ pub struct Device {
     ipc: ipc::Channel<GlobalRawMutex, Request, Response>,
     /// Signal for enable
     enable: Signal<GlobalRawMutex, ()>,
+    /// Signal to cut the current sampling period short
+    wake: Signal<GlobalRawMutex, ()>,
+    /// Charge current limit (in mA) currently requested by this sensor
+    charge_limit_ma: Mutex<GlobalRawMutex, Option<u16>>,
 }
 
 impl Device {
@@ -162,6 +168,8 @@ impl Device {
             id,
             ipc: ipc::Channel::new(),
             enable: Signal::new(),
+            wake: Signal::new(),
+            charge_limit_ma: Mutex::new(None),
         }
     }
 
@@ -174,6 +182,16 @@ impl Device {
     pub async fn execute_request(&self, request: Request) -> Response {
         self.ipc.execute(request).await
     }
+
+    /// Sample right away instead of waiting out the current sampling period
+    pub(crate) fn wake(&self) {
+        self.wake.signal(());
+    }
+
+    /// Charge current limit (in mA) currently requested by this sensor, `None` if it doesn't throttle charging
+    pub async fn charge_limit_ma(&self) -> Option<u16> {
+        *self.charge_limit_ma.lock().await
+    }
 }
 
 impl intrusive_list::NodeContainer for Device {
@@ -192,6 +210,8 @@ pub struct Profile {
     pub sample_period: u64,
     /// Period (in ms) sensor will sample its temperature when in fast sampling state
     pub fast_sample_period: u64,
+    /// Period (in ms) sensor will sample its temperature while the host isn't running
+    pub sleep_sample_period: u64,
     /// Whether or not automatic background sampling is enabled or not
     pub sampling_enabled: bool,
     /// Hysteresis value (in degrees Celsius) preventing sensor from rapidly reporting threshold events
@@ -210,6 +230,8 @@ pub struct Profile {
     pub offset: DegreesCelsius,
     /// Number of attempts sensor will make to communicate with the physical device over the bus
     pub retry_attempts: u8,
+    /// Charge current limit (in mA) applied while the WARN HIGH threshold is exceeded, `None` doesn't throttle charging
+    pub charge_limit_ma: Option<u16>,
 }
 
 impl Default for Profile {
@@ -218,6 +240,7 @@ impl Default for Profile {
             id: 0,
             sample_period: 1000,
             fast_sample_period: 200,
+            sleep_sample_period: 10000,
             sampling_enabled: true,
             warn_low_threshold: DegreesCelsius::MIN,
             warn_high_threshold: DegreesCelsius::MAX,
@@ -227,6 +250,7 @@ impl Default for Profile {
             offset: 0.0,
             retry_attempts: 5,
             hysteresis: 2.0,
+            charge_limit_ma: None,
         }
     }
 }
@@ -431,6 +455,21 @@ impl<T: Controller, const SAMPLE_BUF_LEN: usize> Sensor<T, SAMPLE_BUF_LEN> {
             send_event(Event::ThresholdCleared(self.device.id, ThresholdType::Critical)).await;
             state.is_critical = false;
         }
+
+        // Throttle charging while hot, the thermal service applies the lowest limit of all sensors
+        let charge_limit_ma = if state.is_warn_high {
+            profile.charge_limit_ma
+        } else {
+            None
+        };
+        drop(state);
+        drop(profile);
+        let mut current_limit_ma = self.device.charge_limit_ma.lock().await;
+        if *current_limit_ma != charge_limit_ma {
+            *current_limit_ma = charge_limit_ma;
+            drop(current_limit_ma);
+            crate::update_charge_limit().await;
+        }
     }
 
     /// Periodically samples temperature from physical sensor and caches it
@@ -457,17 +496,20 @@ impl<T: Controller, const SAMPLE_BUF_LEN: usize> Sensor<T, SAMPLE_BUF_LEN> {
                 // Check thresholds
                 self.check_thresholds(temp).await;
 
-                // Adjust sampling rate based on how hot we are getting
+                // Adjust sampling rate based on how hot we are getting and whether the host is running
+                let host_running = system_power::state().await.is_running();
                 let profile = self.profile.lock().await;
                 let sleep_duration = if temp >= profile.fast_sampling_threshold {
                     profile.fast_sample_period
+                } else if !host_running {
+                    profile.sleep_sample_period
                 } else {
                     profile.sample_period
                 };
                 drop(profile);
 
-                // Sleep in-between sampling periods
-                Timer::after_millis(sleep_duration).await;
+                // Sleep in-between sampling periods, a system power state change ends the sleep early
+                select(Timer::after_millis(sleep_duration), self.device.wake.wait()).await;
 
             // Otherwise sleep and wait to be re-enabled
             } else {
@@ -1,3 +1,4 @@
+use embedded_services::power::system as system_power;
 use embedded_services::{comms, error};
 
 use crate::{self as ts, mptf::process_request};
@@ -18,6 +19,23 @@ pub async fn handle_requests() {
     }
 }
 
+/// Wakes sensors on system power state changes so they pick up the new sampling period right away
+pub async fn system_power_task() {
+    let hook = &ts::SERVICE.get().await.system_power_hook;
+    loop {
+        match hook.wait_event().await {
+            // Nothing to prepare, sampling is only adjusted once the state is entered
+            system_power::Event::Prepare(_) => hook.respond(system_power::Response::Ready).await,
+            system_power::Event::Cancelled(_) => {}
+            system_power::Event::Entered(_) => {
+                for sensor in ts::sensors().await.iter_only::<crate::sensor::Device>() {
+                    sensor.wake();
+                }
+            }
+        }
+    }
+}
+
 pub async fn fan_task<T: crate::fan::Controller, const SAMPLE_BUF_LEN: usize>(
     fan: &'static crate::fan::Fan<T, SAMPLE_BUF_LEN>,
 ) {
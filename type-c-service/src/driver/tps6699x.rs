@@ -10,6 +10,7 @@ use core::num::NonZeroU8;
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_time::Delay;
 use embedded_hal_async::i2c::I2c;
+use embedded_services::fw_update::{self, FwUpdate, Progress};
 use embedded_services::power::policy::PowerCapability;
 use embedded_services::type_c::ATTN_VDM_LEN;
 use embedded_services::type_c::controller::{
@@ -49,6 +50,8 @@ struct FwUpdateState<'a, M: RawMutex, B: I2c> {
     /// This value is never read, only used to keep the interrupt guard alive
     #[allow(dead_code)]
     guards: [Option<tps6699x_drv::InterruptGuard<'a, M, B>>; MAX_SUPPORTED_PORTS],
+    /// Number of image bytes written
+    bytes_written: usize,
 }
 
 /// The method used to control USB capabilities.
@@ -342,6 +345,8 @@ impl<M: RawMutex, B: I2c> Controller for Tps6699x<'_, M, B> {
 
             if pdo_raw != 0 && rdo_raw != 0 {
                 // Got a valid explicit contract
+                port_status.active_pdo = Some(pdo_raw);
+                port_status.active_rdo = Some(rdo_raw);
                 if pd_status.is_source() {
                     let pdo = source::Pdo::try_from(pdo_raw).map_err(|_| Error::from(PdError::InvalidParams))?;
                     let rdo = Rdo::for_pdo(rdo_raw, pdo).ok_or(Error::Pd(PdError::InvalidParams))?;
@@ -519,92 +524,6 @@ impl<M: RawMutex, B: I2c> Controller for Tps6699x<'_, M, B> {
         self.tps6699x.set_unconstrained_power(port, unconstrained)
     }
 
-    async fn get_active_fw_version(&mut self) -> Result<u32, Error<Self::BusError>> {
-        let customer_use = CustomerUse(self.tps6699x.get_customer_use().await?);
-        Ok(customer_use.custom_fw_version())
-    }
-
-    async fn start_fw_update(&mut self) -> Result<(), Error<Self::BusError>> {
-        let mut delay = Delay;
-        let mut updater: BorrowedUpdater<tps6699x_drv::Tps6699x<'_, M, B>> =
-            BorrowedUpdater::with_config(self.fw_update_config.clone());
-
-        // Abandon any previous in-progress update
-        if let Some(update) = self.update_state.take() {
-            warn!("Abandoning in-progress update");
-            update
-                .updater
-                .abort_fw_update(&mut [&mut self.tps6699x], &mut delay)
-                .await;
-        }
-
-        let mut guards = [const { None }; MAX_SUPPORTED_PORTS];
-        // Disable all interrupts on both ports, use guards[1] to ensure that this set of guards is dropped last
-        disable_all_interrupts::<tps6699x_drv::Tps6699x<'_, M, B>>(&mut [&mut self.tps6699x], &mut guards[1..]).await?;
-        let in_progress = updater.start_fw_update(&mut [&mut self.tps6699x], &mut delay).await?;
-        // Re-enable interrupts on port 0 only
-        enable_port0_interrupts::<tps6699x_drv::Tps6699x<'_, M, B>>(&mut [&mut self.tps6699x], &mut guards[0..1])
-            .await?;
-        self.update_state = Some(FwUpdateState {
-            updater: in_progress,
-            guards,
-        });
-        Ok(())
-    }
-
-    /// Aborts the firmware update in progress
-    ///
-    /// This can reset the controller
-    async fn abort_fw_update(&mut self) -> Result<(), Error<Self::BusError>> {
-        // Check if we're still in firmware update mode
-        if self.tps6699x.get_mode().await? == tps6699x::Mode::F211 {
-            let mut delay = Delay;
-
-            if let Some(update) = self.update_state.take() {
-                // Attempt to abort the firmware update by consuming our update object
-                update
-                    .updater
-                    .abort_fw_update(&mut [&mut self.tps6699x], &mut delay)
-                    .await;
-                Ok(())
-            } else {
-                // Bypass our update object since we've gotten into a state where we don't have one
-                self.tps6699x.fw_update_mode_exit(&mut delay).await
-            }
-        } else {
-            // Not in FW update mode, don't need to do anything
-            Ok(())
-        }
-    }
-
-    /// Finalize the firmware update
-    ///
-    /// This will reset the controller
-    async fn finalize_fw_update(&mut self) -> Result<(), Error<Self::BusError>> {
-        if let Some(update) = self.update_state.take() {
-            let mut delay = Delay;
-            update
-                .updater
-                .complete_fw_update(&mut [&mut self.tps6699x], &mut delay)
-                .await
-        } else {
-            Err(PdError::InvalidMode.into())
-        }
-    }
-
-    async fn write_fw_contents(&mut self, _offset: usize, data: &[u8]) -> Result<(), Error<Self::BusError>> {
-        if let Some(update) = &mut self.update_state {
-            let mut delay = Delay;
-            update
-                .updater
-                .write_bytes(&mut [&mut self.tps6699x], &mut delay, data)
-                .await?;
-            Ok(())
-        } else {
-            Err(PdError::InvalidMode.into())
-        }
-    }
-
     fn set_max_sink_voltage(
         &mut self,
         port: LocalPortId,
@@ -904,6 +823,169 @@ impl<M: RawMutex, B: I2c> Controller for Tps6699x<'_, M, B> {
             }
         }
     }
+
+    // Extended messages aren't exposed by the TPS6699x driver yet, these report PdError::UnrecognizedCommand so that
+    // callers stop asking
+    async fn get_partner_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: controller::BatteryRef,
+    ) -> Result<controller::BatteryCapabilities, Error<Self::BusError>> {
+        debug!("Port{}: Get_Battery_Cap({}) not supported", port.0, battery.0);
+        Err(PdError::UnrecognizedCommand.into())
+    }
+
+    async fn get_partner_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: controller::BatteryRef,
+    ) -> Result<controller::BatteryStatus, Error<Self::BusError>> {
+        debug!("Port{}: Get_Battery_Status({}) not supported", port.0, battery.0);
+        Err(PdError::UnrecognizedCommand.into())
+    }
+
+    async fn get_partner_source_info(
+        &mut self,
+        port: LocalPortId,
+    ) -> Result<controller::SourceInfo, Error<Self::BusError>> {
+        debug!("Port{}: Get_Source_Info not supported", port.0);
+        Err(PdError::UnrecognizedCommand.into())
+    }
+
+    async fn get_partner_status(&mut self, port: LocalPortId) -> Result<controller::PdStatus, Error<Self::BusError>> {
+        debug!("Port{}: Get_Status not supported", port.0);
+        Err(PdError::UnrecognizedCommand.into())
+    }
+
+    async fn set_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: controller::BatteryRef,
+        _capabilities: controller::BatteryCapabilities,
+    ) -> Result<(), Error<Self::BusError>> {
+        debug!("Port{}: Set battery capabilities({}) not supported", port.0, battery.0);
+        Err(PdError::UnrecognizedCommand.into())
+    }
+
+    async fn set_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: controller::BatteryRef,
+        _status: controller::BatteryStatus,
+    ) -> Result<(), Error<Self::BusError>> {
+        debug!("Port{}: Set battery status({}) not supported", port.0, battery.0);
+        Err(PdError::UnrecognizedCommand.into())
+    }
+}
+
+impl<M: RawMutex, B: I2c> fw_update::ErrorType for Tps6699x<'_, M, B> {
+    type Error = Error<B::Error>;
+}
+
+impl<M: RawMutex, B: I2c> FwUpdate for Tps6699x<'_, M, B> {
+    async fn get_active_fw_version(&mut self) -> Result<u32, Self::Error> {
+        let customer_use = CustomerUse(self.tps6699x.get_customer_use().await?);
+        Ok(customer_use.custom_fw_version())
+    }
+
+    async fn start_fw_update(&mut self) -> Result<(), Self::Error> {
+        let mut delay = Delay;
+        let mut updater: BorrowedUpdater<tps6699x_drv::Tps6699x<'_, M, B>> =
+            BorrowedUpdater::with_config(self.fw_update_config.clone());
+
+        // Abandon any previous in-progress update
+        if let Some(update) = self.update_state.take() {
+            warn!("Abandoning in-progress update");
+            update
+                .updater
+                .abort_fw_update(&mut [&mut self.tps6699x], &mut delay)
+                .await;
+        }
+
+        let mut guards = [const { None }; MAX_SUPPORTED_PORTS];
+        // Disable all interrupts on both ports, use guards[1] to ensure that this set of guards is dropped last
+        disable_all_interrupts::<tps6699x_drv::Tps6699x<'_, M, B>>(&mut [&mut self.tps6699x], &mut guards[1..]).await?;
+        let in_progress = updater.start_fw_update(&mut [&mut self.tps6699x], &mut delay).await?;
+        // Re-enable interrupts on port 0 only
+        enable_port0_interrupts::<tps6699x_drv::Tps6699x<'_, M, B>>(&mut [&mut self.tps6699x], &mut guards[0..1])
+            .await?;
+        self.update_state = Some(FwUpdateState {
+            updater: in_progress,
+            guards,
+            bytes_written: 0,
+        });
+        Ok(())
+    }
+
+    /// Aborts the firmware update in progress
+    ///
+    /// This can reset the controller
+    async fn abort_fw_update(&mut self) -> Result<(), Self::Error> {
+        // Check if we're still in firmware update mode
+        if self.tps6699x.get_mode().await? == tps6699x::Mode::F211 {
+            let mut delay = Delay;
+
+            if let Some(update) = self.update_state.take() {
+                // Attempt to abort the firmware update by consuming our update object
+                update
+                    .updater
+                    .abort_fw_update(&mut [&mut self.tps6699x], &mut delay)
+                    .await;
+                Ok(())
+            } else {
+                // Bypass our update object since we've gotten into a state where we don't have one
+                self.tps6699x.fw_update_mode_exit(&mut delay).await
+            }
+        } else {
+            // Not in FW update mode, don't need to do anything
+            Ok(())
+        }
+    }
+
+    /// Complete the firmware update and boot into the new image
+    ///
+    /// This will reset the controller
+    async fn activate_fw_update(&mut self) -> Result<(), Self::Error> {
+        if let Some(update) = self.update_state.take() {
+            let mut delay = Delay;
+            update
+                .updater
+                .complete_fw_update(&mut [&mut self.tps6699x], &mut delay)
+                .await
+        } else {
+            Err(PdError::InvalidMode.into())
+        }
+    }
+
+    async fn write_fw_contents(&mut self, _offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        if let Some(update) = &mut self.update_state {
+            let mut delay = Delay;
+            update
+                .updater
+                .write_bytes(&mut [&mut self.tps6699x], &mut delay, data)
+                .await?;
+            update.bytes_written += data.len();
+            Ok(())
+        } else {
+            Err(PdError::InvalidMode.into())
+        }
+    }
+
+    /// The controller doesn't support switching back to the previous bank
+    async fn rollback_fw_update(&mut self) -> Result<(), Self::Error> {
+        debug!("FW rollback not supported");
+        Err(PdError::UnrecognizedCommand.into())
+    }
+
+    fn progress(&self) -> Progress {
+        match &self.update_state {
+            Some(update) => Progress {
+                state: fw_update::State::Writing,
+                bytes_written: update.bytes_written,
+            },
+            None => Progress::default(),
+        }
+    }
 }
 
 impl<'a, M: RawMutex, BUS: I2c> AsRef<tps6699x_drv::Tps6699x<'a, M, BUS>> for Tps6699x<'a, M, BUS> {
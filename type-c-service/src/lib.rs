@@ -1,5 +1,7 @@
 #![no_std]
 pub mod driver;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod service;
 pub mod task;
 pub mod wrapper;
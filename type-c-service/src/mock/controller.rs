@@ -0,0 +1,460 @@
+//! Controller trait implementation for the mock controller
+use core::num::NonZeroU8;
+
+use embedded_services::fw_update::{self, FwUpdate, Progress};
+use embedded_services::power::policy::PowerCapability;
+use embedded_services::type_c::controller::{
+    self, AttnVdm, BatteryCapabilities, BatteryRef, BatteryStatus, ControllerStatus, DiscoveredSvids, DpConfig,
+    DpStatus, OtherVdm, PdStateMachineConfig, PdStatus, PortStatus, RetimerFwUpdateState, SendVdm, SourceInfo,
+    SystemPowerState, TbtConfig, TypeCStateMachineState, UsbControlConfig,
+};
+use embedded_services::type_c::event::PortEvent;
+use embedded_services::{debug, trace};
+use embedded_usb_pd::ado::Ado;
+use embedded_usb_pd::ucsi::lpm;
+use embedded_usb_pd::vdm::structured::command::discover_identity::{sop, sop_prime};
+use embedded_usb_pd::{Error, LocalPortId, PdError};
+
+use super::{FaultKind, MockBusError, Operation, Port, State};
+
+/// Simulated PD controller
+pub struct Controller<'a> {
+    state: &'a State,
+}
+
+impl<'a> Controller<'a> {
+    /// Create a new controller backed by the given state
+    pub const fn new(state: &'a State) -> Self {
+        Self { state }
+    }
+
+    /// Returns the backing state
+    pub fn state(&self) -> &'a State {
+        self.state
+    }
+
+    /// Record a controller-wide call and check for injected faults
+    fn check(&self, operation: Operation) -> Result<(), Error<MockBusError>> {
+        trace!("Mock controller: {:?}", operation);
+        self.state.record(operation, None).map_err(fault_to_error)
+    }
+
+    /// Record a port call, check for injected faults and then run `f` on the port state
+    fn port<R>(
+        &self,
+        operation: Operation,
+        port: LocalPortId,
+        f: impl FnOnce(&mut Port) -> Result<R, PdError>,
+    ) -> Result<R, Error<MockBusError>> {
+        trace!("Mock controller: port{}: {:?}", port.0, operation);
+        self.state.record(operation, Some(port)).map_err(fault_to_error)?;
+        self.state
+            .with_port(port, f)
+            .ok_or(Error::Pd(PdError::InvalidPort))?
+            .map_err(Error::Pd)
+    }
+}
+
+/// Convert an injected fault into the error returned to the caller
+fn fault_to_error(kind: FaultKind) -> Error<MockBusError> {
+    match kind {
+        FaultKind::Bus => Error::Bus(MockBusError),
+        FaultKind::Pd(e) => Error::Pd(e),
+    }
+}
+
+impl controller::Controller for Controller<'_> {
+    type BusError = MockBusError;
+
+    async fn wait_port_event(&mut self) -> Result<(), Error<Self::BusError>> {
+        // Check faults first so an injected fault doesn't require an event to be reported
+        self.check(Operation::WaitPortEvent)?;
+        self.state.event.wait().await;
+        Ok(())
+    }
+
+    async fn clear_port_events(&mut self, port: LocalPortId) -> Result<PortEvent, Error<Self::BusError>> {
+        let events = self.port(Operation::ClearPortEvents, port, |port| {
+            Ok(core::mem::replace(&mut port.events, PortEvent::none()))
+        })?;
+        debug!("Mock controller: port{}: events {:?}", port.0, events);
+        Ok(events)
+    }
+
+    async fn get_port_status(&mut self, port: LocalPortId) -> Result<PortStatus, Error<Self::BusError>> {
+        self.port(Operation::GetPortStatus, port, |port| Ok(port.status))
+    }
+
+    async fn reset_controller(&mut self) -> Result<(), Error<Self::BusError>> {
+        self.check(Operation::ResetController)
+    }
+
+    async fn get_rt_fw_update_status(
+        &mut self,
+        port: LocalPortId,
+    ) -> Result<RetimerFwUpdateState, Error<Self::BusError>> {
+        self.port(Operation::GetRtFwUpdateStatus, port, |port| Ok(port.rt_fw_update_state))
+    }
+
+    async fn set_rt_fw_update_state(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetRtFwUpdateState, port, |port| {
+            port.rt_fw_update_state = RetimerFwUpdateState::Active;
+            Ok(())
+        })
+    }
+
+    async fn clear_rt_fw_update_state(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::ClearRtFwUpdateState, port, |port| {
+            port.rt_fw_update_state = RetimerFwUpdateState::Inactive;
+            Ok(())
+        })
+    }
+
+    async fn set_rt_compliance(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetRtCompliance, port, |_| Ok(()))
+    }
+
+    async fn reconfigure_retimer(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::ReconfigureRetimer, port, |_| Ok(()))
+    }
+
+    async fn clear_dead_battery_flag(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::ClearDeadBatteryFlag, port, |port| {
+            port.dead_battery = false;
+            Ok(())
+        })
+    }
+
+    async fn enable_sink_path(&mut self, port: LocalPortId, enable: bool) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::EnableSinkPath, port, |port| {
+            port.sink_path_enabled = enable;
+            Ok(())
+        })
+    }
+
+    async fn get_controller_status(&mut self) -> Result<ControllerStatus<'static>, Error<Self::BusError>> {
+        self.check(Operation::GetControllerStatus)?;
+        Ok(self.state.with_inner(|inner| inner.controller_status))
+    }
+
+    async fn get_pd_alert(&mut self, port: LocalPortId) -> Result<Option<Ado>, Error<Self::BusError>> {
+        self.port(Operation::GetPdAlert, port, |port| Ok(port.pd_alerts.pop_front()))
+    }
+
+    async fn set_max_sink_voltage(
+        &mut self,
+        port: LocalPortId,
+        voltage_mv: Option<u16>,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetMaxSinkVoltage, port, |port| {
+            port.max_sink_voltage_mv = voltage_mv;
+            Ok(())
+        })
+    }
+
+    async fn set_source_capability(
+        &mut self,
+        port: LocalPortId,
+        capability: PowerCapability,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetSourceCapability, port, |port| {
+            port.source_capability = Some(capability);
+            Ok(())
+        })
+    }
+
+    async fn set_unconstrained_power(
+        &mut self,
+        port: LocalPortId,
+        unconstrained: bool,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetUnconstrainedPower, port, |port| {
+            port.unconstrained = unconstrained;
+            Ok(())
+        })
+    }
+
+    async fn get_other_vdm(&mut self, port: LocalPortId) -> Result<OtherVdm, Error<Self::BusError>> {
+        self.port(Operation::GetOtherVdm, port, |port| Ok(port.other_vdm))
+    }
+
+    async fn get_attn_vdm(&mut self, port: LocalPortId) -> Result<AttnVdm, Error<Self::BusError>> {
+        self.port(Operation::GetAttnVdm, port, |port| Ok(port.attn_vdm))
+    }
+
+    async fn send_vdm(&mut self, port: LocalPortId, tx_vdm: SendVdm) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SendVdm, port, |port| {
+            port.sent_vdm = Some(tx_vdm);
+            Ok(())
+        })
+    }
+
+    async fn set_usb_control(
+        &mut self,
+        port: LocalPortId,
+        config: UsbControlConfig,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetUsbControl, port, |port| {
+            port.usb_control = config;
+            Ok(())
+        })
+    }
+
+    async fn get_dp_status(&mut self, port: LocalPortId) -> Result<DpStatus, Error<Self::BusError>> {
+        self.port(Operation::GetDpStatus, port, |port| Ok(port.dp_status))
+    }
+
+    async fn set_dp_config(&mut self, port: LocalPortId, config: DpConfig) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetDpConfig, port, |port| {
+            port.dp_config = Some(config);
+            Ok(())
+        })
+    }
+
+    async fn execute_drst(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::ExecuteDrst, port, |_| Ok(()))
+    }
+
+    async fn set_tbt_config(&mut self, port: LocalPortId, config: TbtConfig) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetTbtConfig, port, |port| {
+            port.tbt_config = config;
+            Ok(())
+        })
+    }
+
+    async fn set_pd_state_machine_config(
+        &mut self,
+        port: LocalPortId,
+        config: PdStateMachineConfig,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetPdStateMachineConfig, port, |port| {
+            port.pd_state_machine_config = config;
+            Ok(())
+        })
+    }
+
+    async fn set_type_c_state_machine_config(
+        &mut self,
+        port: LocalPortId,
+        state: TypeCStateMachineState,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetTypeCStateMachineConfig, port, |port| {
+            port.type_c_state_machine_config = state;
+            Ok(())
+        })
+    }
+
+    async fn execute_ucsi_command(
+        &mut self,
+        command: lpm::LocalCommand,
+    ) -> Result<Option<lpm::ResponseData>, Error<Self::BusError>> {
+        self.port(Operation::ExecuteUcsiCommand, command.port(), |port| {
+            if let Some(reply) = port.ucsi_replies.pop_front() {
+                return reply;
+            }
+
+            // Default replies for commands that don't need scripting
+            match command.operation() {
+                lpm::CommandData::GetConnectorStatus => Ok(Some(lpm::ResponseData::GetConnectorStatus(
+                    lpm::get_connector_status::ResponseData::default(),
+                ))),
+                _ => Err(PdError::UnrecognizedCommand),
+            }
+        })
+    }
+
+    async fn execute_electrical_disconnect(
+        &mut self,
+        port: LocalPortId,
+        _reconnect_time_s: Option<NonZeroU8>,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::ExecuteElectricalDisconnect, port, |_| Ok(()))
+    }
+
+    async fn set_power_state(
+        &mut self,
+        port: LocalPortId,
+        state: SystemPowerState,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetPowerState, port, |port| {
+            port.power_state = Some(state);
+            Ok(())
+        })
+    }
+
+    async fn get_discovered_svids(&mut self, port: LocalPortId) -> Result<DiscoveredSvids, Error<Self::BusError>> {
+        self.port(Operation::GetDiscoveredSvids, port, |port| Ok(port.svids))
+    }
+
+    async fn hard_reset(&mut self, port: LocalPortId) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::HardReset, port, |_| Ok(()))
+    }
+
+    async fn get_discover_identity_sop_response(
+        &mut self,
+        port: LocalPortId,
+    ) -> Result<sop::ResponseVdos, Error<Self::BusError>> {
+        self.port(Operation::GetDiscoverIdentitySop, port, |port| {
+            port.sop_identity.ok_or(PdError::Failed)
+        })
+    }
+
+    async fn get_discover_identity_sop_prime_response(
+        &mut self,
+        port: LocalPortId,
+    ) -> Result<sop_prime::ResponseVdos, Error<Self::BusError>> {
+        self.port(Operation::GetDiscoverIdentitySopPrime, port, |port| {
+            port.sop_prime_identity.ok_or(PdError::Failed)
+        })
+    }
+
+    async fn get_partner_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+    ) -> Result<BatteryCapabilities, Error<Self::BusError>> {
+        self.port(Operation::GetPartnerBatteryCapabilities, port, |port| {
+            if battery.is_valid() {
+                port.partner_battery_capabilities.ok_or(PdError::Failed)
+            } else {
+                Ok(BatteryCapabilities::invalid())
+            }
+        })
+    }
+
+    async fn get_partner_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+    ) -> Result<BatteryStatus, Error<Self::BusError>> {
+        self.port(Operation::GetPartnerBatteryStatus, port, |port| {
+            if battery.is_valid() {
+                port.partner_battery_status.ok_or(PdError::Failed)
+            } else {
+                Ok(BatteryStatus::invalid())
+            }
+        })
+    }
+
+    async fn get_partner_source_info(&mut self, port: LocalPortId) -> Result<SourceInfo, Error<Self::BusError>> {
+        self.port(Operation::GetPartnerSourceInfo, port, |port| {
+            port.partner_source_info.ok_or(PdError::Failed)
+        })
+    }
+
+    async fn get_partner_status(&mut self, port: LocalPortId) -> Result<PdStatus, Error<Self::BusError>> {
+        self.port(Operation::GetPartnerStatus, port, |port| {
+            port.partner_status.ok_or(PdError::Failed)
+        })
+    }
+
+    async fn set_battery_capabilities(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+        capabilities: BatteryCapabilities,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetBatteryCapabilities, port, |port| {
+            port.battery_capabilities = Some((battery, capabilities));
+            Ok(())
+        })
+    }
+
+    async fn set_battery_status(
+        &mut self,
+        port: LocalPortId,
+        battery: BatteryRef,
+        status: BatteryStatus,
+    ) -> Result<(), Error<Self::BusError>> {
+        self.port(Operation::SetBatteryStatus, port, |port| {
+            port.battery_status = Some((battery, status));
+            Ok(())
+        })
+    }
+}
+
+impl fw_update::ErrorType for Controller<'_> {
+    type Error = Error<MockBusError>;
+}
+
+impl FwUpdate for Controller<'_> {
+    async fn get_active_fw_version(&mut self) -> Result<u32, Self::Error> {
+        self.check(Operation::GetActiveFwVersion)?;
+        Ok(self.state.with_inner(|inner| inner.fw_version))
+    }
+
+    async fn start_fw_update(&mut self) -> Result<(), Self::Error> {
+        self.check(Operation::StartFwUpdate)?;
+        self.state.with_inner(|inner| {
+            inner.fw_update.in_progress = true;
+            inner.fw_update.bytes_written = 0;
+            inner.fw_update.verified = false;
+        });
+        Ok(())
+    }
+
+    async fn write_fw_contents(&mut self, _offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        self.check(Operation::WriteFwContents)?;
+        self.state.with_inner(|inner| {
+            if inner.fw_update.in_progress {
+                inner.fw_update.bytes_written += data.len();
+                inner.fw_update.verified = false;
+                Ok(())
+            } else {
+                Err(Error::Pd(PdError::InvalidMode))
+            }
+        })
+    }
+
+    async fn verify_fw_update(&mut self) -> Result<(), Self::Error> {
+        self.check(Operation::VerifyFwUpdate)?;
+        self.state.with_inner(|inner| {
+            if inner.fw_update.in_progress {
+                inner.fw_update.verified = true;
+                Ok(())
+            } else {
+                Err(Error::Pd(PdError::InvalidMode))
+            }
+        })
+    }
+
+    async fn activate_fw_update(&mut self) -> Result<(), Self::Error> {
+        self.check(Operation::ActivateFwUpdate)?;
+        self.state.with_inner(|inner| {
+            if inner.fw_update.in_progress {
+                inner.fw_update.in_progress = false;
+                inner.fw_update.verified = false;
+                inner.fw_update.completed += 1;
+                Ok(())
+            } else {
+                Err(Error::Pd(PdError::InvalidMode))
+            }
+        })
+    }
+
+    async fn abort_fw_update(&mut self) -> Result<(), Self::Error> {
+        self.check(Operation::AbortFwUpdate)?;
+        self.state.with_inner(|inner| {
+            inner.fw_update.in_progress = false;
+            inner.fw_update.verified = false;
+        });
+        Ok(())
+    }
+
+    async fn rollback_fw_update(&mut self) -> Result<(), Self::Error> {
+        self.check(Operation::RollbackFwUpdate)?;
+        self.state.with_inner(|inner| inner.fw_update.rollbacks += 1);
+        Ok(())
+    }
+
+    fn progress(&self) -> Progress {
+        self.state.with_inner(|inner| Progress {
+            state: match (inner.fw_update.in_progress, inner.fw_update.verified) {
+                (false, _) => fw_update::State::Idle,
+                (true, false) => fw_update::State::Writing,
+                (true, true) => fw_update::State::Verified,
+            },
+            bytes_written: inner.fw_update.bytes_written,
+        })
+    }
+}
@@ -0,0 +1,182 @@
+//! Integration tests running the mock controller through the type-C service, power policy and UCSI
+//!
+//! The services use global state so everything is exercised from a single test.
+use embassy_futures::select::{Either4, select4};
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Timer, with_timeout};
+use embedded_cfu_protocol::protocol_definitions::{FwUpdateOffer, FwUpdateOfferResponse, FwVersion, HostToken};
+use embedded_services::GlobalRawMutex;
+use embedded_services::power::policy::{self, PowerCapability};
+use embedded_services::type_c::ControllerId;
+use embedded_services::type_c::controller::{DataMode, DiscoveredSvids};
+use embedded_services::type_c::external::{UcsiResponseResult, execute_ucsi_command};
+use embedded_usb_pd::ucsi::ppm::ack_cc_ci::Ack;
+use embedded_usb_pd::ucsi::ppm::set_notification_enable::NotificationEnable;
+use embedded_usb_pd::ucsi::{Command, lpm, ppm};
+use embedded_usb_pd::{GlobalPortId, LocalPortId, PowerRole};
+use heapless::Vec;
+use static_cell::StaticCell;
+
+use super::{Controller, Operation, State};
+use crate::service::config::{Config, DataModeConfig};
+use crate::service::data_mode::SVID_DISPLAYPORT;
+use crate::wrapper::backing::{ReferencedStorage, Storage};
+use crate::wrapper::{ControllerWrapper, FwOfferValidator};
+
+const CONTROLLER0_ID: ControllerId = ControllerId(0);
+const PORT0: LocalPortId = LocalPortId(0);
+const PORT0_ID: GlobalPortId = GlobalPortId(0);
+const POWER0_ID: policy::DeviceId = policy::DeviceId(0);
+const CFU0_ID: u8 = 0x00;
+
+/// Time to wait for the services to react to a simulated event
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+const CAPABILITY: PowerCapability = PowerCapability {
+    voltage_mv: 20000,
+    current_ma: 3000,
+};
+
+struct Validator;
+
+impl FwOfferValidator for Validator {
+    fn validate(&self, _current: FwVersion, _offer: &FwUpdateOffer) -> FwUpdateOfferResponse {
+        FwUpdateOfferResponse::new_accept(HostToken::Driver)
+    }
+}
+
+type Wrapper = ControllerWrapper<'static, GlobalRawMutex, Mutex<GlobalRawMutex, Controller<'static>>, Validator>;
+
+/// Poll `f` until it returns true, returns false if it doesn't within [`TIMEOUT`]
+async fn wait_for(mut f: impl AsyncFnMut() -> bool) -> bool {
+    with_timeout(TIMEOUT, async {
+        while !f().await {
+            Timer::after_millis(10).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+/// Execute a UCSI command and return the response, [`None`] if the command failed
+async fn ucsi(command: Command) -> Option<embedded_usb_pd::ucsi::GlobalResponse> {
+    let response: UcsiResponseResult = execute_ucsi_command(command).await.into();
+    response.ok()
+}
+
+/// Acknowledge the current command and connector change
+async fn ack(connector_change: bool) {
+    let response = ucsi(Command::PpmCommand(ppm::Command::AckCcCi(ppm::ack_cc_ci::Args {
+        ack: *Ack::default()
+            .set_command_complete(true)
+            .set_connector_change(connector_change),
+    })))
+    .await
+    .unwrap();
+    assert!(response.cci.ack_command());
+    assert!(!response.cci.error());
+}
+
+/// Power policy device of [`PORT0`]
+fn power_device(wrapper: &Wrapper) -> &policy::device::Device {
+    wrapper.power_policy_devices().first().unwrap()
+}
+
+/// Scripted host and port partner interaction
+async fn test_body(state: &'static State, wrapper: &'static Wrapper) {
+    // Bring up the PPM with connect change notifications enabled
+    let response = ucsi(Command::PpmCommand(ppm::Command::PpmReset)).await.unwrap();
+    assert!(response.cci.reset_complete());
+
+    let mut notifications = NotificationEnable::default();
+    notifications.set_cmd_complete(true);
+    notifications.set_connect_change(true);
+    let response = ucsi(Command::PpmCommand(ppm::Command::SetNotificationEnable(
+        ppm::set_notification_enable::Args {
+            notification_enable: notifications,
+        },
+    )))
+    .await
+    .unwrap();
+    assert!(response.cci.cmd_complete());
+    assert!(!response.cci.error());
+    ack(false).await;
+
+    // Attaching a source makes the power policy connect it as the consumer
+    state.attach(PORT0, PowerRole::Sink, CAPABILITY, false);
+    assert!(wait_for(async || state.with_port(PORT0, |port| port.sink_path_enabled) == Some(true)).await);
+    assert!(wait_for(async || power_device(wrapper).is_consumer().await).await);
+
+    // The attach is reported to the OPM as a connector change on connector 1
+    let response = ucsi(Command::LpmCommand(lpm::GlobalCommand::new(
+        PORT0_ID,
+        lpm::CommandData::GetConnectorStatus,
+    )))
+    .await
+    .unwrap();
+    assert!(response.cci.cmd_complete());
+    assert!(!response.cci.error());
+    assert_eq!(response.cci.connector_change(), GlobalPortId(1));
+    assert!(matches!(
+        response.data,
+        Some(embedded_usb_pd::ucsi::ResponseData::Lpm(
+            lpm::ResponseData::GetConnectorStatus(_)
+        ))
+    ));
+    ack(true).await;
+
+    // Discovery of a DisplayPort partner enters DisplayPort alt-mode
+    let mut sop = Vec::new();
+    sop.push(SVID_DISPLAYPORT).unwrap();
+    state.complete_discovery(PORT0, DiscoveredSvids::new(sop, Vec::new()), None, None);
+    assert!(wait_for(async || state.with_port(PORT0, |port| port.data_mode()) == Some(DataMode::DisplayPort)).await);
+
+    // Detaching disconnects the consumer and leaves the data mode
+    state.detach(PORT0);
+    assert!(wait_for(async || state.with_port(PORT0, |port| port.sink_path_enabled) == Some(false)).await);
+    assert!(wait_for(async || state.with_port(PORT0, |port| port.data_mode()) == Some(DataMode::None)).await);
+    assert!(wait_for(async || !power_device(wrapper).is_consumer().await).await);
+    assert!(state.call_count(Operation::EnableSinkPath) > 0);
+}
+
+/// Test attach, data mode entry and detach through the type-C service, power policy and UCSI
+#[tokio::test]
+async fn test_services() {
+    embedded_services::init().await;
+
+    static STATE: State = State::with_num_ports(1);
+    static STORAGE: StaticCell<Storage<1, GlobalRawMutex>> = StaticCell::new();
+    let storage = STORAGE.init(Storage::new(CONTROLLER0_ID, CFU0_ID, [(PORT0_ID, POWER0_ID)]));
+    static REFERENCED: StaticCell<ReferencedStorage<1, GlobalRawMutex>> = StaticCell::new();
+    let referenced = REFERENCED.init(storage.create_referenced().unwrap());
+    static CONTROLLER: StaticCell<Mutex<GlobalRawMutex, Controller<'static>>> = StaticCell::new();
+    let controller = CONTROLLER.init(Mutex::new(Controller::new(&STATE)));
+    static WRAPPER: StaticCell<Wrapper> = StaticCell::new();
+    let wrapper: &'static Wrapper =
+        WRAPPER.init(Wrapper::try_new(controller, Default::default(), referenced, Validator).unwrap());
+    wrapper.register().await.unwrap();
+
+    let config = Config {
+        data_mode: DataModeConfig {
+            enabled: true,
+            dp_supported: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let wrapper_task = async {
+        loop {
+            let _ = wrapper.process_next_event().await;
+        }
+    };
+
+    let result = select4(
+        crate::task(config),
+        power_policy_service::task::task(Default::default()),
+        wrapper_task,
+        test_body(&STATE, wrapper),
+    )
+    .await;
+    assert!(matches!(result, Either4::Fourth(())));
+}
@@ -0,0 +1,767 @@
+//! Hardware-independent simulated PD controller
+//!
+//! [`Controller`] implements [`embedded_services::type_c::controller::Controller`] on top of a shared [`State`]. Tests
+//! script the state (attach/detach, contract changes, alerts, VDMs, UCSI replies) and the controller reports the
+//! resulting port events exactly like a real PD controller would. Bus errors can be injected with [`Fault`] and every
+//! controller call is recorded so tests can assert on what the wrapper or service did.
+//!
+//! Scripting functions are synchronous so they can be called from anywhere in a test, the controller is notified
+//! through a signal.
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embedded_services::GlobalRawMutex;
+use embedded_services::power::policy::PowerCapability;
+use embedded_services::type_c::controller::{
+    AttnVdm, BatteryCapabilities, BatteryRef, BatteryStatus, ControllerStatus, DataMode, DiscoveredSvids, DpConfig,
+    DpPinConfig, DpStatus, OtherVdm, PdStateMachineConfig, PdStatus, PortStatus, RetimerFwUpdateState, SendVdm,
+    SourceInfo, SystemPowerState, TbtConfig, TypeCStateMachineState, UsbControlConfig,
+};
+use embedded_services::type_c::event::{PortEvent, VdmNotification};
+use embedded_usb_pd::ado::Ado;
+use embedded_usb_pd::type_c::ConnectionState;
+use embedded_usb_pd::ucsi::lpm;
+use embedded_usb_pd::vdm::structured::command::discover_identity::{sop, sop_prime};
+use embedded_usb_pd::{LocalPortId, PdError, PowerRole};
+use heapless::{Deque, Vec};
+
+mod controller;
+pub use controller::Controller;
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod integration;
+
+/// Maximum number of ports supported by the mock controller
+pub const MAX_MOCK_PORTS: usize = crate::wrapper::MAX_SUPPORTED_PORTS;
+/// Maximum number of buffered PD alerts per port
+pub const MAX_PD_ALERTS: usize = 4;
+/// Maximum number of queued UCSI replies per port
+pub const MAX_UCSI_REPLIES: usize = 4;
+/// Maximum number of simultaneously active faults
+pub const MAX_FAULTS: usize = 4;
+/// Maximum number of recorded calls, older calls are discarded first
+pub const MAX_RECORDED_CALLS: usize = 32;
+
+/// Bus error type reported by the mock controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MockBusError;
+
+/// Controller operations, one for each function of the controller trait
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Operation {
+    WaitPortEvent,
+    ClearPortEvents,
+    GetPortStatus,
+    ResetController,
+    GetRtFwUpdateStatus,
+    SetRtFwUpdateState,
+    ClearRtFwUpdateState,
+    SetRtCompliance,
+    ReconfigureRetimer,
+    ClearDeadBatteryFlag,
+    EnableSinkPath,
+    GetControllerStatus,
+    GetPdAlert,
+    SetMaxSinkVoltage,
+    SetSourceCapability,
+    SetUnconstrainedPower,
+    GetActiveFwVersion,
+    StartFwUpdate,
+    AbortFwUpdate,
+    WriteFwContents,
+    VerifyFwUpdate,
+    ActivateFwUpdate,
+    RollbackFwUpdate,
+    GetOtherVdm,
+    GetAttnVdm,
+    SendVdm,
+    SetUsbControl,
+    GetDpStatus,
+    SetDpConfig,
+    ExecuteDrst,
+    SetTbtConfig,
+    SetPdStateMachineConfig,
+    SetTypeCStateMachineConfig,
+    ExecuteUcsiCommand,
+    ExecuteElectricalDisconnect,
+    SetPowerState,
+    GetDiscoveredSvids,
+    HardReset,
+    GetDiscoverIdentitySop,
+    GetDiscoverIdentitySopPrime,
+    GetPartnerBatteryCapabilities,
+    GetPartnerBatteryStatus,
+    GetPartnerSourceInfo,
+    GetPartnerStatus,
+    SetBatteryCapabilities,
+    SetBatteryStatus,
+}
+
+/// A recorded controller call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Call {
+    /// Operation that was called
+    pub operation: Operation,
+    /// Port the operation targeted, [`None`] for controller-wide operations
+    pub port: Option<LocalPortId>,
+}
+
+/// Error returned by an injected fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FaultKind {
+    /// Return [`embedded_usb_pd::Error::Bus`]
+    Bus,
+    /// Return [`embedded_usb_pd::Error::Pd`] with the given error
+    Pd(PdError),
+}
+
+/// Fault injection description
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Fault {
+    /// Operation to fail, [`None`] fails any operation
+    pub operation: Option<Operation>,
+    /// Port to fail, [`None`] fails any port
+    pub port: Option<LocalPortId>,
+    /// Error to return
+    pub kind: FaultKind,
+    /// Number of calls to fail before the fault is removed
+    pub count: usize,
+}
+
+impl Fault {
+    /// Create a fault that fails the next call to `operation` with a bus error
+    pub const fn bus_error(operation: Operation) -> Self {
+        Self {
+            operation: Some(operation),
+            port: None,
+            kind: FaultKind::Bus,
+            count: 1,
+        }
+    }
+
+    /// Returns true if this fault applies to the given call
+    fn matches(&self, call: &Call) -> bool {
+        self.operation.is_none_or(|operation| operation == call.operation)
+            && self.port.is_none_or(|port| Some(port) == call.port)
+    }
+}
+
+/// Simulated per-port state
+///
+/// Fields are public so tests can inspect or modify the state directly through [`State::with_port`].
+#[derive(Debug, Clone)]
+pub struct Port {
+    /// Current port status
+    pub status: PortStatus,
+    /// Pending port events
+    pub events: PortEvent,
+    /// Buffered PD alerts
+    pub pd_alerts: Deque<Ado, MAX_PD_ALERTS>,
+    /// Last received other VDM
+    pub other_vdm: OtherVdm,
+    /// Last received attention VDM
+    pub attn_vdm: AttnVdm,
+    /// Last VDM sent through the controller
+    pub sent_vdm: Option<SendVdm>,
+    /// Current DisplayPort status
+    pub dp_status: DpStatus,
+    /// Discovered SVIDs
+    pub svids: DiscoveredSvids,
+    /// Discover Identity response on SOP, [`None`] if the partner didn't respond
+    pub sop_identity: Option<sop::ResponseVdos>,
+    /// Discover Identity response on SOP', [`None`] if the cable didn't respond
+    pub sop_prime_identity: Option<sop_prime::ResponseVdos>,
+    /// Queued UCSI replies, returned in order before falling back to the default replies
+    pub ucsi_replies: Deque<Result<Option<lpm::ResponseData>, PdError>, MAX_UCSI_REPLIES>,
+    /// Sink path enabled
+    pub sink_path_enabled: bool,
+    /// Maximum sink voltage
+    pub max_sink_voltage_mv: Option<u16>,
+    /// Maximum advertised source capability
+    pub source_capability: Option<PowerCapability>,
+    /// Unconstrained power
+    pub unconstrained: bool,
+    /// Dead battery flag
+    pub dead_battery: bool,
+    /// Retimer firmware update state
+    pub rt_fw_update_state: RetimerFwUpdateState,
+    /// USB control configuration
+    pub usb_control: UsbControlConfig,
+    /// Thunderbolt configuration
+    pub tbt_config: TbtConfig,
+    /// DisplayPort configuration
+    pub dp_config: Option<DpConfig>,
+    /// PD state-machine configuration
+    pub pd_state_machine_config: PdStateMachineConfig,
+    /// Type-C state-machine configuration
+    pub type_c_state_machine_config: TypeCStateMachineState,
+    /// Last system power state
+    pub power_state: Option<SystemPowerState>,
+    /// Partner response to Get_Battery_Cap, [`None`] if the partner doesn't respond
+    pub partner_battery_capabilities: Option<BatteryCapabilities>,
+    /// Partner response to Get_Battery_Status, [`None`] if the partner doesn't respond
+    pub partner_battery_status: Option<BatteryStatus>,
+    /// Partner response to Get_Source_Info, [`None`] if the partner doesn't respond
+    pub partner_source_info: Option<SourceInfo>,
+    /// Partner response to Get_Status, [`None`] if the partner doesn't respond
+    pub partner_status: Option<PdStatus>,
+    /// Last battery capabilities set for reporting to the partner
+    pub battery_capabilities: Option<(BatteryRef, BatteryCapabilities)>,
+    /// Last battery status set for reporting to the partner
+    pub battery_status: Option<(BatteryRef, BatteryStatus)>,
+}
+
+impl Port {
+    /// Create a new detached port
+    pub const fn new() -> Self {
+        Self {
+            status: PortStatus::new(),
+            events: PortEvent::none(),
+            pd_alerts: Deque::new(),
+            other_vdm: OtherVdm {
+                data: [0; embedded_services::type_c::OTHER_VDM_LEN],
+            },
+            attn_vdm: AttnVdm {
+                data: [0; embedded_services::type_c::ATTN_VDM_LEN],
+            },
+            sent_vdm: None,
+            dp_status: DpStatus {
+                alt_mode_entered: false,
+                dfp_d_pin_cfg: DpPinConfig {
+                    pin_c: false,
+                    pin_d: false,
+                    pin_e: false,
+                },
+            },
+            svids: DiscoveredSvids::new(Vec::new(), Vec::new()),
+            sop_identity: None,
+            sop_prime_identity: None,
+            ucsi_replies: Deque::new(),
+            sink_path_enabled: false,
+            max_sink_voltage_mv: None,
+            source_capability: None,
+            unconstrained: false,
+            dead_battery: false,
+            rt_fw_update_state: RetimerFwUpdateState::Inactive,
+            usb_control: UsbControlConfig {
+                usb2_enabled: true,
+                usb3_enabled: true,
+                usb4_enabled: true,
+            },
+            tbt_config: TbtConfig { tbt_enabled: false },
+            dp_config: None,
+            pd_state_machine_config: PdStateMachineConfig { enabled: true },
+            type_c_state_machine_config: TypeCStateMachineState::Drp,
+            power_state: None,
+            partner_battery_capabilities: None,
+            partner_battery_status: None,
+            partner_source_info: None,
+            partner_status: None,
+            battery_capabilities: None,
+            battery_status: None,
+        }
+    }
+
+    /// Data mode implied by the current USB, Thunderbolt and DisplayPort configuration
+    pub fn data_mode(&self) -> DataMode {
+        if !self.status.is_connected() {
+            DataMode::None
+        } else if self.usb_control.usb4_enabled {
+            DataMode::Usb4
+        } else if self.tbt_config.tbt_enabled {
+            DataMode::Tbt3
+        } else if self.dp_config.is_some_and(|config| config.enable) {
+            DataMode::DisplayPort
+        } else if self.usb_control.usb3_enabled {
+            DataMode::Usb3
+        } else if self.usb_control.usb2_enabled {
+            DataMode::Usb2
+        } else {
+            DataMode::None
+        }
+    }
+}
+
+impl Default for Port {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Simulated firmware update state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FwUpdate {
+    /// Firmware update in progress
+    pub in_progress: bool,
+    /// Total number of bytes written during the current update
+    pub bytes_written: usize,
+    /// Current update has been verified
+    pub verified: bool,
+    /// Number of completed updates
+    pub completed: usize,
+    /// Number of rollbacks to the previous image
+    pub rollbacks: usize,
+}
+
+/// Internal state
+struct Inner {
+    ports: [Port; MAX_MOCK_PORTS],
+    num_ports: usize,
+    controller_status: ControllerStatus<'static>,
+    fw_version: u32,
+    fw_update: FwUpdate,
+    faults: Vec<Fault, MAX_FAULTS>,
+    calls: Deque<Call, MAX_RECORDED_CALLS>,
+}
+
+/// Shared mock controller state
+pub struct State {
+    inner: Mutex<GlobalRawMutex, RefCell<Inner>>,
+    /// Signaled when there are new port events
+    event: Signal<GlobalRawMutex, ()>,
+}
+
+impl State {
+    /// Create a new state with [`MAX_MOCK_PORTS`] ports
+    pub const fn new() -> Self {
+        Self::with_num_ports(MAX_MOCK_PORTS)
+    }
+
+    /// Create a new state with the given number of ports, clamped to [`MAX_MOCK_PORTS`]
+    pub const fn with_num_ports(num_ports: usize) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Inner {
+                ports: [const { Port::new() }; MAX_MOCK_PORTS],
+                num_ports: if num_ports > MAX_MOCK_PORTS {
+                    MAX_MOCK_PORTS
+                } else {
+                    num_ports
+                },
+                controller_status: ControllerStatus {
+                    mode: "Mock",
+                    valid_fw_bank: true,
+                    fw_version0: 0,
+                    fw_version1: 0,
+                },
+                fw_version: 0,
+                fw_update: FwUpdate {
+                    in_progress: false,
+                    bytes_written: 0,
+                    verified: false,
+                    completed: 0,
+                    rollbacks: 0,
+                },
+                faults: Vec::new(),
+                calls: Deque::new(),
+            })),
+            event: Signal::new(),
+        }
+    }
+
+    /// Number of simulated ports
+    pub fn num_ports(&self) -> usize {
+        self.inner.lock(|inner| inner.borrow().num_ports)
+    }
+
+    /// Access the state of the given port, returns [`None`] if the port is invalid
+    pub fn with_port<R>(&self, port: LocalPortId, f: impl FnOnce(&mut Port) -> R) -> Option<R> {
+        self.inner.lock(|inner| {
+            let mut inner = inner.borrow_mut();
+            let num_ports = inner.num_ports;
+            inner.ports.get_mut(..num_ports)?.get_mut(port.0 as usize).map(f)
+        })
+    }
+
+    /// Modify the given port and its pending events, then notify the controller
+    fn pend(&self, port: LocalPortId, f: impl FnOnce(&mut Port)) {
+        if self.with_port(port, f).is_some() {
+            self.event.signal(());
+        }
+    }
+
+    /// Simulate a port partner attaching with an explicit contract
+    ///
+    /// `role` is the power role of this port, a [`PowerRole::Sink`] port consumes `capability`
+    /// from the partner while a [`PowerRole::Source`] port provides it.
+    pub fn attach(&self, port: LocalPortId, role: PowerRole, capability: PowerCapability, unconstrained: bool) {
+        self.attach_with_state(port, ConnectionState::Attached, role, capability, unconstrained);
+    }
+
+    /// Simulate a debug accessory attaching
+    pub fn attach_debug_accessory(&self, port: LocalPortId, role: PowerRole, capability: PowerCapability) {
+        self.attach_with_state(port, ConnectionState::DebugAccessory, role, capability, false);
+    }
+
+    fn attach_with_state(
+        &self,
+        port: LocalPortId,
+        connection_state: ConnectionState,
+        role: PowerRole,
+        capability: PowerCapability,
+        unconstrained: bool,
+    ) {
+        self.pend(port, |port| {
+            let mut status = PortStatus::new();
+            status.connection_state = Some(connection_state);
+            status.power_role = role;
+            status.unconstrained_power = unconstrained;
+            port.status = status;
+            port.events.status.set_plug_inserted_or_removed(true);
+            Self::set_contract(port, role, capability);
+        });
+    }
+
+    /// Update the contract on the given port and pend the corresponding events
+    fn set_contract(port: &mut Port, role: PowerRole, capability: PowerCapability) {
+        port.status.power_role = role;
+        match role {
+            PowerRole::Sink => {
+                port.status.available_sink_contract = Some(capability);
+                port.status.available_source_contract = None;
+                port.events.status.set_new_power_contract_as_consumer(true);
+                port.events.status.set_sink_ready(true);
+            }
+            PowerRole::Source => {
+                port.status.available_source_contract = Some(capability);
+                port.status.available_sink_contract = None;
+                port.events.status.set_new_power_contract_as_provider(true);
+            }
+        }
+    }
+
+    /// Simulate a port partner detaching
+    pub fn detach(&self, port: LocalPortId) {
+        self.pend(port, |port| {
+            port.status = PortStatus::new();
+            port.sink_path_enabled = false;
+            port.svids = DiscoveredSvids::default();
+            port.sop_identity = None;
+            port.sop_prime_identity = None;
+            port.events.status.set_plug_inserted_or_removed(true);
+        });
+    }
+
+    /// Simulate a new contract on an attached port, e.g. after a renegotiation or power role swap
+    pub fn change_contract(&self, port: LocalPortId, role: PowerRole, capability: PowerCapability) {
+        self.pend(port, |port| {
+            if port.status.power_role != role {
+                port.events.status.set_power_swap_completed(true);
+            }
+            Self::set_contract(port, role, capability);
+        });
+    }
+
+    /// Simulate a PD hard reset, the existing contract is lost
+    pub fn hard_reset_received(&self, port: LocalPortId) {
+        self.pend(port, |port| {
+            port.status.available_sink_contract = None;
+            port.status.available_source_contract = None;
+            port.sink_path_enabled = false;
+            port.events.status.set_pd_hard_reset(true);
+        });
+    }
+
+    /// Simulate a PD alert from the port partner
+    pub fn send_pd_alert(&self, port: LocalPortId, ado: Ado) {
+        self.pend(port, |port| {
+            if port.pd_alerts.is_full() {
+                // Drop the oldest alert, matching the behavior of a hardware FIFO
+                let _ = port.pd_alerts.pop_front();
+            }
+            // Can't fail, we just made space
+            let _ = port.pd_alerts.push_back(ado);
+            port.events.notification.set_alert(true);
+        });
+    }
+
+    /// Simulate a VDM event, `vdm` is returned as the other VDM for all kinds except attention
+    pub fn receive_vdm(&self, port: LocalPortId, kind: VdmNotification, vdm: OtherVdm) {
+        self.pend(port, |port| {
+            port.other_vdm = vdm;
+            match kind {
+                VdmNotification::Entered => port.events.notification.set_custom_mode_entered(true),
+                VdmNotification::Exited => port.events.notification.set_custom_mode_exited(true),
+                VdmNotification::OtherReceived => port.events.notification.set_custom_mode_other_vdm_received(true),
+                VdmNotification::AttentionReceived => port.events.notification.set_custom_mode_attention_received(true),
+            }
+        });
+    }
+
+    /// Simulate an attention VDM from the port partner
+    pub fn receive_attention(&self, port: LocalPortId, vdm: AttnVdm) {
+        self.pend(port, |port| {
+            port.attn_vdm = vdm;
+            port.events.notification.set_custom_mode_attention_received(true);
+        });
+    }
+
+    /// Simulate discovery completing with the given SVIDs and identity responses
+    pub fn complete_discovery(
+        &self,
+        port: LocalPortId,
+        svids: DiscoveredSvids,
+        sop_identity: Option<sop::ResponseVdos>,
+        sop_prime_identity: Option<sop_prime::ResponseVdos>,
+    ) {
+        self.pend(port, |port| {
+            port.svids = svids;
+            port.sop_identity = sop_identity;
+            port.sop_prime_identity = sop_prime_identity;
+            port.events.notification.set_discover_mode_completed(true);
+        });
+    }
+
+    /// Simulate a DisplayPort status update
+    pub fn update_dp_status(&self, port: LocalPortId, status: DpStatus) {
+        self.pend(port, |port| {
+            port.dp_status = status;
+            port.events.notification.set_dp_status_update(true);
+        });
+    }
+
+    /// Queue a reply for the next UCSI command executed on the given port
+    ///
+    /// Returns false if the reply queue is full.
+    pub fn queue_ucsi_reply(&self, port: LocalPortId, reply: Result<Option<lpm::ResponseData>, PdError>) -> bool {
+        self.with_port(port, |port| port.ucsi_replies.push_back(reply).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Set the status returned by [`Operation::GetControllerStatus`]
+    pub fn set_controller_status(&self, status: ControllerStatus<'static>) {
+        self.inner.lock(|inner| inner.borrow_mut().controller_status = status);
+    }
+
+    /// Set the active firmware version
+    pub fn set_fw_version(&self, version: u32) {
+        self.inner.lock(|inner| inner.borrow_mut().fw_version = version);
+    }
+
+    /// Get the current firmware update state
+    pub fn fw_update(&self) -> FwUpdate {
+        self.inner.lock(|inner| inner.borrow().fw_update)
+    }
+
+    /// Inject a fault, returns false if too many faults are active
+    pub fn inject_fault(&self, fault: Fault) -> bool {
+        self.inner.lock(|inner| inner.borrow_mut().faults.push(fault).is_ok())
+    }
+
+    /// Remove all injected faults
+    pub fn clear_faults(&self) {
+        self.inner.lock(|inner| inner.borrow_mut().faults.clear());
+    }
+
+    /// Take all recorded calls, oldest first
+    pub fn take_calls(&self) -> Deque<Call, MAX_RECORDED_CALLS> {
+        self.inner.lock(|inner| core::mem::take(&mut inner.borrow_mut().calls))
+    }
+
+    /// Returns the number of recorded calls to the given operation
+    pub fn call_count(&self, operation: Operation) -> usize {
+        self.inner.lock(|inner| {
+            inner
+                .borrow()
+                .calls
+                .iter()
+                .filter(|call| call.operation == operation)
+                .count()
+        })
+    }
+
+    /// Record a call and check for injected faults
+    fn record(&self, operation: Operation, port: Option<LocalPortId>) -> Result<(), FaultKind> {
+        self.inner.lock(|inner| {
+            let mut inner = inner.borrow_mut();
+            let call = Call { operation, port };
+            if inner.calls.is_full() {
+                let _ = inner.calls.pop_front();
+            }
+            // Can't fail, we just made space
+            let _ = inner.calls.push_back(call);
+
+            if let Some(port) = port
+                && port.0 as usize >= inner.num_ports
+            {
+                return Err(FaultKind::Pd(PdError::InvalidPort));
+            }
+
+            let index = inner.faults.iter().position(|fault| fault.matches(&call));
+            if let Some(index) = index {
+                let fault = inner.faults.get_mut(index).ok_or(FaultKind::Pd(PdError::Failed))?;
+                let kind = fault.kind;
+                fault.count = fault.count.saturating_sub(1);
+                if fault.count == 0 {
+                    inner.faults.remove(index);
+                }
+                Err(kind)
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Run `f` on the controller-wide state
+    fn with_inner<R>(&self, f: impl FnOnce(&mut Inner) -> R) -> R {
+        self.inner.lock(|inner| f(&mut inner.borrow_mut()))
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use embedded_services::type_c::controller::Controller as _;
+    use embedded_usb_pd::Error;
+
+    use super::*;
+
+    const CAPABILITY: PowerCapability = PowerCapability {
+        voltage_mv: 20000,
+        current_ma: 3000,
+    };
+
+    /// Test attach and detach event generation
+    #[tokio::test]
+    async fn test_attach_detach() {
+        let state = State::new();
+        let mut controller = Controller::new(&state);
+
+        state.attach(LocalPortId(0), PowerRole::Sink, CAPABILITY, false);
+        controller.wait_port_event().await.unwrap();
+
+        let event = controller.clear_port_events(LocalPortId(0)).await.unwrap();
+        assert!(event.status.plug_inserted_or_removed());
+        assert!(event.status.new_power_contract_as_consumer());
+        assert!(event.status.sink_ready());
+        assert_eq!(
+            controller.clear_port_events(LocalPortId(1)).await.unwrap(),
+            PortEvent::none()
+        );
+
+        let status = controller.get_port_status(LocalPortId(0)).await.unwrap();
+        assert!(status.is_connected());
+        assert_eq!(status.available_sink_contract, Some(CAPABILITY));
+
+        state.detach(LocalPortId(0));
+        controller.wait_port_event().await.unwrap();
+        let event = controller.clear_port_events(LocalPortId(0)).await.unwrap();
+        assert!(event.status.plug_inserted_or_removed());
+        assert!(!controller.get_port_status(LocalPortId(0)).await.unwrap().is_connected());
+    }
+
+    /// Test contract changes and power role swaps
+    #[tokio::test]
+    async fn test_change_contract() {
+        let state = State::new();
+        let mut controller = Controller::new(&state);
+
+        state.attach(LocalPortId(1), PowerRole::Sink, CAPABILITY, false);
+        let _ = controller.clear_port_events(LocalPortId(1)).await.unwrap();
+
+        state.change_contract(LocalPortId(1), PowerRole::Source, CAPABILITY);
+        let event = controller.clear_port_events(LocalPortId(1)).await.unwrap();
+        assert!(event.status.power_swap_completed());
+        assert!(event.status.new_power_contract_as_provider());
+
+        let status = controller.get_port_status(LocalPortId(1)).await.unwrap();
+        assert_eq!(status.available_source_contract, Some(CAPABILITY));
+        assert_eq!(status.available_sink_contract, None);
+    }
+
+    /// Test queued UCSI replies
+    #[tokio::test]
+    async fn test_ucsi_replies() {
+        let state = State::new();
+        let mut controller = Controller::new(&state);
+        let command = lpm::Command::new(LocalPortId(0), lpm::CommandData::GetConnectorStatus);
+
+        assert!(state.queue_ucsi_reply(LocalPortId(0), Err(PdError::Busy)));
+        assert_eq!(
+            controller.execute_ucsi_command(command).await,
+            Err(Error::Pd(PdError::Busy))
+        );
+
+        // Falls back to the default reply
+        assert!(matches!(
+            controller.execute_ucsi_command(command).await,
+            Ok(Some(lpm::ResponseData::GetConnectorStatus(_)))
+        ));
+    }
+
+    /// Test fault injection
+    #[tokio::test]
+    async fn test_fault_injection() {
+        let state = State::new();
+        let mut controller = Controller::new(&state);
+
+        assert!(state.inject_fault(Fault {
+            count: 2,
+            ..Fault::bus_error(Operation::GetPortStatus)
+        }));
+        assert_eq!(
+            controller.get_port_status(LocalPortId(0)).await.err(),
+            Some(Error::Bus(MockBusError))
+        );
+        assert_eq!(
+            controller.get_port_status(LocalPortId(1)).await.err(),
+            Some(Error::Bus(MockBusError))
+        );
+        assert!(controller.get_port_status(LocalPortId(0)).await.is_ok());
+
+        // Other operations are unaffected
+        assert!(state.inject_fault(Fault::bus_error(Operation::HardReset)));
+        assert!(controller.reset_controller().await.is_ok());
+        assert!(controller.hard_reset(LocalPortId(0)).await.is_err());
+        assert!(controller.hard_reset(LocalPortId(0)).await.is_ok());
+    }
+
+    /// Test call recording
+    #[tokio::test]
+    async fn test_call_recording() {
+        let state = State::with_num_ports(1);
+        let mut controller = Controller::new(&state);
+
+        controller
+            .set_tbt_config(LocalPortId(0), TbtConfig { tbt_enabled: true })
+            .await
+            .unwrap();
+        assert_eq!(
+            controller.hard_reset(LocalPortId(1)).await,
+            Err(Error::Pd(PdError::InvalidPort))
+        );
+
+        assert_eq!(state.call_count(Operation::SetTbtConfig), 1);
+        let calls = state.take_calls();
+        assert_eq!(
+            calls.iter().copied().collect::<Vec<_, 2>>().as_slice(),
+            &[
+                Call {
+                    operation: Operation::SetTbtConfig,
+                    port: Some(LocalPortId(0)),
+                },
+                Call {
+                    operation: Operation::HardReset,
+                    port: Some(LocalPortId(1)),
+                },
+            ]
+        );
+        assert!(state.take_calls().is_empty());
+        assert!(
+            state
+                .with_port(LocalPortId(0), |port| port.tbt_config.tbt_enabled)
+                .unwrap()
+        );
+    }
+}
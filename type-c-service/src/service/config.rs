@@ -1,3 +1,4 @@
+use embedded_services::type_c::controller::DpPinConfig;
 use embedded_usb_pd::ucsi::{self, lpm::get_connector_status::BatteryChargingCapabilityStatus};
 
 /// UCSI battery charging capability status configuration.
@@ -98,6 +99,71 @@ impl UcsiBatteryChargingThresholdConfig {
     }
 }
 
+/// Host Thunderbolt security level, as configured by the host firmware.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HostSecurityLevel {
+    /// SL0, no security, all tunneling modes are permitted
+    NoSecurity,
+    /// SL1, tunneling is permitted, devices are authorized by the user on the host
+    #[default]
+    UserAuthorization,
+    /// SL2, tunneling is permitted, devices are authorized through a host challenge
+    SecureConnect,
+    /// SL3, PCIe tunneling is not permitted, only DisplayPort and USB are allowed
+    DisplayPortOnly,
+    /// SL4, only USB data is permitted
+    UsbOnly,
+}
+
+impl HostSecurityLevel {
+    /// Returns true if this security level permits Thunderbolt 3 or USB4 tunneling
+    pub const fn allows_tunneling(self) -> bool {
+        matches!(self, Self::NoSecurity | Self::UserAuthorization | Self::SecureConnect)
+    }
+
+    /// Returns true if this security level permits DisplayPort alt-mode
+    pub const fn allows_displayport(self) -> bool {
+        !matches!(self, Self::UsbOnly)
+    }
+}
+
+/// Data mode entry policy configuration.
+///
+/// Describes which data modes the system supports, the policy will never attempt to enter a mode that isn't enabled here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataModeConfig {
+    /// Enable automatic data mode entry, if false the policy doesn't configure any port
+    pub enabled: bool,
+    /// System supports USB4
+    pub usb4_supported: bool,
+    /// System supports Thunderbolt 3 alt-mode
+    pub tbt3_supported: bool,
+    /// System supports DisplayPort alt-mode
+    pub dp_supported: bool,
+    /// DisplayPort pin assignments used when entering DisplayPort alt-mode
+    pub dp_pin_config: DpPinConfig,
+    /// Initial host security level
+    pub security_level: HostSecurityLevel,
+}
+
+impl Default for DataModeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            usb4_supported: false,
+            tbt3_supported: false,
+            dp_supported: false,
+            dp_pin_config: DpPinConfig {
+                pin_c: true,
+                pin_d: true,
+                pin_e: false,
+            },
+            security_level: HostSecurityLevel::default(),
+        }
+    }
+}
+
 /// Type-c service configuration
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Config {
@@ -107,6 +173,8 @@ pub struct Config {
     pub ucsi_port_capabilities: Option<ucsi::lpm::get_connector_capability::ResponseData>,
     /// UCSI battery charging configuration
     pub ucsi_battery_charging_config: UcsiBatteryChargingThresholdConfig,
+    /// Thunderbolt/USB4 data mode entry policy configuration
+    pub data_mode: DataModeConfig,
 }
 
 #[cfg(test)]
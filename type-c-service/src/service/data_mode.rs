@@ -0,0 +1,366 @@
+//! Thunderbolt/USB4 data mode entry policy.
+//!
+//! After discovery completes on a port the partner and cable capabilities are evaluated against the system
+//! configuration and the host security level. The most capable permitted mode is entered first, if entry fails the
+//! policy falls back through the remaining candidates, ending with USB 3.x and finally USB 2.0.
+use embedded_services::type_c::controller::{DataMode, DiscoveredSvids, DpConfig, TbtConfig, UsbControlConfig};
+use embedded_services::warn;
+use embedded_usb_pd::vdm::structured::Svid;
+use heapless::Vec;
+
+use super::config::{DataModeConfig, HostSecurityLevel};
+use super::*;
+
+/// Intel SVID, used by Thunderbolt 3 and USB4 devices
+pub const SVID_TBT: Svid = Svid(0x8087);
+/// VESA SVID, used by DisplayPort alt-mode
+pub const SVID_DISPLAYPORT: Svid = Svid(0xFF01);
+
+/// Maximum number of candidate modes, one for each [`DataMode`] variant other than [`DataMode::None`]
+const MAX_CANDIDATE_MODES: usize = 5;
+
+/// Data mode candidates, ordered from most to least preferred
+pub type Candidates = Vec<DataMode, MAX_CANDIDATE_MODES>;
+
+/// Port partner capabilities relevant to data mode selection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PartnerCapabilities {
+    /// Partner advertises the Thunderbolt SVID, this is required for both Thunderbolt 3 and USB4 entry
+    pub tbt: bool,
+    /// Partner advertises the DisplayPort SVID
+    pub displayport: bool,
+}
+
+impl PartnerCapabilities {
+    /// Determine partner capabilities from the SVIDs discovered on SOP
+    pub fn from_svids(svids: &DiscoveredSvids) -> Self {
+        Self {
+            tbt: svids.svid_sop().any(|svid| svid == SVID_TBT),
+            displayport: svids.svid_sop().any(|svid| svid == SVID_DISPLAYPORT),
+        }
+    }
+}
+
+/// Cable capabilities relevant to data mode selection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CableCapabilities {
+    /// Cable responded to Discover Identity on SOP'
+    pub emarked: bool,
+    /// Cable advertises the Thunderbolt SVID on SOP', USB4 Gen3 cables are required to advertise it as well
+    pub tbt: bool,
+}
+
+impl CableCapabilities {
+    /// Determine cable capabilities from the SVIDs discovered on SOP' and whether the cable responded to
+    /// Discover Identity on SOP'
+    pub fn from_svids(svids: &DiscoveredSvids, emarked: bool) -> Self {
+        Self {
+            emarked,
+            tbt: svids.svid_sop_prime().any(|svid| svid == SVID_TBT),
+        }
+    }
+
+    /// Returns true if the cable can carry USB4 or Thunderbolt 3
+    ///
+    /// Tunneling requires an e-marked cable that advertises the Thunderbolt SVID, other cables are limited to
+    /// USB 3.x speeds.
+    pub fn supports_tunneling(&self) -> bool {
+        self.emarked && self.tbt
+    }
+}
+
+/// Determine the data modes to attempt on a port, ordered from most to least preferred.
+///
+/// USB 3.x and USB 2.0 are always included as the final fallbacks.
+pub fn candidate_modes(
+    config: &DataModeConfig,
+    security_level: HostSecurityLevel,
+    partner: PartnerCapabilities,
+    cable: CableCapabilities,
+) -> Candidates {
+    let mut candidates = Candidates::new();
+    let tunneling = security_level.allows_tunneling() && partner.tbt && cable.supports_tunneling();
+
+    // Push can't fail, each mode is pushed at most once
+    if tunneling && config.usb4_supported {
+        let _ = candidates.push(DataMode::Usb4);
+    }
+
+    if tunneling && config.tbt3_supported {
+        let _ = candidates.push(DataMode::Tbt3);
+    }
+
+    if security_level.allows_displayport() && config.dp_supported && partner.displayport {
+        let _ = candidates.push(DataMode::DisplayPort);
+    }
+
+    let _ = candidates.push(DataMode::Usb3);
+    let _ = candidates.push(DataMode::Usb2);
+    candidates
+}
+
+/// Per-port data mode state
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct State {
+    /// Current data mode for each port
+    modes: [DataMode; MAX_SUPPORTED_PORTS],
+    /// Current host security level
+    security_level: HostSecurityLevel,
+}
+
+impl State {
+    /// Create a new state with the given initial security level
+    pub(super) fn new(security_level: HostSecurityLevel) -> Self {
+        Self {
+            modes: [DataMode::None; MAX_SUPPORTED_PORTS],
+            security_level,
+        }
+    }
+}
+
+impl Service<'_> {
+    /// Get the data mode currently active on the given port
+    pub async fn get_data_mode(&self, port_id: GlobalPortId) -> Result<DataMode, Error> {
+        let state = self.state.lock().await;
+        Ok(*state
+            .data_mode
+            .modes
+            .get(port_id.0 as usize)
+            .ok_or(Error::InvalidPort)?)
+    }
+
+    /// Set the cached data mode for the given port
+    async fn set_data_mode(&self, port_id: GlobalPortId, mode: DataMode) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        *state
+            .data_mode
+            .modes
+            .get_mut(port_id.0 as usize)
+            .ok_or(Error::InvalidPort)? = mode;
+        Ok(())
+    }
+
+    /// Get the current host security level
+    pub async fn get_host_security_level(&self) -> HostSecurityLevel {
+        self.state.lock().await.data_mode.security_level
+    }
+
+    /// Update the host security level
+    ///
+    /// This only affects future data mode entry, modes that are already active are left as is.
+    pub async fn set_host_security_level(&self, security_level: HostSecurityLevel) {
+        info!("Host security level: {:?}", security_level);
+        self.state.lock().await.data_mode.security_level = security_level;
+    }
+
+    /// Configure the controller for the given data mode
+    async fn enter_data_mode(&self, port_id: GlobalPortId, mode: DataMode) -> Result<(), Error> {
+        let usb_control = UsbControlConfig {
+            usb2_enabled: mode != DataMode::None,
+            usb3_enabled: mode >= DataMode::Usb3,
+            usb4_enabled: mode == DataMode::Usb4,
+        };
+
+        self.context.set_usb_control(port_id, usb_control).await?;
+        self.context
+            .set_tbt_config(
+                port_id,
+                TbtConfig {
+                    tbt_enabled: mode == DataMode::Tbt3,
+                },
+            )
+            .await?;
+
+        if self.config.data_mode.dp_supported {
+            self.context
+                .set_dp_config(
+                    port_id,
+                    DpConfig {
+                        enable: mode == DataMode::DisplayPort,
+                        dfp_d_pin_cfg: self.config.data_mode.dp_pin_config,
+                    },
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate the port partner and cable and enter the most capable permitted data mode
+    pub(super) async fn process_data_mode_entry(&self, port_id: GlobalPortId) -> Result<DataMode, Error> {
+        if !self.config.data_mode.enabled {
+            return Ok(DataMode::None);
+        }
+
+        let svids = self.context.get_discovered_svids(port_id).await?;
+        let emarked = self
+            .context
+            .get_discover_identity_sop_prime_response(port_id)
+            .await
+            .is_ok();
+        let partner = PartnerCapabilities::from_svids(&svids);
+        let cable = CableCapabilities::from_svids(&svids, emarked);
+        let security_level = self.get_host_security_level().await;
+        debug!(
+            "Port{}: Data mode partner: {:?}, cable: {:?}, security level: {:?}",
+            port_id.0, partner, cable, security_level
+        );
+
+        for mode in candidate_modes(&self.config.data_mode, security_level, partner, cable) {
+            match self.enter_data_mode(port_id, mode).await {
+                Ok(()) => {
+                    info!("Port{}: Entered data mode {:?}", port_id.0, mode);
+                    self.set_data_mode(port_id, mode).await?;
+                    return Ok(mode);
+                }
+                Err(e) => {
+                    warn!("Port{}: Failed to enter data mode {:?}: {:?}", port_id.0, mode, e);
+                }
+            }
+        }
+
+        error!("Port{}: Failed to enter any data mode", port_id.0);
+        self.set_data_mode(port_id, DataMode::None).await?;
+        Err(Error::Failed)
+    }
+
+    /// Reset data mode state on detach
+    pub(super) async fn process_data_mode_detach(&self, port_id: GlobalPortId) -> Result<(), Error> {
+        self.set_data_mode(port_id, DataMode::None).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn config_all() -> DataModeConfig {
+        DataModeConfig {
+            enabled: true,
+            usb4_supported: true,
+            tbt3_supported: true,
+            dp_supported: true,
+            ..Default::default()
+        }
+    }
+
+    const PARTNER_ALL: PartnerCapabilities = PartnerCapabilities {
+        tbt: true,
+        displayport: true,
+    };
+
+    const CABLE_TBT: CableCapabilities = CableCapabilities {
+        emarked: true,
+        tbt: true,
+    };
+
+    /// Test that the full fallback chain is produced when everything is supported
+    #[test]
+    fn test_all_supported() {
+        let candidates = candidate_modes(&config_all(), HostSecurityLevel::NoSecurity, PARTNER_ALL, CABLE_TBT);
+        assert_eq!(
+            candidates.as_slice(),
+            &[
+                DataMode::Usb4,
+                DataMode::Tbt3,
+                DataMode::DisplayPort,
+                DataMode::Usb3,
+                DataMode::Usb2
+            ]
+        );
+    }
+
+    /// Test that a non-marked cable prevents tunneling
+    #[test]
+    fn test_passive_cable() {
+        let candidates = candidate_modes(
+            &config_all(),
+            HostSecurityLevel::UserAuthorization,
+            PARTNER_ALL,
+            CableCapabilities::default(),
+        );
+        assert_eq!(
+            candidates.as_slice(),
+            &[DataMode::DisplayPort, DataMode::Usb3, DataMode::Usb2]
+        );
+    }
+
+    /// Test that an e-marked cable without the Thunderbolt SVID prevents tunneling
+    #[test]
+    fn test_emarked_cable_no_tunneling() {
+        let cable = CableCapabilities {
+            emarked: true,
+            tbt: false,
+        };
+        assert!(!cable.supports_tunneling());
+
+        let candidates = candidate_modes(&config_all(), HostSecurityLevel::NoSecurity, PARTNER_ALL, cable);
+        assert_eq!(
+            candidates.as_slice(),
+            &[DataMode::DisplayPort, DataMode::Usb3, DataMode::Usb2]
+        );
+    }
+
+    /// Test that the host security level restricts candidates
+    #[test]
+    fn test_security_level() {
+        let candidates = candidate_modes(
+            &config_all(),
+            HostSecurityLevel::DisplayPortOnly,
+            PARTNER_ALL,
+            CABLE_TBT,
+        );
+        assert_eq!(
+            candidates.as_slice(),
+            &[DataMode::DisplayPort, DataMode::Usb3, DataMode::Usb2]
+        );
+
+        let candidates = candidate_modes(&config_all(), HostSecurityLevel::UsbOnly, PARTNER_ALL, CABLE_TBT);
+        assert_eq!(candidates.as_slice(), &[DataMode::Usb3, DataMode::Usb2]);
+    }
+
+    /// Test that system configuration restricts candidates
+    #[test]
+    fn test_system_config() {
+        let config = DataModeConfig {
+            usb4_supported: false,
+            dp_supported: false,
+            ..config_all()
+        };
+        let candidates = candidate_modes(&config, HostSecurityLevel::SecureConnect, PARTNER_ALL, CABLE_TBT);
+        assert_eq!(candidates.as_slice(), &[DataMode::Tbt3, DataMode::Usb3, DataMode::Usb2]);
+    }
+
+    /// Test a partner without any alt-mode SVIDs
+    #[test]
+    fn test_usb_partner() {
+        let candidates = candidate_modes(
+            &config_all(),
+            HostSecurityLevel::NoSecurity,
+            PartnerCapabilities::default(),
+            CABLE_TBT,
+        );
+        assert_eq!(candidates.as_slice(), &[DataMode::Usb3, DataMode::Usb2]);
+    }
+
+    /// Test capability detection from discovered SVIDs
+    #[test]
+    fn test_from_svids() {
+        let mut sop = heapless::Vec::new();
+        sop.push(SVID_DISPLAYPORT).unwrap();
+        sop.push(SVID_TBT).unwrap();
+        let mut sop_prime = heapless::Vec::new();
+        sop_prime.push(SVID_TBT).unwrap();
+        let svids = DiscoveredSvids::new(sop, sop_prime);
+
+        assert_eq!(PartnerCapabilities::from_svids(&svids), PARTNER_ALL);
+        assert_eq!(CableCapabilities::from_svids(&svids, true), CABLE_TBT);
+        assert_eq!(
+            CableCapabilities::from_svids(&DiscoveredSvids::default(), false),
+            CableCapabilities::default()
+        );
+    }
+}
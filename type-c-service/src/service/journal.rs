@@ -0,0 +1,101 @@
+//! Per-port PD event journal
+use embedded_services::type_c::event::PortStatusChanged;
+use embedded_services::type_c::journal::{Entry, Event, Journal};
+use embedded_usb_pd::PowerRole;
+
+use super::*;
+
+/// Per-port journal state
+#[derive(Default)]
+pub(super) struct State {
+    journals: [Journal; MAX_SUPPORTED_PORTS],
+}
+
+impl Service<'_> {
+    /// Record an event in the journal of the given port
+    pub(super) async fn record_journal_event(&self, port_id: GlobalPortId, event: Event) {
+        let mut state = self.state.lock().await;
+        if let Some(journal) = state.journal.journals.get_mut(port_id.0 as usize) {
+            trace!("Port{}: Journal event: {:?}", port_id.0, event);
+            journal.record(event);
+        }
+    }
+
+    /// Record the journal events that correspond to a port status change
+    pub(super) async fn record_journal_status_change(
+        &self,
+        port_id: GlobalPortId,
+        event: PortStatusChanged,
+        old_status: &PortStatus,
+        status: &PortStatus,
+    ) {
+        if status.is_connected() != old_status.is_connected() {
+            self.record_journal_event(
+                port_id,
+                if status.is_connected() {
+                    Event::Attach {
+                        debug_accessory: status.is_debug_accessory(),
+                    }
+                } else {
+                    Event::Detach
+                },
+            )
+            .await;
+        }
+
+        if event.pd_hard_reset() {
+            self.record_journal_event(port_id, Event::HardReset).await;
+        }
+
+        if event.new_power_contract_as_consumer() || event.new_power_contract_as_provider() {
+            let (role, capability) = if event.new_power_contract_as_consumer() {
+                (PowerRole::Sink, status.available_sink_contract)
+            } else {
+                (PowerRole::Source, status.available_source_contract)
+            };
+
+            if let Some(capability) = capability {
+                self.record_journal_event(
+                    port_id,
+                    Event::Contract {
+                        role,
+                        capability,
+                        unconstrained: status.unconstrained_power,
+                        epr: status.epr,
+                        dual_power: status.dual_power,
+                        pdo: status.active_pdo,
+                        rdo: status.active_rdo,
+                    },
+                )
+                .await;
+            }
+        }
+
+        if event.alt_mode_entered() {
+            self.record_journal_event(port_id, Event::AltModeEntered).await;
+        }
+    }
+
+    /// Get the oldest journal entry with a sequence number at or after `sequence`
+    pub async fn get_journal_entry(&self, port_id: GlobalPortId, sequence: u32) -> Result<Option<Entry>, Error> {
+        let state = self.state.lock().await;
+        Ok(state
+            .journal
+            .journals
+            .get(port_id.0 as usize)
+            .ok_or(Error::InvalidPort)?
+            .get(sequence))
+    }
+
+    /// Clear the journal of the given port
+    pub async fn clear_journal(&self, port_id: GlobalPortId) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        state
+            .journal
+            .journals
+            .get_mut(port_id.0 as usize)
+            .ok_or(Error::InvalidPort)?
+            .clear();
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
-use embassy_futures::select::{Either3, select3};
+use embassy_futures::select::{Either4, select4};
 use embassy_sync::{
     mutex::Mutex,
     pubsub::{DynImmediatePublisher, DynSubscriber},
 };
+use embedded_services::power::system as system_power;
 use embedded_services::{
     GlobalRawMutex, debug, error, info, intrusive_list,
     ipc::deferred,
@@ -22,9 +23,12 @@ use crate::{PortEventStreamer, PortEventVariant};
 
 pub mod config;
 mod controller;
+pub mod data_mode;
+mod journal;
 pub mod pd;
 mod port;
 mod power;
+mod system_power_state;
 mod ucsi;
 pub mod vdm;
 
@@ -43,6 +47,10 @@ struct State {
     port_event_streaming_state: Option<PortEventStreamer>,
     /// UCSI state
     ucsi: ucsi::State,
+    /// Data mode entry policy state
+    data_mode: data_mode::State,
+    /// Per-port event journal
+    journal: journal::State,
 }
 
 /// Type-C service
@@ -63,6 +71,8 @@ pub struct Service<'a> {
     /// This is the corresponding subscriber to [`Self::power_policy_event_publisher`], needs to be a mutex because getting a message
     /// from the channel requires mutable access.
     power_policy_event_subscriber: Mutex<GlobalRawMutex, DynSubscriber<'a, power_policy::CommsMessage>>,
+    /// System power state transition hook
+    system_power_hook: system_power::Hook,
 }
 
 /// Power policy events
@@ -76,6 +86,8 @@ pub enum PowerPolicyEvent {
     ConsumerDisconnected,
     /// Consumer connected
     ConsumerConnected,
+    /// External power is stable after boot
+    PowerStable,
 }
 
 /// Type-C service events
@@ -88,6 +100,8 @@ pub enum Event<'a> {
     ExternalCommand(deferred::Request<'a, GlobalRawMutex, external::Command, external::Response<'static>>),
     /// Power policy event
     PowerPolicy(PowerPolicyEvent),
+    /// System power state event
+    SystemPower(system_power::Event),
 }
 
 impl<'a> Service<'a> {
@@ -99,10 +113,14 @@ impl<'a> Service<'a> {
     ) -> Option<Self> {
         Some(Self {
             context: type_c::controller::ContextToken::create()?,
-            state: Mutex::new(State::default()),
+            state: Mutex::new(State {
+                data_mode: data_mode::State::new(config.data_mode.security_level),
+                ..Default::default()
+            }),
             config,
             power_policy_event_publisher: power_policy_publisher.into(),
             power_policy_event_subscriber: Mutex::new(power_policy_subscriber),
+            system_power_hook: system_power::Hook::new("type-c"),
         })
     }
 
@@ -135,6 +153,9 @@ impl<'a> Service<'a> {
         debug!("Port{} Previous status: {:#?}", port_id.0, old_status);
         debug!("Port{} Status: {:#?}", port_id.0, status);
 
+        self.record_journal_status_change(port_id, event, &old_status, &status)
+            .await;
+
         let connection_changed = status.is_connected() != old_status.is_connected();
         if connection_changed && (status.is_debug_accessory() || old_status.is_debug_accessory()) {
             // Notify that a debug connection has connected/disconnected
@@ -152,6 +173,10 @@ impl<'a> Service<'a> {
                 .await;
         }
 
+        if connection_changed && !status.is_connected() {
+            self.process_data_mode_detach(port_id).await?;
+        }
+
         self.set_cached_port_status(port_id, status).await?;
         self.handle_ucsi_port_event(port_id, event, &status).await;
 
@@ -170,14 +195,15 @@ impl<'a> Service<'a> {
     /// Wait for the next event
     pub async fn wait_next(&self) -> Result<Event<'_>, Error> {
         loop {
-            match select3(
+            match select4(
                 self.wait_port_flags(),
                 self.context.wait_external_command(),
                 self.wait_power_policy_event(),
+                self.system_power_hook.wait_event(),
             )
             .await
             {
-                Either3::First(mut stream) => {
+                Either4::First(mut stream) => {
                     if let Some((port_id, event)) = stream
                         .next(|port_id| self.context.get_port_event(GlobalPortId(port_id as u8)))
                         .await?
@@ -200,10 +226,11 @@ impl<'a> Service<'a> {
                         self.state.lock().await.port_event_streaming_state = None;
                     }
                 }
-                Either3::Second(request) => {
+                Either4::Second(request) => {
                     return Ok(Event::ExternalCommand(request));
                 }
-                Either3::Third(event) => return Ok(event),
+                Either4::Third(event) => return Ok(event),
+                Either4::Fourth(event) => return Ok(Event::SystemPower(event)),
             }
         }
     }
@@ -213,11 +240,38 @@ impl<'a> Service<'a> {
         match event {
             Event::PortStatusChanged(port, event_kind, status) => {
                 trace!("Port{}: Processing port status changed", port.0);
-                self.process_port_event(port, event_kind, status).await
+                let result = self.process_port_event(port, event_kind, status).await;
+                if let Err(e) = result {
+                    self.record_journal_event(port, type_c::journal::Event::Error(e)).await;
+                }
+                result
+            }
+            Event::PortNotification(port, PortNotificationSingle::DiscoverModeCompleted) => {
+                trace!("Port{}: Discover mode completed", port.0);
+                self.record_journal_event(
+                    port,
+                    type_c::journal::Event::Notification(PortNotificationSingle::DiscoverModeCompleted),
+                )
+                .await;
+                match self.process_data_mode_entry(port).await {
+                    Ok(mode) => {
+                        if mode != type_c::controller::DataMode::None {
+                            self.record_journal_event(port, type_c::journal::Event::DataModeEntered(mode))
+                                .await;
+                        }
+                        Ok(())
+                    }
+                    Err(e) => {
+                        self.record_journal_event(port, type_c::journal::Event::Error(e)).await;
+                        Err(e)
+                    }
+                }
             }
             Event::PortNotification(port, notification) => {
                 // Other port notifications
                 info!("Port{}: Got port notification: {:?}", port.0, notification);
+                self.record_journal_event(port, type_c::journal::Event::Notification(notification))
+                    .await;
                 Ok(())
             }
             Event::ExternalCommand(request) => {
@@ -230,6 +284,10 @@ impl<'a> Service<'a> {
                 trace!("Processing power policy event");
                 self.process_power_policy_event(&event).await
             }
+            Event::SystemPower(event) => {
+                trace!("Processing system power state event");
+                self.process_system_power_event(event).await
+            }
         }
     }
 
@@ -239,8 +297,9 @@ impl<'a> Service<'a> {
         self.process_event(event).await
     }
 
-    /// Register the Type-C service with the power policy service
+    /// Register the Type-C service with the power policy service and the system power state manager
     pub fn register_comms(&'static self) -> Result<(), intrusive_list::Error> {
-        power_policy::policy::register_message_receiver(&self.power_policy_event_publisher)
+        power_policy::policy::register_message_receiver(&self.power_policy_event_publisher)?;
+        system_power::register_hook(&self.system_power_hook)
     }
 }
@@ -3,7 +3,10 @@ use core::num::NonZeroU8;
 use embedded_services::{
     debug, error,
     type_c::{
-        controller::{DpConfig, PdStateMachineConfig, TbtConfig, TypeCStateMachineState, UsbControlConfig},
+        controller::{
+            BatteryCapabilities, BatteryRef, BatteryStatus, DpConfig, PdStateMachineConfig, TbtConfig,
+            TypeCStateMachineState, UsbControlConfig,
+        },
         external,
     },
 };
@@ -83,6 +86,27 @@ impl<'a> Service<'a> {
                 self.process_get_discover_identity_sop_prime_response(command.port)
                     .await
             }
+            external::PortCommandData::GetDataMode => self.process_get_data_mode(command.port).await,
+            external::PortCommandData::GetPartnerBatteryCapabilities(battery) => {
+                self.process_get_partner_battery_capabilities(command.port, battery)
+                    .await
+            }
+            external::PortCommandData::GetPartnerBatteryStatus(battery) => {
+                self.process_get_partner_battery_status(command.port, battery).await
+            }
+            external::PortCommandData::GetPartnerSourceInfo => self.process_get_partner_source_info(command.port).await,
+            external::PortCommandData::GetPartnerStatus => self.process_get_partner_status(command.port).await,
+            external::PortCommandData::SetBatteryCapabilities(battery, capabilities) => {
+                self.process_set_battery_capabilities(command.port, battery, capabilities)
+                    .await
+            }
+            external::PortCommandData::SetBatteryStatus(battery, status) => {
+                self.process_set_battery_status(command.port, battery, status).await
+            }
+            external::PortCommandData::GetJournalEntry(sequence) => {
+                self.process_get_journal_entry(command.port, sequence).await
+            }
+            external::PortCommandData::ClearJournal => self.process_clear_journal(command.port).await,
         }
     }
 
@@ -338,4 +362,115 @@ impl<'a> Service<'a> {
 
         external::Response::Port(status.map(external::PortResponseData::DiscoverIdentitySopPrime))
     }
+
+    /// Process [`external::PortCommandData::GetDataMode`] command
+    async fn process_get_data_mode(&self, port_id: GlobalPortId) -> external::Response<'static> {
+        let status = self.get_data_mode(port_id).await;
+        if let Err(e) = status {
+            error!("Error getting data mode: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(external::PortResponseData::DataMode))
+    }
+
+    /// Process [`external::PortCommandData::GetPartnerBatteryCapabilities`] command
+    async fn process_get_partner_battery_capabilities(
+        &self,
+        port_id: GlobalPortId,
+        battery: BatteryRef,
+    ) -> external::Response<'static> {
+        let status = self.context.get_partner_battery_capabilities(port_id, battery).await;
+        if let Err(e) = status {
+            error!("Error getting partner battery capabilities: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(external::PortResponseData::BatteryCapabilities))
+    }
+
+    /// Process [`external::PortCommandData::GetPartnerBatteryStatus`] command
+    async fn process_get_partner_battery_status(
+        &self,
+        port_id: GlobalPortId,
+        battery: BatteryRef,
+    ) -> external::Response<'static> {
+        let status = self.context.get_partner_battery_status(port_id, battery).await;
+        if let Err(e) = status {
+            error!("Error getting partner battery status: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(external::PortResponseData::BatteryStatus))
+    }
+
+    /// Process [`external::PortCommandData::GetPartnerSourceInfo`] command
+    async fn process_get_partner_source_info(&self, port_id: GlobalPortId) -> external::Response<'static> {
+        let status = self.context.get_partner_source_info(port_id).await;
+        if let Err(e) = status {
+            error!("Error getting partner source info: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(external::PortResponseData::SourceInfo))
+    }
+
+    /// Process [`external::PortCommandData::GetPartnerStatus`] command
+    async fn process_get_partner_status(&self, port_id: GlobalPortId) -> external::Response<'static> {
+        let status = self.context.get_partner_status(port_id).await;
+        if let Err(e) = status {
+            error!("Error getting partner status: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(external::PortResponseData::PdStatus))
+    }
+
+    /// Process [`external::PortCommandData::SetBatteryCapabilities`] command
+    async fn process_set_battery_capabilities(
+        &self,
+        port_id: GlobalPortId,
+        battery: BatteryRef,
+        capabilities: BatteryCapabilities,
+    ) -> external::Response<'static> {
+        let status = self
+            .context
+            .set_battery_capabilities(port_id, battery, capabilities)
+            .await;
+        if let Err(e) = status {
+            error!("Error setting battery capabilities: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(|_| external::PortResponseData::Complete))
+    }
+
+    /// Process [`external::PortCommandData::SetBatteryStatus`] command
+    async fn process_set_battery_status(
+        &self,
+        port_id: GlobalPortId,
+        battery: BatteryRef,
+        status: BatteryStatus,
+    ) -> external::Response<'static> {
+        let status = self.context.set_battery_status(port_id, battery, status).await;
+        if let Err(e) = status {
+            error!("Error setting battery status: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(|_| external::PortResponseData::Complete))
+    }
+
+    /// Process [`external::PortCommandData::GetJournalEntry`] command
+    async fn process_get_journal_entry(&self, port_id: GlobalPortId, sequence: u32) -> external::Response<'static> {
+        let status = self.get_journal_entry(port_id, sequence).await;
+        if let Err(e) = status {
+            error!("Error getting journal entry: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(external::PortResponseData::JournalEntry))
+    }
+
+    /// Process [`external::PortCommandData::ClearJournal`] command
+    async fn process_clear_journal(&self, port_id: GlobalPortId) -> external::Response<'static> {
+        let status = self.clear_journal(port_id).await;
+        if let Err(e) = status {
+            error!("Error clearing journal: {:#?}", e);
+        }
+
+        external::Response::Port(status.map(|_| external::PortResponseData::Complete))
+    }
 }
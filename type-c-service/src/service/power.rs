@@ -22,6 +22,11 @@ impl<'a> Service<'a> {
                     power_policy::CommsData::ConsumerConnected(_, _) => {
                         return Event::PowerPolicy(PowerPolicyEvent::ConsumerConnected);
                     }
+                    power_policy::CommsData::Boot(
+                        power_policy::BootState::Running | power_policy::BootState::AcOnly,
+                    ) => {
+                        return Event::PowerPolicy(PowerPolicyEvent::PowerStable);
+                    }
                     _ => {
                         // No other events currently implemented
                     }
@@ -40,6 +45,21 @@ impl<'a> Service<'a> {
         Ok(())
     }
 
+    /// Clear the dead battery flag on all ports
+    ///
+    /// Every port is attempted even if one fails, the last error is returned.
+    pub(super) async fn clear_dead_battery_flag_all(&self) -> Result<(), Error> {
+        let mut result = Ok(());
+        for port_index in 0..self.context.get_num_ports() {
+            let port = GlobalPortId(port_index as u8);
+            if let Err(e) = self.context.clear_dead_battery_flag(port).await {
+                error!("Port{}: Failed to clear dead battery flag: {:?}", port.0, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
     /// Processed unconstrained state change
     pub(super) async fn process_unconstrained_state_change(
         &self,
@@ -111,6 +131,11 @@ impl<'a> Service<'a> {
                 self.pend_ucsi_connected_ports(&mut state).await;
                 Ok(())
             }
+            PowerPolicyEvent::PowerStable => {
+                // Controllers can run their full state machines now that we won't brown out
+                info!("Power stable, clearing dead battery flags");
+                self.clear_dead_battery_flag_all().await
+            }
         }
     }
 }
@@ -0,0 +1,39 @@
+use embedded_services::power::system as system_power;
+
+use super::*;
+
+impl<'a> Service<'a> {
+    /// Set the system power state on all ports
+    async fn set_power_state_all(&self, state: system_power::SystemPowerState) -> Result<(), Error> {
+        let mut result = Ok(());
+        for port_index in 0..self.context.get_num_ports() {
+            let port = GlobalPortId(port_index as u8);
+            // Keep going so one failing port doesn't leave the others in the wrong state
+            if let Err(e) = self.context.set_power_state(port, state).await {
+                error!("Port{}: Failed to set system power state: {:?}", port.0, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Process system power state events
+    pub(super) async fn process_system_power_event(&self, event: system_power::Event) -> Result<(), Error> {
+        match event {
+            system_power::Event::Prepare(_) => {
+                // Nothing to prepare, controllers are updated once the state is entered
+                self.system_power_hook.respond(system_power::Response::Ready).await;
+                Ok(())
+            }
+            system_power::Event::Cancelled(transition) => {
+                // Nothing was prepared, controllers stay as they are
+                info!("System power transition to {:?} cancelled", transition.to);
+                Ok(())
+            }
+            system_power::Event::Entered(transition) => {
+                info!("Entered system power state {:?}", transition.to);
+                self.set_power_state_all(transition.to).await
+            }
+        }
+    }
+}
@@ -47,6 +47,7 @@ use embassy_sync::{
 use embassy_time::Instant;
 use embedded_cfu_protocol::protocol_definitions::ComponentId;
 use embedded_services::{
+    cfu::adapter::FwUpdateState,
     power,
     type_c::{
         ControllerId,
@@ -56,7 +57,7 @@ use embedded_services::{
 };
 use embedded_usb_pd::{GlobalPortId, ado::Ado};
 
-use crate::{PortEventStreamer, wrapper::cfu};
+use crate::PortEventStreamer;
 
 /// Per-port state
 pub struct PortState<'a> {
@@ -78,7 +79,7 @@ pub struct PortState<'a> {
 #[derive(Copy, Clone)]
 pub struct ControllerState {
     /// If we're currently doing a firmware update
-    pub(crate) fw_update_state: cfu::FwUpdateState,
+    pub(crate) fw_update_state: FwUpdateState,
     /// State used to keep track of where we are as we turn the event bitfields into a stream of events
     pub(crate) port_event_streaming_state: Option<PortEventStreamer>,
 }
@@ -86,7 +87,7 @@ pub struct ControllerState {
 impl Default for ControllerState {
     fn default() -> Self {
         Self {
-            fw_update_state: cfu::FwUpdateState::Idle,
+            fw_update_state: FwUpdateState::Idle,
             port_event_streaming_state: None,
         }
     }
@@ -1,8 +1,17 @@
 //! CFU message bridge
-//! TODO: remove this once we have a more generic FW update implementation
+//!
+//! CFU requests are handled by the generic [`Adapter`](embedded_services::cfu::adapter::Adapter), this module
+//! provides the wrapper-level [`FwUpdate`] implementation and takes care of detaching from the power policy before
+//! handing the update off to the controller.
+use core::future::Future;
+
 use embassy_futures::select::{Either, select};
-use embedded_cfu_protocol::protocol_definitions::*;
+use embedded_cfu_protocol::protocol_definitions::{
+    CfuUpdateContentResponseStatus, FW_UPDATE_FLAG_FIRST_BLOCK, FwUpdateContentResponse,
+};
+use embedded_services::cfu::adapter::FwUpdateState;
 use embedded_services::cfu::component::{InternalResponseData, RequestData};
+use embedded_services::fw_update::{self, FwUpdate, Progress};
 use embedded_services::power;
 use embedded_services::type_c::controller::Controller;
 use embedded_services::{debug, error};
@@ -10,291 +19,118 @@ use embedded_services::{debug, error};
 use super::message::EventCfu;
 use super::*;
 
-/// Current state of the firmware update process
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum FwUpdateState {
-    /// None in progress
-    Idle,
-    /// Firmware update in progress
-    /// Contains number of ticks [`super::DEFAULT_FW_UPDATE_TICK_INTERVAL_MS`] that have passed
-    InProgress(u8),
-    /// Firmware update has failed and the device is in an unknown state
-    Recovery,
+/// Firmware update implementation for a controller managed by a [`ControllerWrapper`]
+struct Updater<'a, 'device, 's, M: RawMutex, C: Lockable, V: FwOfferValidator>
+where
+    <C as Lockable>::Inner: Controller,
+{
+    wrapper: &'a ControllerWrapper<'device, M, C, V>,
+    controller: &'a mut C::Inner,
+    state: &'a mut dyn DynPortState<'s>,
 }
 
-impl FwUpdateState {
-    /// Check if the firmware update is in progress
-    pub fn in_progress(&self) -> bool {
-        matches!(self, FwUpdateState::InProgress(_) | FwUpdateState::Recovery)
-    }
+impl<M: RawMutex, C: Lockable, V: FwOfferValidator> fw_update::ErrorType for Updater<'_, '_, '_, M, C, V>
+where
+    <C as Lockable>::Inner: Controller,
+{
+    type Error = Error<<C::Inner as Controller>::BusError>;
 }
 
-impl<'device, M: RawMutex, C: Lockable, V: FwOfferValidator> ControllerWrapper<'device, M, C, V>
+impl<M: RawMutex, C: Lockable, V: FwOfferValidator> Updater<'_, '_, '_, M, C, V>
 where
     <C as Lockable>::Inner: Controller,
 {
-    /// Create a new invalid FW version response
-    fn create_invalid_fw_version_response(&self) -> InternalResponseData {
-        let dev_inf = FwVerComponentInfo::new(FwVersion::new(0xffffffff), self.registration.cfu_device.component_id());
-        let comp_info: [FwVerComponentInfo; MAX_CMPT_COUNT] = [dev_inf; MAX_CMPT_COUNT];
-        InternalResponseData::FwVersionResponse(GetFwVersionResponse {
-            header: GetFwVersionResponseHeader::new(1, GetFwVerRespHeaderByte3::NoSpecialFlags),
-            component_info: comp_info,
-        })
-    }
+    /// Detach from the power policy so it doesn't attempt to do anything while we are updating
+    async fn detach_power_devices(&mut self) -> Result<(), Error<<C::Inner as Controller>::BusError>> {
+        let controller_id = self.wrapper.registration.pd_controller.id();
+        for power in self.wrapper.registration.power_devices {
+            info!("Controller{}: checking power device", controller_id.0);
+            if power.state().await != power::policy::device::State::Detached {
+                info!("Controller{}: Detaching power device", controller_id.0);
+                if let Err(e) = power.detach().await {
+                    error!("Controller{}: Failed to detach power device: {:?}", controller_id.0, e);
+
+                    // Sync to bring the controller to a known state with all services
+                    match self.wrapper.sync_state_internal(self.controller, self.state).await {
+                        Ok(_) => debug!(
+                            "Controller{}: Synced state after detaching power device",
+                            controller_id.0
+                        ),
+                        Err(Error::Pd(e)) => error!(
+                            "Controller{}: Failed to sync state after detaching power device: {:?}",
+                            controller_id.0, e
+                        ),
+                        Err(Error::Bus(_)) => error!(
+                            "Controller{}: Failed to sync state after detaching power device, bus error",
+                            controller_id.0
+                        ),
+                    }
 
-    /// Process a GetFwVersion command
-    async fn process_get_fw_version(&self, target: &mut C::Inner) -> InternalResponseData {
-        let version = match target.get_active_fw_version().await {
-            Ok(v) => v,
-            Err(Error::Pd(e)) => {
-                error!("Failed to get active firmware version: {:?}", e);
-                return self.create_invalid_fw_version_response();
-            }
-            Err(Error::Bus(_)) => {
-                error!("Failed to get active firmware version, bus error");
-                return self.create_invalid_fw_version_response();
+                    return Err(Error::Pd(PdError::Failed));
+                }
             }
-        };
+        }
 
-        let dev_inf = FwVerComponentInfo::new(FwVersion::new(version), self.registration.cfu_device.component_id());
-        let comp_info: [FwVerComponentInfo; MAX_CMPT_COUNT] = [dev_inf; MAX_CMPT_COUNT];
-        InternalResponseData::FwVersionResponse(GetFwVersionResponse {
-            header: GetFwVersionResponseHeader::new(1, GetFwVerRespHeaderByte3::NoSpecialFlags),
-            component_info: comp_info,
-        })
+        Ok(())
     }
+}
 
-    /// Create an offer rejection response
-    fn create_offer_rejection() -> InternalResponseData {
-        InternalResponseData::OfferResponse(FwUpdateOfferResponse::new_with_failure(
-            HostToken::Driver,
-            OfferRejectReason::InvalidComponent,
-            OfferStatus::Reject,
-        ))
+impl<M: RawMutex, C: Lockable, V: FwOfferValidator> FwUpdate for Updater<'_, '_, '_, M, C, V>
+where
+    <C as Lockable>::Inner: Controller,
+{
+    fn get_active_fw_version(&mut self) -> impl Future<Output = Result<u32, Self::Error>> {
+        self.controller.get_active_fw_version()
     }
 
-    /// Process a GiveOffer command
-    async fn process_give_offer(&self, target: &mut C::Inner, offer: &FwUpdateOffer) -> InternalResponseData {
-        if offer.component_info.component_id != self.registration.cfu_device.component_id() {
-            return Self::create_offer_rejection();
-        }
-
-        let version = match target.get_active_fw_version().await {
-            Ok(v) => v,
-            Err(Error::Pd(e)) => {
-                error!("Failed to get active firmware version: {:?}", e);
-                return Self::create_offer_rejection();
-            }
-            Err(Error::Bus(_)) => {
-                error!("Failed to get active firmware version, bus error");
-                return Self::create_offer_rejection();
-            }
-        };
-
-        InternalResponseData::OfferResponse(self.fw_version_validator.validate(FwVersion::new(version), offer))
+    fn validate_offer(&mut self) -> impl Future<Output = Result<fw_update::OfferStatus, Self::Error>> {
+        self.controller.validate_offer()
     }
 
-    async fn process_abort_update(
-        &self,
-        controller: &mut C::Inner,
-        state: &mut dyn DynPortState<'_>,
-    ) -> InternalResponseData {
-        // abort the update process
-        match controller.abort_fw_update().await {
-            Ok(_) => {
-                debug!("FW update aborted successfully");
-                state.controller_state_mut().fw_update_state = FwUpdateState::Idle;
-            }
-            Err(Error::Pd(e)) => {
-                error!("Failed to abort FW update: {:?}", e);
-                state.controller_state_mut().fw_update_state = FwUpdateState::Recovery;
-            }
-            Err(Error::Bus(_)) => {
-                error!("Failed to abort FW update, bus error");
-                state.controller_state_mut().fw_update_state = FwUpdateState::Recovery;
-            }
-        }
-
-        InternalResponseData::ComponentPrepared
+    async fn start_fw_update(&mut self) -> Result<(), Self::Error> {
+        self.wrapper.fw_update_ticker.lock().await.reset();
+        self.controller.start_fw_update().await
     }
 
-    /// Process a GiveContent command
-    async fn process_give_content(
-        &self,
-        controller: &mut C::Inner,
-        state: &mut dyn DynPortState<'_>,
-        content: &FwUpdateContentCommand,
-    ) -> InternalResponseData {
-        let data = if let Some(data) = content.data.get(0..content.header.data_length as usize) {
-            data
-        } else {
-            return InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
-                content.header.sequence_num,
-                CfuUpdateContentResponseStatus::ErrorPrepare,
-            ));
-        };
-
-        debug!("Got content {:#?}", content);
-        if content.header.flags & FW_UPDATE_FLAG_FIRST_BLOCK != 0 {
-            debug!("Got first block");
-
-            // Detach from the power policy so it doesn't attempt to do anything while we are updating
-            let controller_id = self.registration.pd_controller.id();
-            let mut detached_all = true;
-            for power in self.registration.power_devices {
-                info!("Controller{}: checking power device", controller_id.0);
-                if power.state().await != power::policy::device::State::Detached {
-                    info!("Controller{}: Detaching power device", controller_id.0);
-                    if let Err(e) = power.detach().await {
-                        error!("Controller{}: Failed to detach power device: {:?}", controller_id.0, e);
-
-                        // Sync to bring the controller to a known state with all services
-                        match self.sync_state_internal(controller, state).await {
-                            Ok(_) => debug!(
-                                "Controller{}: Synced state after detaching power device",
-                                controller_id.0
-                            ),
-                            Err(Error::Pd(e)) => error!(
-                                "Controller{}: Failed to sync state after detaching power device: {:?}",
-                                controller_id.0, e
-                            ),
-                            Err(Error::Bus(_)) => error!(
-                                "Controller{}: Failed to sync state after detaching power device, bus error",
-                                controller_id.0
-                            ),
-                        }
-
-                        detached_all = false;
-                        break;
-                    }
-                }
-            }
-
-            if !detached_all {
-                error!(
-                    "Controller{}: Failed to detach all power devices, rejecting offer",
-                    controller_id.0
-                );
-                return InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
-                    content.header.sequence_num,
-                    CfuUpdateContentResponseStatus::ErrorPrepare,
-                ));
-            }
+    fn write_fw_contents(&mut self, offset: usize, data: &[u8]) -> impl Future<Output = Result<(), Self::Error>> {
+        self.controller.write_fw_contents(offset, data)
+    }
 
-            // Need to start the update
-            self.fw_update_ticker.lock().await.reset();
-            match controller.start_fw_update().await {
-                Ok(_) => {
-                    debug!("FW update started successfully");
-                }
-                Err(Error::Pd(e)) => {
-                    error!("Failed to start FW update: {:?}", e);
-                    state.controller_state_mut().fw_update_state = FwUpdateState::Recovery;
-                    return InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
-                        content.header.sequence_num,
-                        CfuUpdateContentResponseStatus::ErrorPrepare,
-                    ));
-                }
-                Err(Error::Bus(_)) => {
-                    error!("Failed to start FW update, bus error");
-                    state.controller_state_mut().fw_update_state = FwUpdateState::Recovery;
-                    return InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
-                        content.header.sequence_num,
-                        CfuUpdateContentResponseStatus::ErrorPrepare,
-                    ));
-                }
-            }
+    fn verify_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        self.controller.verify_fw_update()
+    }
 
-            state.controller_state_mut().fw_update_state = FwUpdateState::InProgress(0);
-        }
+    fn activate_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        self.controller.activate_fw_update()
+    }
 
-        match controller
-            .write_fw_contents(content.header.firmware_address as usize, data)
-            .await
-        {
-            Ok(_) => {
-                debug!("Block written successfully");
-            }
-            Err(Error::Pd(e)) => {
-                error!("Failed to write block: {:?}", e);
-                return InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
-                    content.header.sequence_num,
-                    CfuUpdateContentResponseStatus::ErrorWrite,
-                ));
-            }
-            Err(Error::Bus(_)) => {
-                error!("Failed to write block, bus error");
-                return InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
-                    content.header.sequence_num,
-                    CfuUpdateContentResponseStatus::ErrorWrite,
-                ));
-            }
-        }
+    fn abort_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        self.controller.abort_fw_update()
+    }
 
-        if content.header.flags & FW_UPDATE_FLAG_LAST_BLOCK != 0 {
-            match controller.finalize_fw_update().await {
-                Ok(_) => {
-                    debug!("FW update finalized successfully");
-                    state.controller_state_mut().fw_update_state = FwUpdateState::Idle;
-                }
-                Err(Error::Pd(e)) => {
-                    error!("Failed to finalize FW update: {:?}", e);
-                    state.controller_state_mut().fw_update_state = FwUpdateState::Recovery;
-                    return Self::create_offer_rejection();
-                }
-                Err(Error::Bus(_)) => {
-                    error!("Failed to finalize FW update, bus error");
-                    state.controller_state_mut().fw_update_state = FwUpdateState::Recovery;
-                    return Self::create_offer_rejection();
-                }
-            }
-        }
+    fn rollback_fw_update(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        self.controller.rollback_fw_update()
+    }
 
-        InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
-            content.header.sequence_num,
-            CfuUpdateContentResponseStatus::Success,
-        ))
+    fn progress(&self) -> Progress {
+        self.controller.progress()
     }
+}
 
+impl<'device, M: RawMutex, C: Lockable, V: FwOfferValidator> ControllerWrapper<'device, M, C, V>
+where
+    <C as Lockable>::Inner: Controller,
+{
     /// Process a CFU tick
     pub async fn process_cfu_tick(&self, controller: &mut C::Inner, state: &mut dyn DynPortState<'_>) {
-        match state.controller_state_mut().fw_update_state {
-            FwUpdateState::Idle => {
-                // No FW update in progress, nothing to do
-                return;
-            }
-            FwUpdateState::InProgress(ticks) => {
-                if ticks + 1 < DEFAULT_FW_UPDATE_TIMEOUT_TICKS {
-                    trace!("CFU tick: {}", ticks);
-                    state.controller_state_mut().fw_update_state = FwUpdateState::InProgress(ticks + 1);
-                    return;
-                } else {
-                    error!("FW update timed out after {} ticks", ticks);
-                }
-            }
-            FwUpdateState::Recovery => {
-                // Continue recovery process
-            }
+        let mut fw_update_state = state.controller_state().fw_update_state;
+        let mut updater = Updater {
+            wrapper: self,
+            controller,
+            state: &mut *state,
         };
-
-        // Update timed out, attempt to exit the FW update
-        state.controller_state_mut().fw_update_state = FwUpdateState::Recovery;
-        match controller.abort_fw_update().await {
-            Ok(_) => {
-                debug!("FW update aborted successfully");
-            }
-            Err(Error::Pd(e)) => {
-                error!("Failed to abort FW update: {:?}", e);
-                return;
-            }
-            Err(Error::Bus(_)) => {
-                error!("Failed to abort FW update, bus error");
-                return;
-            }
-        }
-
-        state.controller_state_mut().fw_update_state = FwUpdateState::Idle;
+        self.cfu_adapter.process_tick(&mut updater, &mut fw_update_state).await;
+        state.controller_state_mut().fw_update_state = fw_update_state;
     }
 
     /// Process a CFU command
@@ -304,45 +140,35 @@ where
         state: &mut dyn DynPortState<'_>,
         command: &RequestData,
     ) -> InternalResponseData {
-        if state.controller_state().fw_update_state == FwUpdateState::Recovery {
-            debug!("FW update in recovery state, rejecting command");
-            return InternalResponseData::ComponentBusy;
-        }
+        let mut fw_update_state = state.controller_state().fw_update_state;
+        let mut updater = Updater {
+            wrapper: self,
+            controller,
+            state: &mut *state,
+        };
 
-        match command {
-            RequestData::FwVersionRequest => {
-                debug!("Got FwVersionRequest");
-                self.process_get_fw_version(controller).await
-            }
-            RequestData::GiveOffer(offer) => {
-                debug!("Got GiveOffer");
-                self.process_give_offer(controller, offer).await
-            }
-            RequestData::GiveContent(content) => {
-                debug!("Got GiveContent");
-                self.process_give_content(controller, state, content).await
-            }
-            RequestData::AbortUpdate => {
-                debug!("Got AbortUpdate");
-                self.process_abort_update(controller, state).await
-            }
-            RequestData::FinalizeUpdate => {
-                debug!("Got FinalizeUpdate");
-                InternalResponseData::ComponentPrepared
-            }
-            RequestData::PrepareComponentForUpdate => {
-                debug!("Got PrepareComponentForUpdate");
-                InternalResponseData::ComponentPrepared
-            }
-            RequestData::GiveOfferExtended(_) => {
-                debug!("Got GiveExtendedOffer, rejecting");
-                Self::create_offer_rejection()
-            }
-            RequestData::GiveOfferInformation(_) => {
-                debug!("Got GiveOfferInformation, rejecting");
-                Self::create_offer_rejection()
-            }
+        // Detach before the adapter starts the update, failing to detach leaves the controller untouched so there's
+        // nothing to recover from
+        if let RequestData::GiveContent(content) = command
+            && content.header.flags & FW_UPDATE_FLAG_FIRST_BLOCK != 0
+            && updater.detach_power_devices().await.is_err()
+        {
+            error!(
+                "Controller{}: Failed to detach all power devices, rejecting update",
+                self.registration.pd_controller.id().0
+            );
+            return InternalResponseData::ContentResponse(FwUpdateContentResponse::new(
+                content.header.sequence_num,
+                CfuUpdateContentResponseStatus::ErrorPrepare,
+            ));
         }
+
+        let response = self
+            .cfu_adapter
+            .process_request(&mut updater, &mut fw_update_state, command)
+            .await;
+        state.controller_state_mut().fw_update_state = fw_update_state;
+        response
     }
 
     /// Sends a CFU response to the command
@@ -26,8 +26,8 @@ use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::signal::Signal;
 use embassy_time::Instant;
-use embedded_cfu_protocol::protocol_definitions::{FwUpdateOffer, FwUpdateOfferResponse, FwVersion};
 use embedded_services::GlobalRawMutex;
+use embedded_services::cfu::adapter::Adapter;
 use embedded_services::power::policy::device::StateKind;
 use embedded_services::power::policy::{self, action};
 use embedded_services::sync::Lockable;
@@ -50,19 +50,9 @@ mod pd;
 mod power;
 mod vdm;
 
-/// Base interval for checking for FW update timeouts and recovery attempts
-pub const DEFAULT_FW_UPDATE_TICK_INTERVAL_MS: u64 = 5000;
-/// Default number of ticks before we consider a firmware update to have timed out
-/// 300 seconds at 5 seconds per tick
-pub const DEFAULT_FW_UPDATE_TIMEOUT_TICKS: u8 = 60;
-
-/// Trait for validating firmware versions before applying an update
-// TODO: remove this once we have a better framework for OEM customization
-// See https://github.com/OpenDevicePartnership/embedded-services/issues/326
-pub trait FwOfferValidator {
-    /// Determine if we are accepting the firmware update offer, returns a CFU offer response
-    fn validate(&self, current: FwVersion, offer: &FwUpdateOffer) -> FwUpdateOfferResponse;
-}
+pub use embedded_services::cfu::adapter::{
+    DEFAULT_FW_UPDATE_TICK_INTERVAL_MS, DEFAULT_FW_UPDATE_TIMEOUT_TICKS, FwOfferValidator,
+};
 
 /// Maximum number of supported ports
 pub const MAX_SUPPORTED_PORTS: usize = 2;
@@ -73,8 +63,8 @@ where
     <C as Lockable>::Inner: Controller,
 {
     controller: &'device C,
-    /// Trait object for validating firmware versions
-    fw_version_validator: V,
+    /// Bridge between CFU requests and the controller firmware update implementation
+    cfu_adapter: Adapter<V>,
     /// FW update ticker used to check for timeouts and recovery attempts
     fw_update_ticker: Mutex<M, embassy_time::Ticker>,
     /// Registration information for services
@@ -106,7 +96,7 @@ where
         Some(Self {
             controller,
             config,
-            fw_version_validator,
+            cfu_adapter: Adapter::new(backing.registration.cfu_device.component_id(), fw_version_validator),
             fw_update_ticker: Mutex::new(embassy_time::Ticker::every(embassy_time::Duration::from_millis(
                 DEFAULT_FW_UPDATE_TICK_INTERVAL_MS,
             ))),
@@ -290,7 +280,13 @@ where
         }
 
         // Only notify power policy of a contract after Sink Ready event (always after explicit or implicit contract)
-        if status_event.sink_ready() {
+        // While booting without a usable battery, notify as soon as the contract is accepted so the system gets power
+        if status_event.sink_ready()
+            || (status_event.new_power_contract_as_consumer()
+                && policy::policy::boot_state()
+                    .await
+                    .is_some_and(|boot| boot.needs_power()))
+        {
             self.process_new_consumer_contract(power, &status).await?;
         }
 
@@ -458,6 +458,61 @@ where
                     },
                 }
             }
+            controller::PortCommandData::GetPartnerBatteryCapabilities(battery) => {
+                match controller.get_partner_battery_capabilities(local_port, battery).await {
+                    Ok(capabilities) => Ok(controller::PortResponseData::BatteryCapabilities(capabilities)),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
+            controller::PortCommandData::GetPartnerBatteryStatus(battery) => {
+                match controller.get_partner_battery_status(local_port, battery).await {
+                    Ok(status) => Ok(controller::PortResponseData::BatteryStatus(status)),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
+            controller::PortCommandData::GetPartnerSourceInfo => {
+                match controller.get_partner_source_info(local_port).await {
+                    Ok(info) => Ok(controller::PortResponseData::SourceInfo(info)),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
+            controller::PortCommandData::GetPartnerStatus => match controller.get_partner_status(local_port).await {
+                Ok(status) => Ok(controller::PortResponseData::PdStatus(status)),
+                Err(e) => match e {
+                    Error::Bus(_) => Err(PdError::Failed),
+                    Error::Pd(e) => Err(e),
+                },
+            },
+            controller::PortCommandData::SetBatteryCapabilities(battery, capabilities) => {
+                match controller
+                    .set_battery_capabilities(local_port, battery, capabilities)
+                    .await
+                {
+                    Ok(()) => Ok(controller::PortResponseData::Complete),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
+            controller::PortCommandData::SetBatteryStatus(battery, status) => {
+                match controller.set_battery_status(local_port, battery, status).await {
+                    Ok(()) => Ok(controller::PortResponseData::Complete),
+                    Err(e) => match e {
+                        Error::Bus(_) => Err(PdError::Failed),
+                        Error::Pd(e) => Err(e),
+                    },
+                }
+            }
         })
     }
 
@@ -199,15 +199,40 @@ where
     }
 
     /// Handle a connect as provider command
-    fn process_connect_as_provider(
+    ///
+    /// Returns the capability the port keeps providing if the controller can't change it, the power policy must
+    /// account for that instead of the requested capability.
+    async fn process_connect_as_provider(
         &self,
         port: LocalPortId,
         capability: ProviderPowerCapability,
-        _controller: &mut C::Inner,
-    ) -> Result<(), Error<<C::Inner as Controller>::BusError>> {
+        controller: &mut C::Inner,
+        power: &policy::device::Device,
+    ) -> Result<Option<ProviderPowerCapability>, Error<<C::Inner as Controller>::BusError>> {
         info!("Port{}: Connect as provider: {:#?}", port.0, capability);
-        // TODO: double check explicit contract handling
-        Ok(())
+        // Update the advertised source capabilities, the controller renegotiates if needed
+        match controller.set_source_capability(port, capability.capability).await {
+            Err(Error::Pd(PdError::UnrecognizedCommand)) => {
+                // The requested capability comes from the current contract, which is what the port keeps providing
+                let Some(current) = power.requested_provider_capability().await else {
+                    error!(
+                        "Port{}: Controller can't set its source capability, no current contract",
+                        port.0
+                    );
+                    return Err(Error::Pd(PdError::UnrecognizedCommand));
+                };
+                warn!(
+                    "Port{}: Controller can't set its source capability, keeping the current contract",
+                    port.0
+                );
+                Ok(Some(current))
+            }
+            Err(e) => {
+                error!("Port{}: Error setting source capability", port.0);
+                Err(e)
+            }
+            Ok(()) => Ok(None),
+        }
     }
 
     /// Wait for a power command
@@ -268,9 +293,16 @@ where
                 }
             }
             policy::device::CommandData::ConnectAsProvider(capability) => {
-                if self.process_connect_as_provider(port, *capability, controller).is_err() {
-                    error!("Error processing connect provider");
-                    return Err(policy::Error::Failed);
+                match self
+                    .process_connect_as_provider(port, *capability, controller, power)
+                    .await
+                {
+                    Ok(Some(current)) => return Ok(policy::device::ResponseData::ProviderCapability(current)),
+                    Ok(None) => {}
+                    Err(_) => {
+                        error!("Error processing connect provider");
+                        return Err(policy::Error::Failed);
+                    }
                 }
             }
             policy::device::CommandData::Disconnect => {